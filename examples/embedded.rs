@@ -0,0 +1,54 @@
+//! Runs the digital human without the HTTP server: feeds it a message and
+//! prints the response.
+//!
+//!     cargo run --example embedded
+
+use actix::prelude::*;
+use live_streamer::config::AppConfig;
+use live_streamer::events::{EventMetadata, MessagePriority, ResponseBundle, TextInputEvent};
+use live_streamer::DigitalHumanService;
+use uuid::Uuid;
+
+/// Prints each response and stops the system after the first one.
+struct Printer;
+
+impl Actor for Printer {
+    type Context = Context<Self>;
+}
+
+impl Handler<ResponseBundle> for Printer {
+    type Result = ();
+
+    fn handle(&mut self, bundle: ResponseBundle, _ctx: &mut Context<Self>) -> Self::Result {
+        println!("[{}] {}", bundle.text.model, bundle.text.response);
+        System::current().stop();
+    }
+}
+
+fn main() {
+    let system = System::new();
+
+    system.block_on(async {
+        let service = DigitalHumanService::new(AppConfig::default()).start();
+        service.subscribe(Printer.start().recipient());
+
+        service.send_text(TextInputEvent {
+            metadata: EventMetadata {
+                session_id: Some(Uuid::new_v4()),
+                user_id: Some("embedded-user".to_string()),
+                ..Default::default()
+            },
+            text: "你好，今天直播什么内容？".to_string(),
+            language: Some("zh-CN".to_string()),
+            username: None,
+            room_mood: None,
+            priority: MessagePriority::Normal,
+            intent: None,
+            viewer: None,
+            max_age_seconds: None,
+            operator: false,
+        });
+    });
+
+    system.run().expect("actix system failed");
+}
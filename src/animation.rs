@@ -0,0 +1,198 @@
+use crate::events::{AnimationEvent, ViewerInfo};
+use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Expression a session is assumed to show before its first one.
+const NEUTRAL_EMOTION: &str = "neutral";
+
+/// Maps a message's importance (0-1) to how much of the remaining headroom
+/// its animations use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScalingCurve {
+    #[default]
+    Linear,
+    /// Only the most important messages get noticeably bigger animations.
+    EaseIn,
+    /// Even slightly important messages get most of the boost.
+    EaseOut,
+}
+
+impl FromStr for ScalingCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "linear" => Ok(ScalingCurve::Linear),
+            "ease_in" => Ok(ScalingCurve::EaseIn),
+            "ease_out" => Ok(ScalingCurve::EaseOut),
+            other => Err(format!("unknown scaling curve: {}", other)),
+        }
+    }
+}
+
+impl ScalingCurve {
+    fn apply(self, importance: f64) -> f64 {
+        let x = importance.clamp(0.0, 1.0);
+        match self {
+            ScalingCurve::Linear => x,
+            ScalingCurve::EaseIn => x * x,
+            ScalingCurve::EaseOut => x.sqrt(),
+        }
+    }
+}
+
+/// Makes animations bigger and longer for VIPs, high-level viewers and gifts.
+#[derive(Debug, Clone)]
+pub struct AnimationScaling {
+    pub curve: ScalingCurve,
+    /// Importance added by a VIP sender.
+    pub vip_weight: f64,
+    /// Importance added by a sender at `level_cap` or above, less below it.
+    pub level_weight: f64,
+    pub level_cap: u32,
+    /// Importance added by a gift worth `gift_cap` or more, less below it.
+    pub gift_weight: f64,
+    pub gift_cap: f64,
+    /// Duration multiplier at full importance.
+    pub max_duration_scale: f64,
+}
+
+impl Default for AnimationScaling {
+    fn default() -> Self {
+        Self {
+            curve: ScalingCurve::Linear,
+            vip_weight: 0.5,
+            level_weight: 0.3,
+            level_cap: 50,
+            gift_weight: 0.5,
+            gift_cap: 100.0,
+            max_duration_scale: 1.5,
+        }
+    }
+}
+
+impl AnimationScaling {
+    /// Applies weights such as `vip=0.5,level=0.3,gift=0.5`.
+    pub fn apply_weights(&mut self, spec: &str) -> Result<(), String> {
+        for pair in spec.split(',').filter(|p| !p.trim().is_empty()) {
+            let (factor, weight) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected factor=weight, got: {}", pair))?;
+            let weight: f64 = weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight: {}", weight))?;
+            match factor.trim() {
+                "vip" => self.vip_weight = weight,
+                "level" => self.level_weight = weight,
+                "gift" => self.gift_weight = weight,
+                other => return Err(format!("unknown importance factor: {}", other)),
+            }
+        }
+        Ok(())
+    }
+
+    /// How important a message from `viewer` is, from 0 (routine or direct
+    /// input) to 1.
+    pub fn importance(&self, viewer: Option<&ViewerInfo>) -> f64 {
+        let Some(viewer) = viewer else {
+            return 0.0;
+        };
+        let fraction = |value: f64, cap: f64| {
+            if cap > 0.0 {
+                (value / cap).clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
+        let vip = if viewer.is_vip { self.vip_weight } else { 0.0 };
+        let level = self.level_weight
+            * fraction(viewer.user_level.unwrap_or(0) as f64, self.level_cap as f64);
+        let gift = self.gift_weight * fraction(viewer.gift_value.unwrap_or(0.0), self.gift_cap);
+        (vip + level + gift).clamp(0.0, 1.0)
+    }
+
+    /// Raises `intensity`/`strength` towards 1 and stretches the duration
+    /// according to `importance`.
+    pub fn scale(&self, animation: &mut AnimationEvent, importance: f64) {
+        let boost = self.curve.apply(importance);
+        if boost <= 0.0 {
+            return;
+        }
+        if let Some(parameters) = animation.parameters.as_object_mut() {
+            for key in ["intensity", "strength"] {
+                if let Some(base) = parameters.get(key).and_then(|v| v.as_f64()) {
+                    parameters.insert(key.to_string(), (base + (1.0 - base) * boost).into());
+                }
+            }
+        }
+        if let Some(duration) = animation.duration.as_mut() {
+            *duration *= (1.0 + (self.max_duration_scale - 1.0) * boost) as f32;
+        }
+    }
+}
+
+/// How long clients take to tween from one expression to the next.
+#[derive(Debug, Clone)]
+pub struct EmotionTransitionConfig {
+    pub transition_ms: u64,
+}
+
+impl Default for EmotionTransitionConfig {
+    fn default() -> Self {
+        Self { transition_ms: 300 }
+    }
+}
+
+/// Where an expression is shown: a viewer's session, or the overlays of a
+/// room when responses are routed there, which every danmaku session of the
+/// room shares.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EmotionTarget {
+    Session(Uuid),
+    Room(String),
+}
+
+/// Tracks the expression each target shows, so a new one names the one it
+/// replaces and the client can tween between them instead of snapping.
+#[derive(Debug, Default)]
+pub struct EmotionTransitions {
+    config: EmotionTransitionConfig,
+    current: HashMap<EmotionTarget, String>,
+}
+
+impl EmotionTransitions {
+    pub fn new(config: EmotionTransitionConfig) -> Self {
+        Self {
+            config,
+            current: HashMap::new(),
+        }
+    }
+
+    /// Adds `from`, `to` and `transition_ms` to an expression's parameters
+    /// and makes its `emotion` the target's current one.
+    pub fn apply(&mut self, target: EmotionTarget, expression: &mut AnimationEvent) {
+        let Some(parameters) = expression.parameters.as_object_mut() else {
+            return;
+        };
+        let Some(to) = parameters.get("emotion").and_then(|e| e.as_str()) else {
+            return;
+        };
+        let to = to.to_string();
+        let from = self
+            .current
+            .insert(target, to.clone())
+            .unwrap_or_else(|| NEUTRAL_EMOTION.to_string());
+        parameters.insert("from".to_string(), from.into());
+        parameters.insert("to".to_string(), to.into());
+        parameters.insert(
+            "transition_ms".to_string(),
+            self.config.transition_ms.into(),
+        );
+    }
+
+    pub fn forget(&mut self, session_id: &Uuid) {
+        self.current.remove(&EmotionTarget::Session(*session_id));
+    }
+}
@@ -0,0 +1,267 @@
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// What to do when a session's token expires without being refreshed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenExpiryPolicy {
+    /// Close the session.
+    Enforce,
+    /// Log a warning and keep the session open.
+    Warn,
+}
+
+impl FromStr for TokenExpiryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "enforce" => Ok(TokenExpiryPolicy::Enforce),
+            "warn" => Ok(TokenExpiryPolicy::Warn),
+            other => Err(format!("unknown token expiry policy: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthConfig {
+    /// HS256 secret for WebSocket tokens; sessions are unauthenticated when unset.
+    pub jwt_secret: Option<String>,
+    /// How often open sessions have their token expiry checked.
+    pub check_interval_seconds: u64,
+    /// How long before expiry the client is asked for a fresh token.
+    pub refresh_before_seconds: u64,
+    pub expiry_policy: TokenExpiryPolicy,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            jwt_secret: None,
+            check_interval_seconds: 30,
+            refresh_before_seconds: 60,
+            expiry_policy: TokenExpiryPolicy::Enforce,
+        }
+    }
+}
+
+// Keep the secret out of logs
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field(
+                "jwt_secret",
+                &self.jwt_secret.as_ref().map(|_| "<redacted>"),
+            )
+            .field("check_interval_seconds", &self.check_interval_seconds)
+            .field("refresh_before_seconds", &self.refresh_before_seconds)
+            .field("expiry_policy", &self.expiry_policy)
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    /// The user the token was issued to.
+    pub sub: String,
+    /// Expiry as a Unix timestamp.
+    pub exp: i64,
+    /// Grants operator access, such as the monitor feed.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl AuthConfig {
+    pub fn enabled(&self) -> bool {
+        self.jwt_secret.is_some()
+    }
+
+    pub fn check_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.check_interval_seconds.max(1))
+    }
+
+    fn decode(&self, token: &str) -> Result<Claims, String> {
+        let secret = self
+            .jwt_secret
+            .as_ref()
+            .ok_or("authentication is disabled")?;
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+
+        jsonwebtoken::decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(secret.as_bytes()),
+            &validation,
+        )
+        .map(|data| data.claims)
+        .map_err(|e| e.to_string())
+    }
+
+    /// Verifies a token issued to `user_id` and returns when it expires.
+    pub fn verify(&self, token: &str, user_id: &str) -> Result<DateTime<Utc>, String> {
+        let claims = self.decode(token)?;
+        if claims.sub != user_id {
+            return Err("token was issued to another user".to_string());
+        }
+        DateTime::from_timestamp(claims.exp, 0).ok_or_else(|| "invalid expiry".to_string())
+    }
+
+    /// Verifies an operator token and returns who it was issued to.
+    pub fn verify_admin(&self, token: &str) -> Result<String, String> {
+        let claims = self.decode(token)?;
+        if !claims.admin {
+            return Err("token does not grant admin access".to_string());
+        }
+        Ok(claims.sub)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenAction {
+    /// The token expires soon; ask the client for a fresh one.
+    RequestRefresh(DateTime<Utc>),
+    /// The token has expired.
+    Expired,
+}
+
+#[derive(Debug)]
+struct SessionToken {
+    expires_at: DateTime<Utc>,
+    refresh_requested: bool,
+    expiry_reported: bool,
+}
+
+/// Token expiry of each authenticated session.
+#[derive(Debug, Default)]
+pub struct SessionTokens {
+    tokens: HashMap<Uuid, SessionToken>,
+}
+
+impl SessionTokens {
+    /// Records the expiry of a session's current token, replacing any earlier one.
+    pub fn track(&mut self, session_id: Uuid, expires_at: DateTime<Utc>) {
+        self.tokens.insert(
+            session_id,
+            SessionToken {
+                expires_at,
+                refresh_requested: false,
+                expiry_reported: false,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, session_id: &Uuid) {
+        self.tokens.remove(session_id);
+    }
+
+    /// Sessions needing attention at `now`. Each token triggers at most one
+    /// refresh request and one expiry.
+    pub fn check(
+        &mut self,
+        now: DateTime<Utc>,
+        refresh_before: Duration,
+    ) -> Vec<(Uuid, TokenAction)> {
+        let mut actions = Vec::new();
+        for (session_id, token) in self.tokens.iter_mut() {
+            if now >= token.expires_at {
+                if !token.expiry_reported {
+                    token.expiry_reported = true;
+                    actions.push((*session_id, TokenAction::Expired));
+                }
+            } else if now >= token.expires_at - refresh_before && !token.refresh_requested {
+                token.refresh_requested = true;
+                actions.push((*session_id, TokenAction::RequestRefresh(token.expires_at)));
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            jwt_secret: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn token(sub: &str, exp: DateTime<Utc>) -> String {
+        sign(false, sub, exp)
+    }
+
+    fn sign(admin: bool, sub: &str, exp: DateTime<Utc>) -> String {
+        let claims = Claims {
+            sub: sub.to_string(),
+            exp: exp.timestamp(),
+            admin,
+        };
+        jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_token() {
+        let config = config();
+        let exp = DateTime::from_timestamp(Utc::now().timestamp() + 300, 0).unwrap();
+
+        assert_eq!(config.verify(&token("alice", exp), "alice"), Ok(exp));
+        assert!(config.verify(&token("alice", exp), "mallory").is_err());
+        assert!(config
+            .verify(&token("alice", Utc::now() - Duration::seconds(5)), "alice")
+            .is_err());
+        assert!(AuthConfig::default()
+            .verify(&token("alice", exp), "alice")
+            .is_err());
+
+        // Only tokens carrying the admin flag open the monitor feed
+        assert_eq!(
+            config.verify_admin(&sign(true, "ops", exp)),
+            Ok("ops".to_string())
+        );
+        assert!(config.verify_admin(&token("alice", exp)).is_err());
+    }
+
+    #[test]
+    fn test_expiring_token_prompts_refresh() {
+        let mut tokens = SessionTokens::default();
+        let session_id = Uuid::new_v4();
+        let start = Utc::now();
+        let refresh_before = Duration::seconds(60);
+        let expires_at = start + Duration::seconds(90);
+
+        tokens.track(session_id, expires_at);
+        assert!(tokens.check(start, refresh_before).is_empty());
+
+        // Within a minute of expiry: prompt once
+        let soon = start + Duration::seconds(40);
+        assert_eq!(
+            tokens.check(soon, refresh_before),
+            [(session_id, TokenAction::RequestRefresh(expires_at))]
+        );
+        assert!(tokens.check(soon, refresh_before).is_empty());
+
+        // Never refreshed: expires once
+        let later = start + Duration::seconds(90);
+        assert_eq!(
+            tokens.check(later, refresh_before),
+            [(session_id, TokenAction::Expired)]
+        );
+        assert!(tokens.check(later, refresh_before).is_empty());
+
+        // A refreshed token starts over
+        tokens.track(session_id, later + Duration::seconds(600));
+        assert!(tokens.check(later, refresh_before).is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    pub user_id: String,
+    pub reason: Option<String>,
+    pub banned_at: DateTime<Utc>,
+    /// Permanent when unset.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Ban {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+/// 被封禁的用户，消息在校验最前面被忽略；设置路径时每次变更都写入文件
+#[derive(Debug, Default)]
+pub struct BanList {
+    bans: HashMap<String, Ban>,
+    path: Option<String>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the list in `path` as JSON, starting from what is already there.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let bans: Vec<Ban> = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).map_err(|e| e.to_string())?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.to_string()),
+        };
+        Ok(Self {
+            bans: bans
+                .into_iter()
+                .map(|ban| (ban.user_id.clone(), ban))
+                .collect(),
+            path: Some(path.to_string()),
+        })
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let bans: Vec<&Ban> = self.bans.values().collect();
+        let result = serde_json::to_string(&bans)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| std::fs::write(path, contents).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Failed to save ban list to {}: {}", path, e);
+        }
+    }
+
+    /// Bans `ban.user_id`, replacing any earlier ban, and forgets expired ones.
+    pub fn ban(&mut self, ban: Ban) {
+        let now = Utc::now();
+        self.bans.retain(|_, ban| ban.is_active(now));
+        self.bans.insert(ban.user_id.clone(), ban);
+        self.save();
+    }
+
+    /// Lifts a ban; false if the user was not banned.
+    pub fn unban(&mut self, user_id: &str) -> bool {
+        let removed = self.bans.remove(user_id).is_some();
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    pub fn is_banned(&self, user_id: &str, now: DateTime<Utc>) -> bool {
+        self.bans.get(user_id).is_some_and(|ban| ban.is_active(now))
+    }
+}
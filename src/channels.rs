@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// A kind of output a client can choose to receive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    /// Responses, streamed tokens and retractions.
+    Text,
+    /// `tts_response` frames and binary audio chunks.
+    Audio,
+    Animation,
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(Channel::Text),
+            "audio" => Ok(Channel::Audio),
+            "animation" => Ok(Channel::Animation),
+            other => Err(format!("unknown output channel: {}", other)),
+        }
+    }
+}
+
+impl Channel {
+    /// The channel an outbound frame belongs to; None for control frames
+    /// such as `stats` or `session`, which every client receives.
+    pub fn of_frame(frame_type: &str) -> Option<Channel> {
+        match frame_type {
+            "llm_response" | "llm_token" | "retract" => Some(Channel::Text),
+            "tts_response" => Some(Channel::Audio),
+            "animation" => Some(Channel::Animation),
+            _ => None,
+        }
+    }
+}
+
+/// The output channels a session receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Channels {
+    pub text: bool,
+    pub audio: bool,
+    pub animation: bool,
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Self {
+            text: true,
+            audio: true,
+            animation: true,
+        }
+    }
+}
+
+impl FromStr for Channels {
+    type Err = String;
+
+    /// Parses a list such as `text,audio`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let channels: Vec<Channel> = s
+            .split(',')
+            .filter(|c| !c.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Channels::only(&channels))
+    }
+}
+
+impl Channels {
+    pub fn only(channels: &[Channel]) -> Self {
+        Self {
+            text: channels.contains(&Channel::Text),
+            audio: channels.contains(&Channel::Audio),
+            animation: channels.contains(&Channel::Animation),
+        }
+    }
+
+    pub fn includes(&self, channel: Channel) -> bool {
+        match channel {
+            Channel::Text => self.text,
+            Channel::Audio => self.audio,
+            Channel::Animation => self.animation,
+        }
+    }
+
+    pub fn list(&self) -> Vec<Channel> {
+        [Channel::Text, Channel::Audio, Channel::Animation]
+            .into_iter()
+            .filter(|&channel| self.includes(channel))
+            .collect()
+    }
+}
+
+/// Changes to the global channels; unset fields stay as they are.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChannelsUpdate {
+    pub text: Option<bool>,
+    pub audio: Option<bool>,
+    pub animation: Option<bool>,
+}
+
+/// Output channels switched on for every session, shared by the actors that
+/// produce and deliver output. Operators switch one off while it is broken,
+/// e.g. audio during a TTS outage, without a restart.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalChannels(Arc<RwLock<Channels>>);
+
+impl GlobalChannels {
+    pub fn new(channels: Channels) -> Self {
+        Self(Arc::new(RwLock::new(channels)))
+    }
+
+    pub fn get(&self) -> Channels {
+        *self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn includes(&self, channel: Channel) -> bool {
+        self.get().includes(channel)
+    }
+
+    /// Applies `update` and returns the channels now on.
+    pub fn update(&self, update: &ChannelsUpdate) -> Channels {
+        let mut channels = self.0.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(text) = update.text {
+            channels.text = text;
+        }
+        if let Some(audio) = update.audio {
+            channels.audio = audio;
+        }
+        if let Some(animation) = update.animation {
+            channels.animation = animation;
+        }
+        *channels
+    }
+}
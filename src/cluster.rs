@@ -0,0 +1,363 @@
+use crate::events::migrate_event;
+use crate::redis_conn::RedisConnector;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+const CHANNEL_PREFIX: &str = "live_streamer:events";
+/// Pause before a dropped subscription reconnects.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+/// Events waiting for the publisher; more are dropped while Redis is down.
+const PUBLISH_QUEUE: usize = 1024;
+
+/// Event types another instance knows how to deliver locally.
+pub const SUPPORTED_TOPICS: &[&str] = &["response_bundle", "response_retracted"];
+
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    /// Redis server whose pub/sub links instances; single-instance when unset.
+    pub redis_url: Option<String>,
+    /// Event types shared with other instances.
+    pub topics: Vec<String>,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            redis_url: None,
+            topics: vec![
+                "response_bundle".to_string(),
+                "response_retracted".to_string(),
+            ],
+        }
+    }
+}
+
+/// An event on its way between instances. `topic` is the event type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Instance that published the event, so it can skip its own echo.
+    pub origin: Uuid,
+    pub topic: String,
+    pub payload: serde_json::Value,
+}
+
+pub type EnvelopeSink = Arc<dyn Fn(Envelope) + Send + Sync>;
+
+/// Carries events between `EventBus` instances, possibly in other processes.
+pub trait EventTransport: Send + fmt::Debug {
+    fn publish(&mut self, envelope: &Envelope) -> Result<(), String>;
+
+    /// Calls `sink` for every envelope published on `topics` by any instance,
+    /// including this one.
+    fn subscribe(&mut self, topics: &[String], sink: EnvelopeSink) -> Result<(), String>;
+}
+
+/// Redis pub/sub transport: one channel per topic.
+pub struct RedisTransport {
+    client: redis::Client,
+    connector: RedisConnector,
+    /// Feeds the publisher task, started with the first publish.
+    publisher: Option<mpsc::Sender<(String, String)>>,
+    /// Events dropped because the publisher fell behind.
+    dropped: u64,
+}
+
+impl RedisTransport {
+    /// Checks the URL; the publisher connects on first use and reconnects
+    /// after failures, so publishing never blocks the event bus.
+    pub fn connect(url: &str) -> redis::RedisResult<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            connector: RedisConnector::open(url)?,
+            publisher: None,
+            dropped: 0,
+        })
+    }
+
+    fn channel(topic: &str) -> String {
+        format!("{}:{}", CHANNEL_PREFIX, topic)
+    }
+
+    /// Publishes queued events in order until the transport is dropped.
+    fn start_publisher(connector: &RedisConnector) -> mpsc::Sender<(String, String)> {
+        let (tx, mut rx) = mpsc::channel::<(String, String)>(PUBLISH_QUEUE);
+        let connector = connector.clone();
+        actix::spawn(async move {
+            while let Some((channel, payload)) = rx.recv().await {
+                let published = match connector.connection().await {
+                    Ok(mut connection) => redis::cmd("PUBLISH")
+                        .arg(&channel)
+                        .arg(payload)
+                        .query_async::<i64>(&mut connection)
+                        .await
+                        .map(|_| ()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = published {
+                    warn!("Failed to publish to cluster channel {}: {}", channel, e);
+                }
+            }
+        });
+        tx
+    }
+}
+
+impl fmt::Debug for RedisTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisTransport").finish_non_exhaustive()
+    }
+}
+
+/// Parses a published envelope, migrating its event from the publishing
+/// instance's schema version, which may be older during a rolling upgrade.
+fn decode_envelope(payload: &str) -> Result<Envelope, String> {
+    let mut envelope: Envelope = serde_json::from_str(payload).map_err(|e| e.to_string())?;
+    envelope.payload = migrate_event(envelope.payload)?;
+    Ok(envelope)
+}
+
+/// Reads one subscription until the connection drops.
+fn run_subscription(
+    client: &redis::Client,
+    channels: &[String],
+    sink: &EnvelopeSink,
+) -> redis::RedisResult<()> {
+    let mut connection = client.get_connection()?;
+    let mut pubsub = connection.as_pubsub();
+    pubsub.subscribe(channels)?;
+    info!("Subscribed to cluster channels {:?}", channels);
+
+    loop {
+        let payload: String = pubsub.get_message()?.get_payload()?;
+        match decode_envelope(&payload) {
+            Ok(envelope) => sink(envelope),
+            Err(e) => warn!("Dropping malformed cluster event: {}", e),
+        }
+    }
+}
+
+impl EventTransport for RedisTransport {
+    fn publish(&mut self, envelope: &Envelope) -> Result<(), String> {
+        let payload = serde_json::to_string(envelope).map_err(|e| e.to_string())?;
+        let connector = &self.connector;
+        let publisher = self
+            .publisher
+            .get_or_insert_with(|| Self::start_publisher(connector));
+        match publisher.try_send((Self::channel(&envelope.topic), payload)) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.dropped += 1;
+                if self.dropped == 1 || self.dropped.is_multiple_of(100) {
+                    warn!(
+                        "Cluster publisher is behind, {} event(s) dropped so far",
+                        self.dropped
+                    );
+                }
+                Ok(())
+            }
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn subscribe(&mut self, topics: &[String], sink: EnvelopeSink) -> Result<(), String> {
+        let client = self.client.clone();
+        let channels: Vec<String> = topics.iter().map(|t| Self::channel(t)).collect();
+
+        // The blocking pub/sub connection gets a thread of its own
+        std::thread::Builder::new()
+            .name("cluster-subscriber".to_string())
+            .spawn(move || loop {
+                if let Err(e) = run_subscription(&client, &channels, &sink) {
+                    warn!("Cluster subscription lost, reconnecting: {}", e);
+                }
+                std::thread::sleep(RESUBSCRIBE_DELAY);
+            })
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// In-process transport linking several buses in one process, e.g. when
+/// embedding more than one service.
+#[derive(Clone, Default)]
+pub struct LocalTransport {
+    subscribers: Arc<Mutex<Vec<(String, EnvelopeSink)>>>,
+}
+
+impl LocalTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for LocalTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalTransport").finish_non_exhaustive()
+    }
+}
+
+impl EventTransport for LocalTransport {
+    fn publish(&mut self, envelope: &Envelope) -> Result<(), String> {
+        let subscribers = self.subscribers.lock().map_err(|e| e.to_string())?;
+        for (topic, sink) in subscribers.iter() {
+            if *topic == envelope.topic {
+                sink(envelope.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn subscribe(&mut self, topics: &[String], sink: EnvelopeSink) -> Result<(), String> {
+        let mut subscribers = self.subscribers.lock().map_err(|e| e.to_string())?;
+        for topic in topics {
+            subscribers.push((topic.clone(), sink.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_bus::{EventBus, ListValidationRules, SubscribeResponses};
+    use crate::events::{EventMetadata, LLMResponseEvent, ResponseBundle};
+    use crate::testing::Collect;
+    use actix::prelude::*;
+
+    #[test]
+    fn test_envelope_event_is_migrated() {
+        let envelope = |version: u64| {
+            serde_json::json!({
+                "origin": Uuid::new_v4(),
+                "topic": "response_retracted",
+                "payload": {"metadata": {"schema_version": version}},
+            })
+            .to_string()
+        };
+
+        let decoded = decode_envelope(&envelope(0)).unwrap();
+        assert_eq!(
+            decoded.payload["metadata"]["schema_version"],
+            crate::events::EVENT_SCHEMA_VERSION
+        );
+        assert!(decode_envelope(&envelope(u16::MAX as u64)).is_err());
+    }
+
+    fn bundle() -> ResponseBundle {
+        ResponseBundle {
+            metadata: EventMetadata::default(),
+            response_id: Uuid::new_v4(),
+            text: LLMResponseEvent {
+                metadata: EventMetadata::default(),
+                response: "大家好".to_string(),
+                model: "digital_human".to_string(),
+                tokens_used: None,
+                length_limit: None,
+                sampling: None,
+                language: None,
+                translation_of: None,
+                replying_to: None,
+                segments: None,
+            },
+            animation: None,
+            emotion: None,
+            audio: None,
+        }
+    }
+
+    async fn bus(transport: &LocalTransport) -> (Addr<EventBus>, Addr<Collect<ResponseBundle>>) {
+        let bus = EventBus::new()
+            .with_cluster(Box::new(transport.clone()), ClusterConfig::default().topics)
+            .start();
+        let collector = Collect::<ResponseBundle>::default().start();
+        bus.do_send(SubscribeResponses {
+            recipient: collector.clone().recipient(),
+        });
+        // Any round trip means the bus has started and subscribed
+        bus.send(ListValidationRules).await.unwrap();
+        (bus, collector)
+    }
+
+    #[actix_web::test]
+    async fn test_event_published_on_one_bus_reaches_another() {
+        let transport = LocalTransport::new();
+        let (ingest_bus, ingest_collector) = bus(&transport).await;
+        let (_serving_bus, serving_collector) = bus(&transport).await;
+
+        let bundle = bundle();
+        ingest_bus.send(bundle.clone()).await.unwrap();
+        actix::clock::sleep(Duration::from_millis(20)).await;
+
+        // Delivered once on each instance: no echo back to the publisher
+        let expected = vec![bundle.response_id];
+        let response_ids = |bundles: Vec<ResponseBundle>| -> Vec<Uuid> {
+            bundles.iter().map(|bundle| bundle.response_id).collect()
+        };
+        assert_eq!(
+            response_ids(Collect::received(&ingest_collector).await),
+            expected
+        );
+        assert_eq!(
+            response_ids(Collect::received(&serving_collector).await),
+            expected
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_publishing_to_unreachable_redis_does_not_block() {
+        // Nothing listens on port 1, so connecting fails in the publisher task
+        let mut transport = RedisTransport::connect("redis://127.0.0.1:1/").unwrap();
+        let envelope = Envelope {
+            origin: Uuid::new_v4(),
+            topic: "response_bundle".to_string(),
+            payload: serde_json::json!({"text": "hi"}),
+        };
+
+        // Past the queue's capacity events are dropped rather than piling up
+        let start = std::time::Instant::now();
+        for _ in 0..PUBLISH_QUEUE + 10 {
+            transport.publish(&envelope).unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    /// Requires a running Redis: `REDIS_URL=redis://127.0.0.1/ cargo test --features redis-tests`
+    #[cfg(feature = "redis-tests")]
+    #[actix_web::test]
+    async fn test_redis_transport_links_instances() {
+        let url = std::env::var("REDIS_URL").unwrap_or("redis://127.0.0.1/".to_string());
+        let topic = format!("test_{}", Uuid::new_v4());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let mut subscriber = RedisTransport::connect(&url).unwrap();
+        subscriber
+            .subscribe(
+                std::slice::from_ref(&topic),
+                Arc::new(move |envelope| {
+                    let _ = tx.send(envelope);
+                }),
+            )
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(200)).await;
+
+        let mut publisher = RedisTransport::connect(&url).unwrap();
+        let envelope = Envelope {
+            origin: Uuid::new_v4(),
+            topic,
+            payload: serde_json::json!({"text": "hi"}),
+        };
+        publisher.publish(&envelope).unwrap();
+
+        let received = actix::clock::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.origin, envelope.origin);
+        assert_eq!(received.payload, envelope.payload);
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Sample rate of the PCM the built-in TTS providers produce, 16-bit mono.
+const PCM_SAMPLE_RATE: u32 = 16_000;
+
+/// An audio encoding a client can play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioCodec {
+    /// Raw 16 kHz, 16-bit little-endian mono samples.
+    Pcm,
+    /// The same samples behind a WAV header.
+    Wav,
+    Opus,
+    Mp3,
+}
+
+impl FromStr for AudioCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pcm" => Ok(AudioCodec::Pcm),
+            "wav" => Ok(AudioCodec::Wav),
+            "opus" => Ok(AudioCodec::Opus),
+            "mp3" => Ok(AudioCodec::Mp3),
+            other => Err(format!("unknown audio codec: {}", other)),
+        }
+    }
+}
+
+/// Parses a list such as `opus,wav,pcm`.
+pub fn parse_codecs(s: &str) -> Result<Vec<AudioCodec>, String> {
+    s.split(',')
+        .filter(|c| !c.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// The codec TTS audio comes out in, and the order codecs are offered to
+/// clients that declare which ones they can play.
+#[derive(Debug, Clone)]
+pub struct CodecConfig {
+    pub source: AudioCodec,
+    pub preference: Vec<AudioCodec>,
+}
+
+impl Default for CodecConfig {
+    fn default() -> Self {
+        Self {
+            source: AudioCodec::Pcm,
+            preference: vec![
+                AudioCodec::Opus,
+                AudioCodec::Mp3,
+                AudioCodec::Wav,
+                AudioCodec::Pcm,
+            ],
+        }
+    }
+}
+
+impl CodecConfig {
+    /// Whether audio can be sent in `codec`: as produced, or wrapped when
+    /// PCM only needs a WAV header. Nothing is re-encoded.
+    pub fn can_deliver(&self, codec: AudioCodec) -> bool {
+        codec == self.source || (self.source == AudioCodec::Pcm && codec == AudioCodec::Wav)
+    }
+
+    /// The preferred codec among those a client `supported` that audio can
+    /// be sent in; None when the client can play none of them.
+    pub fn negotiate(&self, supported: &[AudioCodec]) -> Option<AudioCodec> {
+        self.preference
+            .iter()
+            .copied()
+            .find(|&codec| supported.contains(&codec) && self.can_deliver(codec))
+    }
+
+    /// A chunk of an utterance, converted from the source codec to `codec`.
+    /// The `first` chunk a client gets of a WAV stream carries its header,
+    /// even when the client switched codecs partway through the utterance.
+    pub fn convert(&self, codec: AudioCodec, first: bool, audio: &[u8]) -> Vec<u8> {
+        if codec == AudioCodec::Wav && self.source == AudioCodec::Pcm && first {
+            let mut wav = wav_stream_header();
+            wav.extend_from_slice(audio);
+            return wav;
+        }
+        audio.to_vec()
+    }
+}
+
+/// Header for a WAV stream of unknown length, sizes left at their maximum
+/// as streaming players expect.
+fn wav_stream_header() -> Vec<u8> {
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, mono
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&PCM_SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(PCM_SAMPLE_RATE * 2).to_le_bytes());
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header
+}
@@ -0,0 +1,705 @@
+use crate::actor::{DigitalHumanConfig, ViewerContextConfig};
+use crate::animation::{AnimationScaling, EmotionTransitionConfig};
+use crate::audit::AuditSink;
+use crate::auth::AuthConfig;
+use crate::channels::Channels;
+use crate::cluster::ClusterConfig;
+use crate::codec::{self, CodecConfig};
+use crate::engagement::EngagementConfig;
+use crate::escalation::{self, EscalationConfig};
+use crate::idle::IdleConfig;
+use crate::injection::InjectionConfig;
+use crate::input_queue::InputQueueConfig;
+use crate::intent::IntentPolicy;
+use crate::lifecycle::LifecycleConfig;
+use crate::llm::{BudgetConfig, IntentSampling, LengthPolicy, LlmConfig};
+use crate::load::LoadConfig;
+use crate::mask::MaskStyle;
+use crate::moderation::ModerationConfig;
+use crate::outage::OutageConfig;
+use crate::overlay::DanmakuDelivery;
+use crate::platform::{
+    DanmakuStoreConfig, DedupConfig, DouyinConfig, FaqConfig, FieldMappings, IdempotencyConfig,
+    MergeConfig, RoomQuota, SamplingPolicy, ThrottleConfig,
+};
+use crate::redact::RedactionConfig;
+use crate::refusal::RefusalConfig;
+use crate::repeat::RepeatPolicy;
+use crate::resume::ResumeConfig;
+use crate::summary::SummaryConfig;
+use crate::templates::{ResponseTemplates, SystemPromptTemplate};
+use crate::translate::{self, TranslationConfig};
+use crate::tts::TtsConfig;
+use crate::username::UsernameDisplay;
+use crate::vad::VadConfig;
+use crate::websocket::{MessageLimits, OutboundQueueConfig, SendRetryConfig, SessionLimitConfig};
+use chrono_tz::Tz;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct AppConfig {
+    pub digital_human: DigitalHumanConfig,
+    pub redaction: RedactionConfig,
+    /// Timezone for timestamps in API responses, exports and logs; storage
+    /// stays in UTC.
+    pub display_timezone: Tz,
+    pub llm: LlmConfig,
+    /// Estimated LLM/TTS spend per room and day, and its cap.
+    pub budget: BudgetConfig,
+    /// Sends a throwaway request to the LLM and TTS providers at startup,
+    /// failing it when their credentials are rejected.
+    pub preflight: bool,
+    /// Names viewers in the prompt; off by default.
+    pub username_display: UsernameDisplay,
+    pub intent_policy: IntentPolicy,
+    /// Bigger, longer animations for VIPs, high-level viewers and gifts.
+    pub animation_scaling: AnimationScaling,
+    /// Tween length sent with each change of expression.
+    pub emotion_transitions: EmotionTransitionConfig,
+    pub length_policy: LengthPolicy,
+    /// Temperature and top-p by intent.
+    pub sampling_by_intent: IntentSampling,
+    /// Rewords or varies responses that repeat the session's recent ones.
+    pub repeat_policy: RepeatPolicy,
+    /// Adds the message answered to each response as `replying_to`.
+    pub response_attribution: bool,
+    /// Splits responses into language-tagged spans.
+    pub language_segments: bool,
+    /// Condenses older turns of long conversations; off by default.
+    pub summary: SummaryConfig,
+    /// In-character replacements for LLM refusals.
+    pub refusals: RefusalConfig,
+    /// Canned replies while the LLM is down.
+    pub outage: OutageConfig,
+    /// Idle animations and filler lines during quiet stretches; off by default.
+    pub idle: IdleConfig,
+    /// Questions to quiet rooms; off by default.
+    pub engagement: EngagementConfig,
+    /// Intro, outro and danmaku gating as room streams start and end.
+    pub lifecycle: LifecycleConfig,
+    /// Masks blacklisted words in responses; off when unset.
+    pub profanity_mask: Option<MaskStyle>,
+    /// Shared rate-limit state; in-memory when unset.
+    pub redis_url: Option<String>,
+    /// Where banned users are kept across restarts; in-memory when unset.
+    pub ban_list_path: Option<String>,
+    /// Users never throttled by the rate limit.
+    pub rate_limit_exempt: Vec<String>,
+    pub moderation: ModerationConfig,
+    /// Where moderation decisions are audited; off when unset.
+    pub audit_sink: Option<AuditSink>,
+    /// Webhook alerts for serious moderation hits; off without a URL.
+    pub escalation: EscalationConfig,
+    pub session_limit: SessionLimitConfig,
+    pub message_limits: MessageLimits,
+    pub send_retry: SendRetryConfig,
+    pub outbound_queue: OutboundQueueConfig,
+    /// Lets clients request per-session debug stats with `get_stats`.
+    pub client_stats: bool,
+    /// Takes client text that opens like JSON but fails to parse as a plain
+    /// question instead of answering it with an `invalid_json` error frame.
+    pub lenient_json: bool,
+    /// Output channels a session receives until it sends `set_channels`.
+    pub default_channels: Channels,
+    /// Output channels on for every session at startup; operators can
+    /// switch them at runtime.
+    pub output_channels: Channels,
+    /// TTS output codec and the codecs offered to clients, best first.
+    pub audio_codecs: CodecConfig,
+    /// Whether danmaku responses go to the room's overlay clients.
+    pub danmaku_delivery: DanmakuDelivery,
+    pub resume: ResumeConfig,
+    /// Seconds for the room mood score to decay halfway back to neutral.
+    pub mood_half_life_seconds: Option<u64>,
+    pub throttle: ThrottleConfig,
+    /// Which danmaku get a response, unless a room overrides it.
+    pub sampling: SamplingPolicy,
+    /// Merges a viewer's quick successive danmaku; off by default.
+    pub merge: MergeConfig,
+    /// Collapses near-identical danmaku into one input; off by default.
+    pub dedup: DedupConfig,
+    /// Where Douyin danmaku comes from; the webhook bridge by default.
+    pub douyin: DouyinConfig,
+    /// Danmaku per minute each room may feed in, unless a room overrides it;
+    /// unlimited when unset.
+    pub room_quota: Option<RoomQuota>,
+    /// Seconds a danmaku may wait before it is dropped rather than answered,
+    /// unless a room overrides it; never dropped when unset.
+    pub max_danmaku_age_seconds: Option<u64>,
+    pub faq: FaqConfig,
+    /// Keeps every danmaku received for post-stream analysis; off when unset.
+    pub danmaku_store: Option<DanmakuStoreConfig>,
+    /// Answers viewers' follow-ups with their earlier danmaku in context.
+    pub viewer_context: ViewerContextConfig,
+    /// Where bridges put each danmaku field, for payloads not in the
+    /// built-in shape.
+    pub field_mappings: FieldMappings,
+    /// Suppresses webhook retries carrying an already seen idempotency key.
+    pub webhook_idempotency: IdempotencyConfig,
+    /// Threads for CPU-bound work; defaults to one per CPU.
+    pub worker_pool_size: Option<usize>,
+    pub templates: ResponseTemplates,
+    pub tts: TtsConfig,
+    /// Splits audio input into utterances, dropping silence, before it is
+    /// transcribed.
+    pub vad: VadConfig,
+    pub translation: TranslationConfig,
+    pub auth: AuthConfig,
+    pub injection: InjectionConfig,
+    /// Holds input while the digital human is paused or restarting.
+    pub input_queue: InputQueueConfig,
+    pub load: LoadConfig,
+    pub cluster: ClusterConfig,
+}
+
+impl AppConfig {
+    /// Builds the configuration from the environment. Malformed tuning values
+    /// fall back to their defaults with a warning; settings that would change
+    /// what viewers see, such as the display timezone, fail instead.
+    pub fn from_env() -> eyre::Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(log_pii) = env_parse::<bool>("LOG_PII") {
+            config.redaction.enabled = !log_pii;
+        }
+        if let Some(max_chars) = env_parse::<usize>("LOG_REDACT_TEXT_OVER") {
+            config.redaction.elide_text_over = Some(max_chars);
+        }
+        if let Ok(salt) = env::var("LOG_REDACT_SALT") {
+            if salt.is_empty() {
+                log::warn!("LOG_REDACT_SALT is empty, using a random salt");
+            } else {
+                config.redaction.salt = salt.into_bytes();
+            }
+        }
+        if let Some(max_concurrent) = env_parse("LLM_MAX_CONCURRENT") {
+            config.llm.max_concurrent = max_concurrent;
+        }
+        if let Some(max_queued) = env_parse("LLM_MAX_QUEUED") {
+            config.llm.max_queued = max_queued;
+        }
+        if let Some(stream_tokens) = env_parse("LLM_STREAM_TOKENS") {
+            config.llm.stream_tokens = stream_tokens;
+        }
+        if let Ok(message) = env::var("LLM_STREAM_RECOVERY_MESSAGE") {
+            config.llm.stream_recovery = Some(message).filter(|m| !m.trim().is_empty());
+        }
+        if let Some(failures) = env_parse("LLM_CIRCUIT_FAILURES") {
+            config.outage.failure_threshold = failures;
+        }
+        if let Some(seconds) = env_seconds("LLM_CIRCUIT_OPEN_SECONDS") {
+            config.outage.open_seconds = seconds;
+        }
+        if let Ok(path) = env::var("LLM_OUTAGE_TEMPLATES_FILE") {
+            match ResponseTemplates::load(&path) {
+                Ok(templates) => config.outage.templates = templates,
+                Err(e) => log::warn!("Failed to load outage templates from {}: {}", path, e),
+            }
+        }
+        if let Ok(reply) = env::var("LLM_OUTAGE_REPLY") {
+            config.outage.fallback_reply = Some(reply).filter(|r| !r.trim().is_empty());
+        }
+        if let Some(debug_prompts) = env_parse("LLM_DEBUG_PROMPTS") {
+            config.llm.debug_prompts = debug_prompts;
+        }
+        if let Some(preflight) = env_parse("STARTUP_PREFLIGHT") {
+            config.preflight = preflight;
+        }
+        if let Ok(spec) = env::var("LLM_CONTEXT_TOKENS") {
+            if let Err(e) = config.llm.apply_context_tokens(&spec) {
+                log::warn!("Ignoring invalid LLM_CONTEXT_TOKENS: {}", e);
+            }
+        }
+        if let Ok(spec) = env::var("LLM_REASONING_DELIMITERS") {
+            if let Err(e) = config.llm.apply_reasoning_delimiters(&spec) {
+                log::warn!("Ignoring invalid LLM_REASONING_DELIMITERS: {}", e);
+            }
+        }
+        config.budget.daily_cap = env_parse("BUDGET_DAILY_CAP");
+        if let Ok(spec) = env::var("BUDGET_PRICES") {
+            if let Err(e) = config.budget.apply_prices(&spec) {
+                log::warn!("Ignoring invalid BUDGET_PRICES: {}", e);
+            }
+        }
+        if let Some(price) = env_parse("BUDGET_DEFAULT_PRICE") {
+            config.budget.default_price = price;
+        }
+        if let Some(price) = env_parse("BUDGET_TTS_PRICE") {
+            config.budget.tts_price = price;
+        }
+        if let Some(action) = env_parse("BUDGET_ACTION") {
+            config.budget.action = action;
+        }
+        if let Ok(reply) = env::var("BUDGET_FALLBACK_REPLY") {
+            config.budget.fallback_reply = reply;
+        }
+        if let Ok(spec) = env::var("LLM_PROVIDER_TIMEOUTS") {
+            match spec
+                .split(',')
+                .map(|seconds| seconds.trim().parse())
+                .collect::<Result<Vec<u64>, _>>()
+            {
+                Ok(timeouts) => config.llm.provider_timeout_seconds = timeouts,
+                Err(e) => log::warn!("Ignoring invalid LLM_PROVIDER_TIMEOUTS: {}", e),
+            }
+        }
+        if let Some(max_sessions) = env_parse("WS_MAX_SESSIONS_PER_USER") {
+            config.session_limit.max_sessions_per_user = max_sessions;
+        }
+        if let Some(policy) = env_parse("WS_SESSION_OVERFLOW") {
+            config.session_limit.overflow_policy = policy;
+        }
+        if let Some(policy) = env_parse("WS_RECONNECT_POLICY") {
+            config.session_limit.reconnect_policy = policy;
+        }
+        if let Some(max_bytes) = env_parse("WS_MAX_MESSAGE_BYTES") {
+            config.message_limits.max_message_bytes = max_bytes;
+        }
+        if let Some(max_bytes) = env_parse("WS_MAX_AUDIO_BYTES") {
+            config.message_limits.max_audio_bytes = max_bytes;
+            config.vad.max_audio_bytes = max_bytes;
+        }
+        if let Some(retries) = env_parse("WS_SEND_RETRIES") {
+            config.send_retry.max_retries = retries;
+        }
+        if let Some(backoff) = env_parse("WS_SEND_RETRY_BACKOFF_MS") {
+            config.send_retry.backoff_ms = backoff;
+        }
+        if let Some(timeout) = env_parse("WS_SEND_TIMEOUT_MS") {
+            config.send_retry.timeout_ms = timeout;
+        }
+        if let Some(capacity) = env_parse("WS_OUTBOUND_QUEUE") {
+            config.outbound_queue.capacity = capacity;
+        }
+        if let Ok(policy) = env::var("WS_OUTBOUND_OVERFLOW") {
+            match policy.parse() {
+                Ok(policy) => config.outbound_queue.overflow = policy,
+                Err(e) => log::warn!("Ignoring invalid WS_OUTBOUND_OVERFLOW: {}", e),
+            }
+        }
+        if let Some(window) = env_seconds("WS_RESUME_WINDOW_SECONDS") {
+            config.resume.window_seconds = window;
+        }
+        if let Some(max_frames) = env_parse("WS_RESUME_BUFFER") {
+            config.resume.max_buffered_frames = max_frames;
+        }
+        if let Some(max_sessions) = env_parse("WS_RESUME_MAX_SESSIONS") {
+            config.resume.max_detached_sessions = max_sessions;
+        }
+        if let Some(interval) = env_seconds("WS_RESUME_SWEEP_SECONDS") {
+            config.resume.sweep_interval_seconds = interval;
+        }
+        if let Some(client_stats) = env_parse("WS_CLIENT_STATS") {
+            config.client_stats = client_stats;
+        }
+        if let Some(lenient) = env_parse("WS_LENIENT_JSON") {
+            config.lenient_json = lenient;
+        }
+        if let Some(channels) = env_parse("WS_DEFAULT_CHANNELS") {
+            config.default_channels = channels;
+        }
+        if let Some(channels) = env_parse("OUTPUT_CHANNELS") {
+            config.output_channels = channels;
+        }
+        if let Some(source) = env_parse("TTS_CODEC") {
+            config.audio_codecs.source = source;
+        }
+        if let Ok(spec) = env::var("WS_AUDIO_CODECS") {
+            match codec::parse_codecs(&spec) {
+                Ok(preference) => config.audio_codecs.preference = preference,
+                Err(e) => log::warn!("Ignoring invalid WS_AUDIO_CODECS: {}", e),
+            }
+        }
+        if let Some(delivery) = env_parse("DANMAKU_RESPONSE_DELIVERY") {
+            config.danmaku_delivery = delivery;
+        }
+        config.mood_half_life_seconds = env_parse("MOOD_HALF_LIFE_SECONDS");
+        config.worker_pool_size = env_parse("WORKER_POOL_SIZE");
+        if let Some(enabled) = env_parse("THROTTLE_FEEDBACK") {
+            config.throttle.enabled = enabled;
+        }
+        if let Some(threshold) = env_parse("THROTTLE_DROP_RATE") {
+            config.throttle.drop_rate_threshold = threshold;
+        }
+        if let Some(debounce) = env_seconds("THROTTLE_DEBOUNCE_SECONDS") {
+            config.throttle.debounce_seconds = debounce;
+        }
+        config.cluster.redis_url = env::var("CLUSTER_REDIS_URL").ok().filter(|s| !s.is_empty());
+        if let Ok(topics) = env::var("CLUSTER_TOPICS") {
+            config.cluster.topics = topics
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+        }
+        if let Some(enabled) = env_parse("WS_LOAD_SIGNAL") {
+            config.load.enabled = enabled;
+        }
+        if let Some(depth) = env_parse("WS_LOAD_QUEUE_HIGH") {
+            config.load.global_queue_high = depth;
+        }
+        if let Some(depth) = env_parse("WS_LOAD_SESSION_PENDING_HIGH") {
+            config.load.session_pending_high = depth;
+        }
+        if let Some(debounce) = env_parse("WS_LOAD_DEBOUNCE_SECONDS") {
+            config.load.debounce_seconds = debounce;
+        }
+        config.auth.jwt_secret = env::var("WS_JWT_SECRET").ok().filter(|s| !s.is_empty());
+        if let Some(interval) = env_parse("WS_TOKEN_CHECK_SECONDS") {
+            config.auth.check_interval_seconds = interval;
+        }
+        if let Some(refresh_before) = env_seconds("WS_TOKEN_REFRESH_BEFORE_SECONDS") {
+            config.auth.refresh_before_seconds = refresh_before;
+        }
+        if let Some(policy) = env_parse("WS_TOKEN_EXPIRY_POLICY") {
+            config.auth.expiry_policy = policy;
+        }
+        if let Some(enabled) = env_parse("TTS_ENABLED") {
+            config.tts.enabled = enabled;
+        }
+        if let Some(chunk_bytes) = env_parse("TTS_CHUNK_BYTES") {
+            config.tts.chunk_bytes = chunk_bytes;
+        }
+        if let Some(enabled) = env_parse("VAD_ENABLED") {
+            config.vad.enabled = enabled;
+        }
+        if let Some(threshold) = env_parse("VAD_THRESHOLD_DBFS") {
+            config.vad.threshold_dbfs = threshold;
+        }
+        if let Some(silence) = env_parse("VAD_ENDPOINT_SILENCE_MS") {
+            config.vad.endpoint_silence_ms = silence;
+        }
+        if let Some(min_speech) = env_parse("VAD_MIN_SPEECH_MS") {
+            config.vad.min_speech_ms = min_speech;
+        }
+        if let Some(max_concurrent) = env_parse("TTS_MAX_CONCURRENT") {
+            config.tts.max_concurrent = max_concurrent;
+        }
+        if let Some(max_queued) = env_parse("TTS_MAX_QUEUED") {
+            config.tts.max_queued = max_queued;
+        }
+        if let Some(enabled) = env_parse("TTS_EMOTION_STYLES") {
+            config.tts.styles.enabled = enabled;
+        }
+        if let Ok(spec) = env::var("TTS_VOICE_STYLES") {
+            if let Err(e) = config.tts.styles.apply(&spec) {
+                log::warn!("Ignoring invalid TTS_VOICE_STYLES: {}", e);
+            }
+        }
+        if let Ok(spec) = env::var("TTS_VOICE_FAMILIES") {
+            if let Err(e) = config.tts.families.apply(&spec) {
+                log::warn!("Ignoring invalid TTS_VOICE_FAMILIES: {}", e);
+            }
+        }
+        if let Ok(languages) = env::var("TRANSLATE_LANGUAGES") {
+            config.translation.languages = translate::parse_languages(&languages, ',');
+        }
+        if let Ok(spec) = env::var("TRANSLATE_LANGUAGES_BY_ROOM") {
+            if let Err(e) = config.translation.apply_room_overrides(&spec) {
+                log::warn!("Ignoring invalid TRANSLATE_LANGUAGES_BY_ROOM: {}", e);
+            }
+        }
+        if let Some(sampling) = env_parse("DANMAKU_SAMPLING") {
+            config.sampling = sampling;
+        }
+        config.room_quota = env_parse("ROOM_QUOTA");
+        config.max_danmaku_age_seconds = env_seconds("DANMAKU_MAX_AGE_SECONDS");
+        if let Some(ttl) = env_parse("WEBHOOK_IDEMPOTENCY_TTL_SECONDS") {
+            config.webhook_idempotency.ttl_seconds = ttl;
+        }
+        if let Some(capacity) = env_parse("WEBHOOK_IDEMPOTENCY_CAPACITY") {
+            config.webhook_idempotency.capacity = capacity;
+        }
+        config.merge.window_ms = env_parse("DANMAKU_MERGE_WINDOW_MS");
+        if let Some(max_parts) = env_parse("DANMAKU_MERGE_MAX_PARTS") {
+            config.merge.max_parts = max_parts;
+        }
+        if let Some(source) = env_parse("DOUYIN_SOURCE") {
+            config.douyin.source = source;
+        }
+        if let Some(interval) = env_parse("DOUYIN_POLL_INTERVAL_MS") {
+            config.douyin.poll_interval_ms = interval;
+        }
+        config.dedup.window_seconds = env_parse("DANMAKU_DEDUP_WINDOW_SECONDS");
+        if let Some(similarity) = env_parse("DANMAKU_DEDUP_SIMILARITY") {
+            config.dedup.similarity = similarity;
+        }
+        if let Some(max_entries) = env_parse("FAQ_MAX_ENTRIES") {
+            config.faq.max_entries = max_entries;
+        }
+        if let Some(similarity) = env_parse("FAQ_SIMILARITY") {
+            config.faq.similarity = similarity;
+        }
+        config.faq.persist_path = env::var("FAQ_PERSIST_FILE").ok().filter(|p| !p.is_empty());
+        if let Some(path) = env::var("DANMAKU_STORE_PATH")
+            .ok()
+            .filter(|p| !p.is_empty())
+        {
+            let mut store = DanmakuStoreConfig::new(&path);
+            if let Some(max_bytes) = env_parse("DANMAKU_STORE_MAX_BYTES") {
+                store.max_bytes = max_bytes;
+            }
+            if let Some(max_files) = env_parse("DANMAKU_STORE_MAX_FILES") {
+                store.max_files = max_files;
+            }
+            store.max_age = env_parse("DANMAKU_STORE_ROTATE_SECONDS").map(Duration::from_secs);
+            store.retention = env_parse("DANMAKU_STORE_RETENTION_SECONDS").map(Duration::from_secs);
+            if let Some(gzip) = env_parse("DANMAKU_STORE_GZIP") {
+                store.gzip = gzip;
+            }
+            config.danmaku_store = Some(store);
+        }
+        if let Some(enabled) = env_parse("DANMAKU_CONTEXT_CARRYOVER") {
+            config.viewer_context.enabled = enabled;
+        }
+        if let Some(idle) = env_seconds("DANMAKU_CONTEXT_IDLE_SECONDS") {
+            config.viewer_context.idle_seconds = idle;
+        }
+        if let Ok(spec) = env::var("DANMAKU_FIELDS_DOUYIN") {
+            if let Err(e) = config.field_mappings.douyin.apply(&spec) {
+                log::warn!("Ignoring invalid DANMAKU_FIELDS_DOUYIN: {}", e);
+            }
+        }
+        if let Ok(spec) = env::var("DANMAKU_FIELDS_BILIBILI") {
+            if let Err(e) = config.field_mappings.bilibili.apply(&spec) {
+                log::warn!("Ignoring invalid DANMAKU_FIELDS_BILIBILI: {}", e);
+            }
+        }
+        if let Some(policy) = env_parse("PROMPT_INJECTION_POLICY") {
+            config.injection.policy = policy;
+        }
+        if let Ok(path) = env::var("PROMPT_INJECTION_PATTERNS_FILE") {
+            match InjectionConfig::load_patterns(&path) {
+                Ok(patterns) => config.injection.patterns = patterns,
+                Err(e) => log::warn!(
+                    "Failed to load prompt injection patterns from {}: {}",
+                    path,
+                    e
+                ),
+            }
+        }
+        if let Ok(path) = env::var("RESPONSE_TEMPLATES_FILE") {
+            match ResponseTemplates::load(&path) {
+                Ok(templates) => config.templates = templates,
+                Err(e) => log::warn!("Failed to load response templates from {}: {}", path, e),
+            }
+        }
+        if let Ok(path) = env::var("REFUSAL_TEMPLATES_FILE") {
+            match RefusalConfig::load(&path) {
+                Ok(refusals) => config.refusals = refusals,
+                Err(e) => log::warn!("Failed to load refusal templates from {}: {}", path, e),
+            }
+        }
+        if let Some(enabled) = env_parse("REFUSAL_DEFLECTION") {
+            config.refusals.enabled = enabled;
+        }
+        if let Ok(path) = env::var("SYSTEM_PROMPT_FILE") {
+            match SystemPromptTemplate::load(&path) {
+                Ok(prompt) => {
+                    let unknown = prompt.unknown_variables();
+                    if !unknown.is_empty() {
+                        log::warn!(
+                            "System prompt references unknown variables {:?}; they will render empty",
+                            unknown
+                        );
+                    }
+                    config.digital_human.system_prompt = prompt;
+                }
+                Err(e) => log::warn!("Failed to load system prompt from {}: {}", path, e),
+            }
+        }
+        config.input_queue.backend = env_parse("INPUT_QUEUE");
+        if let Some(max_age) = env_seconds("INPUT_QUEUE_MAX_AGE_SECONDS") {
+            config.input_queue.max_age_seconds = max_age;
+        }
+        if let Some(max_len) = env_parse("INPUT_QUEUE_MAX_LEN") {
+            config.input_queue.max_len = max_len;
+        }
+        if let Some(interval) = env_parse("INPUT_QUEUE_DRAIN_INTERVAL_MS") {
+            config.input_queue.drain_interval_ms = interval;
+        }
+        if let Ok(spec) = env::var("WAKE_WORDS") {
+            config.digital_human.wake_words.apply_spec(&spec);
+        }
+        config.redis_url = env::var("REDIS_URL").ok().filter(|url| !url.is_empty());
+        config.ban_list_path = env::var("BAN_LIST_PATH").ok().filter(|p| !p.is_empty());
+        if let Ok(users) = env::var("RATE_LIMIT_EXEMPT_USERS") {
+            config.rate_limit_exempt = users
+                .split(',')
+                .map(str::trim)
+                .filter(|u| !u.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Ok(users) = env::var("MODERATORS") {
+            config.moderation.moderators = users
+                .split(',')
+                .map(str::trim)
+                .filter(|u| !u.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(channel) = env_parse("MODERATOR_ACK_CHANNEL") {
+            config.moderation.ack_channel = channel;
+        }
+        if let Some(allow) = env_parse("MODERATION_ALLOW_UNAUTHENTICATED") {
+            config.moderation.allow_unauthenticated = allow;
+        }
+        config.audit_sink = env_parse("AUDIT_LOG");
+        config.escalation.url = env::var("ESCALATION_WEBHOOK_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty());
+        if let Some(severity) = env_parse("ESCALATION_MIN_SEVERITY") {
+            config.escalation.min_severity = severity;
+        }
+        if let Ok(spec) = env::var("ESCALATION_RULE_SEVERITIES") {
+            match escalation::parse_severities(&spec) {
+                Ok(severities) => config.escalation.severities = severities,
+                Err(e) => log::warn!("Ignoring invalid ESCALATION_RULE_SEVERITIES: {}", e),
+            }
+        }
+        if let Some(max_per_minute) = env_parse("ESCALATION_MAX_PER_MINUTE") {
+            config.escalation.max_per_minute = max_per_minute;
+        }
+        if let Ok(name) = env::var("DISPLAY_TIMEZONE") {
+            config.display_timezone = name
+                .trim()
+                .parse()
+                .map_err(|_| eyre::eyre!("Invalid DISPLAY_TIMEZONE: {}", name))?;
+        }
+        if let Some(enabled) = env_parse("PROMPT_USERNAMES") {
+            config.username_display.enabled = enabled;
+        }
+        if let Some(max_chars) = env_parse("PROMPT_USERNAME_MAX_CHARS") {
+            config.username_display.max_chars = max_chars;
+        }
+        if let Ok(spec) = env::var("PROMPT_USERNAME_TRANSFORMS") {
+            if let Err(e) = config.username_display.apply_transforms(&spec) {
+                log::warn!("Ignoring invalid PROMPT_USERNAME_TRANSFORMS: {}", e);
+            }
+        }
+        if let Ok(spec) = env::var("INTENT_POLICY") {
+            if let Err(e) = config.intent_policy.apply_overrides(&spec) {
+                log::warn!("Ignoring invalid INTENT_POLICY: {}", e);
+            }
+        }
+        if let Some(curve) = env_parse("ANIMATION_SCALING_CURVE") {
+            config.animation_scaling.curve = curve;
+        }
+        if let Ok(spec) = env::var("ANIMATION_IMPORTANCE_WEIGHTS") {
+            if let Err(e) = config.animation_scaling.apply_weights(&spec) {
+                log::warn!("Ignoring invalid ANIMATION_IMPORTANCE_WEIGHTS: {}", e);
+            }
+        }
+        if let Some(transition_ms) = env_parse("EMOTION_TRANSITION_MS") {
+            config.emotion_transitions.transition_ms = transition_ms;
+        }
+        config.idle.after_seconds = env_parse("IDLE_AFTER_SECONDS");
+        if let Some(interval) = env_parse("IDLE_ANIMATION_INTERVAL_SECONDS") {
+            config.idle.animation_interval_seconds = interval;
+        }
+        if let Ok(animations) = env::var("IDLE_ANIMATIONS") {
+            config.idle.animations = animations
+                .split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        config.idle.filler_interval_seconds = env_parse("IDLE_FILLER_INTERVAL_SECONDS");
+        config.engagement.interval_seconds = env_parse("ENGAGEMENT_INTERVAL_SECONDS");
+        if let Some(below) = env_parse("ENGAGEMENT_BELOW_PER_MINUTE") {
+            config.engagement.below_per_minute = below;
+        }
+        if let Some(require_start) = env_parse("STREAM_REQUIRE_START") {
+            config.lifecycle.require_start = require_start;
+        }
+        if let Ok(animation) = env::var("STREAM_INTRO_ANIMATION") {
+            config.lifecycle.intro_animation = animation;
+        }
+        if let Ok(animation) = env::var("STREAM_OUTRO_ANIMATION") {
+            config.lifecycle.outro_animation = animation;
+        }
+        if let Some(enabled) = env_parse("RESPONSE_ATTRIBUTION") {
+            config.response_attribution = enabled;
+        }
+        if let Some(enabled) = env_parse("RESPONSE_LANGUAGE_SEGMENTS") {
+            config.language_segments = enabled;
+        }
+        if let Some(mode) = env_parse("RESPONSE_REPEAT_POLICY") {
+            config.repeat_policy.mode = mode;
+        }
+        config.summary.max_turns = env_parse("SUMMARY_AFTER_TURNS");
+        if let Some(keep_turns) = env_parse("SUMMARY_KEEP_TURNS") {
+            config.summary.keep_turns = keep_turns;
+        }
+        if let Some(key_points) = env_parse("HANDOFF_KEY_POINTS") {
+            config.summary.handoff_key_points = key_points;
+        }
+        if let Some(similarity) = env_parse("RESPONSE_REPEAT_SIMILARITY") {
+            config.repeat_policy.similarity = similarity;
+        }
+        if let Some(window) = env_parse("RESPONSE_REPEAT_WINDOW") {
+            config.repeat_policy.window = window;
+        }
+        config.length_policy.default.max_tokens = env_parse("RESPONSE_MAX_TOKENS");
+        config.length_policy.default.max_chars = env_parse("RESPONSE_MAX_CHARS");
+        config.profanity_mask = env_parse("RESPONSE_PROFANITY_MASK");
+        if let Ok(spec) = env::var("RESPONSE_MAX_TOKENS_BY_INTENT") {
+            if let Err(e) = config.length_policy.apply_token_overrides(&spec) {
+                log::warn!("Ignoring invalid RESPONSE_MAX_TOKENS_BY_INTENT: {}", e);
+            }
+        }
+        if let Ok(spec) = env::var("RESPONSE_MAX_CHARS_BY_INTENT") {
+            if let Err(e) = config.length_policy.apply_char_overrides(&spec) {
+                log::warn!("Ignoring invalid RESPONSE_MAX_CHARS_BY_INTENT: {}", e);
+            }
+        }
+        config.sampling_by_intent.default.temperature = env_parse("LLM_TEMPERATURE");
+        config.sampling_by_intent.default.top_p = env_parse("LLM_TOP_P");
+        if let Ok(spec) = env::var("LLM_TEMPERATURE_BY_INTENT") {
+            if let Err(e) = config.sampling_by_intent.apply_temperature_overrides(&spec) {
+                log::warn!("Ignoring invalid LLM_TEMPERATURE_BY_INTENT: {}", e);
+            }
+        }
+        if let Ok(spec) = env::var("LLM_TOP_P_BY_INTENT") {
+            if let Err(e) = config.sampling_by_intent.apply_top_p_overrides(&spec) {
+                log::warn!("Ignoring invalid LLM_TOP_P_BY_INTENT: {}", e);
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Longest span a seconds setting may have, so that it cannot overflow the
+/// time arithmetic it is used in.
+const MAX_SETTING_SECONDS: u64 = 365 * 24 * 60 * 60;
+
+/// Reads a span in seconds, cut to [`MAX_SETTING_SECONDS`].
+fn env_seconds(key: &str) -> Option<u64> {
+    let seconds = env_parse::<u64>(key)?;
+    if seconds > MAX_SETTING_SECONDS {
+        log::warn!(
+            "{} is over a year, using {} seconds",
+            key,
+            MAX_SETTING_SECONDS
+        );
+        return Some(MAX_SETTING_SECONDS);
+    }
+    Some(seconds)
+}
+
+/// Reads and parses an environment variable, ignoring it when unset or malformed.
+pub fn env_parse<T: FromStr>(key: &str) -> Option<T> {
+    let value = env::var(key).ok()?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            log::warn!("Ignoring invalid value for {}: {}", key, value);
+            None
+        }
+    }
+}
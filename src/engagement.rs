@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Asked of the LLM for a question to put to a quiet room.
+pub const ENGAGEMENT_PROMPT: &str = "Chat has gone quiet. Ask the audience one short, open question to get them talking, such as what they would like to see next.";
+
+/// When the persona asks a quiet room a question. Off unless
+/// `interval_seconds` is set.
+#[derive(Debug, Clone)]
+pub struct EngagementConfig {
+    /// Minimum seconds between questions in a room.
+    pub interval_seconds: Option<u64>,
+    /// Questions are asked only while a room sends fewer danmaku per minute
+    /// than this.
+    pub below_per_minute: usize,
+}
+
+impl Default for EngagementConfig {
+    fn default() -> Self {
+        Self {
+            interval_seconds: None,
+            below_per_minute: 5,
+        }
+    }
+}
+
+impl EngagementConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.interval_seconds.is_some()
+    }
+}
+
+/// Paces questions to the audience per room.
+#[derive(Debug)]
+pub struct EngagementScheduler {
+    config: EngagementConfig,
+    /// When each room was last asked, or first seen.
+    last_asked: HashMap<String, Instant>,
+}
+
+impl EngagementScheduler {
+    pub fn new(config: EngagementConfig) -> Self {
+        Self {
+            config,
+            last_asked: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &EngagementConfig {
+        &self.config
+    }
+
+    /// Whether to ask `room_id` a question at `now`, given the danmaku it
+    /// sent in the last minute; a question is recorded as asked when it is.
+    /// A room is never asked within an interval of first being seen.
+    pub fn due(&mut self, room_id: &str, per_minute: usize, now: Instant) -> bool {
+        let Some(interval) = self.config.interval_seconds else {
+            return false;
+        };
+        let last_asked = self.last_asked.entry(room_id.to_string()).or_insert(now);
+        if per_minute >= self.config.below_per_minute
+            || now.saturating_duration_since(*last_asked) < Duration::from_secs(interval)
+        {
+            return false;
+        }
+        *last_asked = now;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_question_is_due_only_in_a_quiet_room() {
+        let mut scheduler = EngagementScheduler::new(EngagementConfig {
+            interval_seconds: Some(60),
+            below_per_minute: 5,
+        });
+        let start = Instant::now();
+        assert!(!scheduler.due("quiet", 0, start));
+        assert!(!scheduler.due("busy", 30, start));
+
+        let later = start + Duration::from_secs(61);
+        assert!(scheduler.due("quiet", 2, later));
+        assert!(!scheduler.due("busy", 30, later));
+
+        // Rate-limited to one question per interval
+        assert!(!scheduler.due("quiet", 0, later + Duration::from_secs(30)));
+        assert!(scheduler.due("quiet", 0, later + Duration::from_secs(60)));
+
+        let mut off = EngagementScheduler::new(EngagementConfig::default());
+        assert!(!off.due("quiet", 0, later));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::events::TextInputEvent;
+use crate::validator::ValidationResult;
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Longest a webhook call may take, so hung calls do not pile up.
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How serious a moderation hit is, by the rule that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            other => Err(format!("unknown severity: {}", other)),
+        }
+    }
+}
+
+/// Parses severities by rule id, e.g. `blacklist=high,length_filter=low`.
+pub fn parse_severities(s: &str) -> Result<HashMap<String, Severity>, String> {
+    s.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (rule_id, severity) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected rule=severity, got {}", pair))?;
+            Ok((rule_id.trim().to_string(), severity.parse()?))
+        })
+        .collect()
+}
+
+/// Where and when moderation hits are escalated to operators.
+#[derive(Debug, Clone)]
+pub struct EscalationConfig {
+    /// Receives a JSON POST per escalated hit; nothing is sent when unset.
+    pub url: Option<String>,
+    /// Hits from rules below this are not escalated.
+    pub min_severity: Severity,
+    /// Severity of each rule by id; unlisted rules and bans are low. Blacklist
+    /// and prompt injection hits are high unless configured otherwise.
+    pub severities: HashMap<String, Severity>,
+    /// Alerts sent in any minute; the rest are counted and reported with
+    /// the next one sent.
+    pub max_per_minute: usize,
+}
+
+impl Default for EscalationConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            min_severity: Severity::High,
+            severities: HashMap::from([
+                ("blacklist".to_string(), Severity::High),
+                ("prompt_injection".to_string(), Severity::High),
+            ]),
+            max_per_minute: 6,
+        }
+    }
+}
+
+impl EscalationConfig {
+    pub fn severity(&self, rule_id: &str) -> Severity {
+        self.severities
+            .get(rule_id)
+            .copied()
+            .unwrap_or(Severity::Low)
+    }
+}
+
+/// The body POSTed for an escalated hit.
+#[derive(Debug, Clone, Serialize)]
+pub struct Escalation {
+    pub timestamp: DateTime<Utc>,
+    pub event_id: Uuid,
+    pub rule_id: String,
+    pub severity: Severity,
+    pub outcome: &'static str,
+    /// The warning, rewrite or reply, when there is one.
+    pub detail: Option<String>,
+    pub user_id: Option<String>,
+    pub username: Option<String>,
+    pub session_id: Option<Uuid>,
+    pub room_id: Option<String>,
+    /// The offending message, in full.
+    pub message: String,
+    /// Alerts held back by the rate limit since the last one sent.
+    pub suppressed: u64,
+}
+
+/// POSTs serious moderation hits to an operator webhook, apart from the
+/// response pipeline and at a bounded rate.
+#[derive(Debug)]
+pub struct EscalationWebhook {
+    config: EscalationConfig,
+    url: String,
+    client: reqwest::Client,
+    /// When the alerts of the last minute were sent, oldest first.
+    sent: VecDeque<DateTime<Utc>>,
+    suppressed: u64,
+}
+
+impl EscalationWebhook {
+    /// None when the config has no URL.
+    pub fn new(config: EscalationConfig) -> Option<Self> {
+        let url = config.url.clone()?;
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Failed to set escalation webhook timeout: {}", e);
+                reqwest::Client::new()
+            });
+        // Unlisted rules are low, so only a higher minimum can leave none
+        if config.min_severity > Severity::Low
+            && !config
+                .severities
+                .values()
+                .any(|&severity| severity >= config.min_severity)
+        {
+            warn!(
+                "No rule is {:?} severity or above, so nothing will be escalated",
+                config.min_severity
+            );
+        }
+        Some(Self {
+            config,
+            url,
+            client,
+            sent: VecDeque::new(),
+            suppressed: 0,
+        })
+    }
+
+    /// The alert for a hit by `rule_id`, or None when the hit is not serious
+    /// enough or the rate limit holds it back.
+    pub fn evaluate(
+        &mut self,
+        event: &TextInputEvent,
+        rule_id: &str,
+        result: &ValidationResult,
+        now: DateTime<Utc>,
+    ) -> Option<Escalation> {
+        let severity = self.config.severity(rule_id);
+        if severity < self.config.min_severity {
+            return None;
+        }
+
+        while self
+            .sent
+            .front()
+            .is_some_and(|&sent| now - sent >= Duration::minutes(1))
+        {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= self.config.max_per_minute {
+            self.suppressed += 1;
+            return None;
+        }
+        self.sent.push_back(now);
+
+        Some(Escalation {
+            timestamp: now,
+            event_id: event.metadata.id,
+            rule_id: rule_id.to_string(),
+            severity,
+            outcome: result.outcome(),
+            detail: result.detail().map(str::to_string),
+            user_id: event.metadata.user_id.clone(),
+            username: event.username.clone(),
+            session_id: event.metadata.session_id,
+            room_id: event.viewer.as_ref().map(|viewer| viewer.room_id.clone()),
+            message: event.text.clone(),
+            suppressed: std::mem::take(&mut self.suppressed),
+        })
+    }
+
+    /// Escalates a hit by `rule_id` if it is serious enough, without waiting
+    /// for the webhook.
+    pub fn escalate(&mut self, event: &TextInputEvent, rule_id: &str, result: &ValidationResult) {
+        let Some(alert) = self.evaluate(event, rule_id, result, Utc::now()) else {
+            return;
+        };
+        let client = self.client.clone();
+        let url = self.url.clone();
+        actix::spawn(async move {
+            match client.post(&url).json(&alert).send().await {
+                Ok(response) if response.status().is_success() => {
+                    info!(
+                        "Escalated {} hit on event {}",
+                        alert.rule_id, alert.event_id
+                    )
+                }
+                Ok(response) => warn!(
+                    "Escalation webhook {} failed with status {}",
+                    url,
+                    response.status()
+                ),
+                Err(e) => warn!("Escalation webhook {} failed: {}", url, e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventMetadata, MessagePriority};
+
+    #[test]
+    fn test_alert_storm_is_held_to_the_rate_limit() {
+        // Blacklist hits are high severity by default
+        let mut webhook = EscalationWebhook::new(EscalationConfig {
+            url: Some("http://127.0.0.1:9/alerts".to_string()),
+            max_per_minute: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let event = TextInputEvent {
+            metadata: EventMetadata::default(),
+            text: "这是广告".to_string(),
+            language: None,
+            username: None,
+            room_mood: None,
+            priority: MessagePriority::Normal,
+            intent: None,
+            viewer: None,
+            max_age_seconds: None,
+            operator: false,
+        };
+        let hit = ValidationResult::Warn("包含敏感词: 广告".to_string());
+        let start = Utc::now();
+
+        // Unlisted rules are low severity
+        assert!(webhook
+            .evaluate(&event, "length_filter", &hit, start)
+            .is_none());
+        assert!(webhook.evaluate(&event, "blacklist", &hit, start).is_some());
+        assert!(webhook.evaluate(&event, "blacklist", &hit, start).is_some());
+        for _ in 0..3 {
+            assert!(webhook.evaluate(&event, "blacklist", &hit, start).is_none());
+        }
+
+        // The next minute's first alert reports what was held back
+        let later = start + Duration::seconds(61);
+        let alert = webhook.evaluate(&event, "blacklist", &hit, later).unwrap();
+        assert_eq!(alert.suppressed, 3);
+        assert_eq!(
+            webhook
+                .evaluate(&event, "blacklist", &hit, later)
+                .unwrap()
+                .suppressed,
+            0
+        );
+    }
+}
@@ -1,45 +1,769 @@
-use crate::actor::DigitalHumanActor;
+use crate::actor::{AskAudience, DigitalHumanActor, GetLlmStats};
+use crate::audit::{AuditLog, AuditRecord};
+use crate::ban::{Ban, BanList};
+use crate::cluster::{Envelope, EventTransport, SUPPORTED_TOPICS};
+use crate::escalation::EscalationWebhook;
 use crate::events::*;
-use crate::validator::{TextValidator, ValidationResult};
-use crate::websocket::WebSocketManager;
+use crate::injection::InjectionConfig;
+use crate::input_queue::InputQueue;
+use crate::intent;
+use crate::mask::ResponseMask;
+use crate::moderation::{self, CommandAck, ModerationConfig, ModeratorCommand};
+use crate::platform::DanmakuMessage;
+use crate::rate_limit::RateLimitStore;
+use crate::redact;
+use crate::validator::{RuleTriggerStats, TextValidator, ValidationResult, ValidationRule};
+use crate::websocket::{
+    CloseUserSessions, FrameKind, RouteDanmaku, SendMessage, SendToSession, WebSocketManager,
+};
 use actix::prelude::*;
-use log::info;
-// use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often monitors receive a `stats` frame.
+const MONITOR_STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Decides from an event's metadata whether a subscriber receives it.
+pub type EventFilter = Arc<dyn Fn(&EventMetadata) -> bool + Send + Sync>;
+
+/// One recipient of events of type `E`, stored type-erased by the bus.
+struct Subscriber<E: Event> {
+    recipient: Recipient<E>,
+    filter: Option<EventFilter>,
+}
+
+impl<E: Event> Subscriber<E> {
+    fn accepts(&self, event: &E) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter(event.metadata()))
+    }
+}
 
 #[derive(Debug)]
 pub struct EventBus {
-    // subscribers: HashMap<String, Vec<String>>,
+    /// Embedders listening alongside the built-in actors, keyed by the
+    /// event type; each entry holds `Subscriber<E>`s.
+    subscribers: HashMap<TypeId, Vec<Box<dyn Any + Send>>>,
     digital_human_actor: Option<Addr<DigitalHumanActor>>,
+    /// Input is held back (and queued, if configured) while set.
+    digital_human_paused: bool,
+    /// Validated input waiting for the digital human; dropped when unset.
+    input_queue: Option<InputQueue>,
+    input_flow: InputFlow,
+    /// Users whose rate limit state is being read or written back, with
+    /// their input that arrived meanwhile, checked in order once it is.
+    validating: HashMap<String, VecDeque<TextInputEvent>>,
     websocket_manager: Option<Addr<WebSocketManager>>,
+    /// Operator dashboards receiving every event as a JSON frame.
+    monitors: HashMap<Uuid, Recipient<SendMessage>>,
     text_validator: TextValidator,
+    /// Who may issue `!` commands and where they are acknowledged.
+    moderation: ModerationConfig,
+    /// Records every moderation decision other than allow; off when unset.
+    audit: Option<AuditLog>,
+    /// Alerts operators to serious moderation hits; off when unset.
+    escalation: Option<EscalationWebhook>,
+    /// Masks blacklisted words in responses; responses pass unchanged when unset.
+    profanity_mask: Option<ResponseMask>,
+    cluster: Option<ClusterLink>,
+}
+
+/// Input on its way through the queue store, in arrival order.
+#[derive(Debug, Default)]
+struct InputFlow {
+    /// Input not yet handed to the store or the digital human.
+    pending: VecDeque<TextInputEvent>,
+    /// A store call is in flight.
+    busy: bool,
+    /// The store is drained once the digital human is available.
+    drain_due: bool,
+}
+
+/// Connection to the other instances sharing events with this one.
+#[derive(Debug)]
+struct ClusterLink {
+    origin: Uuid,
+    topics: Vec<String>,
+    transport: Box<dyn EventTransport>,
 }
 
 impl EventBus {
     pub fn new() -> Self {
         Self {
-            // subscribers: HashMap::new(),
+            subscribers: HashMap::new(),
             digital_human_actor: None,
+            digital_human_paused: false,
+            input_queue: None,
+            input_flow: InputFlow::default(),
+            validating: HashMap::new(),
             websocket_manager: None,
+            monitors: HashMap::new(),
             text_validator: TextValidator::new(),
+            moderation: ModerationConfig::default(),
+            audit: None,
+            escalation: None,
+            profanity_mask: None,
+            cluster: None,
+        }
+    }
+
+    /// Shares events of the given types with other instances through
+    /// `transport`; without it events stay in-process.
+    /// Topics other than `SUPPORTED_TOPICS` are left out, since other
+    /// instances could not deliver them.
+    pub fn with_cluster(mut self, transport: Box<dyn EventTransport>, topics: Vec<String>) -> Self {
+        let topics = topics
+            .into_iter()
+            .filter(|topic| {
+                let supported = SUPPORTED_TOPICS.contains(&topic.as_str());
+                if !supported {
+                    warn!(
+                        "Not sharing {} with other instances; only {:?} are supported",
+                        topic, SUPPORTED_TOPICS
+                    );
+                }
+                supported
+            })
+            .collect();
+        self.cluster = Some(ClusterLink {
+            origin: Uuid::new_v4(),
+            topics,
+            transport,
+        });
+        self
+    }
+
+    /// Queues input while the digital human is paused or not running, and
+    /// delivers it once it is back.
+    pub fn with_input_queue(mut self, queue: InputQueue) -> Self {
+        self.input_queue = Some(queue);
+        self
+    }
+
+    pub fn with_rate_limit_store(mut self, store: Box<dyn RateLimitStore>) -> Self {
+        self.text_validator = self.text_validator.with_rate_limit_store(store);
+        self
+    }
+
+    /// Ignores messages from the users on `bans`.
+    pub fn with_ban_list(mut self, bans: BanList) -> Self {
+        self.text_validator = self.text_validator.with_ban_list(bans);
+        self
+    }
+
+    /// Lets the users in `user_ids` past the rate limit.
+    pub fn with_rate_limit_exemptions(mut self, user_ids: Vec<String>) -> Self {
+        self.text_validator = self.text_validator.with_rate_limit_exemptions(user_ids);
+        self
+    }
+
+    /// Runs `!` commands from moderators instead of answering them.
+    pub fn with_moderation(mut self, moderation: ModerationConfig) -> Self {
+        self.moderation = moderation;
+        self
+    }
+
+    pub fn with_injection_guard(mut self, config: &InjectionConfig) -> Self {
+        self.text_validator.set_injection_config(config);
+        self
+    }
+
+    /// Writes which rule ignored, warned, rewrote or deflected whose message.
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// POSTs hits by rules at or above the configured severity to a webhook.
+    pub fn with_escalation_webhook(mut self, escalation: EscalationWebhook) -> Self {
+        self.escalation = Some(escalation);
+        self
+    }
+
+    /// Masks the words of the enabled blacklist rules in responses. Share
+    /// `mask` with the digital human so what it stores and speaks is masked too.
+    pub fn with_profanity_mask(mut self, mask: ResponseMask) -> Self {
+        mask.set_words(self.text_validator.blacklist_words());
+        self.profanity_mask = Some(mask);
+        self
+    }
+
+    /// Applies the profanity mask to outgoing response text.
+    fn mask_response(&self, text: &mut String) {
+        if let Some(mask) = &self.profanity_mask {
+            let count = mask.apply(text);
+            if count > 0 {
+                debug!("Masked {} blacklisted word(s) in response", count);
+            }
+        }
+    }
+
+    /// Keeps the masked words in step with the enabled blacklist rules.
+    fn refresh_mask_words(&self) {
+        if let Some(mask) = &self.profanity_mask {
+            mask.set_words(self.text_validator.blacklist_words());
         }
     }
 
-    pub fn register_digital_human(&mut self, addr: Addr<DigitalHumanActor>) {
+    pub fn register_digital_human(
+        &mut self,
+        addr: Addr<DigitalHumanActor>,
+        ctx: &mut Context<Self>,
+    ) {
         self.digital_human_actor = Some(addr);
         info!("Registered DigitalHumanActor with EventBus");
+        self.drain_input_queue(ctx);
+    }
+
+    /// The digital human if it can take input now.
+    fn available_digital_human(&self) -> Option<&Addr<DigitalHumanActor>> {
+        if self.digital_human_paused {
+            return None;
+        }
+        self.digital_human_actor
+            .as_ref()
+            .filter(|digital_human| digital_human.connected())
+    }
+
+    /// Delivers input queued while the digital human was unavailable, oldest
+    /// first, once it is available. New input waits in `input_flow` until it
+    /// is delivered, so none overtakes it.
+    fn drain_input_queue(&mut self, ctx: &mut Context<Self>) {
+        if self.input_queue.is_some() {
+            self.input_flow.drain_due = true;
+            self.pump_input(ctx);
+        }
+    }
+
+    /// Starts the next store call for queued input, one at a time so input
+    /// keeps its order without holding up the mailbox while the store answers.
+    fn pump_input(&mut self, ctx: &mut Context<Self>) {
+        if self.input_flow.busy {
+            return;
+        }
+        let digital_human = self.available_digital_human().cloned();
+        let Some(queue) = &mut self.input_queue else {
+            return;
+        };
+        match digital_human {
+            Some(_) if self.input_flow.drain_due => {
+                self.input_flow.drain_due = false;
+                self.input_flow.busy = true;
+                let drain = queue.drain(Utc::now());
+                ctx.spawn(drain.into_actor(self).map(|events, act, ctx| {
+                    act.input_flow.busy = false;
+                    act.deliver_drained(events);
+                    act.pump_input(ctx);
+                }));
+            }
+            Some(digital_human) => {
+                for event in self.input_flow.pending.drain(..) {
+                    digital_human.do_send(event);
+                }
+            }
+            None => {
+                let Some(event) = self.input_flow.pending.pop_front() else {
+                    return;
+                };
+                self.input_flow.busy = true;
+                let id = event.metadata.id;
+                ctx.spawn(
+                    queue
+                        .enqueue(&event)
+                        .into_actor(self)
+                        .map(move |queued, act, ctx| {
+                            act.input_flow.busy = false;
+                            if queued {
+                                debug!("Queued input {} until DigitalHumanActor is available", id);
+                            }
+                            act.pump_input(ctx);
+                        }),
+                );
+            }
+        }
+    }
+
+    /// Hands input drained from the store to the digital human, or puts it
+    /// back ahead of newer input if it became unavailable meanwhile.
+    fn deliver_drained(&mut self, events: Vec<TextInputEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        match self.available_digital_human() {
+            Some(digital_human) => {
+                info!(
+                    "Delivering {} queued input(s) to DigitalHumanActor",
+                    events.len()
+                );
+                for event in events {
+                    digital_human.do_send(event);
+                }
+            }
+            None => {
+                for event in events.into_iter().rev() {
+                    self.input_flow.pending.push_front(event);
+                }
+            }
+        }
     }
 
     pub fn register_websocket_manager(&mut self, addr: Addr<WebSocketManager>) {
         self.websocket_manager = Some(addr);
         info!("Registered WebSocketManager with EventBus");
     }
+
+    fn subscribe<E: Event>(&mut self, recipient: Recipient<E>, filter: Option<EventFilter>) {
+        self.subscribers
+            .entry(TypeId::of::<E>())
+            .or_default()
+            .push(Box::new(Subscriber { recipient, filter }));
+    }
+
+    /// Sends `event` to the subscribers of its type whose filter accepts it,
+    /// dropping those that have stopped.
+    fn publish<E: Event>(&mut self, event: &E) {
+        let Some(subscribers) = self.subscribers.get_mut(&TypeId::of::<E>()) else {
+            return;
+        };
+        subscribers.retain(|s| {
+            s.downcast_ref::<Subscriber<E>>()
+                .is_some_and(|s| s.recipient.connected())
+        });
+        for subscriber in subscribers.iter() {
+            if let Some(subscriber) = subscriber.downcast_ref::<Subscriber<E>>() {
+                if subscriber.accepts(event) {
+                    subscriber.recipient.do_send(event.clone());
+                }
+            }
+        }
+    }
+
+    /// 标注意图后转发给DigitalHumanActor；暂停或未运行时放入队列
+    fn forward_text(&mut self, mut event: TextInputEvent, ctx: &mut Context<Self>) {
+        event.intent = Some(intent::classify(&event.text));
+        let Some(queue) = &self.input_queue else {
+            match self.available_digital_human() {
+                Some(digital_human) => digital_human.do_send(event),
+                None => debug!(
+                    "Dropping input {}: DigitalHumanActor unavailable",
+                    event.metadata.id
+                ),
+            }
+            return;
+        };
+        // 排在队列中的消息之后，保证排空时的顺序
+        if self.input_flow.pending.len() >= queue.max_len() {
+            warn!(
+                "Dropping input {}: too much input waiting for the queue",
+                event.metadata.id
+            );
+            return;
+        }
+        self.input_flow.pending.push_back(event);
+        self.pump_input(ctx);
+    }
+
+    /// 校验输入，按结果转发、直接回复或丢弃
+    /// Validates `event`, first reading its user's rate limit state when the
+    /// store is remote. The user's later input waits in `validating` until
+    /// the state is written back, so each message is checked against what
+    /// the one before it wrote; other input and events carry on meanwhile.
+    fn validate_in_order(
+        &mut self,
+        user_id: String,
+        event: TextInputEvent,
+        ctx: &mut Context<Self>,
+    ) {
+        let Some(load) = self.text_validator.load_rate_limit_state(&user_id) else {
+            self.validate_text(event, ctx);
+            return;
+        };
+        self.validating.insert(user_id.clone(), VecDeque::new());
+        ctx.spawn(
+            load.into_actor(self)
+                .then(move |(), act, ctx| {
+                    act.validate_text(event, ctx);
+                    // 只含这次校验的写入：校验和取出写入之间没有其他消息插入
+                    let flush = act.text_validator.flush_rate_limit_state();
+                    async move {
+                        if let Some(flush) = flush {
+                            flush.await;
+                        }
+                    }
+                    .into_actor(act)
+                })
+                .map(move |(), act, ctx| {
+                    let mut waiting = act.validating.remove(&user_id).unwrap_or_default();
+                    while let Some(next) = waiting.pop_front() {
+                        act.validate_in_order(user_id.clone(), next, ctx);
+                        if let Some(queue) = act.validating.get_mut(&user_id) {
+                            queue.extend(waiting);
+                            return;
+                        }
+                    }
+                }),
+        );
+    }
+
+    fn validate_text(&mut self, mut event: TextInputEvent, ctx: &mut Context<Self>) {
+        // 校验弹幕内容
+        let (result, rule_id) = self.text_validator.validate_with_rule(&event);
+        self.monitor_validation(&event, &result);
+        if let (Some(escalation), Some(rule_id)) = (&mut self.escalation, &rule_id) {
+            escalation.escalate(&event, rule_id, &result);
+        }
+        if let (Some(audit), Some(rule_id)) = (&mut self.audit, rule_id) {
+            audit.record(&AuditRecord::new(&event, &rule_id, &result));
+        }
+        match result {
+            ValidationResult::Allow => self.forward_text(event, ctx),
+            ValidationResult::Rewrite(text) => {
+                // 改写（如包裹可疑的提示词注入）后照常转发
+                event.text = text;
+                self.forward_text(event, ctx);
+            }
+            ValidationResult::Ignore => {
+                // 忽略：什么都不做
+                info!("TextInputEvent ignored due to validation rules");
+            }
+            ValidationResult::Warn(warning_msg) => {
+                // 警告：使用LLM生成警告文本
+                self.reply_directly(&event, format!("⚠️ {}", warning_msg));
+            }
+            ValidationResult::Deflect(reply) => {
+                // 回避：不交给LLM，直接回复
+                self.reply_directly(&event, reply);
+            }
+        }
+    }
+
+    /// 绕过LLM，由校验系统直接回复该用户
+    fn reply_directly(&self, event: &TextInputEvent, response: String) {
+        let reply = LLMResponseEvent {
+            metadata: EventMetadata {
+                session_id: event.metadata.session_id,
+                user_id: event.metadata.user_id.clone(),
+                ..Default::default()
+            },
+            response,
+            model: "validation_system".to_string(),
+            tokens_used: None,
+            length_limit: None,
+            sampling: None,
+            language: None,
+            translation_of: None,
+            replying_to: None,
+            segments: None,
+        };
+
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(reply);
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool, ctx: &mut Context<Self>) {
+        self.digital_human_paused = paused;
+        info!(
+            "DigitalHumanActor {}",
+            if paused { "paused" } else { "resumed" }
+        );
+        if !paused {
+            self.drain_input_queue(ctx);
+        }
+    }
+
+    fn ban(&mut self, user_id: String, reason: Option<String>, expires_at: Option<DateTime<Utc>>) {
+        info!(
+            "Banning user {} until {:?}",
+            redact::user(&user_id),
+            expires_at
+        );
+        self.text_validator.ban(Ban {
+            user_id: user_id.clone(),
+            reason,
+            banned_at: Utc::now(),
+            expires_at,
+        });
+        // 封禁后立即断开该用户的WebSocket会话
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(CloseUserSessions {
+                user_id,
+                reason: "banned".to_string(),
+            });
+        }
+    }
+
+    /// The command in a moderator's message, if it is one.
+    fn moderator_command(
+        &self,
+        event: &TextInputEvent,
+    ) -> Option<Result<ModeratorCommand, String>> {
+        let user_id = event.metadata.user_id.as_deref()?;
+        if !self.moderation.is_moderator(user_id) {
+            return None;
+        }
+        let command = moderation::parse_command(&event.text)?;
+        // 声称是主持人的未认证来源不能执行指令，也不交给LLM
+        if !self.moderation.trusts_source(event) {
+            warn!(
+                "Refusing command from unauthenticated source claiming moderator {}",
+                redact::user(user_id)
+            );
+            return Some(Err("commands need an authenticated source".to_string()));
+        }
+        Some(command)
+    }
+
+    /// Runs a moderator command, returning what was done or why not.
+    fn run_command(
+        &mut self,
+        command: ModeratorCommand,
+        ctx: &mut Context<Self>,
+    ) -> Result<String, String> {
+        match command {
+            ModeratorCommand::Pause if self.digital_human_paused => {
+                Err("already paused".to_string())
+            }
+            ModeratorCommand::Resume if !self.digital_human_paused => Err("not paused".to_string()),
+            ModeratorCommand::Pause => {
+                self.set_paused(true, ctx);
+                Ok("paused; input is held until resumed".to_string())
+            }
+            ModeratorCommand::Resume => {
+                self.set_paused(false, ctx);
+                Ok("resumed".to_string())
+            }
+            ModeratorCommand::Mute { user_id, minutes } => {
+                let expires_at = match minutes.map(|minutes| {
+                    let minutes = chrono::TimeDelta::try_minutes(i64::try_from(minutes).ok()?)?;
+                    Utc::now().checked_add_signed(minutes)
+                }) {
+                    Some(None) => {
+                        return Err(format!("too many minutes: {}", minutes.unwrap_or_default()))
+                    }
+                    expires_at => expires_at.flatten(),
+                };
+                self.ban(
+                    user_id.clone(),
+                    Some("muted by moderator".to_string()),
+                    expires_at,
+                );
+                Ok(match minutes {
+                    Some(minutes) => format!("muted {} for {} minutes", user_id, minutes),
+                    None => format!("muted {} until unmuted", user_id),
+                })
+            }
+            ModeratorCommand::Unmute { user_id } => {
+                if self.text_validator.unban(&user_id) {
+                    Ok(format!("unmuted {}", user_id))
+                } else {
+                    Err(format!("{} is not muted", user_id))
+                }
+            }
+        }
+    }
+
+    /// Sends a `command_ack` frame to the moderator's session and/or the
+    /// monitors, as configured.
+    fn acknowledge(&self, event: &TextInputEvent, ack: &CommandAck) {
+        let channel = self.moderation.ack_channel;
+        if let (true, Some(session_id), Some(websocket_manager)) = (
+            channel.to_session(),
+            event.metadata.session_id,
+            &self.websocket_manager,
+        ) {
+            websocket_manager.do_send(SendToSession {
+                session_id,
+                frame: serde_json::json!({"type": "command_ack", "data": ack}),
+            });
+        }
+        if channel.to_monitors() {
+            self.monitor("command_ack", ack);
+        }
+    }
+
+    /// Shares a locally published event with the other instances, if its
+    /// type is one of the cluster topics.
+    fn publish_remote<E: Event + Serialize>(&mut self, event: &E) {
+        let Some(cluster) = &mut self.cluster else {
+            return;
+        };
+        let topic = event.event_type();
+        if !cluster.topics.iter().any(|t| t == topic) {
+            return;
+        }
+
+        let payload = match serde_json::to_value(event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize {} for the cluster: {}", topic, e);
+                return;
+            }
+        };
+        let envelope = Envelope {
+            origin: cluster.origin,
+            topic: topic.to_string(),
+            payload,
+        };
+        if let Err(e) = cluster.transport.publish(&envelope) {
+            warn!("Failed to publish {} to the cluster: {}", topic, e);
+        }
+    }
+
+    /// Sends `{"type": kind, "data": data}` to every monitor.
+    fn monitor<T: Serialize>(&self, kind: &str, data: &T) {
+        if self.monitors.is_empty() {
+            return;
+        }
+        let data = match serde_json::to_value(data) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Failed to serialize {} for monitors: {}", kind, e);
+                return;
+            }
+        };
+        let message = serde_json::json!({
+            "type": kind,
+            "data": data,
+            "schema_version": EVENT_SCHEMA_VERSION,
+        })
+        .to_string();
+        for monitor in self.monitors.values() {
+            monitor.do_send(SendMessage {
+                message: message.clone(),
+                kind: FrameKind::Message,
+            });
+        }
+    }
+
+    fn monitor_event<E: Event + Serialize>(&self, event: &E) {
+        self.monitor(event.event_type(), event);
+    }
+
+    fn monitor_validation(&self, event: &TextInputEvent, result: &ValidationResult) {
+        self.monitor(
+            "validation",
+            &serde_json::json!({
+                "event_id": event.metadata.id,
+                "session_id": event.metadata.session_id,
+                "outcome": result.outcome(),
+                "detail": result.detail(),
+            }),
+        );
+    }
+
+    /// Shared by the stream starting and ending: overlays are told either
+    /// way, and unless the room is silent the persona's intro or outro is
+    /// routed to them.
+    fn stream_changed<E>(&mut self, event: E, room_id: &str, silent: bool)
+    where
+        E: Event + Serialize,
+        DigitalHumanActor: Handler<E>,
+        WebSocketManager: Handler<E>,
+    {
+        info!(
+            "EventBus received {} for room {}",
+            event.event_type(),
+            room_id
+        );
+        self.monitor_event(&event);
+        self.publish(&event);
+
+        if !silent {
+            // 开场和收尾的回复送到直播间的叠加层
+            if let (Some(session_id), Some(websocket_manager)) =
+                (event.metadata().session_id, &self.websocket_manager)
+            {
+                websocket_manager.do_send(RouteDanmaku {
+                    session_id,
+                    room_id: room_id.to_string(),
+                });
+            }
+            if let Some(ref digital_human) = self.digital_human_actor {
+                digital_human.do_send(event.clone());
+            }
+        }
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(event);
+        }
+    }
+
+    fn push_monitor_stats(&self, ctx: &mut Context<Self>) {
+        if self.monitors.is_empty() {
+            return;
+        }
+        let Some(digital_human) = self.digital_human_actor.clone() else {
+            return;
+        };
+        ctx.spawn(
+            async move { digital_human.send(GetLlmStats).await }
+                .into_actor(self)
+                .map(|result, act, _ctx| {
+                    if let Ok(llm) = result {
+                        act.monitor("stats", &serde_json::json!({ "llm": llm }));
+                    }
+                }),
+        );
+    }
+
+    fn deliver_bundle(&mut self, event: ResponseBundle) {
+        self.monitor_event(&event);
+        self.publish(&event);
+
+        // Forward to WebSocketManager to send back to client
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(event);
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Actor for EventBus {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("EventBus started");
+
+        ctx.run_interval(MONITOR_STATS_INTERVAL, |act, ctx| {
+            act.push_monitor_stats(ctx)
+        });
+
+        // Other instances sharing the queue store leave input there while
+        // this one's digital human is available
+        if let Some(queue) = &self.input_queue {
+            ctx.run_interval(queue.drain_interval(), |act, ctx| {
+                act.drain_input_queue(ctx)
+            });
+        }
+
+        if let Some(cluster) = &mut self.cluster {
+            let addr = ctx.address();
+            let origin = cluster.origin;
+            let sink = Arc::new(move |envelope: Envelope| {
+                if envelope.origin != origin {
+                    addr.do_send(RemoteEvent { envelope });
+                }
+            });
+            match cluster.transport.subscribe(&cluster.topics, sink) {
+                Ok(()) => info!(
+                    "EventBus {} sharing {:?} with other instances",
+                    origin, cluster.topics
+                ),
+                Err(e) => warn!("Failed to subscribe to cluster events: {}", e),
+            }
+        }
     }
 }
 
@@ -49,8 +773,11 @@ impl Handler<UserConnectedEvent> for EventBus {
     fn handle(&mut self, event: UserConnectedEvent, _ctx: &mut Context<Self>) -> Self::Result {
         info!(
             "EventBus received UserConnectedEvent: {} for session {}",
-            event.user_id, event.session_id
+            redact::user(&event.user_id),
+            event.session_id
         );
+        self.monitor_event(&event);
+        self.publish(&event);
 
         // Forward to DigitalHumanActor
         if let Some(ref digital_human) = self.digital_human_actor {
@@ -65,8 +792,11 @@ impl Handler<UserDisconnectedEvent> for EventBus {
     fn handle(&mut self, event: UserDisconnectedEvent, _ctx: &mut Context<Self>) -> Self::Result {
         info!(
             "EventBus received UserDisconnectedEvent: {} for session {}",
-            event.user_id, event.session_id
+            redact::user(&event.user_id),
+            event.session_id
         );
+        self.monitor_event(&event);
+        self.publish(&event);
 
         // Forward to DigitalHumanActor
         if let Some(ref digital_human) = self.digital_human_actor {
@@ -78,43 +808,87 @@ impl Handler<UserDisconnectedEvent> for EventBus {
 impl Handler<TextInputEvent> for EventBus {
     type Result = ();
 
-    fn handle(&mut self, event: TextInputEvent, _ctx: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, event: TextInputEvent, ctx: &mut Context<Self>) -> Self::Result {
         info!(
             "EventBus received TextInputEvent: {} for session {:?}",
-            event.text, event.metadata.session_id
+            redact::text(&event.text),
+            event.metadata.session_id
         );
 
-        // 校验弹幕内容
-        match self.text_validator.validate(&event) {
-            ValidationResult::Allow => {
-                // 允许：转发给DigitalHumanActor
-                if let Some(ref digital_human) = self.digital_human_actor {
-                    digital_human.do_send(event);
-                }
-            }
-            ValidationResult::Ignore => {
-                // 忽略：什么都不做
-                info!("TextInputEvent ignored due to validation rules");
-            }
-            ValidationResult::Warn(warning_msg) => {
-                // 警告：使用LLM生成警告文本
-                let warning_response = LLMResponseEvent {
-                    metadata: EventMetadata {
-                        session_id: event.metadata.session_id,
-                        user_id: event.metadata.user_id,
-                        ..Default::default()
-                    },
-                    response: format!("⚠️ {}", warning_msg),
-                    model: "validation_system".to_string(),
-                    tokens_used: None,
-                };
+        self.monitor_event(&event);
 
-                // 发送警告消息
-                if let Some(ref websocket_manager) = self.websocket_manager {
-                    websocket_manager.do_send(warning_response);
-                }
-            }
+        // 主持人的指令直接执行并确认，不经过校验，也不交给LLM
+        if let Some(command) = self.moderator_command(&event) {
+            let user_id = event.metadata.user_id.clone().unwrap_or_default();
+            let result = command.and_then(|command| self.run_command(command, ctx));
+            info!(
+                "Moderator {} command {}: {:?}",
+                redact::user(&user_id),
+                event.text.trim(),
+                result
+            );
+            let (accepted, detail) = match result {
+                Ok(detail) => (true, detail),
+                Err(reason) => (false, reason),
+            };
+            self.acknowledge(
+                &event,
+                &CommandAck {
+                    user_id,
+                    command: event.text.trim().to_string(),
+                    accepted,
+                    detail,
+                },
+            );
+            return;
+        }
+
+        self.publish(&event);
+
+        // 弹幕会话没有WebSocket连接，由WebSocketManager决定是否把回复转给直播间的叠加层
+        if let (Some(viewer), Some(session_id), Some(websocket_manager)) = (
+            &event.viewer,
+            event.metadata.session_id,
+            &self.websocket_manager,
+        ) {
+            websocket_manager.do_send(RouteDanmaku {
+                session_id,
+                room_id: viewer.room_id.clone(),
+            });
+        }
+
+        // 同一用户的消息依次校验，其他用户的消息和事件不必等待
+        let user_id = event
+            .metadata
+            .user_id
+            .clone()
+            .unwrap_or_else(|| "anonymous".to_string());
+        match self.validating.get_mut(&user_id) {
+            Some(waiting) => waiting.push_back(event),
+            None => self.validate_in_order(user_id, event, ctx),
+        }
+    }
+}
+
+impl Handler<AskAudience> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: AskAudience, _ctx: &mut Context<Self>) -> Self::Result {
+        let Some(digital_human) = self.available_digital_human() else {
+            debug!(
+                "Not asking room {}: DigitalHumanActor unavailable",
+                msg.room_id
+            );
+            return;
+        };
+        // 提问的回复和弹幕回复一样送到直播间的叠加层
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(RouteDanmaku {
+                session_id: msg.session_id,
+                room_id: msg.room_id.clone(),
+            });
         }
+        digital_human.do_send(msg);
     }
 }
 
@@ -126,6 +900,7 @@ impl Handler<AudioInputEvent> for EventBus {
             "EventBus received AudioInputEvent: {} for session {:?}",
             event.format, event.metadata.session_id
         );
+        self.publish(&event);
 
         // Forward to DigitalHumanActor
         if let Some(ref digital_human) = self.digital_human_actor {
@@ -140,9 +915,23 @@ impl Handler<TTSResponseEvent> for EventBus {
     fn handle(&mut self, event: TTSResponseEvent, _ctx: &mut Context<Self>) -> Self::Result {
         info!(
             "EventBus received TTSResponseEvent: {} for session {:?}",
-            event.text, event.metadata.session_id
+            redact::text(&event.text),
+            event.metadata.session_id
         );
+        self.publish(&event);
+
+        // Forward to WebSocketManager to send back to client
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(event);
+        }
+    }
+}
+
+impl Handler<TTSChunkEvent> for EventBus {
+    type Result = ();
 
+    fn handle(&mut self, event: TTSChunkEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        self.publish(&event);
         // Forward to WebSocketManager to send back to client
         if let Some(ref websocket_manager) = self.websocket_manager {
             websocket_manager.do_send(event);
@@ -158,6 +947,7 @@ impl Handler<AnimationEvent> for EventBus {
             "EventBus received AnimationEvent: {} for session {:?}",
             event.animation_type, event.metadata.session_id
         );
+        self.publish(&event);
 
         // Forward to WebSocketManager to send back to client
         if let Some(ref websocket_manager) = self.websocket_manager {
@@ -169,11 +959,15 @@ impl Handler<AnimationEvent> for EventBus {
 impl Handler<LLMResponseEvent> for EventBus {
     type Result = ();
 
-    fn handle(&mut self, event: LLMResponseEvent, _ctx: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, mut event: LLMResponseEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        self.mask_response(&mut event.response);
         info!(
             "EventBus received LLMResponseEvent: {} for session {:?}",
-            event.response, event.metadata.session_id
+            redact::text(&event.response),
+            event.metadata.session_id
         );
+        self.monitor_event(&event);
+        self.publish(&event);
 
         // Forward to WebSocketManager to send back to client
         if let Some(ref websocket_manager) = self.websocket_manager {
@@ -182,46 +976,1149 @@ impl Handler<LLMResponseEvent> for EventBus {
     }
 }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct RegisterDigitalHuman {
-    pub addr: Addr<DigitalHumanActor>,
-}
+impl Handler<LLMTokenEvent> for EventBus {
+    type Result = ();
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct RegisterWebSocketManager {
-    pub addr: Addr<WebSocketManager>,
+    fn handle(&mut self, mut event: LLMTokenEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        // Best effort: a word split across deltas is only masked in the final text
+        self.mask_response(&mut event.delta);
+        self.publish(&event);
+        // Forward to WebSocketManager to send back to client
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(event);
+        }
+    }
 }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct RegisterActor {
-    pub actor_type: String,
-    // pub addr: Recipient<PublishEvent<UserConnectedEvent>>,
+impl Handler<LLMPromptEvent> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, event: LLMPromptEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        self.publish(&event);
+        // Forward to WebSocketManager to send back to client
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(event);
+        }
+    }
 }
 
-impl Handler<RegisterDigitalHuman> for EventBus {
+impl Handler<ResponseBundle> for EventBus {
     type Result = ();
 
-    fn handle(&mut self, msg: RegisterDigitalHuman, _ctx: &mut Context<Self>) -> Self::Result {
-        self.register_digital_human(msg.addr);
+    fn handle(&mut self, mut event: ResponseBundle, _ctx: &mut Context<Self>) -> Self::Result {
+        self.mask_response(&mut event.text.response);
+        info!(
+            "EventBus received ResponseBundle {}: {} for session {:?}",
+            event.response_id,
+            redact::text(&event.text.response),
+            event.metadata.session_id
+        );
+
+        self.publish_remote(&event);
+        self.deliver_bundle(event);
     }
 }
 
-impl Handler<RegisterWebSocketManager> for EventBus {
-    type Result = ();
+impl Handler<RetractResponse> for EventBus {
+    type Result = ResponseFuture<Option<Uuid>>;
 
-    fn handle(&mut self, msg: RegisterWebSocketManager, _ctx: &mut Context<Self>) -> Self::Result {
-        self.register_websocket_manager(msg.addr);
+    fn handle(&mut self, event: RetractResponse, _ctx: &mut Context<Self>) -> Self::Result {
+        info!("EventBus received RetractResponse: {}", event.response_id);
+
+        // Forward to DigitalHumanActor to correct history and resolve the session
+        let digital_human = self.digital_human_actor.clone();
+        Box::pin(async move { digital_human?.send(event).await.ok().flatten() })
     }
 }
 
-impl Handler<RegisterActor> for EventBus {
+impl Handler<ResponseRetractedEvent> for EventBus {
     type Result = ();
 
-    fn handle(&mut self, msg: RegisterActor, _ctx: &mut Context<Self>) -> Self::Result {
-        info!("Registered actor type: {}", msg.actor_type);
-        // In a full implementation, you would store the addr for routing events
+    fn handle(&mut self, event: ResponseRetractedEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        info!(
+            "EventBus received ResponseRetractedEvent: {} for session {:?}",
+            event.response_id, event.metadata.session_id
+        );
+
+        self.publish_remote(&event);
+        self.monitor_event(&event);
+        self.publish(&event);
+
+        // Forward to WebSocketManager so the client can remove the bubble
+        if let Some(ref websocket_manager) = self.websocket_manager {
+            websocket_manager.do_send(event);
+        }
+    }
+}
+
+impl Handler<StreamStartedEvent> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, event: StreamStartedEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let room_id = event.room_id.clone();
+        let silent = event.silent;
+        self.stream_changed(event, &room_id, silent);
+    }
+}
+
+impl Handler<StreamEndedEvent> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, event: StreamEndedEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let room_id = event.room_id.clone();
+        let silent = event.silent;
+        self.stream_changed(event, &room_id, silent);
+    }
+}
+
+/// An event published by another instance.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RemoteEvent {
+    pub envelope: Envelope,
+}
+
+impl Handler<RemoteEvent> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: RemoteEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let Envelope {
+            origin,
+            topic,
+            payload,
+        } = msg.envelope;
+        debug!("EventBus received {} from instance {}", topic, origin);
+
+        // Delivered locally only; republishing would bounce between instances
+        match topic.as_str() {
+            "response_bundle" => match load_event::<ResponseBundle>(payload) {
+                Ok(event) => self.deliver_bundle(event),
+                Err(e) => warn!("Dropping malformed remote response_bundle: {}", e),
+            },
+            "response_retracted" => match load_event::<ResponseRetractedEvent>(payload) {
+                Ok(event) => {
+                    self.publish(&event);
+                    if let Some(ref websocket_manager) = self.websocket_manager {
+                        websocket_manager.do_send(event);
+                    }
+                }
+                Err(e) => warn!("Dropping malformed remote response_retracted: {}", e),
+            },
+            other => warn!("Dropping remote event of unsupported topic {}", other),
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RegisterDigitalHuman {
+    pub addr: Addr<DigitalHumanActor>,
+}
+
+/// Stops or resumes handing input to the digital human. Input arriving while
+/// paused waits in the input queue, if one is configured.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetDigitalHumanPaused {
+    pub paused: bool,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RegisterWebSocketManager {
+    pub addr: Addr<WebSocketManager>,
+}
+
+/// Subscribes to every `ResponseBundle` the digital human publishes.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SubscribeResponses {
+    pub recipient: Recipient<ResponseBundle>,
+}
+
+/// Subscribes to events of type `E`, optionally only those whose metadata
+/// matches a filter, e.g. one platform's viewers:
+/// `Subscribe::filtered(recipient, |meta| meta.user_id.as_deref().is_some_and(|u| u.starts_with("douyin_")))`.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Subscribe<E: Event> {
+    pub recipient: Recipient<E>,
+    pub filter: Option<EventFilter>,
+}
+
+impl<E: Event> Subscribe<E> {
+    pub fn all(recipient: Recipient<E>) -> Self {
+        Self {
+            recipient,
+            filter: None,
+        }
+    }
+
+    pub fn filtered(
+        recipient: Recipient<E>,
+        filter: impl Fn(&EventMetadata) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            recipient,
+            filter: Some(Arc::new(filter)),
+        }
+    }
+}
+
+/// Adds an operator dashboard that receives every event as a JSON frame.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SubscribeMonitor {
+    pub monitor_id: Uuid,
+    pub recipient: Recipient<SendMessage>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UnsubscribeMonitor {
+    pub monitor_id: Uuid,
+}
+
+/// A danmaku as received from its platform, before sampling and validation.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct MonitorDanmaku {
+    pub danmaku: DanmakuMessage,
+}
+
+/// Remaining rate-limit budget for a user in the current window.
+#[derive(Message)]
+#[rtype(result = "Option<u32>")]
+pub struct GetRateLimitBudget {
+    pub user_id: String,
+}
+
+/// The actors the bus forwards to, for checking a deployment is wired up.
+#[derive(Message)]
+#[rtype(result = "BusWiring")]
+pub struct GetWiring;
+
+#[derive(Debug, Clone)]
+pub struct BusWiring {
+    pub digital_human: Option<Addr<DigitalHumanActor>>,
+    pub websocket_manager: bool,
+}
+
+/// Validates an event without recording it against rate limits or
+/// forwarding it anywhere.
+#[derive(Message)]
+#[rtype(result = "ValidationResult")]
+pub struct DryRunValidation {
+    pub event: TextInputEvent,
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<ValidationRule>")]
+pub struct ListValidationRules;
+
+/// How often each validation rule fired, by outcome.
+#[derive(Message)]
+#[rtype(result = "RuleTriggerStats")]
+pub struct GetRuleTriggerStats;
+
+/// Zeroes the rule trigger counts.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ResetRuleTriggerStats;
+
+/// Flips a validation rule on or off; resolves to None for unknown rule ids.
+#[derive(Message)]
+#[rtype(result = "Option<ValidationRule>")]
+pub struct SetValidationRuleEnabled {
+    pub rule_id: String,
+    pub enabled: bool,
+}
+
+/// Sets or, when neither `min_level` nor `vip_only` is given, clears a
+/// room's temporary gate. Resolves to the active gate rule.
+#[derive(Message)]
+#[rtype(result = "Option<ValidationRule>")]
+pub struct SetRoomGate {
+    pub room_id: String,
+    pub min_level: Option<u32>,
+    pub vip_only: bool,
+    /// Lifts the gate automatically at this time.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Bans a user, replacing any earlier ban, and closes their WebSocket sessions.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BanUser {
+    pub user_id: String,
+    pub reason: Option<String>,
+    /// Permanent when unset.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Lifts a ban. Resolves to false if the user was not banned.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct UnbanUser {
+    pub user_id: String,
+}
+
+/// Lets a user past the rate limit; bans still apply. Resolves to false if
+/// the user was already exempt.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ExemptFromRateLimit {
+    pub user_id: String,
+}
+
+/// Resolves to false if the user was not exempt.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct RemoveRateLimitExemption {
+    pub user_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<String>")]
+pub struct ListRateLimitExemptions;
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RegisterActor {
+    pub actor_type: String,
+    // pub addr: Recipient<PublishEvent<UserConnectedEvent>>,
+}
+
+impl Handler<RegisterDigitalHuman> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterDigitalHuman, ctx: &mut Context<Self>) -> Self::Result {
+        self.register_digital_human(msg.addr, ctx);
+    }
+}
+
+impl Handler<SetDigitalHumanPaused> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetDigitalHumanPaused, ctx: &mut Context<Self>) -> Self::Result {
+        self.set_paused(msg.paused, ctx);
+    }
+}
+
+impl Handler<RegisterWebSocketManager> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterWebSocketManager, _ctx: &mut Context<Self>) -> Self::Result {
+        self.register_websocket_manager(msg.addr);
+    }
+}
+
+impl Handler<SubscribeResponses> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeResponses, _ctx: &mut Context<Self>) -> Self::Result {
+        self.subscribe(msg.recipient, None);
+        info!("Added response subscriber to EventBus");
+    }
+}
+
+impl<E: Event> Handler<Subscribe<E>> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: Subscribe<E>, _ctx: &mut Context<Self>) -> Self::Result {
+        debug!(
+            "Added {} subscriber to EventBus (filtered: {})",
+            std::any::type_name::<E>(),
+            msg.filter.is_some()
+        );
+        self.subscribe(msg.recipient, msg.filter);
+    }
+}
+
+impl Handler<SubscribeMonitor> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeMonitor, _ctx: &mut Context<Self>) -> Self::Result {
+        self.monitors.insert(msg.monitor_id, msg.recipient);
+        info!("Added monitor {} to EventBus", msg.monitor_id);
+    }
+}
+
+impl Handler<UnsubscribeMonitor> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: UnsubscribeMonitor, _ctx: &mut Context<Self>) -> Self::Result {
+        if self.monitors.remove(&msg.monitor_id).is_some() {
+            info!("Removed monitor {} from EventBus", msg.monitor_id);
+        }
+    }
+}
+
+impl Handler<MonitorDanmaku> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: MonitorDanmaku, _ctx: &mut Context<Self>) -> Self::Result {
+        self.monitor("danmaku", &msg.danmaku);
+    }
+}
+
+impl Handler<GetWiring> for EventBus {
+    type Result = MessageResult<GetWiring>;
+
+    fn handle(&mut self, _msg: GetWiring, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(BusWiring {
+            digital_human: self.digital_human_actor.clone(),
+            websocket_manager: self.websocket_manager.is_some(),
+        })
+    }
+}
+
+impl Handler<DryRunValidation> for EventBus {
+    type Result = MessageResult<DryRunValidation>;
+
+    fn handle(&mut self, msg: DryRunValidation, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.text_validator.dry_run(&msg.event))
+    }
+}
+
+impl Handler<GetRateLimitBudget> for EventBus {
+    type Result = ResponseActFuture<Self, Option<u32>>;
+
+    fn handle(&mut self, msg: GetRateLimitBudget, _ctx: &mut Context<Self>) -> Self::Result {
+        let load = self.text_validator.load_rate_limit_state(&msg.user_id);
+        Box::pin(
+            async move {
+                if let Some(load) = load {
+                    load.await;
+                }
+            }
+            .into_actor(self)
+            .map(move |(), act, _ctx| act.text_validator.remaining_budget(&msg.user_id)),
+        )
+    }
+}
+
+impl Handler<ListValidationRules> for EventBus {
+    type Result = Vec<ValidationRule>;
+
+    fn handle(&mut self, _msg: ListValidationRules, _ctx: &mut Context<Self>) -> Self::Result {
+        self.text_validator.rules().to_vec()
+    }
+}
+
+impl Handler<GetRuleTriggerStats> for EventBus {
+    type Result = MessageResult<GetRuleTriggerStats>;
+
+    fn handle(&mut self, _msg: GetRuleTriggerStats, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.text_validator.trigger_stats())
+    }
+}
+
+impl Handler<ResetRuleTriggerStats> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ResetRuleTriggerStats, _ctx: &mut Context<Self>) -> Self::Result {
+        info!("Resetting validation rule trigger counts");
+        self.text_validator.reset_trigger_stats();
+    }
+}
+
+impl Handler<SetValidationRuleEnabled> for EventBus {
+    type Result = Option<ValidationRule>;
+
+    fn handle(&mut self, msg: SetValidationRuleEnabled, _ctx: &mut Context<Self>) -> Self::Result {
+        let rule = self
+            .text_validator
+            .set_rule_enabled(&msg.rule_id, msg.enabled);
+        self.refresh_mask_words();
+        rule
+    }
+}
+
+impl Handler<SetRoomGate> for EventBus {
+    type Result = Option<ValidationRule>;
+
+    fn handle(&mut self, msg: SetRoomGate, _ctx: &mut Context<Self>) -> Self::Result {
+        self.text_validator
+            .set_room_gate(&msg.room_id, msg.min_level, msg.vip_only, msg.expires_at)
+    }
+}
+
+impl Handler<BanUser> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: BanUser, _ctx: &mut Context<Self>) -> Self::Result {
+        self.ban(msg.user_id, msg.reason, msg.expires_at);
+    }
+}
+
+impl Handler<UnbanUser> for EventBus {
+    type Result = bool;
+
+    fn handle(&mut self, msg: UnbanUser, _ctx: &mut Context<Self>) -> Self::Result {
+        self.text_validator.unban(&msg.user_id)
+    }
+}
+
+impl Handler<ExemptFromRateLimit> for EventBus {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ExemptFromRateLimit, _ctx: &mut Context<Self>) -> Self::Result {
+        info!(
+            "Exempting user {} from the rate limit",
+            redact::user(&msg.user_id)
+        );
+        self.text_validator.exempt_from_rate_limit(&msg.user_id)
+    }
+}
+
+impl Handler<RemoveRateLimitExemption> for EventBus {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RemoveRateLimitExemption, _ctx: &mut Context<Self>) -> Self::Result {
+        self.text_validator
+            .remove_rate_limit_exemption(&msg.user_id)
+    }
+}
+
+impl Handler<ListRateLimitExemptions> for EventBus {
+    type Result = MessageResult<ListRateLimitExemptions>;
+
+    fn handle(&mut self, _msg: ListRateLimitExemptions, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.text_validator.rate_limit_exemptions())
+    }
+}
+
+impl Handler<RegisterActor> for EventBus {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterActor, _ctx: &mut Context<Self>) -> Self::Result {
+        info!("Registered actor type: {}", msg.actor_type);
+        // In a full implementation, you would store the addr for routing events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escalation::{parse_severities, EscalationConfig};
+    use crate::input_queue::{InMemoryInputQueue, InputQueueConfig};
+    use crate::llm::{LlmError, LlmProvider, LlmRequest, LlmResponse};
+    use crate::moderation::AckChannel;
+    use crate::platform::{LiveStreamManager, Platform, ProcessDanmaku};
+    use crate::testing::{received_frames, Collect};
+
+    /// Records the latest user message of every completion request, in order.
+    struct RecordingProvider {
+        prompts: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl LlmProvider for RecordingProvider {
+        fn model(&self) -> &str {
+            "recording"
+        }
+
+        fn complete(
+            &self,
+            request: LlmRequest,
+        ) -> futures_util::future::BoxFuture<'static, Result<LlmResponse, LlmError>> {
+            let prompt = request.messages.last().unwrap().content.clone();
+            self.prompts.lock().unwrap().push(prompt);
+            Box::pin(async {
+                Ok(LlmResponse {
+                    content: "ok".to_string(),
+                    model: "recording".to_string(),
+                    tokens_used: None,
+                    refused: false,
+                })
+            })
+        }
+    }
+
+    fn text_input(user_id: &str, text: &str) -> TextInputEvent {
+        TextInputEvent {
+            metadata: EventMetadata {
+                user_id: Some(user_id.to_string()),
+                ..Default::default()
+            },
+            text: text.to_string(),
+            language: None,
+            username: None,
+            room_mood: None,
+            priority: MessagePriority::Normal,
+            intent: None,
+            viewer: None,
+            max_age_seconds: None,
+            operator: false,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_input_queued_while_paused_is_delivered_on_resume() {
+        let queue = InputQueue::new(
+            Box::new(InMemoryInputQueue::new()),
+            &InputQueueConfig::default(),
+        );
+        let bus = EventBus::new().with_input_queue(queue).start();
+        let prompts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let digital_human =
+            DigitalHumanActor::new("Maya".to_string(), "cheerful".to_string(), bus.clone())
+                .with_llm_provider(Arc::new(RecordingProvider {
+                    prompts: prompts.clone(),
+                }))
+                .start();
+        bus.send(RegisterDigitalHuman {
+            addr: digital_human,
+        })
+        .await
+        .unwrap();
+
+        bus.send(SetDigitalHumanPaused { paused: true })
+            .await
+            .unwrap();
+        let mut stale = text_input("viewer0", "过时的问题？");
+        stale.metadata.timestamp = chrono::Utc::now() - chrono::Duration::minutes(10);
+        bus.send(stale).await.unwrap();
+        for (user, text) in [("viewer1", "第一个问题？"), ("viewer2", "第二个问题？")] {
+            bus.send(text_input(user, text)).await.unwrap();
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+        assert!(prompts.lock().unwrap().is_empty());
+
+        bus.send(SetDigitalHumanPaused { paused: false })
+            .await
+            .unwrap();
+        bus.send(text_input("viewer3", "恢复后的问题？"))
+            .await
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        // In arrival order, ahead of new input, without the stale one
+        assert_eq!(
+            *prompts.lock().unwrap(),
+            vec!["第一个问题？", "第二个问题？", "恢复后的问题？"]
+        );
+    }
+
+    /// A queue store that never answers, like a blackholed Redis.
+    #[derive(Debug)]
+    struct HangingInputQueue;
+
+    impl crate::input_queue::InputQueueStore for HangingInputQueue {
+        fn push(
+            &mut self,
+            _event: &TextInputEvent,
+            _max_len: usize,
+        ) -> futures_util::future::BoxFuture<'static, Result<(), String>> {
+            Box::pin(futures_util::future::pending())
+        }
+
+        fn drain(
+            &mut self,
+        ) -> futures_util::future::BoxFuture<'static, Result<Vec<TextInputEvent>, String>> {
+            Box::pin(futures_util::future::pending())
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_hanging_input_queue_does_not_stall_the_bus() {
+        let bus = EventBus::new()
+            .with_input_queue(InputQueue::new(
+                Box::new(HangingInputQueue),
+                &InputQueueConfig::default(),
+            ))
+            .start();
+        bus.send(SetDigitalHumanPaused { paused: true })
+            .await
+            .unwrap();
+        bus.send(text_input("viewer1", "排队中的问题？"))
+            .await
+            .unwrap();
+
+        // The enqueue is still pending, but the mailbox keeps moving
+        let resumed = actix::clock::timeout(
+            Duration::from_millis(500),
+            bus.send(SetDigitalHumanPaused { paused: false }),
+        )
+        .await;
+        assert!(resumed.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_input_queued_by_another_instance_is_drained() {
+        let store = InMemoryInputQueue::new();
+        let config = InputQueueConfig {
+            drain_interval_ms: 20,
+            ..Default::default()
+        };
+        // The ingest instance has no digital human, so its input is queued
+        let ingest_bus = EventBus::new()
+            .with_input_queue(InputQueue::new(Box::new(store.clone()), &config))
+            .start();
+        let serving_bus = EventBus::new()
+            .with_input_queue(InputQueue::new(Box::new(store), &config))
+            .start();
+        let prompts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let digital_human = DigitalHumanActor::new(
+            "Maya".to_string(),
+            "cheerful".to_string(),
+            serving_bus.clone(),
+        )
+        .with_llm_provider(Arc::new(RecordingProvider {
+            prompts: prompts.clone(),
+        }))
+        .start();
+        serving_bus
+            .send(RegisterDigitalHuman {
+                addr: digital_human,
+            })
+            .await
+            .unwrap();
+
+        ingest_bus
+            .send(text_input("viewer1", "另一台实例收到的问题？"))
+            .await
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(*prompts.lock().unwrap(), vec!["另一台实例收到的问题？"]);
+    }
+
+    #[actix_web::test]
+    async fn test_subscribers_only_receive_matching_platform() {
+        let bus = EventBus::new().start();
+        let douyin = Collect::<TextInputEvent>::default().start();
+        let bilibili = Collect::<TextInputEvent>::default().start();
+        for (inbox, prefix) in [(&douyin, "douyin_"), (&bilibili, "bilibili_")] {
+            bus.send(Subscribe::<TextInputEvent>::filtered(
+                inbox.clone().recipient(),
+                move |meta| {
+                    meta.user_id
+                        .as_deref()
+                        .is_some_and(|user| user.starts_with(prefix))
+                },
+            ))
+            .await
+            .unwrap();
+        }
+
+        for (user, text) in [
+            ("douyin_1", "抖音来的"),
+            ("bilibili_2", "B站来的"),
+            ("kuaishou_3", "快手来的"),
+        ] {
+            bus.send(TextInputEvent {
+                metadata: EventMetadata {
+                    user_id: Some(user.to_string()),
+                    ..Default::default()
+                },
+                text: text.to_string(),
+                language: None,
+                username: None,
+                room_mood: None,
+                priority: MessagePriority::Normal,
+                intent: None,
+                viewer: None,
+                max_age_seconds: None,
+                operator: false,
+            })
+            .await
+            .unwrap();
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let texts = |inputs: Vec<TextInputEvent>| -> Vec<String> {
+            inputs.into_iter().map(|event| event.text).collect()
+        };
+        assert_eq!(texts(Collect::received(&douyin).await), vec!["抖音来的"]);
+        assert_eq!(texts(Collect::received(&bilibili).await), vec!["B站来的"]);
+    }
+
+    #[actix_web::test]
+    async fn test_monitor_receives_danmaku_from_platform() {
+        let bus = EventBus::new().start();
+        let monitor = Collect::<SendMessage>::default().start();
+        bus.send(SubscribeMonitor {
+            monitor_id: Uuid::new_v4(),
+            recipient: monitor.clone().recipient(),
+        })
+        .await
+        .unwrap();
+
+        let live_manager = LiveStreamManager::new(bus.clone()).start();
+        live_manager
+            .send(ProcessDanmaku {
+                danmaku: DanmakuMessage {
+                    platform: Platform::Douyin,
+                    room_id: "room1".to_string(),
+                    user_id: "viewer1".to_string(),
+                    username: "小明".to_string(),
+                    message: "主播好厉害".to_string(),
+                    timestamp: chrono::Utc::now(),
+                    user_level: None,
+                    is_vip: false,
+                    gift_value: None,
+                    trusted: false,
+                },
+            })
+            .await
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let frames = received_frames(&monitor).await;
+        let types: Vec<&str> = frames.iter().filter_map(|f| f["type"].as_str()).collect();
+        assert_eq!(types[0], "danmaku");
+        assert_eq!(frames[0]["data"]["message"], "主播好厉害");
+        assert_eq!(frames[0]["data"]["room_id"], "room1");
+        // The sampled danmaku then passes through the bus and validation
+        assert!(types.contains(&"text_input"));
+        assert!(types.contains(&"validation"));
+    }
+
+    /// Audit sink the test can read back.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    type RemoteState = HashMap<String, (Option<DateTime<Utc>>, u32)>;
+
+    /// Keeps state "remotely", readable only after `load` and written back
+    /// only by `flush`, each after `delay` like a Redis round trip.
+    #[derive(Debug)]
+    struct RemoteStore {
+        remote: Arc<std::sync::Mutex<RemoteState>>,
+        local: Arc<std::sync::Mutex<RemoteState>>,
+        delay: Duration,
+    }
+
+    impl RemoteStore {
+        fn new(delay: Duration) -> Self {
+            Self {
+                remote: Arc::default(),
+                local: Arc::default(),
+                delay,
+            }
+        }
+
+        fn with_local<T>(
+            &self,
+            user_id: &str,
+            f: impl FnOnce(&mut (Option<DateTime<Utc>>, u32)) -> T,
+        ) -> Result<T, String> {
+            let mut local = self.local.lock().unwrap();
+            local
+                .get_mut(user_id)
+                .map(f)
+                .ok_or_else(|| "not loaded".to_string())
+        }
+    }
+
+    impl RateLimitStore for RemoteStore {
+        fn load(&mut self, user_id: &str) -> Option<futures_util::future::BoxFuture<'static, ()>> {
+            let (remote, local) = (self.remote.clone(), self.local.clone());
+            let user_id = user_id.to_string();
+            let delay = self.delay;
+            Some(Box::pin(async move {
+                actix::clock::sleep(delay).await;
+                let state = remote.lock().unwrap().get(&user_id).copied();
+                local
+                    .lock()
+                    .unwrap()
+                    .insert(user_id, state.unwrap_or_default());
+            }))
+        }
+
+        fn flush(&mut self) -> Option<futures_util::future::BoxFuture<'static, ()>> {
+            // Other users' state may still be waiting for its check
+            let written = self.local.lock().unwrap().clone();
+            let remote = self.remote.clone();
+            let delay = self.delay;
+            Some(Box::pin(async move {
+                actix::clock::sleep(delay).await;
+                remote.lock().unwrap().extend(written);
+            }))
+        }
+
+        fn last_seen(&mut self, user_id: &str) -> Result<Option<DateTime<Utc>>, String> {
+            self.with_local(user_id, |(last_seen, _)| *last_seen)
+        }
+
+        fn increment(
+            &mut self,
+            user_id: &str,
+            _now: DateTime<Utc>,
+            _window: chrono::Duration,
+        ) -> Result<u32, String> {
+            self.with_local(user_id, |(_, count)| {
+                *count += 1;
+                *count
+            })
+        }
+
+        fn count(
+            &mut self,
+            user_id: &str,
+            _now: DateTime<Utc>,
+            _window: chrono::Duration,
+        ) -> Result<u32, String> {
+            self.with_local(user_id, |(_, count)| *count)
+        }
+
+        fn touch(&mut self, user_id: &str, now: DateTime<Utc>) {
+            let _ = self.with_local(user_id, |(last_seen, _)| *last_seen = Some(now));
+        }
+
+        fn reset(&mut self, user_id: &str) {
+            let _ = self.with_local(user_id, |state| *state = (None, 0));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_remote_rate_limit_state_is_awaited_around_each_check() {
+        let store = RemoteStore::new(Duration::from_millis(10));
+        let remote = store.remote.clone();
+        let bus = EventBus::new()
+            .with_rate_limit_store(Box::new(store))
+            .start();
+        let prompts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let digital_human =
+            DigitalHumanActor::new("Maya".to_string(), "cheerful".to_string(), bus.clone())
+                .with_llm_provider(Arc::new(RecordingProvider {
+                    prompts: prompts.clone(),
+                }))
+                .start();
+        bus.send(RegisterDigitalHuman {
+            addr: digital_human,
+        })
+        .await
+        .unwrap();
+
+        // The second message is checked against what the first one wrote,
+        // so it falls in the cooldown
+        bus.do_send(text_input("viewer1", "第一个问题？"));
+        bus.do_send(text_input("viewer1", "第二个问题？"));
+        bus.do_send(text_input("viewer2", "另一个问题？"));
+        actix::clock::sleep(Duration::from_millis(150)).await;
+
+        assert_eq!(prompts.lock().unwrap().len(), 2);
+        let remote = remote.lock().unwrap();
+        assert_eq!(remote["viewer1"].1, 1);
+        assert!(remote["viewer1"].0.is_some());
+        assert_eq!(remote["viewer2"].1, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_slow_rate_limit_store_holds_up_only_its_user() {
+        let bus = EventBus::new()
+            .with_rate_limit_store(Box::new(RemoteStore::new(Duration::from_millis(300))))
+            .start();
+        let prompts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let digital_human =
+            DigitalHumanActor::new("Maya".to_string(), "cheerful".to_string(), bus.clone())
+                .with_llm_provider(Arc::new(RecordingProvider {
+                    prompts: prompts.clone(),
+                }))
+                .start();
+        bus.send(RegisterDigitalHuman {
+            addr: digital_human,
+        })
+        .await
+        .unwrap();
+
+        bus.do_send(text_input("viewer1", "第一个问题？"));
+        bus.do_send(text_input("viewer1", "第二个问题？"));
+        bus.do_send(text_input("viewer2", "另一个问题？"));
+        // Other requests are answered while the state is read
+        let started = std::time::Instant::now();
+        bus.send(ListValidationRules).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        // viewer2 is checked alongside viewer1's first message, not after
+        // viewer1's second one
+        actix::clock::sleep(Duration::from_millis(450)).await;
+        assert_eq!(prompts.lock().unwrap().len(), 2);
+        actix::clock::sleep(Duration::from_millis(900)).await;
+        // viewer1's second message was checked against what the first wrote
+        assert_eq!(prompts.lock().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_blacklist_hit_is_audited() {
+        let buffer = SharedBuffer::default();
+        let bus = EventBus::new()
+            .with_audit_log(AuditLog::to_writer(Box::new(buffer.clone())))
+            .start();
+
+        bus.send(text_input("viewer1", "主播你好")).await.unwrap();
+        let flagged = text_input("viewer2", "这是广告");
+        bus.send(flagged.clone()).await.unwrap();
+
+        // Only the decision that was not an allow is recorded
+        for _ in 0..50 {
+            if !buffer.0.lock().unwrap().is_empty() {
+                break;
+            }
+            actix::clock::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let records: Vec<serde_json::Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record["rule_id"], "blacklist");
+        assert_eq!(record["outcome"], "warn");
+        assert_eq!(record["user_id"], "viewer2");
+        assert_eq!(record["event_id"], serde_json::json!(flagged.metadata.id));
+        assert_eq!(
+            record["message_sha256"].as_str().unwrap(),
+            openssl::sha::sha256("这是广告".as_bytes())
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+        assert!(record["timestamp"].is_string());
+        assert!(!log.contains("这是广告"));
+    }
+
+    #[actix_web::test]
+    async fn test_only_high_severity_hits_are_escalated() {
+        let alerts = Arc::new(std::sync::Mutex::new(Vec::<serde_json::Value>::new()));
+        let received = alerts.clone();
+        let server = actix_web::HttpServer::new(move || {
+            let received = received.clone();
+            actix_web::App::new().route(
+                "/alerts",
+                actix_web::web::post().to(move |body: actix_web::web::Json<serde_json::Value>| {
+                    received.lock().unwrap().push(body.into_inner());
+                    async { actix_web::HttpResponse::Ok().finish() }
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}/alerts", server.addrs()[0]);
+        actix::spawn(server.run());
+        let webhook = EscalationWebhook::new(EscalationConfig {
+            url: Some(url),
+            severities: parse_severities("blacklist=high,length_filter=low").unwrap(),
+            ..Default::default()
+        })
+        .unwrap();
+        let bus = EventBus::new().with_escalation_webhook(webhook).start();
+
+        // Flagged by the length filter, which is low severity
+        bus.send(text_input("viewer1", &"哈".repeat(80)))
+            .await
+            .unwrap();
+        let flagged = text_input("viewer2", "这是广告");
+        bus.send(flagged.clone()).await.unwrap();
+
+        for _ in 0..50 {
+            if !alerts.lock().unwrap().is_empty() {
+                break;
+            }
+            actix::clock::sleep(Duration::from_millis(10)).await;
+        }
+        actix::clock::sleep(Duration::from_millis(50)).await;
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0]["rule_id"], "blacklist");
+        assert_eq!(alerts[0]["severity"], "high");
+        assert_eq!(alerts[0]["user_id"], "viewer2");
+        assert_eq!(alerts[0]["message"], "这是广告");
+        assert_eq!(
+            alerts[0]["event_id"],
+            serde_json::json!(flagged.metadata.id)
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_moderator_commands_are_acknowledged() {
+        let moderation = ModerationConfig {
+            moderators: ["douyin_mod".to_string()].into(),
+            ack_channel: AckChannel::Monitor,
+            sessions_authenticated: true,
+            ..Default::default()
+        };
+        let bus = EventBus::new().with_moderation(moderation).start();
+        let monitor = Collect::<SendMessage>::default().start();
+        bus.send(SubscribeMonitor {
+            monitor_id: Uuid::new_v4(),
+            recipient: monitor.clone().recipient(),
+        })
+        .await
+        .unwrap();
+        let command = |text: &str| TextInputEvent {
+            operator: true,
+            ..text_input("douyin_mod", text)
+        };
+
+        bus.send(command("!pause")).await.unwrap();
+        bus.send(command("!frobnicate")).await.unwrap();
+        bus.send(command("!pause")).await.unwrap();
+        bus.send(command(&format!("!mute douyin_42 {}", u64::MAX)))
+            .await
+            .unwrap();
+        // Claiming a moderator's id without an admin token runs nothing
+        bus.send(text_input("douyin_mod", "!resume")).await.unwrap();
+        // Viewers' commands are ordinary messages
+        bus.send(text_input("douyin_42", "!resume")).await.unwrap();
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let frames = received_frames(&monitor).await;
+        let acks: Vec<&serde_json::Value> = frames
+            .iter()
+            .filter(|f| f["type"] == "command_ack")
+            .map(|f| &f["data"])
+            .collect();
+        assert_eq!(acks.len(), 5);
+        assert_eq!(acks[0]["command"], "!pause");
+        assert_eq!(acks[0]["accepted"], true);
+        assert_eq!(acks[1]["accepted"], false);
+        assert_eq!(acks[1]["detail"], "unknown command: !frobnicate");
+        assert_eq!(acks[2]["accepted"], false);
+        assert_eq!(acks[2]["detail"], "already paused");
+        assert_eq!(acks[3]["accepted"], false);
+        assert_eq!(acks[3]["detail"], format!("too many minutes: {}", u64::MAX));
+        assert_eq!(acks[4]["accepted"], false);
+        assert_eq!(acks[4]["detail"], "commands need an authenticated source");
+        assert!(acks.iter().all(|ack| ack["user_id"] == "douyin_mod"));
+    }
+
+    #[actix_web::test]
+    async fn test_moderator_commands_are_refused_without_auth() {
+        let moderation = ModerationConfig {
+            moderators: ["douyin_mod".to_string()].into(),
+            ack_channel: AckChannel::Monitor,
+            ..Default::default()
+        };
+        let bus = EventBus::new().with_moderation(moderation).start();
+        let monitor = Collect::<SendMessage>::default().start();
+        bus.send(SubscribeMonitor {
+            monitor_id: Uuid::new_v4(),
+            recipient: monitor.clone().recipient(),
+        })
+        .await
+        .unwrap();
+
+        bus.send(text_input("douyin_mod", "!pause")).await.unwrap();
+        actix::clock::sleep(Duration::from_millis(50)).await;
+
+        let frames = received_frames(&monitor).await;
+        let ack = frames.iter().find(|f| f["type"] == "command_ack").unwrap();
+        assert_eq!(ack["data"]["accepted"], false);
     }
 }
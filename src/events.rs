@@ -1,16 +1,28 @@
 use std::any::Any;
 
+use crate::intent::Intent;
+use crate::language::LanguageSpan;
+use crate::llm::{ChatMessage, LengthLimit, SamplingParams};
+use crate::tts::{VoiceFallback, VoiceStyle};
 use actix::prelude::*;
 use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// Version of the serialized event format. Bump it together with a new step
+/// in `migrate_event` whenever a change needs migrating.
+pub const EVENT_SCHEMA_VERSION: u16 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EventMetadata {
     pub id: Uuid,
     pub timestamp: DateTime<Utc>,
     pub session_id: Option<Uuid>,
     pub user_id: Option<String>,
+    /// 0 for events serialized before versioning was introduced.
+    #[serde(default)]
+    pub schema_version: u16,
 }
 
 impl Default for EventMetadata {
@@ -20,10 +32,40 @@ impl Default for EventMetadata {
             timestamp: Utc::now(),
             session_id: None,
             user_id: None,
+            schema_version: EVENT_SCHEMA_VERSION,
         }
     }
 }
 
+/// Upgrades a serialized event to `EVENT_SCHEMA_VERSION`, one version at a time.
+pub fn migrate_event(mut event: serde_json::Value) -> Result<serde_json::Value, String> {
+    let metadata = event
+        .get_mut("metadata")
+        .and_then(|m| m.as_object_mut())
+        .ok_or("event has no metadata")?;
+    let mut version = metadata
+        .get("schema_version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+
+    if version > EVENT_SCHEMA_VERSION as u64 {
+        return Err(format!("unsupported event schema version {}", version));
+    }
+    while version < EVENT_SCHEMA_VERSION as u64 {
+        // 0 -> 1: the version field itself was added; nothing else changed
+        version += 1;
+    }
+    metadata.insert("schema_version".to_string(), version.into());
+
+    Ok(event)
+}
+
+/// Deserializes a persisted or recorded event, migrating older versions first.
+pub fn load_event<T: DeserializeOwned>(event: serde_json::Value) -> Result<T, String> {
+    let event = migrate_event(event)?;
+    serde_json::from_value(event).map_err(|e| e.to_string())
+}
+
 #[allow(unused)]
 pub trait Event: Message<Result = ()> + Clone + Send + Any + 'static {
     fn event_type(&self) -> &'static str;
@@ -37,6 +79,9 @@ pub struct UserConnectedEvent {
     pub metadata: EventMetadata,
     pub session_id: Uuid,
     pub user_id: String,
+    /// Earlier sessions of the same user whose history carries over.
+    #[serde(default)]
+    pub merged_sessions: Vec<Uuid>,
 }
 
 impl Event for UserConnectedEvent {
@@ -71,12 +116,77 @@ impl Event for UserDisconnectedEvent {
     }
 }
 
+/// How urgently an input deserves a response when the service is under load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessagePriority {
+    /// Ordinary danmaku, shed first under load.
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
 #[derive(Debug, Clone, Message, Serialize, Deserialize)]
 #[rtype(result = "()")]
 pub struct TextInputEvent {
     pub metadata: EventMetadata,
     pub text: String,
     pub language: Option<String>,
+    /// Display name of the sender, when the source provides one.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Mood label of the originating room when the danmaku arrived.
+    #[serde(default)]
+    pub room_mood: Option<String>,
+    #[serde(default)]
+    pub priority: MessagePriority,
+    /// Filled in by the EventBus once the input passes validation.
+    #[serde(default)]
+    pub intent: Option<Intent>,
+    /// Where a danmaku came from; None for direct WebSocket input.
+    #[serde(default)]
+    pub viewer: Option<ViewerInfo>,
+    /// Seconds after `metadata.timestamp`, when the input arrived, past which
+    /// it is dropped rather than answered; never when unset.
+    #[serde(default)]
+    pub max_age_seconds: Option<u64>,
+    /// Sent from a WebSocket session whose token carries the admin claim.
+    #[serde(default)]
+    pub operator: bool,
+}
+
+impl TextInputEvent {
+    /// When the input goes stale, if it has a max age that can be
+    /// represented; a larger one never goes stale.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        let max_age = i64::try_from(self.max_age_seconds?).ok()?;
+        self.metadata
+            .timestamp
+            .checked_add_signed(chrono::TimeDelta::try_seconds(max_age)?)
+    }
+
+    pub fn is_stale(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at().is_some_and(|expires_at| now > expires_at)
+    }
+}
+
+/// The room and standing of the viewer who sent a danmaku.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewerInfo {
+    pub room_id: String,
+    pub user_level: Option<u32>,
+    pub is_vip: bool,
+    /// Value of a gift sent with the message, in the platform's units.
+    #[serde(default)]
+    pub gift_value: Option<f64>,
+    /// Similar danmaku collapsed into this one.
+    #[serde(default)]
+    pub similar_count: u32,
+    /// The platform vouches for the sender's user id; see
+    /// `DanmakuMessage::trusted`.
+    #[serde(default)]
+    pub trusted: bool,
 }
 
 impl Event for TextInputEvent {
@@ -119,6 +229,33 @@ pub struct LLMResponseEvent {
     pub response: String,
     pub model: String,
     pub tokens_used: Option<u32>,
+    /// Length limit the response was generated and trimmed under.
+    #[serde(default)]
+    pub length_limit: Option<LengthLimit>,
+    /// Temperature and top-p the response was generated with.
+    #[serde(default)]
+    pub sampling: Option<SamplingParams>,
+    /// Language of a translated variant; unset on the original response.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Response this one is a translation of.
+    #[serde(default)]
+    pub translation_of: Option<Uuid>,
+    /// The viewer message answered, when response attribution is on.
+    #[serde(default)]
+    pub replying_to: Option<ReplyingTo>,
+    /// The response split into language-tagged spans, when language
+    /// segmentation is on.
+    #[serde(default)]
+    pub segments: Option<Vec<LanguageSpan>>,
+}
+
+/// The viewer message a response answers, so overlays can show
+/// "Replying to @user: ...".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyingTo {
+    pub username: Option<String>,
+    pub message: String,
 }
 
 impl Event for LLMResponseEvent {
@@ -140,6 +277,16 @@ pub struct TTSResponseEvent {
     pub audio_data: Vec<u8>,
     pub text: String,
     pub voice: String,
+    /// Voice and prosody chosen for the response's emotion.
+    #[serde(default)]
+    pub style: Option<VoiceStyle>,
+    /// Set when the response's language had no voice of its own.
+    #[serde(default)]
+    pub voice_fallback: Option<VoiceFallback>,
+    /// Set when the audio is streamed as `TTSChunkEvent`s of this response
+    /// instead of carried in `audio_data`.
+    #[serde(default)]
+    pub response_id: Option<Uuid>,
 }
 
 impl Event for TTSResponseEvent {
@@ -174,3 +321,265 @@ impl Event for AnimationEvent {
         self.metadata = metadata;
     }
 }
+
+/// One conversational turn: the text reply plus the animation, emotion and audio
+/// that must be delivered alongside it.
+#[derive(Debug, Clone, Message, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct ResponseBundle {
+    pub metadata: EventMetadata,
+    pub response_id: Uuid,
+    pub text: LLMResponseEvent,
+    pub animation: Option<AnimationEvent>,
+    pub emotion: Option<AnimationEvent>,
+    pub audio: Option<TTSResponseEvent>,
+}
+
+impl Event for ResponseBundle {
+    fn event_type(&self) -> &'static str {
+        "response_bundle"
+    }
+    fn metadata(&self) -> &EventMetadata {
+        &self.metadata
+    }
+    fn set_metadata(&mut self, metadata: EventMetadata) {
+        self.metadata = metadata;
+    }
+}
+
+/// Partial LLM output streamed ahead of the final `ResponseBundle`.
+#[derive(Debug, Clone, Message, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct LLMTokenEvent {
+    pub metadata: EventMetadata,
+    pub response_id: Uuid,
+    /// Always whole characters; never splits a UTF-8 sequence.
+    pub delta: String,
+    /// Set on the frame that ends a stream which broke off, so clients can
+    /// close the partial response.
+    #[serde(default)]
+    pub finished: bool,
+    /// Why the stream ended early, e.g. `interrupted`.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Event for LLMTokenEvent {
+    fn event_type(&self) -> &'static str {
+        "llm_token"
+    }
+    fn metadata(&self) -> &EventMetadata {
+        &self.metadata
+    }
+    fn set_metadata(&mut self, metadata: EventMetadata) {
+        self.metadata = metadata;
+    }
+}
+
+/// A piece of synthesized speech for a response, streamed after its text.
+#[derive(Debug, Clone, Message, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct TTSChunkEvent {
+    pub metadata: EventMetadata,
+    pub response_id: Uuid,
+    /// Position in the utterance, starting at 0.
+    pub seq: u32,
+    pub is_last: bool,
+    pub audio: Vec<u8>,
+    /// Voice and prosody the utterance is spoken in.
+    #[serde(default)]
+    pub style: Option<VoiceStyle>,
+    #[serde(default)]
+    pub voice: Option<String>,
+    /// Set when the response's language had no voice of its own.
+    #[serde(default)]
+    pub voice_fallback: Option<VoiceFallback>,
+}
+
+impl Event for TTSChunkEvent {
+    fn event_type(&self) -> &'static str {
+        "tts_chunk"
+    }
+    fn metadata(&self) -> &EventMetadata {
+        &self.metadata
+    }
+    fn set_metadata(&mut self, metadata: EventMetadata) {
+        self.metadata = metadata;
+    }
+}
+
+/// Operator request to withdraw a response that was already delivered.
+/// Answered with the session the response was retracted from, if found.
+#[derive(Debug, Clone, Message, Serialize, Deserialize)]
+#[rtype(result = "Option<Uuid>")]
+pub struct RetractResponse {
+    pub metadata: EventMetadata,
+    pub response_id: Uuid,
+    pub reason: Option<String>,
+}
+
+impl Event for RetractResponse {
+    fn event_type(&self) -> &'static str {
+        "retract_response"
+    }
+    fn metadata(&self) -> &EventMetadata {
+        &self.metadata
+    }
+    fn set_metadata(&mut self, metadata: EventMetadata) {
+        self.metadata = metadata;
+    }
+}
+
+/// The exact messages sent to the LLM for a response, for debugging personas.
+/// Contains the system prompt, so only emitted when prompt debugging is on.
+#[derive(Debug, Clone, Message, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct LLMPromptEvent {
+    pub metadata: EventMetadata,
+    pub response_id: Uuid,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl Event for LLMPromptEvent {
+    fn event_type(&self) -> &'static str {
+        "debug_prompt"
+    }
+    fn metadata(&self) -> &EventMetadata {
+        &self.metadata
+    }
+    fn set_metadata(&mut self, metadata: EventMetadata) {
+        self.metadata = metadata;
+    }
+}
+
+/// Published once the owning session of a retracted response is known.
+#[derive(Debug, Clone, Message, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct ResponseRetractedEvent {
+    pub metadata: EventMetadata,
+    pub response_id: Uuid,
+    pub reason: Option<String>,
+}
+
+impl Event for ResponseRetractedEvent {
+    fn event_type(&self) -> &'static str {
+        "response_retracted"
+    }
+    fn metadata(&self) -> &EventMetadata {
+        &self.metadata
+    }
+    fn set_metadata(&mut self, metadata: EventMetadata) {
+        self.metadata = metadata;
+    }
+}
+
+/// A room's stream went live. Its danmaku are processed from now on and the
+/// persona opens with an intro.
+#[derive(Debug, Clone, Message, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct StreamStartedEvent {
+    pub metadata: EventMetadata,
+    pub room_id: String,
+    /// Set when the persona is not answering the room, so it gives no intro.
+    #[serde(default)]
+    pub silent: bool,
+}
+
+impl Event for StreamStartedEvent {
+    fn event_type(&self) -> &'static str {
+        "stream_started"
+    }
+    fn metadata(&self) -> &EventMetadata {
+        &self.metadata
+    }
+    fn set_metadata(&mut self, metadata: EventMetadata) {
+        self.metadata = metadata;
+    }
+}
+
+/// A room's stream ended. The persona closes with an outro and the room's
+/// danmaku are ignored until it starts again.
+#[derive(Debug, Clone, Message, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct StreamEndedEvent {
+    pub metadata: EventMetadata,
+    pub room_id: String,
+    /// Set when the persona is not answering the room, so it gives no outro.
+    #[serde(default)]
+    pub silent: bool,
+}
+
+impl Event for StreamEndedEvent {
+    fn event_type(&self) -> &'static str {
+        "stream_ended"
+    }
+    fn metadata(&self) -> &EventMetadata {
+        &self.metadata
+    }
+    fn set_metadata(&mut self, metadata: EventMetadata) {
+        self.metadata = metadata;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_input() -> TextInputEvent {
+        TextInputEvent {
+            metadata: EventMetadata::default(),
+            text: "你好".to_string(),
+            language: Some("zh-CN".to_string()),
+            username: None,
+            room_mood: None,
+            priority: MessagePriority::Normal,
+            intent: None,
+            viewer: None,
+            max_age_seconds: None,
+            operator: false,
+        }
+    }
+
+    #[test]
+    fn test_event_round_trip_with_version() {
+        let event = text_input();
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["metadata"]["schema_version"], EVENT_SCHEMA_VERSION);
+
+        let loaded: TextInputEvent = load_event(json).unwrap();
+        assert_eq!(loaded.metadata.id, event.metadata.id);
+        assert_eq!(loaded.metadata.schema_version, EVENT_SCHEMA_VERSION);
+        assert_eq!(loaded.text, event.text);
+    }
+
+    #[test]
+    fn test_event_without_version_is_migrated() {
+        let mut json = serde_json::to_value(text_input()).unwrap();
+        json["metadata"]
+            .as_object_mut()
+            .unwrap()
+            .remove("schema_version");
+
+        let raw: TextInputEvent = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(raw.metadata.schema_version, 0);
+
+        let loaded: TextInputEvent = load_event(json.clone()).unwrap();
+        assert_eq!(loaded.metadata.schema_version, EVENT_SCHEMA_VERSION);
+
+        json["metadata"]["schema_version"] = (EVENT_SCHEMA_VERSION + 1).into();
+        assert!(load_event::<TextInputEvent>(json).is_err());
+    }
+
+    #[test]
+    fn test_unrepresentable_max_age_never_goes_stale() {
+        let mut event = text_input();
+        event.max_age_seconds = Some(30);
+        assert!(event.is_stale(event.metadata.timestamp + chrono::Duration::seconds(31)));
+
+        for max_age in [u64::MAX, i64::MAX as u64] {
+            event.max_age_seconds = Some(max_age);
+            assert_eq!(event.expires_at(), None);
+            assert!(!event.is_stale(Utc::now()));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+/// Asked of the LLM, in place of viewer input, for an idle filler line.
+pub const FILLER_PROMPT: &str =
+    "Nobody has said anything for a while. Say one short, casual line to keep the stream lively.";
+
+/// What the persona does during quiet stretches. Off unless `after_seconds`
+/// is set.
+#[derive(Debug, Clone)]
+pub struct IdleConfig {
+    /// Seconds without input before the persona starts idling.
+    pub after_seconds: Option<u64>,
+    /// Seconds between idle animations while idle.
+    pub animation_interval_seconds: u64,
+    /// Played in turn; no idle animations when empty.
+    pub animations: Vec<String>,
+    /// Seconds between LLM-generated filler lines while idle; none when unset.
+    pub filler_interval_seconds: Option<u64>,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            after_seconds: None,
+            animation_interval_seconds: 30,
+            animations: vec![
+                "idle_look_around".to_string(),
+                "idle_stretch".to_string(),
+                "idle_sway".to_string(),
+            ],
+            filler_interval_seconds: None,
+        }
+    }
+}
+
+impl IdleConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.after_seconds.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdleAction {
+    Animation(String),
+    Filler,
+}
+
+/// Tracks time since the last input and paces idle behaviors.
+#[derive(Debug)]
+pub struct IdleTimer {
+    config: IdleConfig,
+    last_input: Instant,
+    last_animation: Option<Instant>,
+    last_filler: Option<Instant>,
+    next_animation: usize,
+}
+
+impl IdleTimer {
+    pub fn new(config: IdleConfig, now: Instant) -> Self {
+        Self {
+            config,
+            last_input: now,
+            last_animation: None,
+            last_filler: None,
+            next_animation: 0,
+        }
+    }
+
+    pub fn config(&self) -> &IdleConfig {
+        &self.config
+    }
+
+    /// Input arrived; idling starts over.
+    pub fn reset(&mut self, now: Instant) {
+        self.last_input = now;
+        self.last_animation = None;
+        self.last_filler = None;
+    }
+
+    /// The idle behaviors due at `now`, if the persona has been idle long enough.
+    pub fn poll(&mut self, now: Instant) -> Vec<IdleAction> {
+        let Some(after) = self.config.after_seconds else {
+            return Vec::new();
+        };
+        if now.saturating_duration_since(self.last_input) < Duration::from_secs(after) {
+            return Vec::new();
+        }
+        let due = |last: Option<Instant>, interval: u64| {
+            last.is_none_or(|last| {
+                now.saturating_duration_since(last) >= Duration::from_secs(interval)
+            })
+        };
+
+        let mut actions = Vec::new();
+        if !self.config.animations.is_empty()
+            && due(self.last_animation, self.config.animation_interval_seconds)
+        {
+            let animation =
+                &self.config.animations[self.next_animation % self.config.animations.len()];
+            actions.push(IdleAction::Animation(animation.clone()));
+            self.next_animation += 1;
+            self.last_animation = Some(now);
+        }
+        if let Some(interval) = self.config.filler_interval_seconds {
+            if due(self.last_filler, interval) {
+                actions.push(IdleAction::Filler);
+                self.last_filler = Some(now);
+            }
+        }
+        actions
+    }
+}
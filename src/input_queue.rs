@@ -0,0 +1,381 @@
+use crate::events::{load_event, TextInputEvent};
+use crate::redis_conn::RedisConnector;
+use actix::clock::timeout;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::future::{self, BoxFuture};
+use log::warn;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+const REDIS_KEY: &str = "live_streamer:input_queue";
+/// Longest a store may take to queue input, or to be reached for a drain,
+/// before it is treated as unavailable.
+const STORE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Where input waits while the digital human is unavailable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueBackend {
+    Memory,
+    /// JSON lines appended to the file, so queued input survives a restart.
+    File(String),
+    /// A Redis list at `REDIS_URL`, shared by every instance.
+    Redis,
+}
+
+impl FromStr for QueueBackend {
+    type Err = String;
+
+    /// Parses `memory`, `file:<path>` or `redis`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.split_once(':') {
+            None if s.eq_ignore_ascii_case("memory") => Ok(QueueBackend::Memory),
+            None if s.eq_ignore_ascii_case("redis") => Ok(QueueBackend::Redis),
+            Some((kind, path)) if kind.eq_ignore_ascii_case("file") && !path.is_empty() => {
+                Ok(QueueBackend::File(path.to_string()))
+            }
+            _ => Err(format!("invalid input queue backend: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputQueueConfig {
+    /// Off when unset: input is dropped while the digital human is unavailable.
+    pub backend: Option<QueueBackend>,
+    /// Queued input older than this is dropped instead of answered late.
+    pub max_age_seconds: u64,
+    /// Input beyond this many queued events is dropped.
+    pub max_len: usize,
+    /// How often an available digital human takes input queued by other
+    /// instances sharing the store.
+    pub drain_interval_ms: u64,
+}
+
+impl Default for InputQueueConfig {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            max_age_seconds: 60,
+            max_len: 1000,
+            drain_interval_ms: 1000,
+        }
+    }
+}
+
+/// Backing store for queued input, oldest first. Calls return futures owning
+/// what they need, so a remote store is never waited on by the caller's thread.
+pub trait InputQueueStore: Send + fmt::Debug {
+    /// Queues `event` unless `max_len` events are already waiting.
+    fn push(
+        &mut self,
+        event: &TextInputEvent,
+        max_len: usize,
+    ) -> BoxFuture<'static, Result<(), String>>;
+
+    /// Removes and returns everything queued, oldest first. Not timed out by
+    /// the caller, since input removed but never returned would be lost; a
+    /// remote store bounds only its wait before removing anything.
+    fn drain(&mut self) -> BoxFuture<'static, Result<Vec<TextInputEvent>, String>>;
+}
+
+/// In-memory queue; clones share it, e.g. between buses in one process.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryInputQueue {
+    events: Arc<Mutex<VecDeque<TextInputEvent>>>,
+}
+
+impl InMemoryInputQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl InputQueueStore for InMemoryInputQueue {
+    fn push(
+        &mut self,
+        event: &TextInputEvent,
+        max_len: usize,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        let result = if events.len() >= max_len {
+            Err(format!("queue is full ({} events)", events.len()))
+        } else {
+            events.push_back(event.clone());
+            Ok(())
+        };
+        Box::pin(future::ready(result))
+    }
+
+    fn drain(&mut self) -> BoxFuture<'static, Result<Vec<TextInputEvent>, String>> {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        Box::pin(future::ready(Ok(events.drain(..).collect())))
+    }
+}
+
+/// File-backed queue; one JSON event per line. The file is read and written
+/// on the blocking thread pool, never by the caller's thread.
+#[derive(Debug)]
+pub struct FileInputQueue {
+    file: Arc<Mutex<QueueFile>>,
+}
+
+#[derive(Debug)]
+struct QueueFile {
+    path: String,
+    len: usize,
+}
+
+impl FileInputQueue {
+    /// Opens the queue at `path`, keeping input left from a previous run.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let len = match fs::read_to_string(path) {
+            Ok(contents) => contents.lines().filter(|l| !l.trim().is_empty()).count(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.to_string()),
+        };
+        Ok(Self {
+            file: Arc::new(Mutex::new(QueueFile {
+                path: path.to_string(),
+                len,
+            })),
+        })
+    }
+
+    /// Runs `job` on the file off the caller's thread, one job at a time.
+    fn with_file<T, F>(&self, job: F) -> BoxFuture<'static, Result<T, String>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut QueueFile) -> Result<T, String> + Send + 'static,
+    {
+        let file = self.file.clone();
+        Box::pin(async move {
+            actix_web::rt::task::spawn_blocking(move || {
+                job(&mut file.lock().unwrap_or_else(|e| e.into_inner()))
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result)
+        })
+    }
+}
+
+impl QueueFile {
+    fn append(&mut self, event: &TextInputEvent, max_len: usize) -> Result<(), String> {
+        if self.len >= max_len {
+            return Err(format!("queue is full ({} events)", self.len));
+        }
+        let line = serde_json::to_string(event).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn take_all(&mut self) -> Result<Vec<TextInputEvent>, String> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.to_string()),
+        };
+        fs::write(&self.path, "").map_err(|e| e.to_string())?;
+        self.len = 0;
+        Ok(parse_events(contents.lines()))
+    }
+}
+
+impl InputQueueStore for FileInputQueue {
+    fn push(
+        &mut self,
+        event: &TextInputEvent,
+        max_len: usize,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        let event = event.clone();
+        self.with_file(move |file| file.append(&event, max_len))
+    }
+
+    fn drain(&mut self) -> BoxFuture<'static, Result<Vec<TextInputEvent>, String>> {
+        self.with_file(QueueFile::take_all)
+    }
+}
+
+/// Pushes unless the list is already `ARGV[2]` long, in one step so
+/// instances pushing together cannot overfill it.
+const PUSH_SCRIPT: &str = r"
+if redis.call('LLEN', KEYS[1]) >= tonumber(ARGV[2]) then
+    return redis.call('LLEN', KEYS[1])
+end
+redis.call('RPUSH', KEYS[1], ARGV[1])
+return -1
+";
+
+/// Redis list shared by every instance, so whichever one has a digital human
+/// drains it.
+#[derive(Debug)]
+pub struct RedisInputQueue {
+    connector: RedisConnector,
+}
+
+impl RedisInputQueue {
+    /// Checks the URL; the connection is made on first use and remade after
+    /// failures.
+    pub fn connect(url: &str) -> redis::RedisResult<Self> {
+        Ok(Self {
+            connector: RedisConnector::open(url)?,
+        })
+    }
+}
+
+impl InputQueueStore for RedisInputQueue {
+    fn push(
+        &mut self,
+        event: &TextInputEvent,
+        max_len: usize,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        let connector = self.connector.clone();
+        let line = serde_json::to_string(event);
+        Box::pin(async move {
+            let line = line.map_err(|e| e.to_string())?;
+            let mut connection = connector.connection().await.map_err(|e| e.to_string())?;
+            let len: i64 = redis::cmd("EVAL")
+                .arg(PUSH_SCRIPT)
+                .arg(1)
+                .arg(REDIS_KEY)
+                .arg(line)
+                .arg(max_len)
+                .query_async(&mut connection)
+                .await
+                .map_err(|e| e.to_string())?;
+            if len >= 0 {
+                return Err(format!("queue is full ({} events)", len));
+            }
+            Ok(())
+        })
+    }
+
+    fn drain(&mut self) -> BoxFuture<'static, Result<Vec<TextInputEvent>, String>> {
+        let connector = self.connector.clone();
+        Box::pin(async move {
+            // Only reaching Redis is timed out: once the list is cleared, its
+            // input must be waited for or it is lost
+            let mut connection = match timeout(STORE_TIMEOUT, connector.connection()).await {
+                Ok(connection) => connection.map_err(|e| e.to_string())?,
+                Err(_) => return Err("timed out connecting to Redis".to_string()),
+            };
+            // Read and clear atomically so two instances never both deliver an event
+            let (lines,): (Vec<String>,) = redis::pipe()
+                .atomic()
+                .cmd("LRANGE")
+                .arg(REDIS_KEY)
+                .arg(0)
+                .arg(-1)
+                .cmd("DEL")
+                .arg(REDIS_KEY)
+                .ignore()
+                .query_async(&mut connection)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(parse_events(lines.iter().map(String::as_str)))
+        })
+    }
+}
+
+fn parse_events<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<TextInputEvent> {
+    lines
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let event = serde_json::from_str(line).map_err(|e| e.to_string());
+            match event.and_then(load_event) {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    warn!("Dropping malformed queued input: {}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Holds validated input while the digital human is paused or restarting,
+/// and hands it back in arrival order once it returns.
+#[derive(Debug)]
+pub struct InputQueue {
+    store: Box<dyn InputQueueStore>,
+    max_age: Duration,
+    max_len: usize,
+    drain_interval: std::time::Duration,
+}
+
+impl InputQueue {
+    pub fn new(store: Box<dyn InputQueueStore>, config: &InputQueueConfig) -> Self {
+        Self {
+            store,
+            max_age: Duration::seconds(config.max_age_seconds as i64),
+            max_len: config.max_len,
+            drain_interval: std::time::Duration::from_millis(config.drain_interval_ms.max(1)),
+        }
+    }
+
+    pub fn drain_interval(&self) -> std::time::Duration {
+        self.drain_interval
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Queues `event`; resolves to false if the queue is full or unavailable.
+    pub fn enqueue(&mut self, event: &TextInputEvent) -> BoxFuture<'static, bool> {
+        let push = self.store.push(event, self.max_len);
+        let id = event.metadata.id;
+        Box::pin(async move {
+            match timeout(STORE_TIMEOUT, push).await {
+                Ok(Ok(())) => true,
+                Ok(Err(e)) => {
+                    warn!("Dropping input {}: {}", id, e);
+                    false
+                }
+                Err(_) => {
+                    warn!("Dropping input {}: input queue timed out", id);
+                    false
+                }
+            }
+        })
+    }
+
+    /// Everything queued that is still fresh at `now`, oldest first.
+    pub fn drain(&mut self, now: DateTime<Utc>) -> BoxFuture<'static, Vec<TextInputEvent>> {
+        let drain = self.store.drain();
+        let max_age = self.max_age;
+        Box::pin(async move {
+            let events = match drain.await {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("Failed to drain input queue: {}", e);
+                    Vec::new()
+                }
+            };
+            let total = events.len();
+            let fresh: Vec<TextInputEvent> = events
+                .into_iter()
+                .filter(|event| now.signed_duration_since(event.metadata.timestamp) <= max_age)
+                .collect();
+            if fresh.len() < total {
+                warn!(
+                    "Dropped {} queued input(s) older than {}s",
+                    total - fresh.len(),
+                    max_age.num_seconds()
+                );
+            }
+            fresh
+        })
+    }
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Intent {
+    Question,
+    Statement,
+    Command,
+    Greeting,
+}
+
+impl FromStr for Intent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "question" => Ok(Intent::Question),
+            "statement" => Ok(Intent::Statement),
+            "command" => Ok(Intent::Command),
+            "greeting" => Ok(Intent::Greeting),
+            other => Err(format!("unknown intent: {}", other)),
+        }
+    }
+}
+
+/// How the digital human reacts to an input of a given intent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseMode {
+    /// Generate a full LLM response.
+    Respond,
+    /// Nod along with an animation, without a text reply.
+    Acknowledge,
+    /// Let the LLM choose between a reply and a facial expression alone.
+    React,
+    /// Do not react at all.
+    Ignore,
+}
+
+impl FromStr for ResponseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "respond" => Ok(ResponseMode::Respond),
+            "acknowledge" => Ok(ResponseMode::Acknowledge),
+            "react" => Ok(ResponseMode::React),
+            "ignore" => Ok(ResponseMode::Ignore),
+            other => Err(format!("unknown response mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntentPolicy {
+    pub question: ResponseMode,
+    pub statement: ResponseMode,
+    pub command: ResponseMode,
+    pub greeting: ResponseMode,
+}
+
+impl Default for IntentPolicy {
+    fn default() -> Self {
+        Self {
+            question: ResponseMode::Respond,
+            statement: ResponseMode::Respond,
+            command: ResponseMode::Respond,
+            greeting: ResponseMode::Respond,
+        }
+    }
+}
+
+impl IntentPolicy {
+    pub fn mode_for(&self, intent: Intent) -> ResponseMode {
+        match intent {
+            Intent::Question => self.question,
+            Intent::Statement => self.statement,
+            Intent::Command => self.command,
+            Intent::Greeting => self.greeting,
+        }
+    }
+
+    /// Applies overrides such as `statement=ignore,greeting=acknowledge`.
+    pub fn apply_overrides(&mut self, spec: &str) -> Result<(), String> {
+        for pair in spec.split(',').filter(|p| !p.trim().is_empty()) {
+            let (intent, mode) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected intent=mode, got: {}", pair))?;
+            let mode = mode.parse()?;
+            match intent.parse()? {
+                Intent::Question => self.question = mode,
+                Intent::Statement => self.statement = mode,
+                Intent::Command => self.command = mode,
+                Intent::Greeting => self.greeting = mode,
+            }
+        }
+        Ok(())
+    }
+}
+
+const ZH_GREETINGS: &[&str] = &[
+    "你好",
+    "您好",
+    "大家好",
+    "哈喽",
+    "嗨",
+    "早上好",
+    "下午好",
+    "晚上好",
+    "早安",
+    "晚安",
+];
+const EN_GREETINGS: &[&str] = &[
+    "hi",
+    "hello",
+    "hey",
+    "hiya",
+    "yo",
+    "good morning",
+    "good afternoon",
+    "good evening",
+];
+const ZH_INTERROGATIVES: &[&str] = &[
+    "什么",
+    "怎么",
+    "为什么",
+    "为啥",
+    "哪",
+    "谁",
+    "几",
+    "多少",
+    "是不是",
+    "能不能",
+    "有没有",
+    "会不会",
+    "可不可以",
+];
+const ZH_QUESTION_PARTICLES: &[char] = &['吗', '呢', '么', '嘛'];
+const EN_QUESTION_WORDS: &[&str] = &[
+    "what", "why", "how", "when", "where", "who", "whom", "whose", "which", "is", "are", "am",
+    "do", "does", "did", "can", "could", "will", "would", "should", "shall", "may", "have", "has",
+];
+
+fn is_cjk(c: char) -> bool {
+    ('\u{4e00}'..='\u{9fff}').contains(&c)
+}
+
+/// Heuristic intent classification based on punctuation and interrogatives.
+pub fn classify(text: &str) -> Intent {
+    let trimmed = text.trim();
+    if trimmed.starts_with(['!', '！', '/']) {
+        return Intent::Command;
+    }
+
+    let body = trimmed.trim_end_matches(|c: char| {
+        c.is_ascii_punctuation() || "。！～~ ，,".contains(c) || c.is_whitespace()
+    });
+    if trimmed.ends_with(['?', '？']) {
+        return Intent::Question;
+    }
+
+    if trimmed.chars().any(is_cjk) {
+        if ZH_GREETINGS.iter().any(|g| body.starts_with(g)) && body.chars().count() <= 8 {
+            return Intent::Greeting;
+        }
+        if body.ends_with(ZH_QUESTION_PARTICLES)
+            || ZH_INTERROGATIVES.iter().any(|w| body.contains(w))
+        {
+            return Intent::Question;
+        }
+        return Intent::Statement;
+    }
+
+    let lower = body.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .collect();
+    if words.len() <= 4
+        && EN_GREETINGS
+            .iter()
+            .any(|g| lower == *g || lower.starts_with(&format!("{} ", g)))
+    {
+        return Intent::Greeting;
+    }
+    if words
+        .first()
+        .is_some_and(|first| EN_QUESTION_WORDS.contains(first))
+    {
+        return Intent::Question;
+    }
+
+    Intent::Statement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chinese_question_detection() {
+        assert_eq!(classify("今天玩什么游戏"), Intent::Question);
+        assert_eq!(classify("主播吃饭了吗"), Intent::Question);
+        assert_eq!(classify("这是哪里？"), Intent::Question);
+        assert_eq!(classify("主播好厉害"), Intent::Statement);
+        assert_eq!(classify("大家好！"), Intent::Greeting);
+    }
+
+    #[test]
+    fn test_english_question_detection() {
+        assert_eq!(classify("what game is this"), Intent::Question);
+        assert_eq!(classify("Can you sing a song"), Intent::Question);
+        assert_eq!(classify("nice stream?"), Intent::Question);
+        assert_eq!(classify("nice stream"), Intent::Statement);
+        assert_eq!(classify("Hello Maya"), Intent::Greeting);
+        assert_eq!(classify("!pause"), Intent::Command);
+    }
+
+    #[test]
+    fn test_policy_overrides() {
+        let mut policy = IntentPolicy::default();
+        policy
+            .apply_overrides("statement=ignore, greeting=acknowledge")
+            .unwrap();
+
+        assert_eq!(policy.mode_for(Intent::Statement), ResponseMode::Ignore);
+        assert_eq!(policy.mode_for(Intent::Greeting), ResponseMode::Acknowledge);
+        assert_eq!(policy.mode_for(Intent::Question), ResponseMode::Respond);
+        assert!(policy.apply_overrides("chatter=ignore").is_err());
+    }
+}
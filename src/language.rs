@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+/// A run of a response in one language.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguageSpan {
+    pub language: String,
+    pub text: String,
+}
+
+/// Guesses a sentence's language from its script. Any Chinese character
+/// makes it Chinese, since English words inside Chinese sentences are
+/// common; kana and Hangul win over Chinese characters.
+pub fn detect(sentence: &str) -> Option<&'static str> {
+    let mut han = false;
+    let mut latin = false;
+    for c in sentence.chars() {
+        match c {
+            '\u{3040}'..='\u{30ff}' => return Some("ja"),
+            '\u{ac00}'..='\u{d7af}' | '\u{1100}'..='\u{11ff}' => return Some("ko"),
+            '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' => han = true,
+            c if c.is_ascii_alphabetic() => latin = true,
+            _ => {}
+        }
+    }
+    if han {
+        Some("zh")
+    } else if latin {
+        Some("en")
+    } else {
+        None
+    }
+}
+
+fn sentences(text: &str) -> impl Iterator<Item = &str> {
+    text.split_inclusive(['.', '!', '?', '\n', '。', '！', '？', '；', ';'])
+}
+
+/// Splits `text` into language-tagged spans, one per run of sentences in
+/// the same language. Sentences without letters (emoji, "...") join the
+/// span before them, or the first span when they lead.
+pub fn segment(text: &str) -> Vec<LanguageSpan> {
+    let mut spans: Vec<LanguageSpan> = Vec::new();
+    let mut pending = String::new();
+    for sentence in sentences(text) {
+        match (detect(sentence), spans.last_mut()) {
+            (None, Some(last)) => last.text.push_str(sentence),
+            (None, None) => pending.push_str(sentence),
+            (Some(language), Some(last)) if last.language == language => {
+                last.text.push_str(sentence)
+            }
+            (Some(language), _) => {
+                let mut text = std::mem::take(&mut pending);
+                text.push_str(sentence);
+                spans.push(LanguageSpan {
+                    language: language.to_string(),
+                    text,
+                });
+            }
+        }
+    }
+    if spans.is_empty() && !pending.trim().is_empty() {
+        spans.push(LanguageSpan {
+            language: "und".to_string(),
+            text: pending,
+        });
+    }
+    for span in &mut spans {
+        span.text = span.text.trim().to_string();
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixed_response_yields_tagged_segments() {
+        let spans = segment("大家好，欢迎来到直播间！ Welcome to the stream, everyone!");
+        assert_eq!(
+            spans,
+            [
+                LanguageSpan {
+                    language: "zh".to_string(),
+                    text: "大家好，欢迎来到直播间！".to_string(),
+                },
+                LanguageSpan {
+                    language: "en".to_string(),
+                    text: "Welcome to the stream, everyone!".to_string(),
+                },
+            ]
+        );
+
+        // English words inside a Chinese sentence keep it Chinese
+        let spans = segment("我最喜欢用Python写代码。Thanks! 😄");
+        assert_eq!(spans[0].language, "zh");
+        assert_eq!(spans[1].text, "Thanks! 😄");
+    }
+}
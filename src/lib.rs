@@ -0,0 +1,59 @@
+//! Digital human engine for live streams. The `live-streamer` binary serves
+//! it over HTTP and WebSocket; other applications can embed it directly with
+//! [`DigitalHumanService`].
+
+pub mod actor;
+pub mod animation;
+pub mod audit;
+pub mod auth;
+pub mod ban;
+pub mod channels;
+pub mod cluster;
+pub mod codec;
+pub mod config;
+pub mod diagnostics;
+pub mod engagement;
+pub mod escalation;
+pub mod event_bus;
+pub mod events;
+pub mod idle;
+pub mod injection;
+pub mod input_queue;
+pub mod intent;
+pub mod language;
+pub mod lifecycle;
+pub mod llm;
+pub mod load;
+pub mod mask;
+pub mod moderation;
+pub mod outage;
+pub mod overlay;
+pub mod platform;
+pub mod preflight;
+pub mod rate_limit;
+pub mod reaction;
+pub mod redact;
+pub mod redis_conn;
+pub mod refusal;
+pub mod repeat;
+pub mod resume;
+pub mod routes;
+pub mod sentiment;
+mod service;
+pub mod similarity;
+pub mod stt;
+pub mod summary;
+pub mod templates;
+#[cfg(test)]
+mod testing;
+pub mod timezone;
+pub mod translate;
+pub mod tts;
+pub mod username;
+pub mod vad;
+pub mod validator;
+pub mod wake;
+pub mod websocket;
+pub mod worker;
+
+pub use service::{DigitalHumanService, ServiceHandles};
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+/// Asked of the LLM when a room's stream goes live.
+pub const INTRO_PROMPT: &str = "The stream has just gone live. Greet the audience and tell them in one or two sentences what today's stream is about.";
+
+/// Asked of the LLM when a room's stream ends.
+pub const OUTRO_PROMPT: &str = "The stream is ending now. Thank the audience for watching and say goodbye in one or two sentences.";
+
+/// How the persona and danmaku processing follow a room's stream going live
+/// and ending.
+#[derive(Debug, Clone)]
+pub struct LifecycleConfig {
+    /// When set, a room's danmaku are ignored until its stream is started.
+    /// Otherwise rooms are live until their stream is ended.
+    pub require_start: bool,
+    /// Played alongside the intro.
+    pub intro_animation: String,
+    /// Played alongside the outro.
+    pub outro_animation: String,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            require_start: false,
+            intro_animation: "wave".to_string(),
+            outro_animation: "bow".to_string(),
+        }
+    }
+}
+
+/// Rooms whose stream state is remembered at once. Room ids come from
+/// callers, so beyond this the room changed longest ago falls back to the
+/// default.
+const MAX_TRACKED_ROOMS: usize = 10_000;
+
+/// Whether each room's stream is live.
+#[derive(Debug, Default)]
+pub struct StreamStates {
+    require_start: bool,
+    /// Rooms whose stream is not in the default state, with the order they
+    /// changed in.
+    changed: HashMap<String, u64>,
+    changes: u64,
+}
+
+impl StreamStates {
+    pub fn new(config: &LifecycleConfig) -> Self {
+        Self {
+            require_start: config.require_start,
+            changed: HashMap::new(),
+            changes: 0,
+        }
+    }
+
+    /// Whether danmaku from `room_id` are processed.
+    pub fn is_live(&self, room_id: &str) -> bool {
+        self.changed.contains_key(room_id) == self.require_start
+    }
+
+    pub fn set_live(&mut self, room_id: &str, live: bool) {
+        if live != self.require_start {
+            self.changed.remove(room_id);
+            return;
+        }
+        self.changes += 1;
+        self.changed.insert(room_id.to_string(), self.changes);
+        while self.changed.len() > MAX_TRACKED_ROOMS {
+            let Some(oldest) = self
+                .changed
+                .iter()
+                .min_by_key(|(_, change)| **change)
+                .map(|(room_id, _)| room_id.clone())
+            else {
+                break;
+            };
+            self.changed.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_rooms_off_the_default_are_remembered() {
+        let mut streams = StreamStates::new(&LifecycleConfig::default());
+        assert!(streams.is_live("1001"));
+
+        streams.set_live("1001", false);
+        assert!(!streams.is_live("1001"));
+        streams.set_live("1001", true);
+        assert!(streams.is_live("1001"));
+        assert!(streams.changed.is_empty());
+
+        for room in 0..=MAX_TRACKED_ROOMS {
+            streams.set_live(&room.to_string(), false);
+        }
+        assert_eq!(streams.changed.len(), MAX_TRACKED_ROOMS);
+        assert!(!streams.is_live(&MAX_TRACKED_ROOMS.to_string()));
+    }
+}
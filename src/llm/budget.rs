@@ -0,0 +1,214 @@
+use crate::llm::{estimate_tokens, LlmResponse};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Spend key for input that did not come from a live room.
+pub const DIRECT_ROOM: &str = "direct";
+
+/// What a room gets once its daily budget is spent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    /// Answered by the cheap provider; paused when there is none.
+    #[default]
+    CheapModel,
+    /// Answered with `BudgetConfig::fallback_reply` without the LLM.
+    Templates,
+    /// Not answered until the next day.
+    Pause,
+}
+
+impl FromStr for BudgetAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "cheap_model" => Ok(BudgetAction::CheapModel),
+            "templates" => Ok(BudgetAction::Templates),
+            "pause" => Ok(BudgetAction::Pause),
+            other => Err(format!("unknown budget action: {}", other)),
+        }
+    }
+}
+
+/// Estimated LLM and TTS cost per room and day, and the cap on it. Spend is
+/// tracked even without a cap so it shows up in stats.
+#[derive(Debug, Clone)]
+pub struct BudgetConfig {
+    /// Daily spend allowed per room; unlimited when unset.
+    pub daily_cap: Option<f64>,
+    /// Price per 1000 tokens by model name.
+    pub prices: HashMap<String, f64>,
+    /// Price per 1000 tokens for models missing from `prices`.
+    pub default_price: f64,
+    /// Price per 1000 characters spoken.
+    pub tts_price: f64,
+    pub action: BudgetAction,
+    /// Said with `BudgetAction::Templates`; `{name}` is the persona's name.
+    pub fallback_reply: String,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            daily_cap: None,
+            prices: HashMap::new(),
+            default_price: 0.0,
+            tts_price: 0.0,
+            action: BudgetAction::default(),
+            fallback_reply: "{name}今天聊得太多啦，先歇一会儿，稍后再陪大家聊～".to_string(),
+        }
+    }
+}
+
+impl BudgetConfig {
+    /// Applies prices per 1000 tokens such as `gpt-4o=0.01,gpt-4o-mini=0.0006`.
+    pub fn apply_prices(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (model, price) = entry
+                .rsplit_once('=')
+                .ok_or_else(|| format!("expected model=price, got '{}'", entry))?;
+            let price = price
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid price: {}", price))?;
+            self.prices.insert(model.trim().to_string(), price);
+        }
+        Ok(())
+    }
+
+    fn price_of(&self, model: &str) -> f64 {
+        self.prices
+            .get(model)
+            .copied()
+            .unwrap_or(self.default_price)
+    }
+}
+
+/// One room's spend today.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomSpend {
+    pub room_id: String,
+    pub llm: f64,
+    pub tts: f64,
+    pub total: f64,
+    pub exhausted: bool,
+}
+
+/// Today's spend across rooms, for stats.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BudgetStats {
+    pub daily_cap: Option<f64>,
+    pub total: f64,
+    pub rooms: Vec<RoomSpend>,
+}
+
+#[derive(Debug, Default)]
+struct Spend {
+    day: Option<NaiveDate>,
+    llm: f64,
+    tts: f64,
+}
+
+/// Accumulates estimated cost per room, starting over each UTC day.
+#[derive(Debug, Default)]
+pub struct CostTracker {
+    config: BudgetConfig,
+    spend: HashMap<String, Spend>,
+}
+
+impl CostTracker {
+    pub fn new(config: BudgetConfig) -> Self {
+        Self {
+            config,
+            spend: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &BudgetConfig {
+        &self.config
+    }
+
+    fn today(&mut self, room_id: &str, now: DateTime<Utc>) -> &mut Spend {
+        let spend = self.spend.entry(room_id.to_string()).or_default();
+        if spend.day != Some(now.date_naive()) {
+            *spend = Spend {
+                day: Some(now.date_naive()),
+                ..Default::default()
+            };
+        }
+        spend
+    }
+
+    /// Adds the cost of `response` and of speaking `spoken_chars` characters,
+    /// returning it. A response without a token count is billed for the
+    /// estimated `prompt_tokens` of its request plus its own estimated tokens.
+    pub fn record(
+        &mut self,
+        room_id: &str,
+        prompt_tokens: usize,
+        response: &LlmResponse,
+        spoken_chars: usize,
+        now: DateTime<Utc>,
+    ) -> f64 {
+        let tokens = response.tokens_used.map_or_else(
+            || prompt_tokens + estimate_tokens(&response.content),
+            |tokens| tokens as usize,
+        );
+        self.record_tokens(room_id, &response.model, tokens, spoken_chars, now)
+    }
+
+    /// Adds the cost of `tokens` on `model` and of speaking `spoken_chars`
+    /// characters, returning it.
+    pub fn record_tokens(
+        &mut self,
+        room_id: &str,
+        model: &str,
+        tokens: usize,
+        spoken_chars: usize,
+        now: DateTime<Utc>,
+    ) -> f64 {
+        let llm = tokens as f64 / 1000.0 * self.config.price_of(model);
+        let tts = spoken_chars as f64 / 1000.0 * self.config.tts_price;
+        let spend = self.today(room_id, now);
+        spend.llm += llm;
+        spend.tts += tts;
+        llm + tts
+    }
+
+    /// Whether `room_id` has spent its daily cap.
+    pub fn exhausted(&mut self, room_id: &str, now: DateTime<Utc>) -> bool {
+        let Some(cap) = self.config.daily_cap else {
+            return false;
+        };
+        let spend = self.today(room_id, now);
+        spend.llm + spend.tts >= cap
+    }
+
+    pub fn stats(&self, now: DateTime<Utc>) -> BudgetStats {
+        let cap = self.config.daily_cap;
+        let mut rooms: Vec<RoomSpend> = self
+            .spend
+            .iter()
+            .filter(|(_, spend)| spend.day == Some(now.date_naive()))
+            .map(|(room_id, spend)| {
+                let total = spend.llm + spend.tts;
+                RoomSpend {
+                    room_id: room_id.clone(),
+                    llm: spend.llm,
+                    tts: spend.tts,
+                    total,
+                    exhausted: cap.is_some_and(|cap| total >= cap),
+                }
+            })
+            .collect();
+        rooms.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+        BudgetStats {
+            daily_cap: cap,
+            total: rooms.iter().map(|room| room.total).sum(),
+            rooms,
+        }
+    }
+}
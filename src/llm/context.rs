@@ -0,0 +1,131 @@
+use crate::llm::LlmRequest;
+
+/// Output tokens kept free when a request sets no generation limit.
+const DEFAULT_OUTPUT_RESERVE: usize = 256;
+/// Tokens each message costs besides its content, for the chat format.
+const MESSAGE_OVERHEAD: usize = 4;
+/// Appended to a user message cut short to fit.
+pub const TRUNCATION_NOTICE: &str = "…[message truncated]";
+
+fn is_wide(c: char) -> bool {
+    matches!(c, '\u{3000}'..='\u{9fff}' | '\u{ac00}'..='\u{d7af}' | '\u{ff00}'..='\u{ffef}')
+}
+
+/// Rough token count without the model's tokenizer: about one token per
+/// CJK character and per four other characters.
+pub fn estimate_tokens(text: &str) -> usize {
+    let (wide, narrow) = text.chars().fold((0usize, 0usize), |(wide, narrow), c| {
+        if is_wide(c) {
+            (wide + 1, narrow)
+        } else {
+            (wide, narrow + 1)
+        }
+    });
+    wide + narrow.div_ceil(4)
+}
+
+pub fn request_tokens(request: &LlmRequest) -> usize {
+    request
+        .messages
+        .iter()
+        .map(|m| estimate_tokens(&m.content) + MESSAGE_OVERHEAD)
+        .sum()
+}
+
+/// The longest prefix of `text` estimated at no more than `tokens`.
+fn truncate_to(text: &str, tokens: usize) -> &str {
+    let (mut wide, mut narrow) = (0usize, 0usize);
+    for (index, c) in text.char_indices() {
+        if is_wide(c) {
+            wide += 1;
+        } else {
+            narrow += 1;
+        }
+        if wide + narrow.div_ceil(4) > tokens {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// Fits `request` into a `limit`-token context window, leaving room for its
+/// output. The oldest history goes first; system messages and the latest
+/// user message are kept, the latter cut short with a notice if it alone is
+/// too long. Returns how many history messages were dropped.
+pub fn fit(request: &mut LlmRequest, limit: usize) -> usize {
+    let reserve = request
+        .max_tokens
+        .map_or(DEFAULT_OUTPUT_RESERVE, |t| t as usize);
+    let budget = limit.saturating_sub(reserve);
+    let Some(latest) = request.messages.iter().rposition(|m| m.role == "user") else {
+        return 0;
+    };
+
+    let mut dropped = 0;
+    let mut latest = latest;
+    while request_tokens(request) > budget {
+        let Some(oldest) = request.messages[..latest]
+            .iter()
+            .position(|m| m.role != "system")
+        else {
+            break;
+        };
+        request.messages.remove(oldest);
+        latest -= 1;
+        dropped += 1;
+    }
+
+    let over = request_tokens(request).saturating_sub(budget);
+    if over > 0 {
+        let content = &request.messages[latest].content;
+        let keep =
+            estimate_tokens(content).saturating_sub(over + estimate_tokens(TRUNCATION_NOTICE));
+        let truncated = format!("{}{}", truncate_to(content, keep), TRUNCATION_NOTICE);
+        request.messages[latest].content = truncated;
+    }
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChatMessage;
+
+    #[test]
+    fn test_oversized_history_is_trimmed_to_fit() {
+        let mut messages = vec![ChatMessage::new("system", "You are Maya.")];
+        for i in 0..50 {
+            messages.push(ChatMessage::new(
+                "user",
+                format!("第{}个问题，{}", i, "很长".repeat(20)),
+            ));
+            messages.push(ChatMessage::new("assistant", "好的，".repeat(20)));
+        }
+        messages.push(ChatMessage::new("user", "最后一个问题？"));
+        let mut request = LlmRequest {
+            messages,
+            max_tokens: Some(100),
+            ..Default::default()
+        };
+        assert!(request_tokens(&request) > 1000);
+
+        let dropped = fit(&mut request, 1000);
+        assert!(dropped > 0);
+        assert!(request_tokens(&request) <= 900);
+        assert_eq!(request.messages[0].content, "You are Maya.");
+        assert_eq!(request.messages.last().unwrap().content, "最后一个问题？");
+
+        // A message too long on its own is cut short with a notice
+        let mut request = LlmRequest {
+            messages: vec![
+                ChatMessage::new("system", "You are Maya."),
+                ChatMessage::new("user", "啊".repeat(2000)),
+            ],
+            max_tokens: None,
+            ..Default::default()
+        };
+        fit(&mut request, 1000);
+        assert!(request_tokens(&request) <= 744);
+        assert!(request.messages[1].content.ends_with(TRUNCATION_NOTICE));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::llm::{LlmError, LlmProvider, LlmRequest, LlmResponse};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self as futures_stream, BoxStream, StreamExt};
+use log::warn;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+struct Fallback {
+    provider: Arc<dyn LlmProvider>,
+    timeout: Option<Duration>,
+}
+
+/// Tries providers in order, moving on to the next when one fails or times
+/// out. Responses carry the model of the provider that served them.
+pub struct FallbackProvider {
+    providers: Vec<Fallback>,
+}
+
+impl FallbackProvider {
+    /// `timeouts[i]` bounds provider `i`; providers without one may take as
+    /// long as they need.
+    pub fn new(providers: Vec<Box<dyn LlmProvider>>, timeouts: &[Option<Duration>]) -> Self {
+        Self {
+            providers: providers
+                .into_iter()
+                .enumerate()
+                .map(|(i, provider)| Fallback {
+                    provider: Arc::from(provider),
+                    timeout: timeouts.get(i).copied().flatten(),
+                })
+                .collect(),
+        }
+    }
+}
+
+async fn within<T>(
+    timeout: Option<Duration>,
+    model: &str,
+    fut: impl Future<Output = Result<T, LlmError>>,
+) -> Result<T, LlmError> {
+    match timeout {
+        Some(timeout) => actix::clock::timeout(timeout, fut)
+            .await
+            .unwrap_or_else(|_| {
+                Err(LlmError::Provider(format!(
+                    "{} timed out after {:?}",
+                    model, timeout
+                )))
+            }),
+        None => fut.await,
+    }
+}
+
+fn no_providers() -> LlmError {
+    LlmError::Provider("no LLM providers configured".to_string())
+}
+
+impl LlmProvider for FallbackProvider {
+    /// The primary's model.
+    fn model(&self) -> &str {
+        self.providers
+            .first()
+            .map_or("none", |fallback| fallback.provider.model())
+    }
+
+    fn complete(&self, request: LlmRequest) -> BoxFuture<'static, Result<LlmResponse, LlmError>> {
+        let providers: Vec<_> = self
+            .providers
+            .iter()
+            .map(|f| (f.provider.clone(), f.timeout))
+            .collect();
+
+        Box::pin(async move {
+            let mut last_error = no_providers();
+            for (provider, timeout) in providers {
+                let model = provider.model().to_string();
+                match within(timeout, &model, provider.complete(request.clone())).await {
+                    Ok(response) => return Ok(response),
+                    Err(e) => {
+                        warn!("LLM provider {} failed, trying the next: {}", model, e);
+                        last_error = e;
+                    }
+                }
+            }
+            Err(last_error)
+        })
+    }
+
+    /// Falls back only until a provider yields its first delta, which is
+    /// what the timeout bounds; later errors end the stream.
+    fn stream(&self, request: LlmRequest) -> BoxStream<'static, Result<Vec<u8>, LlmError>> {
+        let providers: Vec<_> = self
+            .providers
+            .iter()
+            .map(|f| (f.provider.clone(), f.timeout))
+            .collect();
+
+        futures_stream::once(async move {
+            let mut last_error = no_providers();
+            for (provider, timeout) in providers {
+                let model = provider.model().to_string();
+                let mut deltas = provider.stream(request.clone());
+                let first = within(timeout, &model, async { deltas.next().await.transpose() });
+                match first.await {
+                    Ok(Some(delta)) => {
+                        return futures_stream::once(async move { Ok(delta) })
+                            .chain(deltas)
+                            .boxed()
+                    }
+                    Ok(None) => return futures_stream::empty().boxed(),
+                    Err(e) => {
+                        warn!("LLM provider {} failed, trying the next: {}", model, e);
+                        last_error = e;
+                    }
+                }
+            }
+            futures_stream::once(async move { Err(last_error) }).boxed()
+        })
+        .flatten()
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatMessage, EchoProvider};
+
+    struct Failing;
+
+    impl LlmProvider for Failing {
+        fn model(&self) -> &str {
+            "failing"
+        }
+
+        fn complete(
+            &self,
+            _request: LlmRequest,
+        ) -> BoxFuture<'static, Result<LlmResponse, LlmError>> {
+            Box::pin(async { Err(LlmError::Provider("boom".to_string())) })
+        }
+    }
+
+    struct Hanging;
+
+    impl LlmProvider for Hanging {
+        fn model(&self) -> &str {
+            "hanging"
+        }
+
+        fn complete(
+            &self,
+            _request: LlmRequest,
+        ) -> BoxFuture<'static, Result<LlmResponse, LlmError>> {
+            Box::pin(futures_util::future::pending())
+        }
+    }
+
+    fn request() -> LlmRequest {
+        LlmRequest {
+            messages: vec![ChatMessage::new("user", "你好")],
+            max_tokens: None,
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_falls_back_to_next_provider() {
+        let fallback = FallbackProvider::new(
+            vec![
+                Box::new(Failing),
+                Box::new(Hanging),
+                Box::new(EchoProvider::new("Maya")),
+            ],
+            &[None, Some(Duration::from_millis(20))],
+        );
+        assert_eq!(fallback.model(), "failing");
+
+        let response = fallback.complete(request()).await.unwrap();
+        assert_eq!(response.model, "digital_human");
+        assert!(response.content.contains("你好"));
+
+        let streamed: Vec<u8> = fallback
+            .stream(request())
+            .map(|delta| delta.unwrap())
+            .concat()
+            .await;
+        assert_eq!(String::from_utf8(streamed).unwrap(), response.content);
+
+        let none = FallbackProvider::new(vec![Box::new(Failing)], &[]);
+        assert!(none.complete(request()).await.is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::intent::Intent;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Caps on one response. Unset fields are unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LengthLimit {
+    /// Passed to the provider as its generation limit.
+    pub max_tokens: Option<u32>,
+    /// Enforced on the output by trimming to a sentence boundary.
+    pub max_chars: Option<usize>,
+}
+
+impl LengthLimit {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_tokens.is_none() && self.max_chars.is_none()
+    }
+
+    /// Fills fields this limit leaves unset from `fallback`.
+    fn or(self, fallback: LengthLimit) -> LengthLimit {
+        LengthLimit {
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            max_chars: self.max_chars.or(fallback.max_chars),
+        }
+    }
+}
+
+/// Response length limits, with per-intent overrides of the global default.
+#[derive(Debug, Clone, Default)]
+pub struct LengthPolicy {
+    pub default: LengthLimit,
+    pub by_intent: HashMap<Intent, LengthLimit>,
+}
+
+impl LengthPolicy {
+    pub fn limit_for(&self, intent: Option<Intent>) -> LengthLimit {
+        intent
+            .and_then(|intent| self.by_intent.get(&intent))
+            .map_or(self.default, |limit| limit.or(self.default))
+    }
+
+    /// Applies per-intent token limits such as `question=300,greeting=40`.
+    pub fn apply_token_overrides(&mut self, spec: &str) -> Result<(), String> {
+        for (intent, value) in parse_overrides(spec)? {
+            self.by_intent.entry(intent).or_default().max_tokens = Some(value as u32);
+        }
+        Ok(())
+    }
+
+    /// Applies per-intent character limits such as `question=300,greeting=40`.
+    pub fn apply_char_overrides(&mut self, spec: &str) -> Result<(), String> {
+        for (intent, value) in parse_overrides(spec)? {
+            self.by_intent.entry(intent).or_default().max_chars = Some(value);
+        }
+        Ok(())
+    }
+}
+
+fn parse_overrides(spec: &str) -> Result<Vec<(Intent, usize)>, String> {
+    spec.split(',')
+        .filter(|p| !p.trim().is_empty())
+        .map(|pair| {
+            let (intent, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected intent=limit, got: {}", pair))?;
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid limit: {}", value))?;
+            Ok((intent.parse()?, value))
+        })
+        .collect()
+}
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '…' | '\n' | '.' | '!' | '?')
+}
+
+/// Trims `text` to at most `max_chars` characters, ending after the last
+/// complete sentence that fits. Without one, it stops at a word boundary and
+/// appends an ellipsis.
+pub fn truncate_at_sentence(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let byte_at = |n: usize| text.char_indices().nth(n).map_or(text.len(), |(i, _)| i);
+    let head = &text[..byte_at(max_chars)];
+
+    // Latin punctuation only ends a sentence before whitespace ("3.5" does not)
+    let sentence_end = head
+        .char_indices()
+        .rev()
+        .find(|&(i, c)| {
+            let next = text[i + c.len_utf8()..].chars().next();
+            is_sentence_end(c) && (!c.is_ascii() || next.is_none_or(char::is_whitespace))
+        })
+        .map(|(i, c)| i + c.len_utf8());
+    if let Some(end) = sentence_end {
+        return head[..end].trim_end().to_string();
+    }
+
+    // Leave room for the ellipsis, and never split a word
+    let head = &text[..byte_at(max_chars.saturating_sub(1))];
+    let rest = &text[head.len()..];
+    let mid_word = head.ends_with(|c: char| c.is_alphanumeric() && c.is_ascii())
+        && rest.starts_with(|c: char| c.is_alphanumeric() && c.is_ascii());
+    let head = match head.rfind(char::is_whitespace) {
+        Some(space) if mid_word => &head[..space],
+        _ => head,
+    };
+    format!("{}…", head.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_response_is_trimmed_cleanly() {
+        let essay = "Great question! Version 3.5 added streaming. \
+                     It also reworked the queue, which took months of careful work.";
+
+        // Ends after the last sentence that fits, not inside "3.5"
+        assert_eq!(
+            truncate_at_sentence(essay, 60),
+            "Great question! Version 3.5 added streaming."
+        );
+        assert_eq!(truncate_at_sentence(essay, 20), "Great question!");
+
+        // No sentence fits: stop at a word boundary
+        let trimmed = truncate_at_sentence("Absolutely wonderful streaming tonight", 24);
+        assert_eq!(trimmed, "Absolutely wonderful…");
+        assert!(trimmed.chars().count() <= 24);
+
+        assert_eq!(
+            truncate_at_sentence("谢谢大家的支持！今天我们来聊聊新出的游戏吧。", 12),
+            "谢谢大家的支持！"
+        );
+        assert_eq!(truncate_at_sentence("short", 60), "short");
+    }
+
+    #[test]
+    fn test_intent_overrides_fall_back_to_default() {
+        let mut policy = LengthPolicy {
+            default: LengthLimit {
+                max_tokens: Some(120),
+                max_chars: Some(200),
+            },
+            ..Default::default()
+        };
+        policy.apply_char_overrides("greeting=40").unwrap();
+        policy.apply_token_overrides("question=300").unwrap();
+
+        assert_eq!(
+            policy.limit_for(Some(Intent::Greeting)),
+            LengthLimit {
+                max_tokens: Some(120),
+                max_chars: Some(40),
+            }
+        );
+        assert_eq!(
+            policy.limit_for(Some(Intent::Question)).max_tokens,
+            Some(300)
+        );
+        assert_eq!(policy.limit_for(None), policy.default);
+        assert!(policy.apply_char_overrides("greeting").is_err());
+    }
+}
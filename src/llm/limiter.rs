@@ -0,0 +1,162 @@
+use crate::events::MessagePriority;
+use crate::llm::{LlmConfig, LlmError};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Caps concurrent LLM requests across all sessions, queuing the excess and
+/// shedding low-priority requests once the queue is saturated.
+#[derive(Debug)]
+pub struct LlmLimiter {
+    semaphore: Semaphore,
+    max_concurrent: usize,
+    max_queued: usize,
+    in_flight: AtomicUsize,
+    queued: AtomicUsize,
+    shed: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LlmStats {
+    pub max_concurrent: usize,
+    pub in_flight: usize,
+    pub queued: usize,
+    pub shed: u64,
+}
+
+struct CountGuard<'a>(&'a AtomicUsize);
+
+impl<'a> CountGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for CountGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl LlmLimiter {
+    pub fn new(config: &LlmConfig) -> Arc<Self> {
+        let max_concurrent = config.max_concurrent.max(1);
+        Arc::new(Self {
+            semaphore: Semaphore::new(max_concurrent),
+            max_concurrent,
+            max_queued: config.max_queued,
+            in_flight: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            shed: AtomicU64::new(0),
+        })
+    }
+
+    pub async fn run<F, T>(&self, priority: MessagePriority, request: F) -> Result<T, LlmError>
+    where
+        F: Future<Output = Result<T, LlmError>>,
+    {
+        let permit = if let Ok(permit) = self.semaphore.try_acquire() {
+            permit
+        } else {
+            if priority == MessagePriority::Low
+                && self.queued.load(Ordering::SeqCst) >= self.max_queued
+            {
+                self.shed.fetch_add(1, Ordering::SeqCst);
+                return Err(LlmError::Overloaded);
+            }
+
+            let _queued = CountGuard::new(&self.queued);
+            self.semaphore
+                .acquire()
+                .await
+                .map_err(|_| LlmError::Overloaded)?
+        };
+
+        let _in_flight = CountGuard::new(&self.in_flight);
+        let result = request.await;
+        drop(permit);
+        result
+    }
+
+    pub fn stats(&self) -> LlmStats {
+        LlmStats {
+            max_concurrent: self.max_concurrent,
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+            queued: self.queued.load(Ordering::SeqCst),
+            shed: self.shed.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[actix_web::test]
+    async fn test_requests_beyond_limit_are_serialized() {
+        let limiter = LlmLimiter::new(&LlmConfig {
+            max_concurrent: 2,
+            max_queued: 16,
+            ..Default::default()
+        });
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let active = active.clone();
+                let peak = peak.clone();
+                actix::spawn(async move {
+                    limiter
+                        .run(MessagePriority::Normal, async {
+                            let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(now, Ordering::SeqCst);
+                            actix::clock::sleep(Duration::from_millis(20)).await;
+                            active.fetch_sub(1, Ordering::SeqCst);
+                            Ok::<_, LlmError>(())
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert!(task.await.unwrap().is_ok());
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(limiter.stats().in_flight, 0);
+        assert_eq!(limiter.stats().queued, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_low_priority_is_shed_when_queue_saturated() {
+        let limiter = LlmLimiter::new(&LlmConfig {
+            max_concurrent: 1,
+            max_queued: 0,
+            ..Default::default()
+        });
+
+        let busy = limiter.clone();
+        let blocker = actix::spawn(async move {
+            busy.run(MessagePriority::Normal, async {
+                actix::clock::sleep(Duration::from_millis(50)).await;
+                Ok::<_, LlmError>(())
+            })
+            .await
+        });
+        actix::clock::sleep(Duration::from_millis(5)).await;
+
+        let result = limiter
+            .run(MessagePriority::Low, async { Ok::<_, LlmError>(()) })
+            .await;
+        assert!(matches!(result, Err(LlmError::Overloaded)));
+        assert_eq!(limiter.stats().shed, 1);
+
+        blocker.await.unwrap().unwrap();
+    }
+}
@@ -1 +1,272 @@
+mod budget;
+mod context;
+mod fallback;
+mod length;
+mod limiter;
 mod openai;
+mod reasoning;
+mod sampling;
+mod stream;
+
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self as futures_stream, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+pub use budget::{BudgetAction, BudgetConfig, BudgetStats, CostTracker, RoomSpend, DIRECT_ROOM};
+pub use context::{estimate_tokens, fit as fit_to_context, request_tokens, TRUNCATION_NOTICE};
+pub use fallback::FallbackProvider;
+pub use length::{truncate_at_sentence, LengthLimit, LengthPolicy};
+pub use limiter::{LlmLimiter, LlmStats};
+pub use reasoning::{strip_reasoning, HiddenReasoning, ReasoningDelimiters};
+pub use sampling::{IntentSampling, SamplingParams};
+pub use stream::collect_stream;
+
+const DEFAULT_STREAM_RECOVERY: &str = "哎呀，刚才信号不太好没说完，我们接着聊吧～";
+
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    /// Maximum number of in-flight LLM requests across all sessions.
+    pub max_concurrent: usize,
+    /// Once this many requests are waiting, low-priority danmaku is shed.
+    pub max_queued: usize,
+    /// Forward partial output to clients as `llm_token` frames while generating.
+    pub stream_tokens: bool,
+    /// Said in place of a streamed response that broke off; `None` leaves
+    /// the interrupted frame as the only notice.
+    pub stream_recovery: Option<String>,
+    /// Send the assembled prompt to clients as `debug_prompt` frames. Leaks
+    /// the system prompt; never enable in production.
+    pub debug_prompts: bool,
+    /// Seconds each provider of a fallback chain may take, by position; 0 or
+    /// missing means no limit.
+    pub provider_timeout_seconds: Vec<u64>,
+    /// Context window in tokens by model name; requests are trimmed to fit.
+    /// Models without one are sent as they are.
+    pub context_tokens: HashMap<String, usize>,
+    /// Delimiters of the reasoning blocks stripped from output by model
+    /// name, `*` for any other model; `None` leaves the output as it is.
+    pub reasoning_delimiters: HashMap<String, Option<ReasoningDelimiters>>,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            max_queued: 16,
+            stream_tokens: false,
+            stream_recovery: Some(DEFAULT_STREAM_RECOVERY.to_string()),
+            debug_prompts: false,
+            provider_timeout_seconds: Vec::new(),
+            context_tokens: HashMap::new(),
+            reasoning_delimiters: HashMap::from([(
+                "*".to_string(),
+                Some(ReasoningDelimiters::default()),
+            )]),
+        }
+    }
+}
+
+impl LlmConfig {
+    pub fn context_limit(&self, model: &str) -> Option<usize> {
+        self.context_tokens.get(model).copied()
+    }
+
+    /// Applies context windows such as `gpt-4o-mini=128000,qwen-7b=8192`.
+    pub fn apply_context_tokens(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (model, tokens) = entry
+                .rsplit_once('=')
+                .ok_or_else(|| format!("expected model=tokens, got '{}'", entry))?;
+            let tokens = tokens
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid token count: {}", tokens))?;
+            self.context_tokens.insert(model.trim().to_string(), tokens);
+        }
+        Ok(())
+    }
+
+    /// Delimiters of the reasoning blocks to strip from `model`'s output.
+    pub fn reasoning_delimiters_for(&self, model: &str) -> Option<&ReasoningDelimiters> {
+        self.reasoning_delimiters
+            .get(model)
+            .or_else(|| self.reasoning_delimiters.get("*"))
+            .and_then(Option::as_ref)
+    }
+
+    /// Applies delimiters such as `deepseek-r1=<think>|</think>,*=off`.
+    pub fn apply_reasoning_delimiters(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (model, delimiters) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected model=open|close, got '{}'", entry))?;
+            let delimiters = match delimiters.trim() {
+                "off" => None,
+                delimiters => Some(delimiters.parse()?),
+            };
+            self.reasoning_delimiters
+                .insert(model.trim().to_string(), delimiters);
+        }
+        Ok(())
+    }
+
+    pub fn provider_timeouts(&self) -> Vec<Option<Duration>> {
+        self.provider_timeout_seconds
+            .iter()
+            .map(|&seconds| (seconds > 0).then(|| Duration::from_secs(seconds)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String, // "system", "user" or "assistant"
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LlmRequest {
+    pub messages: Vec<ChatMessage>,
+    /// Generation limit for providers that support one.
+    pub max_tokens: Option<u32>,
+    /// Temperature and top-p for providers that support them.
+    pub sampling: SamplingParams,
+}
+
+#[derive(Debug, Clone)]
+pub struct LlmResponse {
+    pub content: String,
+    pub model: String,
+    pub tokens_used: Option<u32>,
+    /// Set by providers that report a policy refusal, e.g. through a
+    /// content-filter finish reason.
+    pub refused: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum LlmError {
+    /// The request was shed because too many requests are already queued.
+    Overloaded,
+    /// The input waited past its max age before reaching the LLM.
+    Stale,
+    /// The provider rejected its credentials; retrying will not help.
+    Unauthorized(String),
+    /// The stream failed after part of the response was sent.
+    Interrupted(String),
+    #[allow(unused)]
+    Provider(String),
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Overloaded => write!(f, "LLM queue is saturated"),
+            LlmError::Stale => write!(f, "input is too old to answer"),
+            LlmError::Unauthorized(msg) => {
+                write!(f, "LLM provider rejected its credentials: {}", msg)
+            }
+            LlmError::Interrupted(msg) => write!(f, "LLM stream interrupted: {}", msg),
+            LlmError::Provider(msg) => write!(f, "LLM provider error: {}", msg),
+        }
+    }
+}
+
+impl LlmError {
+    /// The error for an HTTP error status from the provider. 401 and 403
+    /// mean the credentials were rejected, which retrying will not fix.
+    pub fn from_status(status: u16, body: impl Into<String>) -> Self {
+        match status {
+            401 | 403 => LlmError::Unauthorized(body.into()),
+            _ => LlmError::Provider(format!("HTTP {}: {}", status, body.into())),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+pub trait LlmProvider: Send + Sync {
+    /// Model name reported on responses.
+    fn model(&self) -> &str;
+
+    fn complete(&self, request: LlmRequest) -> BoxFuture<'static, Result<LlmResponse, LlmError>>;
+
+    /// Streams the completion as raw byte deltas, which may split multi-byte
+    /// characters. Providers without native streaming yield a single delta.
+    fn stream(&self, request: LlmRequest) -> BoxStream<'static, Result<Vec<u8>, LlmError>> {
+        let completion = self.complete(request);
+        futures_stream::once(async move { completion.await.map(|r| r.content.into_bytes()) })
+            .boxed()
+    }
+}
+
+/// Offline provider that acknowledges the latest user message.
+pub struct EchoProvider {
+    name: String,
+}
+
+impl EchoProvider {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+impl EchoProvider {
+    /// Byte size of each streamed delta; small enough to split CJK characters.
+    const STREAM_CHUNK_BYTES: usize = 8;
+
+    fn reply(&self, request: &LlmRequest) -> String {
+        let user_text = request
+            .messages
+            .iter()
+            .rev()
+            .find(|m| m.role == "user")
+            .map(|m| m.content.as_str())
+            .unwrap_or_default();
+        format!(
+            "Hello! I'm {}, and I received your message: '{}'",
+            self.name, user_text
+        )
+    }
+}
+
+impl LlmProvider for EchoProvider {
+    fn model(&self) -> &str {
+        "digital_human"
+    }
+
+    fn complete(&self, request: LlmRequest) -> BoxFuture<'static, Result<LlmResponse, LlmError>> {
+        let content = self.reply(&request);
+        let model = self.model().to_string();
+
+        Box::pin(async move {
+            Ok(LlmResponse {
+                content,
+                model,
+                tokens_used: None,
+                refused: false,
+            })
+        })
+    }
+
+    fn stream(&self, request: LlmRequest) -> BoxStream<'static, Result<Vec<u8>, LlmError>> {
+        let deltas: Vec<_> = self
+            .reply(&request)
+            .into_bytes()
+            .chunks(Self::STREAM_CHUNK_BYTES)
+            .map(|chunk| Ok(chunk.to_vec()))
+            .collect();
+        futures_stream::iter(deltas).boxed()
+    }
+}
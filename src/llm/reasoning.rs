@@ -0,0 +1,239 @@
+use crate::llm::stream::Utf8StreamDecoder;
+use crate::llm::{LlmError, LlmProvider, LlmRequest, LlmResponse};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self as futures_stream, BoxStream, StreamExt};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Marks around the chain of thought a reasoning model writes into its
+/// output, e.g. `<think>` and `</think>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReasoningDelimiters {
+    pub open: String,
+    pub close: String,
+}
+
+impl Default for ReasoningDelimiters {
+    fn default() -> Self {
+        Self {
+            open: "<think>".to_string(),
+            close: "</think>".to_string(),
+        }
+    }
+}
+
+impl FromStr for ReasoningDelimiters {
+    type Err = String;
+
+    /// `open|close`, e.g. `<think>|</think>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (open, close) = s
+            .split_once('|')
+            .ok_or_else(|| format!("expected open|close, got '{}'", s))?;
+        let (open, close) = (open.trim(), close.trim());
+        if open.is_empty() || close.is_empty() {
+            return Err(format!("empty reasoning delimiter in '{}'", s));
+        }
+        Ok(Self {
+            open: open.to_string(),
+            close: close.to_string(),
+        })
+    }
+}
+
+/// Length of the longest end of `text` that could begin `delimiter`.
+fn partial_suffix(text: &str, delimiter: &str) -> usize {
+    (1..delimiter.len())
+        .rev()
+        .filter(|&len| delimiter.is_char_boundary(len))
+        .find(|&len| text.ends_with(&delimiter[..len]))
+        .unwrap_or(0)
+}
+
+/// Removes reasoning blocks from text that arrives in pieces. A delimiter
+/// split across pieces is held back until the next piece settles it, and an
+/// unclosed block hides everything after it.
+#[derive(Debug)]
+pub struct ReasoningFilter {
+    delimiters: ReasoningDelimiters,
+    held: String,
+    in_reasoning: bool,
+    /// Whether visible text has been emitted; whitespace before it, usually
+    /// left after a leading block, is dropped.
+    started: bool,
+}
+
+impl ReasoningFilter {
+    pub fn new(delimiters: ReasoningDelimiters) -> Self {
+        Self {
+            delimiters,
+            held: String::new(),
+            in_reasoning: false,
+            started: false,
+        }
+    }
+
+    /// Appends a piece and returns the visible text it completes.
+    pub fn push(&mut self, text: &str) -> String {
+        self.held.push_str(text);
+        let mut visible = String::new();
+        loop {
+            if self.in_reasoning {
+                match self.held.find(&self.delimiters.close) {
+                    Some(end) => {
+                        self.held.drain(..end + self.delimiters.close.len());
+                        self.in_reasoning = false;
+                    }
+                    None => {
+                        let keep = partial_suffix(&self.held, &self.delimiters.close);
+                        self.held.drain(..self.held.len() - keep);
+                        break;
+                    }
+                }
+            } else {
+                match self.held.find(&self.delimiters.open) {
+                    Some(start) => {
+                        visible.push_str(&self.held[..start]);
+                        self.held.drain(..start + self.delimiters.open.len());
+                        self.in_reasoning = true;
+                    }
+                    None => {
+                        let keep = partial_suffix(&self.held, &self.delimiters.open);
+                        visible.extend(self.held.drain(..self.held.len() - keep));
+                        break;
+                    }
+                }
+            }
+        }
+        self.emit(visible)
+    }
+
+    /// Flushes held text that turned out not to start a block.
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.held);
+        if self.in_reasoning {
+            return String::new();
+        }
+        self.emit(rest)
+    }
+
+    fn emit(&mut self, visible: String) -> String {
+        if self.started {
+            return visible;
+        }
+        let visible = visible.trim_start().to_string();
+        self.started = !visible.is_empty();
+        visible
+    }
+}
+
+/// Strips reasoning blocks from `text`.
+pub fn strip_reasoning(text: &str, delimiters: &ReasoningDelimiters) -> String {
+    let mut filter = ReasoningFilter::new(delimiters.clone());
+    let mut visible = filter.push(text);
+    visible.push_str(&filter.finish());
+    visible
+}
+
+/// Forwards only the final answer of a reasoning model, from both complete
+/// and streamed output. Reasoning the provider returns in separate fields is
+/// never part of the content and needs no stripping.
+pub struct HiddenReasoning {
+    provider: Arc<dyn LlmProvider>,
+    delimiters: ReasoningDelimiters,
+}
+
+impl HiddenReasoning {
+    pub fn new(provider: Arc<dyn LlmProvider>, delimiters: ReasoningDelimiters) -> Self {
+        Self {
+            provider,
+            delimiters,
+        }
+    }
+}
+
+impl LlmProvider for HiddenReasoning {
+    fn model(&self) -> &str {
+        self.provider.model()
+    }
+
+    fn complete(&self, request: LlmRequest) -> BoxFuture<'static, Result<LlmResponse, LlmError>> {
+        let completion = self.provider.complete(request);
+        let delimiters = self.delimiters.clone();
+        Box::pin(async move {
+            let mut response = completion.await?;
+            response.content = strip_reasoning(&response.content, &delimiters);
+            Ok(response)
+        })
+    }
+
+    /// Deltas inside a block come through empty, so a fallback timeout still
+    /// sees the provider answering while it reasons.
+    fn stream(&self, request: LlmRequest) -> BoxStream<'static, Result<Vec<u8>, LlmError>> {
+        let state = (
+            self.provider.stream(request),
+            Utf8StreamDecoder::default(),
+            ReasoningFilter::new(self.delimiters.clone()),
+        );
+        futures_stream::unfold(Some(state), |state| async move {
+            let (mut deltas, mut decoder, mut filter) = state?;
+            match deltas.next().await {
+                Some(Ok(bytes)) => {
+                    let visible = filter.push(&decoder.push(&bytes));
+                    Some((Ok(visible.into_bytes()), Some((deltas, decoder, filter))))
+                }
+                Some(Err(e)) => Some((Err(e), None)),
+                None => {
+                    let mut visible = filter.push(&decoder.finish());
+                    visible.push_str(&filter.finish());
+                    Some((Ok(visible.into_bytes()), None))
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{collect_stream, ChatMessage, EchoProvider};
+
+    #[actix_web::test]
+    async fn test_reasoning_block_is_stripped_before_emission() {
+        let think = ReasoningDelimiters::default();
+        assert_eq!(
+            strip_reasoning(
+                "<think>观众在打招呼，热情回应。</think>\n\n大家好！",
+                &think
+            ),
+            "大家好！"
+        );
+        assert_eq!(strip_reasoning("答案<think>还没想完", &think), "答案");
+        assert_eq!(strip_reasoning("a < b <thin", &think), "a < b <thin");
+
+        // Echo streams 8-byte deltas, which split the delimiters
+        let provider = HiddenReasoning::new(Arc::new(EchoProvider::new("Maya")), think);
+        let request = LlmRequest {
+            messages: vec![ChatMessage::new(
+                "user",
+                "<think>The viewer wants a book; pick a short one.</think>试试《小王子》",
+            )],
+            max_tokens: None,
+            ..Default::default()
+        };
+        let expected = "Hello! I'm Maya, and I received your message: '试试《小王子》'";
+
+        let response = provider.complete(request.clone()).await.unwrap();
+        assert_eq!(response.content, expected);
+
+        let mut pieces = Vec::new();
+        let content = collect_stream(provider.stream(request), |text| {
+            pieces.push(text.to_string())
+        })
+        .await
+        .unwrap();
+        assert_eq!(content, expected);
+        assert!(pieces.iter().all(|p| !p.contains("think")));
+    }
+}
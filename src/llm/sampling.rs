@@ -0,0 +1,94 @@
+use crate::intent::Intent;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Sampling parameters for one request. Unset fields leave the provider's
+/// own default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+}
+
+impl SamplingParams {
+    pub fn is_unset(&self) -> bool {
+        self.temperature.is_none() && self.top_p.is_none()
+    }
+
+    /// Fills fields these params leave unset from `fallback`.
+    fn or(self, fallback: SamplingParams) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature.or(fallback.temperature),
+            top_p: self.top_p.or(fallback.top_p),
+        }
+    }
+}
+
+/// Sampling per intent, so factual answers stay precise while banter gets
+/// room to play. Input without an intent uses `default`.
+#[derive(Debug, Clone)]
+pub struct IntentSampling {
+    pub default: SamplingParams,
+    pub by_intent: HashMap<Intent, SamplingParams>,
+}
+
+impl Default for IntentSampling {
+    fn default() -> Self {
+        let params = |temperature, top_p| SamplingParams {
+            temperature: Some(temperature),
+            top_p: Some(top_p),
+        };
+        Self {
+            default: SamplingParams::default(),
+            by_intent: HashMap::from([
+                (Intent::Question, params(0.3, 0.9)),
+                (Intent::Command, params(0.3, 0.9)),
+                (Intent::Statement, params(0.8, 0.95)),
+                (Intent::Greeting, params(0.9, 0.95)),
+            ]),
+        }
+    }
+}
+
+impl IntentSampling {
+    pub fn params_for(&self, intent: Option<Intent>) -> SamplingParams {
+        intent
+            .and_then(|intent| self.by_intent.get(&intent))
+            .map_or(self.default, |params| params.or(self.default))
+    }
+
+    /// Applies per-intent temperatures such as `question=0.2,greeting=1.0`.
+    pub fn apply_temperature_overrides(&mut self, spec: &str) -> Result<(), String> {
+        for (intent, value) in parse_overrides(spec, 2.0)? {
+            self.by_intent.entry(intent).or_default().temperature = Some(value);
+        }
+        Ok(())
+    }
+
+    /// Applies per-intent nucleus sampling such as `question=0.8,greeting=1.0`.
+    pub fn apply_top_p_overrides(&mut self, spec: &str) -> Result<(), String> {
+        for (intent, value) in parse_overrides(spec, 1.0)? {
+            self.by_intent.entry(intent).or_default().top_p = Some(value);
+        }
+        Ok(())
+    }
+}
+
+fn parse_overrides(spec: &str, max: f32) -> Result<Vec<(Intent, f32)>, String> {
+    spec.split(',')
+        .filter(|p| !p.trim().is_empty())
+        .map(|pair| {
+            let (intent, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected intent=value, got: {}", pair))?;
+            let value: f32 = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid value: {}", value))?;
+            if !(0.0..=max).contains(&value) {
+                return Err(format!("{} is outside 0-{}", value, max));
+            }
+            Ok((intent.parse()?, value))
+        })
+        .collect()
+}
@@ -0,0 +1,119 @@
+use crate::llm::LlmError;
+use futures_util::stream::{BoxStream, StreamExt};
+
+/// Reassembles text from byte deltas that may split a multi-byte UTF-8
+/// character, so each emitted piece is valid on its own.
+#[derive(Debug, Default)]
+pub struct Utf8StreamDecoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8StreamDecoder {
+    /// Appends a delta and returns everything that decodes cleanly, holding
+    /// back an incomplete trailing sequence until the next delta completes it.
+    pub fn push(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+
+        let mut output = String::new();
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    output.push_str(text);
+                    self.pending.clear();
+                    return output;
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    // The prefix was just validated by from_utf8
+                    output.push_str(std::str::from_utf8(&self.pending[..valid]).unwrap());
+                    match e.error_len() {
+                        // Truncated sequence at the end: wait for more bytes
+                        None => {
+                            self.pending.drain(..valid);
+                            return output;
+                        }
+                        // Genuinely invalid bytes: replace them and keep going
+                        Some(len) => {
+                            output.push(char::REPLACEMENT_CHARACTER);
+                            self.pending.drain(..valid + len);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flushes whatever is still buffered once the stream has ended.
+    pub fn finish(&mut self) -> String {
+        let rest = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        rest
+    }
+}
+
+/// Drains a byte-delta stream, calling `on_text` with each non-empty piece of
+/// valid text, and returns the full decoded completion.
+pub async fn collect_stream(
+    mut deltas: BoxStream<'static, Result<Vec<u8>, LlmError>>,
+    mut on_text: impl FnMut(&str),
+) -> Result<String, LlmError> {
+    let mut decoder = Utf8StreamDecoder::default();
+    let mut content = String::new();
+
+    while let Some(delta) = deltas.next().await {
+        let text = decoder.push(&delta?);
+        if !text.is_empty() {
+            on_text(&text);
+            content.push_str(&text);
+        }
+    }
+
+    let rest = decoder.finish();
+    if !rest.is_empty() {
+        on_text(&rest);
+        content.push_str(&rest);
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+
+    #[test]
+    fn test_character_split_across_deltas() {
+        let bytes = "你好".as_bytes();
+        let mut decoder = Utf8StreamDecoder::default();
+
+        assert_eq!(decoder.push(&bytes[..1]), "");
+        assert_eq!(decoder.push(&bytes[1..4]), "你");
+        assert_eq!(decoder.push(&bytes[4..]), "好");
+        assert_eq!(decoder.finish(), "");
+
+        // Invalid bytes are replaced without stalling the stream
+        assert_eq!(decoder.push(&[b'a', 0xff, b'b']), "a\u{fffd}b");
+        assert_eq!(decoder.push(&bytes[..2]), "");
+        assert_eq!(decoder.finish(), "\u{fffd}");
+    }
+
+    #[actix_web::test]
+    async fn test_collect_stream_emits_only_valid_text() {
+        let bytes = "主播好".as_bytes().to_vec();
+        let deltas = bytes
+            .chunks(2)
+            .map(|chunk| Ok(chunk.to_vec()))
+            .collect::<Vec<_>>();
+
+        let mut pieces = Vec::new();
+        let content = collect_stream(stream::iter(deltas).boxed(), |text| {
+            pieces.push(text.to_string())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(content, "主播好");
+        assert_eq!(pieces.concat(), "主播好");
+        assert!(pieces.iter().all(|p| !p.is_empty()));
+    }
+}
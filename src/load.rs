@@ -0,0 +1,185 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Response time assumed before a session has any measured latency.
+const DEFAULT_RESPONSE_MS: u64 = 2000;
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    pub enabled: bool,
+    /// LLM requests waiting for a slot, across all sessions, that count as high load.
+    pub global_queue_high: usize,
+    /// Inputs of one session still awaiting a response that count as high load.
+    pub session_pending_high: usize,
+    /// Minimum time a session stays at a load level before it can change again.
+    pub debounce_seconds: u64,
+    /// How often load is evaluated.
+    pub interval_ms: u64,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            global_queue_high: 8,
+            session_pending_high: 3,
+            debounce_seconds: 5,
+            interval_ms: 1000,
+        }
+    }
+}
+
+impl LoadConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.max(100))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadLevel {
+    Normal,
+    High,
+}
+
+/// Queue depths seen by one session.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadSample {
+    pub session_pending: usize,
+    pub global_queued: usize,
+    pub max_concurrent: usize,
+    pub avg_response_ms: Option<u128>,
+}
+
+/// A change in a session's load level to tell the client about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadSignal {
+    pub level: LoadLevel,
+    /// Rough wait for the next response; only set under high load.
+    pub eta_ms: Option<u64>,
+}
+
+#[derive(Debug)]
+struct SessionLoad {
+    level: LoadLevel,
+    changed_at: Instant,
+}
+
+/// Tracks each session's load level and decides when it changes, holding a
+/// level for the debounce period so clients do not flicker.
+#[derive(Debug)]
+pub struct LoadMonitor {
+    config: LoadConfig,
+    sessions: HashMap<Uuid, SessionLoad>,
+}
+
+impl LoadMonitor {
+    pub fn new(config: LoadConfig) -> Self {
+        Self {
+            config,
+            sessions: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &LoadConfig {
+        &self.config
+    }
+
+    pub fn remove(&mut self, session_id: &Uuid) {
+        self.sessions.remove(session_id);
+    }
+
+    /// Returns a signal when the session's load level changes.
+    pub fn evaluate(
+        &mut self,
+        session_id: Uuid,
+        sample: LoadSample,
+        now: Instant,
+    ) -> Option<LoadSignal> {
+        let high = sample.session_pending >= self.config.session_pending_high.max(1)
+            || sample.global_queued >= self.config.global_queue_high.max(1);
+        let level = if high {
+            LoadLevel::High
+        } else {
+            LoadLevel::Normal
+        };
+
+        let debounce = Duration::from_secs(self.config.debounce_seconds);
+        match self.sessions.get(&session_id) {
+            // Sessions start out at normal load
+            None if level == LoadLevel::Normal => return None,
+            Some(current) if current.level == level => return None,
+            Some(current) if now.saturating_duration_since(current.changed_at) < debounce => {
+                return None
+            }
+            _ => {}
+        }
+        self.sessions.insert(
+            session_id,
+            SessionLoad {
+                level,
+                changed_at: now,
+            },
+        );
+
+        let eta_ms = high.then(|| {
+            let per_response = sample
+                .avg_response_ms
+                .map_or(DEFAULT_RESPONSE_MS, |ms| ms as u64);
+            let rounds_ahead = sample.global_queued / sample.max_concurrent.max(1);
+            per_response * (1 + rounds_ahead as u64)
+        });
+        Some(LoadSignal { level, eta_ms })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(session_pending: usize, global_queued: usize) -> LoadSample {
+        LoadSample {
+            session_pending,
+            global_queued,
+            max_concurrent: 4,
+            avg_response_ms: Some(1500),
+        }
+    }
+
+    #[test]
+    fn test_load_level_changes_are_debounced() {
+        let mut monitor = LoadMonitor::new(LoadConfig {
+            enabled: true,
+            debounce_seconds: 5,
+            ..Default::default()
+        });
+        let session_id = Uuid::new_v4();
+        let start = Instant::now();
+
+        assert_eq!(monitor.evaluate(session_id, sample(1, 2), start), None);
+
+        // Global backlog: 9 waiting ahead of 4 slots is two extra rounds
+        assert_eq!(
+            monitor.evaluate(session_id, sample(1, 9), start),
+            Some(LoadSignal {
+                level: LoadLevel::High,
+                eta_ms: Some(4500),
+            })
+        );
+        assert_eq!(monitor.evaluate(session_id, sample(3, 0), start), None);
+
+        // Recovered, but held at high until the debounce period passes
+        let soon = start + Duration::from_secs(2);
+        assert_eq!(monitor.evaluate(session_id, sample(0, 0), soon), None);
+        let later = start + Duration::from_secs(5);
+        assert_eq!(
+            monitor.evaluate(session_id, sample(0, 0), later),
+            Some(LoadSignal {
+                level: LoadLevel::Normal,
+                eta_ms: None,
+            })
+        );
+    }
+}
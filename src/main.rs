@@ -1,64 +1,65 @@
-use actix::prelude::*;
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web, App, HttpServer};
 use env_logger::Env;
 use eyre::Result;
+use std::io::Write;
 
-mod actor;
-mod event_bus;
-mod events;
-mod llm;
-mod platform;
-mod routes;
-mod validator;
-mod websocket;
-
-use actor::DigitalHumanActor;
-use event_bus::{EventBus, RegisterDigitalHuman, RegisterWebSocketManager};
-use websocket::WebSocketManager;
-
-use platform::LiveStreamManager;
+use live_streamer::config::AppConfig;
+use live_streamer::websocket::{CloseAllSessions, OutboundQueue, SendRetries, SessionCloseReason};
+use live_streamer::{platform, redact, routes, timezone, DigitalHumanService, ServiceHandles};
 
 #[actix_web::main]
 async fn main() -> Result<()> {
     // Initialize logging
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    dotenvy::dotenv().ok();
+    env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {}",
+                timezone::now().format("%Y-%m-%dT%H:%M:%S%:z"),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        })
+        .init();
+
+    let config = AppConfig::from_env()?;
+    redact::init(config.redaction.clone());
+    timezone::init(config.display_timezone);
+    platform::init_field_mappings(config.field_mappings.clone());
 
     log::info!("Starting Digital Human Service...");
 
-    // Create and start the event bus
-    let event_bus = EventBus::new().start();
-    log::info!("EventBus started");
-
-    // Create and start the WebSocket manager
-    let ws_manager = WebSocketManager::new(event_bus.clone()).start();
-    log::info!("WebSocketManager started");
-
-    // Create and start the LiveStream manager
-    let live_manager = LiveStreamManager::new(event_bus.clone()).start();
-    log::info!("LiveStreamManager started");
-
-    // Create and start digital human actors
-    let digital_human = DigitalHumanActor::new(
-        "Maya".to_string(),
-        "I am a helpful and friendly digital assistant with a warm personality. I enjoy helping users with their questions and providing engaging conversation.".to_string(),
-        event_bus.clone()
-    ).start();
-    log::info!("DigitalHumanActor 'Maya' started");
-
-    // Register actors with EventBus
-    event_bus.do_send(RegisterDigitalHuman {
-        addr: digital_human.clone(),
-    });
-
-    event_bus.do_send(RegisterWebSocketManager {
-        addr: ws_manager.clone(),
-    });
-
-    log::info!("Actors registered with EventBus");
+    let auth = config.auth.clone();
+    if auth.enabled() {
+        log::info!("WebSocket token authentication enabled");
+    }
+    let message_limits = config.message_limits.clone();
+    let send_retries = SendRetries::new(config.send_retry.clone());
+    let outbound_queue = OutboundQueue::new(config.outbound_queue.clone());
+
+    // Fail fast on rejected provider credentials
+    let service = DigitalHumanService::new(config);
+    service.preflight().await?;
+
+    let ServiceHandles {
+        event_bus,
+        digital_human,
+        ws_manager,
+        live_manager,
+        preflight,
+        channels,
+        ..
+    } = service.start();
+    log::info!("Digital human service started");
+
+    // Close WebSocket sessions cleanly before the server stops
+    let shutdown_ws_manager = ws_manager.clone();
 
     // Start HTTP server
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -70,17 +71,63 @@ async fn main() -> Result<()> {
             .app_data(web::Data::new(event_bus.clone()))
             .app_data(web::Data::new(digital_human.clone()))
             .app_data(web::Data::new(live_manager.clone()))
+            .app_data(web::Data::new(preflight.clone()))
+            .app_data(web::Data::new(channels.clone()))
+            .app_data(web::Data::new(auth.clone()))
+            .app_data(web::Data::new(message_limits.clone()))
+            .app_data(web::Data::new(send_retries.clone()))
+            .app_data(web::Data::new(outbound_queue.clone()))
             .wrap(cors)
-            .wrap(Logger::default())
+            .wrap(access_log())
             .configure(routes::configure_routes)
     })
     .bind("0.0.0.0:8080")?
-    .run()
-    .await?;
+    .disable_signals()
+    .run();
+
+    let server_handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+        log::info!("Shutting down, closing WebSocket sessions");
+        let _ = shutdown_ws_manager
+            .send(CloseAllSessions {
+                reason: SessionCloseReason::Shutdown,
+            })
+            .await;
+        server_handle.stop(true).await;
+    });
+
+    server.await?;
 
     Ok(())
 }
 
+/// Resolves on Ctrl-C or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            let ctrl_c = std::pin::pin!(actix_web::rt::signal::ctrl_c());
+            let terminate = std::pin::pin!(terminate.recv());
+            futures_util::future::select(ctrl_c, terminate).await;
+            return;
+        }
+    }
+
+    let _ = actix_web::rt::signal::ctrl_c().await;
+}
+
+/// The default access log without query strings, which carry WebSocket
+/// `token`s and `resume_token`s.
+fn access_log() -> Logger {
+    Logger::new(r#"%a "%{request_line}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+        .custom_request_replace("request_line", |req| {
+            format!("{} {} {:?}", req.method(), req.path(), req.version())
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
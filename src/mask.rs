@@ -0,0 +1,130 @@
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// How blocklisted words in responses are masked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaskStyle {
+    /// One `*` per masked character, so the text keeps its shape.
+    PreserveLength,
+    /// Every masked word becomes the same string.
+    Fixed(String),
+}
+
+impl FromStr for MaskStyle {
+    type Err = String;
+
+    /// Parses `length` or `fixed:<mask>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.split_once(':') {
+            None if s.eq_ignore_ascii_case("length") => Ok(MaskStyle::PreserveLength),
+            Some((kind, mask)) if kind.eq_ignore_ascii_case("fixed") && !mask.is_empty() => {
+                Ok(MaskStyle::Fixed(mask.to_string()))
+            }
+            _ => Err(format!("invalid mask style: {}", s)),
+        }
+    }
+}
+
+impl MaskStyle {
+    fn mask(&self, word: &str) -> String {
+        match self {
+            MaskStyle::PreserveLength => "*".repeat(word.chars().count()),
+            MaskStyle::Fixed(mask) => mask.clone(),
+        }
+    }
+}
+
+/// Masks every occurrence of `words` in `text`, ignoring ASCII case, and
+/// returns the result with the number of masked occurrences.
+pub fn mask_words(text: &str, words: &[String], style: &MaskStyle) -> (String, usize) {
+    // ASCII lowercasing keeps byte offsets valid in the original text
+    let lowered = text.to_ascii_lowercase();
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for word in words.iter().filter(|w| !w.is_empty()) {
+        let word = word.to_ascii_lowercase();
+        spans.extend(
+            lowered
+                .match_indices(&word)
+                .map(|(start, matched)| (start, start + matched.len())),
+        );
+    }
+    if spans.is_empty() {
+        return (text.to_string(), 0);
+    }
+
+    // Longest match first where words overlap
+    spans.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    let mut masked = String::with_capacity(text.len());
+    let mut cursor = 0;
+    let mut count = 0;
+    for (start, end) in spans {
+        if start < cursor {
+            continue;
+        }
+        masked.push_str(&text[cursor..start]);
+        masked.push_str(&style.mask(&text[start..end]));
+        cursor = end;
+        count += 1;
+    }
+    masked.push_str(&text[cursor..]);
+    (masked, count)
+}
+
+/// A mask style with the words it currently masks, shared between the
+/// EventBus, which keeps the words in step with the blacklist rules, and the
+/// digital human, which masks responses before they are stored or spoken.
+#[derive(Debug, Clone)]
+pub struct ResponseMask {
+    style: MaskStyle,
+    words: Arc<RwLock<Vec<String>>>,
+}
+
+impl ResponseMask {
+    pub fn new(style: MaskStyle) -> Self {
+        Self {
+            style,
+            words: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    pub fn set_words(&self, words: Vec<String>) {
+        *self.words.write().unwrap_or_else(|e| e.into_inner()) = words;
+    }
+
+    /// Masks `text` in place and returns the number of masked occurrences.
+    pub fn apply(&self, text: &mut String) -> usize {
+        let words = self.words.read().unwrap_or_else(|e| e.into_inner());
+        let (masked, count) = mask_words(text, &words, &self.style);
+        if count > 0 {
+            *text = masked;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flagged_word_is_masked_in_place() {
+        let words = vec!["骗子".to_string(), "scam".to_string()];
+
+        let (masked, count) = mask_words(
+            "他不是骗子，这也不是SCAM哦",
+            &words,
+            &MaskStyle::PreserveLength,
+        );
+        assert_eq!(masked, "他不是**，这也不是****哦");
+        assert_eq!(count, 2);
+
+        let style: MaskStyle = "fixed:[哔]".parse().unwrap();
+        let (masked, _) = mask_words("别信骗子的话", &words, &style);
+        assert_eq!(masked, "别信[哔]的话");
+
+        let (clean, count) = mask_words("今天天气不错", &words, &MaskStyle::PreserveLength);
+        assert_eq!((clean.as_str(), count), ("今天天气不错", 0));
+        assert!("stars".parse::<MaskStyle>().is_err());
+    }
+}
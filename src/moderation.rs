@@ -0,0 +1,132 @@
+use crate::events::TextInputEvent;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// A chat command a moderator can issue, e.g. `!pause`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModeratorCommand {
+    /// Stops the digital human answering; input is queued meanwhile.
+    Pause,
+    Resume,
+    /// Ignores a user's messages, for `minutes` or until unmuted.
+    Mute {
+        user_id: String,
+        minutes: Option<u64>,
+    },
+    Unmute {
+        user_id: String,
+    },
+}
+
+/// Parses a message starting with `!`. None for ordinary text; an error
+/// says why a command was not understood.
+pub fn parse_command(text: &str) -> Option<Result<ModeratorCommand, String>> {
+    let mut words = text.trim().strip_prefix('!')?.split_whitespace();
+    let name = words.next()?.to_lowercase();
+    let command = match name.as_str() {
+        "pause" => Ok(ModeratorCommand::Pause),
+        "resume" => Ok(ModeratorCommand::Resume),
+        "mute" => match (words.next(), words.next()) {
+            (None, _) => Err("usage: !mute <user_id> [minutes]".to_string()),
+            (Some(user_id), minutes) => match minutes.map(str::parse).transpose() {
+                Ok(minutes) => Ok(ModeratorCommand::Mute {
+                    user_id: user_id.to_string(),
+                    minutes,
+                }),
+                Err(_) => Err(format!("invalid minutes: {}", minutes.unwrap_or_default())),
+            },
+        },
+        "unmute" => words
+            .next()
+            .map(|user_id| ModeratorCommand::Unmute {
+                user_id: user_id.to_string(),
+            })
+            .ok_or_else(|| "usage: !unmute <user_id>".to_string()),
+        other => Err(format!("unknown command: !{}", other)),
+    };
+    Some(command)
+}
+
+/// Confirmation of a moderator command, sent as a `command_ack` frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandAck {
+    pub user_id: String,
+    pub command: String,
+    pub accepted: bool,
+    /// What was done, or why the command was rejected.
+    pub detail: String,
+}
+
+/// Where command acknowledgements go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckChannel {
+    /// The WebSocket session the command came from, if it is connected.
+    Session,
+    /// Every operator dashboard on `/api/v1/ws/monitor`.
+    Monitor,
+    Both,
+}
+
+impl AckChannel {
+    pub fn to_session(self) -> bool {
+        matches!(self, AckChannel::Session | AckChannel::Both)
+    }
+
+    pub fn to_monitors(self) -> bool {
+        matches!(self, AckChannel::Monitor | AckChannel::Both)
+    }
+}
+
+impl FromStr for AckChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "session" => Ok(AckChannel::Session),
+            "monitor" => Ok(AckChannel::Monitor),
+            "both" => Ok(AckChannel::Both),
+            other => Err(format!("unknown acknowledgement channel: {}", other)),
+        }
+    }
+}
+
+/// Users whose `!` messages are run as commands instead of answered.
+#[derive(Debug, Clone)]
+pub struct ModerationConfig {
+    pub moderators: HashSet<String>,
+    pub ack_channel: AckChannel,
+    /// Whether WebSocket sessions authenticate with a token; set from the
+    /// auth config when the service starts.
+    pub sessions_authenticated: bool,
+    /// Runs commands even without authentication, trusting the user id a
+    /// client claims. Anyone who knows a moderator's id can then use it.
+    pub allow_unauthenticated: bool,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            moderators: HashSet::new(),
+            ack_channel: AckChannel::Both,
+            sessions_authenticated: false,
+            allow_unauthenticated: false,
+        }
+    }
+}
+
+impl ModerationConfig {
+    pub fn is_moderator(&self, user_id: &str) -> bool {
+        self.moderators.contains(user_id)
+    }
+
+    /// Whether the sender of `event` is who it claims to be: a viewer
+    /// reported by a platform listener, or a session holding an admin token.
+    /// Webhook danmaku are not trusted; anyone can post them.
+    pub fn trusts_source(&self, event: &TextInputEvent) -> bool {
+        if !self.sessions_authenticated {
+            return self.allow_unauthenticated;
+        }
+        event.viewer.as_ref().is_some_and(|viewer| viewer.trusted) || event.operator
+    }
+}
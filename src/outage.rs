@@ -0,0 +1,179 @@
+use crate::templates::{ResponseTemplate, ResponseTemplates, TriggerMatch};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How the digital human keeps answering while the LLM is down.
+#[derive(Debug, Clone)]
+pub struct OutageConfig {
+    /// Consecutive LLM failures that open the circuit; 0 never opens it.
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a request is let through to
+    /// see whether the provider is back.
+    pub open_seconds: u64,
+    /// Canned replies while the circuit is open; the defaults greet viewers
+    /// and answer a few common questions.
+    pub templates: ResponseTemplates,
+    /// Said to anything no template matches; those messages go unanswered
+    /// when unset.
+    pub fallback_reply: Option<String>,
+}
+
+impl Default for OutageConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_seconds: 30,
+            templates: default_templates(),
+            fallback_reply: None,
+        }
+    }
+}
+
+impl OutageConfig {
+    /// The canned reply to `text`, rendered with `vars`.
+    pub fn reply(&self, text: &str, vars: &HashMap<&str, String>) -> Option<String> {
+        match self.templates.find(text) {
+            Some(template) => Some(template.render(vars)),
+            None => self
+                .fallback_reply
+                .as_ref()
+                .map(|reply| reply.replace("{name}", vars.get("name").map_or("", |n| n))),
+        }
+    }
+}
+
+fn default_templates() -> ResponseTemplates {
+    let template = |trigger: &str, match_type, response: &str| ResponseTemplate {
+        trigger: trigger.to_string(),
+        match_type,
+        response: response.to_string(),
+    };
+    ResponseTemplates::new(vec![
+        template(
+            "你好",
+            TriggerMatch::Contains,
+            "你好呀{username}，欢迎来到直播间～",
+        ),
+        template(
+            "主播好",
+            TriggerMatch::Contains,
+            "{username}好呀，欢迎欢迎～",
+        ),
+        template(
+            "大家好",
+            TriggerMatch::Contains,
+            "大家好呀，欢迎来到直播间～",
+        ),
+        template("晚上好", TriggerMatch::Contains, "晚上好{username}！"),
+        template("在吗", TriggerMatch::Contains, "在的在的～"),
+        template(
+            "叫什么",
+            TriggerMatch::Contains,
+            "我是{name}，很高兴认识你！",
+        ),
+        template(
+            "hello",
+            TriggerMatch::Prefix,
+            "Hello {username}, welcome to the stream!",
+        ),
+        template("hi", TriggerMatch::Exact, "Hi {username}, welcome!"),
+    ])
+}
+
+/// Stops calling the LLM after repeated failures, so viewers get canned
+/// replies at once instead of waiting on a provider that is down.
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    failures: u32,
+    /// When the circuit opened and how long it stays open.
+    open: Option<(Instant, Duration)>,
+    /// A request is out to see whether the provider is back.
+    probing: bool,
+}
+
+impl CircuitBreaker {
+    /// Whether a request may go to the LLM. Once the open period is over,
+    /// exactly one request is let through as a probe until it is recorded.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let Some((opened_at, open_for)) = self.open else {
+            return true;
+        };
+        if self.probing || now.saturating_duration_since(opened_at) < open_for {
+            return false;
+        }
+        self.probing = true;
+        true
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+        self.open = None;
+        self.probing = false;
+    }
+
+    /// Records a request that never reached the provider, so that a probe
+    /// is handed to the next request instead.
+    pub fn record_skipped(&mut self) {
+        self.probing = false;
+    }
+
+    /// Counts a failure and returns whether it opened the circuit. A failed
+    /// probe after the open period opens it again straight away.
+    pub fn record_failure(&mut self, config: &OutageConfig, now: Instant) -> bool {
+        self.probing = false;
+        self.failures += 1;
+        if config.failure_threshold == 0 || self.failures < config.failure_threshold {
+            return false;
+        }
+        self.open = Some((now, Duration::from_secs(config.open_seconds)));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_one_probe_while_half_open() {
+        let config = OutageConfig {
+            failure_threshold: 1,
+            open_seconds: 30,
+            ..Default::default()
+        };
+        let mut circuit = CircuitBreaker::default();
+        let start = Instant::now();
+        assert!(circuit.try_acquire(start));
+        assert!(circuit.record_failure(&config, start));
+        assert!(!circuit.try_acquire(start + Duration::from_secs(10)));
+
+        // One probe after the open period, none beside it
+        let later = start + Duration::from_secs(31);
+        assert!(circuit.try_acquire(later));
+        assert!(!circuit.try_acquire(later));
+
+        // A failed probe opens it again
+        assert!(circuit.record_failure(&config, later));
+        assert!(!circuit.try_acquire(later + Duration::from_secs(1)));
+
+        // A successful probe closes it
+        let again = later + Duration::from_secs(31);
+        assert!(circuit.try_acquire(again));
+        circuit.record_success();
+        assert!(circuit.try_acquire(again));
+        assert!(circuit.try_acquire(again));
+    }
+
+    #[test]
+    fn test_long_open_period_does_not_overflow() {
+        let config = OutageConfig {
+            failure_threshold: 1,
+            open_seconds: u64::MAX,
+            ..Default::default()
+        };
+        let mut circuit = CircuitBreaker::default();
+        let now = Instant::now();
+        assert!(circuit.record_failure(&config, now));
+        assert!(!circuit.try_acquire(now + Duration::from_secs(3600)));
+    }
+}
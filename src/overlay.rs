@@ -0,0 +1,117 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Where responses to danmaku are delivered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DanmakuDelivery {
+    /// To the danmaku's own session, which has no WebSocket connection, so
+    /// only event bus subscribers see the response.
+    #[default]
+    Session,
+    /// To every overlay client subscribed to the danmaku's room.
+    Room,
+}
+
+impl FromStr for DanmakuDelivery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "session" => Ok(DanmakuDelivery::Session),
+            "room" => Ok(DanmakuDelivery::Room),
+            other => Err(format!("unknown danmaku delivery: {}", other)),
+        }
+    }
+}
+
+/// How long a danmaku session keeps routing to its room; long enough for the
+/// response, its speech and a retraction.
+const ROUTE_TTL: Duration = Duration::from_secs(300);
+
+/// Overlay clients subscribed to each room, and the room each danmaku
+/// session's responses go to.
+#[derive(Debug, Default)]
+pub struct RoomOverlays {
+    subscribers: HashMap<String, HashSet<Uuid>>,
+    /// Each session's room and when it was last routed there.
+    routes: HashMap<Uuid, (String, Instant)>,
+    /// Routes oldest first, for expiry; a session routed again is queued again.
+    routed_at: VecDeque<(Instant, Uuid)>,
+}
+
+impl RoomOverlays {
+    pub fn subscribe(&mut self, room_id: &str, session_id: Uuid) {
+        self.subscribers
+            .entry(room_id.to_string())
+            .or_default()
+            .insert(session_id);
+    }
+
+    /// Unsubscribes an overlay from every room.
+    pub fn unsubscribe(&mut self, session_id: &Uuid) {
+        self.subscribers.retain(|_, sessions| {
+            sessions.remove(session_id);
+            !sessions.is_empty()
+        });
+    }
+
+    /// Sends responses for a danmaku session to `room_id`'s overlays.
+    pub fn route(&mut self, session_id: Uuid, room_id: String, now: Instant) {
+        while let Some(&(at, expired)) = self.routed_at.front() {
+            if now.saturating_duration_since(at) < ROUTE_TTL {
+                break;
+            }
+            // A session routed again since then stays until its latest route expires
+            if self
+                .routes
+                .get(&expired)
+                .is_some_and(|(_, routed)| *routed == at)
+            {
+                self.routes.remove(&expired);
+            }
+            self.routed_at.pop_front();
+        }
+        self.routes.insert(session_id, (room_id, now));
+        self.routed_at.push_back((now, session_id));
+    }
+
+    /// Overlays receiving responses meant for `session_id`, if it is routed
+    /// to a room that has any.
+    pub fn overlays_for(&self, session_id: &Uuid) -> Option<&HashSet<Uuid>> {
+        let (room_id, _) = self.routes.get(session_id)?;
+        self.subscribers.get(room_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing_again_keeps_the_route_past_the_first_ttl() {
+        let mut overlays = RoomOverlays::default();
+        let overlay = Uuid::new_v4();
+        overlays.subscribe("1001", overlay);
+        let viewer = Uuid::new_v4();
+        let start = Instant::now();
+
+        overlays.route(viewer, "1001".to_string(), start);
+        overlays.route(viewer, "1001".to_string(), start + Duration::from_secs(299));
+        // Another session's route sweeps the queue
+        overlays.route(
+            Uuid::new_v4(),
+            "1001".to_string(),
+            start + Duration::from_secs(301),
+        );
+        assert!(overlays.overlays_for(&viewer).unwrap().contains(&overlay));
+
+        overlays.route(
+            Uuid::new_v4(),
+            "1001".to_string(),
+            start + Duration::from_secs(600),
+        );
+        assert!(overlays.overlays_for(&viewer).is_none());
+    }
+}
@@ -1,31 +1,57 @@
-use crate::platform::LiveStreamConfig;
-use crate::platform::PlatformListener;
+use crate::platform::mapping::field_mapping;
+use crate::platform::{
+    DanmakuMessage, LiveStreamConfig, Platform, PlatformListener, ProcessDanmaku,
+};
+use actix::prelude::*;
 use log::info;
 
+/// One connection for every room in the config; danmaku are routed to their
+/// room by the `roomid` each payload carries.
 pub struct BilibiliListener {
     config: LiveStreamConfig,
+    sink: Recipient<ProcessDanmaku>,
     running: bool,
 }
 
 impl BilibiliListener {
-    pub fn new(config: LiveStreamConfig) -> Self {
+    pub fn new(config: LiveStreamConfig, sink: Recipient<ProcessDanmaku>) -> Self {
         Self {
             config,
+            sink,
             running: false,
         }
     }
+
+    /// Parses a payload from the connection and forwards it for its room.
+    /// Payloads without a room belong to the only room, if there is one;
+    /// rooms this listener did not subscribe to are rejected.
+    pub fn dispatch(&self, payload: &serde_json::Value) -> Result<(), String> {
+        let mut danmaku = parse_bilibili_danmaku(payload)?;
+        danmaku.trusted = true;
+        let rooms = self.config.rooms();
+        if danmaku.room_id == "unknown" && rooms.len() == 1 {
+            danmaku.room_id = rooms[0].to_string();
+        }
+        if !rooms.contains(&danmaku.room_id.as_str()) {
+            return Err(format!("room {} is not subscribed", danmaku.room_id));
+        }
+        self.sink.do_send(ProcessDanmaku { danmaku });
+        Ok(())
+    }
 }
 
 impl PlatformListener for BilibiliListener {
     fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         info!(
-            "Starting Bilibili listener for room: {}",
-            self.config.room_id
+            "Starting Bilibili listener for rooms: {}",
+            self.config.rooms().join(", ")
         );
         self.running = true;
 
         // TODO: 实现B站弹幕监听
         // 可以使用bilibili-live-danmaku crate或WebSocket连接
+        // 连接后用 Heartbeat 每30秒发送心跳包，收到人气值回包时 ack
+        // 收到的弹幕交给 dispatch 按直播间分发
 
         Ok(())
     }
@@ -39,3 +65,52 @@ impl PlatformListener for BilibiliListener {
         self.running
     }
 }
+
+pub fn parse_bilibili_danmaku(data: &serde_json::Value) -> Result<DanmakuMessage, String> {
+    field_mapping(&Platform::Bilibili).parse(Platform::Bilibili, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Collect;
+
+    fn danmaku(room_id: u64, message: &str) -> serde_json::Value {
+        serde_json::json!({
+            "cmd": "DANMU_MSG",
+            "roomid": room_id,
+            "info": [[], message, [42, "viewer"]],
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_one_listener_demuxes_danmaku_from_two_rooms() {
+        let config = LiveStreamConfig {
+            platform: Platform::Bilibili,
+            room_id: "1001".to_string(),
+            room_ids: vec!["1002".to_string()],
+            api_key: None,
+            webhook_url: None,
+            replay_path: None,
+            enabled: true,
+            sampling: None,
+            quota: None,
+            max_age_seconds: None,
+            respond: true,
+        };
+        assert_eq!(config.config_id(), "Bilibili_1001+1002");
+
+        let rooms = Collect::<ProcessDanmaku>::default().start();
+        let listener = BilibiliListener::new(config, rooms.clone().recipient());
+        listener.dispatch(&danmaku(1002, "晚上好")).unwrap();
+        listener.dispatch(&danmaku(1001, "主播好")).unwrap();
+        assert!(listener.dispatch(&danmaku(1003, "串台了")).is_err());
+
+        let received: Vec<String> = Collect::received(&rooms)
+            .await
+            .into_iter()
+            .map(|msg| msg.danmaku.room_id)
+            .collect();
+        assert_eq!(received, vec!["1002", "1001"]);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::events::TextInputEvent;
+use crate::similarity::{bigrams, dice, fold, Bigrams};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// 刷屏常见的反应，短弹幕含其中任一说法即视为同一反应
+const REACTIONS: &[(&str, &[&str])] = &[
+    (
+        "laugh",
+        &[
+            "哈哈",
+            "hhh",
+            "笑死",
+            "好笑",
+            "xswl",
+            "lol",
+            "lmao",
+            "funny",
+            "hilarious",
+        ],
+    ),
+    (
+        "praise",
+        &["666", "太牛", "厉害", "awesome", "amazing", "nice"],
+    ),
+    ("love", &["可爱", "爱了", "awsl", "cute", "love"]),
+];
+
+/// Normalized danmaku no longer than this may be read as a reaction.
+const REACTION_MAX_CHARS: usize = 20;
+
+/// Collapses near-identical danmaku into one input. Off unless
+/// `window_seconds` is set.
+#[derive(Debug, Clone)]
+pub struct DedupConfig {
+    /// Seconds the first of a group is held while similar danmaku join it.
+    pub window_seconds: Option<u64>,
+    /// Bigram overlap (Dice coefficient, 0-1) at which two danmaku count as one.
+    pub similarity: f64,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            window_seconds: None,
+            similarity: 0.7,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Held {
+    id: u64,
+    bigrams: Bigrams,
+    event: TextInputEvent,
+    similar: u32,
+}
+
+/// Holds danmaku per room for a window, counting the similar ones that
+/// arrive meanwhile instead of answering each.
+#[derive(Debug, Default)]
+pub struct DanmakuDedup {
+    config: DedupConfig,
+    rooms: HashMap<String, Vec<Held>>,
+    next_id: u64,
+}
+
+impl DanmakuDedup {
+    pub fn new(config: DedupConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn window(&self) -> Option<Duration> {
+        self.config.window_seconds.map(Duration::from_secs)
+    }
+
+    /// Counts `event` against a similar danmaku held for its room, or holds
+    /// it; returns the id to `release` it by after the window when held.
+    pub fn offer(&mut self, event: TextInputEvent) -> Option<u64> {
+        let room_id = event
+            .viewer
+            .as_ref()
+            .map(|viewer| viewer.room_id.clone())
+            .unwrap_or_default();
+        let grams = bigrams(&normalize(&event.text));
+        let held = self.rooms.entry(room_id).or_default();
+        let best = held
+            .iter_mut()
+            .map(|h| (dice(&grams, &h.bigrams), h))
+            .filter(|(score, _)| *score >= self.config.similarity)
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((_, group)) = best {
+            group.similar += 1;
+            return None;
+        }
+
+        self.next_id += 1;
+        held.push(Held {
+            id: self.next_id,
+            bigrams: grams,
+            event,
+            similar: 0,
+        });
+        Some(self.next_id)
+    }
+
+    /// The held danmaku, annotated with how many similar ones arrived.
+    pub fn release(&mut self, id: u64) -> Option<TextInputEvent> {
+        let (room_id, index) = self.rooms.iter().find_map(|(room_id, held)| {
+            let index = held.iter().position(|h| h.id == id)?;
+            Some((room_id.clone(), index))
+        })?;
+        let held = self.rooms.get_mut(&room_id)?;
+        let Held {
+            mut event, similar, ..
+        } = held.remove(index);
+        if held.is_empty() {
+            self.rooms.remove(&room_id);
+        }
+        if let Some(viewer) = &mut event.viewer {
+            viewer.similar_count = similar;
+        }
+        Some(event)
+    }
+}
+
+/// 去掉标点和空白并统一大小写；刷屏反应归为同一说法
+fn normalize(text: &str) -> String {
+    let text = fold(text);
+    if text.chars().count() <= REACTION_MAX_CHARS {
+        if let Some((reaction, _)) = REACTIONS
+            .iter()
+            .find(|(_, words)| words.iter().any(|word| text.contains(word)))
+        {
+            return reaction.to_string();
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventMetadata, MessagePriority, ViewerInfo};
+
+    fn danmaku(room_id: &str, text: &str) -> TextInputEvent {
+        TextInputEvent {
+            metadata: EventMetadata::default(),
+            text: text.to_string(),
+            language: None,
+            username: None,
+            room_mood: None,
+            priority: MessagePriority::Low,
+            intent: None,
+            viewer: Some(ViewerInfo {
+                room_id: room_id.to_string(),
+                user_level: None,
+                is_vip: false,
+                gift_value: None,
+                similar_count: 0,
+                trusted: false,
+            }),
+            max_age_seconds: None,
+            operator: false,
+        }
+    }
+
+    #[test]
+    fn test_paraphrases_collapse_into_one_annotated_input() {
+        let mut dedup = DanmakuDedup::new(DedupConfig {
+            window_seconds: Some(3),
+            ..DedupConfig::default()
+        });
+
+        let held = dedup.offer(danmaku("1001", "哈哈哈哈哈")).unwrap();
+        for text in ["so funny", "This is hilarious!!", "笑死我了", "hhhhhh"] {
+            assert_eq!(dedup.offer(danmaku("1001", text)), None);
+        }
+        // A different message, and the same one in another room, are held apart
+        let question = dedup.offer(danmaku("1001", "主播今天播到几点？")).unwrap();
+        let other_room = dedup.offer(danmaku("2002", "lol")).unwrap();
+
+        let event = dedup.release(held).unwrap();
+        assert_eq!(event.text, "哈哈哈哈哈");
+        assert_eq!(event.viewer.unwrap().similar_count, 4);
+        assert_eq!(
+            dedup
+                .release(question)
+                .unwrap()
+                .viewer
+                .unwrap()
+                .similar_count,
+            0
+        );
+        assert_eq!(
+            dedup
+                .release(other_room)
+                .unwrap()
+                .viewer
+                .unwrap()
+                .similar_count,
+            0
+        );
+        assert!(dedup.release(held).is_none());
+
+        // Released groups no longer absorb new danmaku
+        assert!(dedup.offer(danmaku("1001", "好好笑")).is_some());
+    }
+}
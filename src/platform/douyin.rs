@@ -1,17 +1,218 @@
-use crate::platform::LiveStreamConfig;
-use crate::platform::PlatformListener;
-use log::info;
+use crate::platform::mapping::field_mapping;
+use crate::platform::{
+    ConnectionHealth, DanmakuMessage, ListenerHealth, LiveStreamConfig, Platform, PlatformListener,
+    ProcessDanmaku,
+};
+use actix::prelude::*;
+use futures_util::future::BoxFuture;
+use log::{info, warn};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Longest wait for a connection to the polled bridge.
+const POLL_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest a poll may take in all, so a hung bridge counts as failing.
+const POLL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Where Douyin danmaku comes from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DouyinSourceKind {
+    /// `WebhookBridgeSource`: a bridge POSTs to the HTTP callback.
+    #[default]
+    Webhook,
+    /// `HttpPollSource` polling a bridge at this URL.
+    Poll(String),
+}
+
+impl FromStr for DouyinSourceKind {
+    type Err = String;
+
+    /// Parses `webhook` or `poll:<url>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.split_once(':') {
+            None if s.eq_ignore_ascii_case("webhook") => Ok(DouyinSourceKind::Webhook),
+            Some((kind, url)) if kind.eq_ignore_ascii_case("poll") && !url.is_empty() => {
+                Ok(DouyinSourceKind::Poll(url.to_string()))
+            }
+            _ => Err(format!("invalid Douyin source: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DouyinConfig {
+    pub source: DouyinSourceKind,
+    /// Wait before fetching again after an empty batch.
+    pub poll_interval_ms: u64,
+    /// Wait before retrying a failed source, doubled for each failure after.
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for DouyinConfig {
+    fn default() -> Self {
+        Self {
+            source: DouyinSourceKind::Webhook,
+            poll_interval_ms: 1000,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl DouyinConfig {
+    pub fn build_source(&self) -> Arc<dyn DanmakuSource> {
+        match &self.source {
+            DouyinSourceKind::Webhook => Arc::new(WebhookBridgeSource),
+            DouyinSourceKind::Poll(url) => Arc::new(HttpPollSource::new(url)),
+        }
+    }
+}
+
+/// 抖音没有公开的弹幕API，拉取方式由使用者提供（爬虫、第三方桥接等）
+pub trait DanmakuSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Passive sources never produce danmaku themselves; messages arrive through
+    /// the `/api/v1/danmaku/douyin` HTTP callback instead.
+    fn is_passive(&self) -> bool {
+        false
+    }
+
+    /// Waits for the next batch of raw danmaku payloads for `room_id`.
+    fn fetch(&self, room_id: &str) -> BoxFuture<'static, Result<Vec<serde_json::Value>, String>>;
+}
+
+/// Default source: relies on a bridge POSTing to the existing HTTP callback.
+pub struct WebhookBridgeSource;
+
+impl DanmakuSource for WebhookBridgeSource {
+    fn name(&self) -> &'static str {
+        "webhook_bridge"
+    }
+
+    fn is_passive(&self) -> bool {
+        true
+    }
+
+    fn fetch(&self, _room_id: &str) -> BoxFuture<'static, Result<Vec<serde_json::Value>, String>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+}
+
+/// Polls a bridge that answers `GET <url>?room_id=<id>` with a JSON array
+/// of the danmaku payloads received since the last poll.
+pub struct HttpPollSource {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpPollSource {
+    pub fn new(url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(POLL_CONNECT_TIMEOUT)
+            .timeout(POLL_TIMEOUT)
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Failed to set poll timeouts, polling without: {}", e);
+                reqwest::Client::new()
+            });
+        Self {
+            url: url.to_string(),
+            client,
+        }
+    }
+}
+
+impl DanmakuSource for HttpPollSource {
+    fn name(&self) -> &'static str {
+        "http_poll"
+    }
+
+    fn fetch(&self, room_id: &str) -> BoxFuture<'static, Result<Vec<serde_json::Value>, String>> {
+        let request = self.client.get(&self.url).query(&[("room_id", room_id)]);
+        Box::pin(async move {
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            if !response.status().is_success() {
+                return Err(format!("bridge returned {}", response.status()));
+            }
+            response.json().await.map_err(|e| e.to_string())
+        })
+    }
+}
 
 pub struct DouyinListener {
     config: LiveStreamConfig,
+    source: Arc<dyn DanmakuSource>,
+    settings: DouyinConfig,
+    sink: Recipient<ProcessDanmaku>,
+    handle: Option<actix_web::rt::task::JoinHandle<()>>,
     running: bool,
+    health: ListenerHealth,
 }
 
 impl DouyinListener {
-    pub fn new(config: LiveStreamConfig) -> Self {
+    pub fn new(
+        config: LiveStreamConfig,
+        source: Arc<dyn DanmakuSource>,
+        settings: DouyinConfig,
+        sink: Recipient<ProcessDanmaku>,
+    ) -> Self {
         Self {
             config,
+            source,
+            settings,
+            sink,
+            handle: None,
             running: false,
+            health: ListenerHealth::default(),
+        }
+    }
+}
+
+async fn run_source(
+    room_id: String,
+    source: Arc<dyn DanmakuSource>,
+    settings: DouyinConfig,
+    sink: Recipient<ProcessDanmaku>,
+    health: ListenerHealth,
+) {
+    let initial_backoff = Duration::from_millis(settings.initial_backoff_ms);
+    let max_backoff = Duration::from_millis(settings.max_backoff_ms);
+    let mut backoff = initial_backoff;
+
+    loop {
+        match source.fetch(&room_id).await {
+            Ok(batch) => {
+                backoff = initial_backoff;
+                // Nothing new yet: wait instead of asking again at once
+                if batch.is_empty() {
+                    actix::clock::sleep(Duration::from_millis(settings.poll_interval_ms)).await;
+                }
+                for payload in batch {
+                    match parse_douyin_danmaku(&payload) {
+                        Ok(mut danmaku) => {
+                            danmaku.trusted = true;
+                            sink.do_send(ProcessDanmaku { danmaku });
+                        }
+                        Err(e) => warn!("Dropping unparsable Douyin danmaku: {}", e),
+                    }
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "Douyin source '{}' failed for room {}: {}, reconnecting in {:?}",
+                    source.name(),
+                    room_id,
+                    e,
+                    backoff
+                );
+                health.reconnecting(&e);
+                actix::clock::sleep(backoff).await;
+                backoff = (backoff * 2).min(max_backoff);
+            }
         }
     }
 }
@@ -19,20 +220,154 @@ impl DouyinListener {
 impl PlatformListener for DouyinListener {
     fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         info!("Starting Douyin listener for room: {}", self.config.room_id);
+        if self.running {
+            return Ok(());
+        }
         self.running = true;
 
-        // TODO: 实现抖音弹幕监听
-        // 这里需要连接到抖音的弹幕API或使用第三方服务
+        if self.source.is_passive() {
+            info!(
+                "Douyin source '{}' is passive, waiting for HTTP callbacks",
+                self.source.name()
+            );
+            return Ok(());
+        }
+
+        self.handle = Some(actix::spawn(run_source(
+            self.config.room_id.clone(),
+            self.source.clone(),
+            self.settings.clone(),
+            self.sink.clone(),
+            self.health.clone(),
+        )));
 
         Ok(())
     }
 
     fn stop(&mut self) {
         info!("Stopping Douyin listener");
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
         self.running = false;
     }
 
     fn is_running(&self) -> bool {
         self.running
     }
+
+    fn health(&self) -> Option<ConnectionHealth> {
+        Some(self.health.status())
+    }
+}
+
+pub fn parse_douyin_danmaku(data: &serde_json::Value) -> Result<DanmakuMessage, String> {
+    field_mapping(&Platform::Douyin).parse(Platform::Douyin, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Collect;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    /// Fails the first `failures` fetches, then returns one danmaku, then
+    /// only empty batches; records when each fetch was made.
+    struct ScriptedSource {
+        failures: Mutex<u32>,
+        delivered: Mutex<bool>,
+        fetched_at: Arc<Mutex<Vec<Instant>>>,
+    }
+
+    impl DanmakuSource for ScriptedSource {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        fn fetch(
+            &self,
+            _room_id: &str,
+        ) -> BoxFuture<'static, Result<Vec<serde_json::Value>, String>> {
+            self.fetched_at.lock().unwrap().push(Instant::now());
+            let mut failures = self.failures.lock().unwrap();
+            let mut delivered = self.delivered.lock().unwrap();
+            let result = if *failures > 0 {
+                *failures -= 1;
+                Err("connection refused".to_string())
+            } else if !*delivered {
+                *delivered = true;
+                Ok(vec![serde_json::json!({
+                    "message": "主播好！",
+                    "user_id": "42",
+                    "username": "观众42",
+                    "room_id": "1001"
+                })])
+            } else {
+                Ok(Vec::new())
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_listener_backs_off_polls_and_stops() {
+        let fetched_at = Arc::new(Mutex::new(Vec::new()));
+        let source = ScriptedSource {
+            failures: Mutex::new(2),
+            delivered: Mutex::new(false),
+            fetched_at: fetched_at.clone(),
+        };
+        let collector = Collect::<ProcessDanmaku>::default().start();
+        let mut listener = DouyinListener::new(
+            LiveStreamConfig {
+                platform: Platform::Douyin,
+                room_id: "1001".to_string(),
+                room_ids: Vec::new(),
+                api_key: None,
+                webhook_url: None,
+                replay_path: None,
+                enabled: true,
+                sampling: None,
+                quota: None,
+                max_age_seconds: None,
+                respond: true,
+            },
+            Arc::new(source),
+            DouyinConfig {
+                poll_interval_ms: 20,
+                initial_backoff_ms: 20,
+                max_backoff_ms: 25,
+                ..Default::default()
+            },
+            collector.clone().recipient(),
+        );
+
+        listener.start().unwrap();
+        // Starting again does not start a second poll loop
+        listener.start().unwrap();
+        assert!(listener.is_running());
+        actix::clock::sleep(Duration::from_millis(200)).await;
+
+        let fetches = fetched_at.lock().unwrap().clone();
+        // Retried after 20ms, then after 25ms rather than 40ms
+        assert!(fetches[1] - fetches[0] >= Duration::from_millis(20));
+        assert!(fetches[2] - fetches[1] >= Duration::from_millis(25));
+        assert!(fetches[2] - fetches[1] < Duration::from_millis(40));
+        assert_eq!(listener.health().unwrap().reconnects, 2);
+        let received: Vec<String> = Collect::received(&collector)
+            .await
+            .into_iter()
+            .map(|msg| msg.danmaku.message)
+            .collect();
+        assert_eq!(received, vec!["主播好！"]);
+        // Empty batches are polled at the interval, not in a busy loop
+        assert!(fetches.len() <= 20, "fetched {} times", fetches.len());
+
+        listener.stop();
+        assert!(!listener.is_running());
+        let stopped_at = fetched_at.lock().unwrap().len();
+        actix::clock::sleep(Duration::from_millis(60)).await;
+        assert_eq!(fetched_at.lock().unwrap().len(), stopped_at);
+    }
 }
@@ -0,0 +1,222 @@
+use crate::intent::{self, Intent};
+use crate::similarity::{bigrams, dice, fold, Bigrams};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 口语化的同义说法，归一化时统一
+const ZH_SYNONYMS: &[(&str, &str)] = &[("啥", "什么"), ("咋", "怎么"), ("哪儿", "哪里")];
+/// 句首的称呼与客套话，不影响问题本身
+const ZH_PREFIXES: &[&str] = &["请问", "主播", "老师", "那个", "想问一下", "问一下"];
+/// 句尾语气词
+const ZH_PARTICLES: &[char] = &['吗', '呢', '吧', '啊', '呀', '哦', '嘛'];
+
+#[derive(Debug, Clone)]
+pub struct FaqConfig {
+    /// Distinct questions kept per room; the least asked are dropped first.
+    pub max_entries: usize,
+    /// Bigram overlap (Dice coefficient, 0-1) at which two questions count as one.
+    pub similarity: f64,
+    /// JSON file the buffer is loaded from and saved to; in-memory when unset.
+    pub persist_path: Option<String>,
+    pub persist_interval_seconds: u64,
+}
+
+impl Default for FaqConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 200,
+            similarity: 0.6,
+            persist_path: None,
+            persist_interval_seconds: 60,
+        }
+    }
+}
+
+/// One clustered question: the first phrasing seen and how often it was asked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaqEntry {
+    pub question: String,
+    pub count: u64,
+    pub first_asked: DateTime<Utc>,
+    pub last_asked: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct Cluster {
+    entry: FaqEntry,
+    bigrams: Bigrams,
+}
+
+impl Cluster {
+    fn new(entry: FaqEntry) -> Self {
+        let bigrams = bigrams(&normalize(&entry.question));
+        Self { entry, bigrams }
+    }
+}
+
+/// Groups viewer questions per room so the streamer can review the most
+/// asked ones after the stream.
+#[derive(Debug)]
+pub struct FaqBuffer {
+    config: FaqConfig,
+    rooms: HashMap<String, Vec<Cluster>>,
+    dirty: bool,
+}
+
+impl FaqBuffer {
+    pub fn new(config: FaqConfig) -> Self {
+        Self {
+            config,
+            rooms: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    pub fn config(&self) -> &FaqConfig {
+        &self.config
+    }
+
+    /// Counts `text` if it is a question; returns whether it was counted.
+    pub fn record(&mut self, room_id: &str, text: &str, now: DateTime<Utc>) -> bool {
+        if intent::classify(text) != Intent::Question {
+            return false;
+        }
+        let grams = bigrams(&normalize(text));
+        if grams.is_empty() {
+            return false;
+        }
+
+        let clusters = self.rooms.entry(room_id.to_string()).or_default();
+        let best = clusters
+            .iter_mut()
+            .map(|c| (dice(&grams, &c.bigrams), c))
+            .filter(|(score, _)| *score >= self.config.similarity)
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        match best {
+            Some((_, cluster)) => {
+                cluster.entry.count += 1;
+                cluster.entry.last_asked = now;
+            }
+            None => {
+                if clusters.len() >= self.config.max_entries.max(1) {
+                    evict_least_asked(clusters);
+                }
+                clusters.push(Cluster {
+                    entry: FaqEntry {
+                        question: text.trim().to_string(),
+                        count: 1,
+                        first_asked: now,
+                        last_asked: now,
+                    },
+                    bigrams: grams,
+                });
+            }
+        }
+        self.dirty = true;
+        true
+    }
+
+    /// The room's `limit` most asked questions, most recent first on ties.
+    pub fn top(&self, room_id: &str, limit: usize) -> Vec<FaqEntry> {
+        let mut entries: Vec<FaqEntry> = self
+            .rooms
+            .get(room_id)
+            .map(|clusters| clusters.iter().map(|c| c.entry.clone()).collect())
+            .unwrap_or_default();
+        entries.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_asked.cmp(&a.last_asked)));
+        entries.truncate(limit);
+        entries
+    }
+
+    /// Restores a buffer saved with `save`.
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let rooms: HashMap<String, Vec<FaqEntry>> =
+            serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        self.rooms = rooms
+            .into_iter()
+            .map(|(room, entries)| (room, entries.into_iter().map(Cluster::new).collect()))
+            .collect();
+        Ok(())
+    }
+
+    /// Writes the buffer to `path` if it changed since the last save.
+    pub fn save(&mut self, path: &str) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let rooms: HashMap<&String, Vec<&FaqEntry>> = self
+            .rooms
+            .iter()
+            .map(|(room, clusters)| (room, clusters.iter().map(|c| &c.entry).collect()))
+            .collect();
+        let contents = serde_json::to_string(&rooms).map_err(|e| e.to_string())?;
+        std::fs::write(path, contents).map_err(|e| e.to_string())?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn evict_least_asked(clusters: &mut Vec<Cluster>) {
+    if let Some(index) = clusters
+        .iter()
+        .enumerate()
+        .min_by(|a, b| {
+            a.1.entry
+                .count
+                .cmp(&b.1.entry.count)
+                .then(a.1.entry.last_asked.cmp(&b.1.entry.last_asked))
+        })
+        .map(|(i, _)| i)
+    {
+        clusters.swap_remove(index);
+    }
+}
+
+/// 去掉标点、空白、称呼和语气词，统一大小写与同义说法
+fn normalize(text: &str) -> String {
+    let mut text = fold(text);
+    for (from, to) in ZH_SYNONYMS {
+        text = text.replace(from, to);
+    }
+    while let Some(prefix) = ZH_PREFIXES.iter().find(|p| text.starts_with(*p)) {
+        text.replace_range(..prefix.len(), "");
+    }
+    text.trim_end_matches(ZH_PARTICLES).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paraphrased_questions_cluster_together() {
+        let mut faq = FaqBuffer::new(FaqConfig::default());
+        let now = Utc::now();
+
+        let paraphrases = [
+            "主播今天玩什么游戏？",
+            "请问今天玩啥游戏呀？",
+            "主播今天打算玩什么游戏",
+            "今天玩什么游戏？？",
+        ];
+        for question in paraphrases {
+            assert!(faq.record("room1", question, now));
+        }
+        assert!(faq.record("room1", "主播几点下播？", now));
+        assert!(faq.record("room1", "What game are you playing today?", now));
+        assert!(faq.record("room1", "what game are you playing today", now));
+        // Not a question, not counted
+        assert!(!faq.record("room1", "今天天气不错", now));
+
+        let top = faq.top("room1", 10);
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[0].question, "主播今天玩什么游戏？");
+        assert_eq!(top[0].count, 4);
+        assert_eq!(top[1].count, 2);
+        assert_eq!(top[2].question, "主播几点下播？");
+        assert_eq!(faq.top("room1", 1).len(), 1);
+        assert!(faq.top("room2", 10).is_empty());
+    }
+}
@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Connection trouble a listener has run into, as reported by
+/// `GET /api/v1/platform/status`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConnectionHealth {
+    /// Times the connection was retried after failing.
+    pub reconnects: u64,
+    pub last_error: Option<String>,
+    #[serde(serialize_with = "crate::timezone::serialize_opt")]
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+/// Shared record of a listener's connection failures, updated from the task
+/// that holds the connection and read by the manager.
+#[derive(Debug, Clone, Default)]
+pub struct ListenerHealth {
+    state: Arc<Mutex<ConnectionHealth>>,
+}
+
+impl ListenerHealth {
+    fn state(&self) -> MutexGuard<'_, ConnectionHealth> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records a failure the listener is about to retry after.
+    pub fn reconnecting(&self, error: &str) {
+        let mut state = self.state();
+        state.reconnects += 1;
+        state.last_error = Some(error.to_string());
+        state.last_error_at = Some(Utc::now());
+    }
+
+    pub fn status(&self) -> ConnectionHealth {
+        self.state().clone()
+    }
+}
@@ -0,0 +1,244 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Keep-alive settings for one platform connection.
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    /// Frame sent on every beat, in the platform's wire format.
+    pub payload: Vec<u8>,
+    /// How long beats may go unacknowledged before the connection counts as dead.
+    pub ack_timeout: Duration,
+}
+
+impl HeartbeatConfig {
+    /// Acks are expected within two intervals unless overridden.
+    pub fn new(interval: Duration, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            interval,
+            payload: payload.into(),
+            ack_timeout: interval * 2,
+        }
+    }
+
+    pub fn with_ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+}
+
+/// Writes one heartbeat frame to the platform connection.
+pub type HeartbeatSend = Arc<dyn Fn(&[u8]) -> Result<(), String> + Send + Sync>;
+/// Called when acks stop arriving, so the listener can reconnect.
+pub type ReconnectHook = Arc<dyn Fn() + Send + Sync>;
+
+/// Keep-alive health of one listener, as reported in `/api/v1/stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeartbeatStatus {
+    pub interval_ms: u64,
+    pub sent: u64,
+    #[serde(serialize_with = "crate::timezone::serialize_opt")]
+    pub last_sent: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "crate::timezone::serialize_opt")]
+    pub last_ack: Option<DateTime<Utc>>,
+    /// Reconnections triggered because acks stopped.
+    pub reconnects: u64,
+    /// False from an ack timeout until the next ack arrives.
+    pub healthy: bool,
+}
+
+#[derive(Debug, Default)]
+struct HeartbeatState {
+    sent: u64,
+    last_sent: Option<DateTime<Utc>>,
+    last_ack: Option<DateTime<Utc>>,
+    /// First beat not yet covered by an ack.
+    unacked_since: Option<Instant>,
+    reconnects: u64,
+    timed_out: bool,
+}
+
+/// Sends a listener's keep-alive frames on a timer and watches for acks, so
+/// each platform listener only supplies its payload and connection.
+#[derive(Debug)]
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    state: Arc<Mutex<HeartbeatState>>,
+    handle: Option<actix_web::rt::task::JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    pub fn new(config: HeartbeatConfig) -> Self {
+        Self {
+            config,
+            state: Arc::default(),
+            handle: None,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, HeartbeatState> {
+        lock(&self.state)
+    }
+
+    /// Starts beating through `send`, first beat immediately. `reconnect` runs
+    /// whenever beats go unacknowledged for the ack timeout.
+    pub fn start(&mut self, send: HeartbeatSend, reconnect: ReconnectHook) {
+        self.stop();
+        self.handle = Some(actix::spawn(run(
+            self.config.clone(),
+            self.state.clone(),
+            send,
+            reconnect,
+        )));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+
+    /// Records the platform's reply to a heartbeat.
+    pub fn ack(&self) {
+        let mut state = self.state();
+        state.last_ack = Some(Utc::now());
+        state.unacked_since = None;
+        state.timed_out = false;
+    }
+
+    pub fn status(&self) -> HeartbeatStatus {
+        let state = self.state();
+        HeartbeatStatus {
+            interval_ms: self.config.interval.as_millis() as u64,
+            sent: state.sent,
+            last_sent: state.last_sent,
+            last_ack: state.last_ack,
+            reconnects: state.reconnects,
+            healthy: !state.timed_out,
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn lock(state: &Mutex<HeartbeatState>) -> MutexGuard<'_, HeartbeatState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+async fn run(
+    config: HeartbeatConfig,
+    state: Arc<Mutex<HeartbeatState>>,
+    send: HeartbeatSend,
+    reconnect: ReconnectHook,
+) {
+    let mut ticker = actix::clock::interval(config.interval);
+
+    loop {
+        ticker.tick().await;
+
+        let timed_out = {
+            let mut state = lock(&state);
+            let expired = state
+                .unacked_since
+                .is_some_and(|since| since.elapsed() >= config.ack_timeout);
+            if expired {
+                // The reconnected link gets a full timeout before the next check
+                state.unacked_since = None;
+                state.reconnects += 1;
+                state.timed_out = true;
+            }
+            expired
+        };
+        if timed_out {
+            warn!(
+                "No heartbeat ack for {:?}, reconnecting",
+                config.ack_timeout
+            );
+            reconnect();
+        }
+
+        if let Err(e) = send(&config.payload) {
+            warn!("Failed to send heartbeat: {}", e);
+        }
+        let mut state = lock(&state);
+        state.sent += 1;
+        state.last_sent = Some(Utc::now());
+        state.unacked_since.get_or_insert_with(Instant::now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[actix_web::test]
+    async fn test_heartbeat_fires_at_configured_interval() {
+        let interval = Duration::from_millis(40);
+        let beats = Arc::new(Mutex::new(Vec::new()));
+        let mut heartbeat = Heartbeat::new(
+            HeartbeatConfig::new(interval, b"ping".to_vec())
+                .with_ack_timeout(Duration::from_secs(10)),
+        );
+        let recorded = beats.clone();
+        heartbeat.start(
+            Arc::new(move |payload| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((Instant::now(), payload.to_vec()));
+                Ok(())
+            }),
+            Arc::new(|| {}),
+        );
+
+        actix::clock::sleep(Duration::from_millis(150)).await;
+        heartbeat.ack();
+        heartbeat.stop();
+
+        // Immediately, then at 40, 80 and 120ms
+        let beats = beats.lock().unwrap();
+        assert!((3..=5).contains(&beats.len()), "beats: {}", beats.len());
+        for pair in beats.windows(2) {
+            assert!(pair[1].0 - pair[0].0 >= interval - Duration::from_millis(5));
+        }
+        assert!(beats.iter().all(|(_, payload)| payload == b"ping"));
+
+        let status = heartbeat.status();
+        assert_eq!(status.sent, beats.len() as u64);
+        assert!(status.healthy && status.last_ack.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_missing_acks_trigger_reconnect() {
+        let reconnects = Arc::new(AtomicU32::new(0));
+        let mut heartbeat = Heartbeat::new(
+            HeartbeatConfig::new(Duration::from_millis(20), b"ping".to_vec())
+                .with_ack_timeout(Duration::from_millis(50)),
+        );
+        let counter = reconnects.clone();
+        heartbeat.start(
+            Arc::new(|_| Ok(())),
+            Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+        );
+
+        actix::clock::sleep(Duration::from_millis(130)).await;
+        heartbeat.stop();
+        assert!(reconnects.load(Ordering::SeqCst) >= 1);
+        let status = heartbeat.status();
+        assert!(!status.healthy);
+        assert_eq!(status.reconnects, reconnects.load(Ordering::SeqCst) as u64);
+
+        heartbeat.ack();
+        assert!(heartbeat.status().healthy);
+    }
+}
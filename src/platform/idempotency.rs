@@ -0,0 +1,93 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How long webhook idempotency keys are remembered, and how many.
+#[derive(Debug, Clone)]
+pub struct IdempotencyConfig {
+    /// Seconds a key suppresses retries; 0 turns deduplication off.
+    pub ttl_seconds: u64,
+    /// Keys remembered at most; the oldest are forgotten first.
+    pub capacity: usize,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: 600,
+            capacity: 10_000,
+        }
+    }
+}
+
+/// Idempotency keys of recently accepted webhook deliveries, so a platform
+/// retrying a timed-out callback does not get the same danmaku answered twice.
+#[derive(Debug, Default)]
+pub struct SeenKeys {
+    config: IdempotencyConfig,
+    seen: HashMap<String, Instant>,
+    /// Keys oldest first, for expiry and eviction.
+    order: VecDeque<(Instant, String)>,
+}
+
+impl SeenKeys {
+    pub fn new(config: IdempotencyConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Records `key` at `now`; false if it was already seen within the TTL.
+    pub fn insert(&mut self, key: &str, now: Instant) -> bool {
+        let ttl = Duration::from_secs(self.config.ttl_seconds);
+        if ttl.is_zero() {
+            return true;
+        }
+        while self
+            .order
+            .front()
+            .is_some_and(|(at, _)| now.saturating_duration_since(*at) >= ttl)
+        {
+            self.forget_oldest();
+        }
+        if self.seen.contains_key(key) {
+            return false;
+        }
+        while self.order.len() >= self.config.capacity.max(1) {
+            self.forget_oldest();
+        }
+        self.seen.insert(key.to_string(), now);
+        self.order.push_back((now, key.to_string()));
+        true
+    }
+
+    fn forget_oldest(&mut self) {
+        if let Some((_, key)) = self.order.pop_front() {
+            self.seen.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_expire_and_are_bounded() {
+        let mut keys = SeenKeys::new(IdempotencyConfig {
+            ttl_seconds: 60,
+            capacity: 2,
+        });
+        let start = Instant::now();
+        assert!(keys.insert("a", start));
+        assert!(!keys.insert("a", start + Duration::from_secs(30)));
+        assert!(keys.insert("a", start + Duration::from_secs(61)));
+
+        // The oldest key is forgotten once capacity is reached
+        let later = start + Duration::from_secs(62);
+        assert!(keys.insert("b", later));
+        assert!(keys.insert("c", later));
+        assert!(keys.insert("a", later));
+        assert!(!keys.insert("c", later));
+    }
+}
@@ -1,21 +1,72 @@
-use crate::event_bus::EventBus;
+use crate::actor::AskAudience;
+use crate::engagement::{EngagementConfig, EngagementScheduler};
+use crate::event_bus::{EventBus, MonitorDanmaku};
 use crate::events::*;
+use crate::lifecycle::{LifecycleConfig, StreamStates};
+use crate::llm::LlmLimiter;
 use crate::platform::bilibili::BilibiliListener;
-use crate::platform::douyin::DouyinListener;
+use crate::platform::dedup::{DanmakuDedup, DedupConfig};
+use crate::platform::douyin::{DanmakuSource, DouyinConfig, DouyinListener, WebhookBridgeSource};
+use crate::platform::faq::{FaqBuffer, FaqConfig, FaqEntry};
+use crate::platform::heartbeat::HeartbeatStatus;
+use crate::platform::idempotency::{IdempotencyConfig, SeenKeys};
+use crate::platform::merge::{DanmakuMerger, MergeConfig, Merged};
+use crate::platform::mood::{MoodSnapshot, MoodTracker};
+use crate::platform::quota::{RoomQuota, RoomQuotas, RoomThroughput};
+use crate::platform::replay::{ReplayListener, ReplayStatus, ReplayUpdate};
+use crate::platform::sampling::{self, ResponseSampler, SamplingPolicy, SamplingStats};
+use crate::platform::store::{DanmakuStore, DanmakuStoreStatus};
+use crate::platform::throttle::{self, ThrottleConfig, ThrottleMonitor};
 use crate::platform::websocket::WebSocketListener;
 use crate::platform::youtube::YouTubeListener;
 use crate::platform::{
     DanmakuMessage, LiveStreamConfig, Platform, PlatformListener, ProcessDanmaku,
 };
+use crate::redact;
+use crate::sentiment;
+use crate::worker::{self, WorkerPool};
 use actix::prelude::*;
-use log::info;
-use std::collections::HashMap;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use uuid::Uuid;
 
+const ENGAGEMENT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
 pub struct LiveStreamManager {
     configs: HashMap<String, LiveStreamConfig>,
     event_bus: Addr<EventBus>,
     active_listeners: HashMap<String, Box<dyn PlatformListener>>,
+    /// Why each listener that failed to start did.
+    listener_errors: HashMap<String, String>,
+    /// Danmaku received and when the latest came, by listener.
+    listener_traffic: HashMap<String, (u64, chrono::DateTime<chrono::Utc>)>,
+    douyin_source: Arc<dyn DanmakuSource>,
+    douyin: DouyinConfig,
+    mood: MoodTracker,
+    faq: FaqBuffer,
+    /// Every danmaku received, kept for later analysis; none when unset.
+    store: Option<DanmakuStore>,
+    /// Idempotency keys of recent webhook deliveries.
+    webhook_keys: SeenKeys,
+    merger: DanmakuMerger,
+    /// Rooms whose danmaku are processed.
+    streams: StreamStates,
+    /// Whether a viewer's danmaku share one session per room.
+    viewer_sessions: bool,
+    /// Rooms whose danmaku are recorded but not answered.
+    silent_rooms: HashSet<String>,
+    sampler: ResponseSampler,
+    dedup: DanmakuDedup,
+    quotas: RoomQuotas,
+    max_age_seconds: Option<u64>,
+    room_max_age_seconds: HashMap<String, u64>,
+    throttle: ThrottleMonitor,
+    engagement: EngagementScheduler,
+    limiter: Option<Arc<LlmLimiter>>,
+    http: reqwest::Client,
+    workers: Arc<WorkerPool>,
 }
 
 impl LiveStreamManager {
@@ -24,20 +75,233 @@ impl LiveStreamManager {
             configs: HashMap::new(),
             event_bus,
             active_listeners: HashMap::new(),
+            listener_errors: HashMap::new(),
+            listener_traffic: HashMap::new(),
+            douyin_source: Arc::new(WebhookBridgeSource),
+            douyin: DouyinConfig::default(),
+            mood: MoodTracker::default(),
+            faq: FaqBuffer::new(FaqConfig::default()),
+            store: None,
+            webhook_keys: SeenKeys::new(IdempotencyConfig::default()),
+            merger: DanmakuMerger::default(),
+            streams: StreamStates::default(),
+            viewer_sessions: true,
+            silent_rooms: HashSet::new(),
+            sampler: ResponseSampler::default(),
+            dedup: DanmakuDedup::default(),
+            quotas: RoomQuotas::default(),
+            max_age_seconds: None,
+            room_max_age_seconds: HashMap::new(),
+            throttle: ThrottleMonitor::new(ThrottleConfig::default()),
+            engagement: EngagementScheduler::new(EngagementConfig::default()),
+            limiter: None,
+            http: reqwest::Client::new(),
+            workers: WorkerPool::new(worker::default_pool_size()),
         }
     }
 
-    pub fn add_platform_config(&mut self, config: LiveStreamConfig) {
-        let config_id = format!("{:?}_{}", config.platform, config.room_id);
-        info!("Adding platform config: {}", config_id);
+    pub fn with_worker_pool(mut self, workers: Arc<WorkerPool>) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// 丢弃弹幕过多时通知上游桥接降低发送频率
+    pub fn with_throttle_feedback(
+        mut self,
+        config: ThrottleConfig,
+        limiter: Arc<LlmLimiter>,
+    ) -> Self {
+        self.throttle = ThrottleMonitor::new(config);
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// 默认回复抽样策略，可按直播间覆盖
+    pub fn with_sampling(mut self, policy: SamplingPolicy) -> Self {
+        self.sampler = ResponseSampler::new(policy);
+        self
+    }
+
+    /// 同一观众连续发送的短弹幕在窗口内合并为一条，按完整的一句话回复
+    pub fn with_merging(mut self, config: MergeConfig) -> Self {
+        self.merger = DanmakuMerger::new(config);
+        self
+    }
+
+    /// 按开播和下播事件决定是否处理直播间的弹幕
+    pub fn with_stream_lifecycle(mut self, config: &LifecycleConfig) -> Self {
+        self.streams = StreamStates::new(config);
+        self
+    }
+
+    /// 同一观众在同一直播间的弹幕归入固定会话，追问可以延续上下文；
+    /// 关闭时每条弹幕各用新会话
+    pub fn with_viewer_sessions(mut self, enabled: bool) -> Self {
+        self.viewer_sessions = enabled;
+        self
+    }
+
+    /// 相似弹幕在窗口内合并为一条，附带相似条数
+    pub fn with_dedup(mut self, config: DedupConfig) -> Self {
+        self.dedup = DanmakuDedup::new(config);
+        self
+    }
+
+    /// 每个直播间每分钟处理的弹幕上限，可按直播间覆盖
+    pub fn with_room_quota(mut self, quota: RoomQuota) -> Self {
+        self.quotas = RoomQuotas::new(Some(quota));
+        self
+    }
 
-        if config.enabled {
-            self.start_listener(&config_id, &config);
+    /// 弹幕等待超过该秒数后不再回复，可按直播间覆盖
+    pub fn with_max_danmaku_age(mut self, max_age_seconds: u64) -> Self {
+        self.max_age_seconds = Some(max_age_seconds);
+        self
+    }
+
+    /// 冷场时向观众提问
+    pub fn with_engagement(mut self, config: EngagementConfig) -> Self {
+        self.engagement = EngagementScheduler::new(config);
+        self
+    }
+
+    pub fn with_mood_half_life(mut self, half_life_seconds: u64) -> Self {
+        self.mood = MoodTracker::new(half_life_seconds);
+        self
+    }
+
+    /// 观众提问聚合，可持久化到文件
+    pub fn with_faq(mut self, config: FaqConfig) -> Self {
+        self.faq = FaqBuffer::new(config);
+        self
+    }
+
+    /// 收到的每条弹幕都写入存储，供直播后分析
+    pub fn with_danmaku_store(mut self, store: DanmakuStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// 平台回调重试时按幂等键去重
+    pub fn with_webhook_idempotency(mut self, config: IdempotencyConfig) -> Self {
+        self.webhook_keys = SeenKeys::new(config);
+        self
+    }
+
+    fn save_faq(&mut self) {
+        let Some(path) = self.faq.config().persist_path.clone() else {
+            return;
+        };
+        if let Err(e) = self.faq.save(&path) {
+            warn!("Failed to save FAQ buffer to {}: {}", path, e);
         }
+    }
+
+    /// 开启去重时先暂存，窗口结束后连同相似条数一起交给抽样；
+    /// 去重在抽样之前，一波刷屏只抽一次
+    fn dispatch(&mut self, event: TextInputEvent, ctx: &mut Context<Self>) {
+        let Some(window) = self.dedup.window() else {
+            self.sample(event, ctx);
+            return;
+        };
+        if let Some(id) = self.dedup.offer(event) {
+            ctx.run_later(window, move |act, ctx| {
+                if let Some(event) = act.dedup.release(id) {
+                    act.sample(event, ctx);
+                }
+            });
+        }
+    }
+
+    /// 按抽样策略决定是否回复
+    fn sample(&mut self, event: TextInputEvent, ctx: &mut Context<Self>) {
+        let room_id = event
+            .viewer
+            .as_ref()
+            .map(|viewer| viewer.room_id.clone())
+            .unwrap_or_default();
+        let roll = rand::random::<f64>();
+        if !self.sampler.policy(&room_id).needs_interest() {
+            if self.sampler.select(&room_id, 0.0, roll) {
+                self.event_bus.do_send(event);
+            }
+            return;
+        }
+        let scoring = self.workers.clone().run({
+            let text = event.text.clone();
+            move || sentiment::score(&text)
+        });
+        ctx.spawn(scoring.into_actor(self).map(move |score, act, _ctx| {
+            let interest = sampling::interest(&event.text, score);
+            if act.sampler.select(&room_id, interest, roll) {
+                act.event_bus.do_send(event);
+            }
+        }));
+    }
+
+    /// Feeds Douyin rooms from the configured source, polled and retried
+    /// as configured.
+    pub fn with_douyin(mut self, config: DouyinConfig) -> Self {
+        self.douyin_source = config.build_source();
+        self.douyin = config;
+        self
+    }
+
+    /// Feeds Douyin rooms from a source of the embedder's own, e.g. a scraper.
+    pub fn with_douyin_source(mut self, source: Arc<dyn DanmakuSource>) -> Self {
+        self.douyin_source = source;
+        self
+    }
+
+    pub fn add_platform_config(
+        &mut self,
+        config: LiveStreamConfig,
+        sink: Recipient<ProcessDanmaku>,
+    ) {
+        // 平台不支持单连接多直播间时，每个直播间一个监听器
+        let configs = if config.platform.supports_multi_room() {
+            vec![config]
+        } else {
+            config
+                .rooms()
+                .into_iter()
+                .map(|room_id| config.for_room(room_id))
+                .collect()
+        };
         // 当前只支持一个平台
         self.configs.clear();
 
-        self.configs.insert(config_id, config);
+        for config in configs {
+            let config_id = config.config_id();
+            info!("Adding platform config: {}", config_id);
+
+            for room_id in config.rooms() {
+                if let Some(policy) = &config.sampling {
+                    self.sampler.set_policy(room_id, policy.clone());
+                }
+                if let Some(quota) = &config.quota {
+                    self.quotas.set_quota(room_id, quota.clone());
+                }
+                if let Some(max_age) = config.max_age_seconds {
+                    self.room_max_age_seconds
+                        .insert(room_id.to_string(), max_age);
+                }
+                self.set_responding(room_id, config.respond);
+            }
+            if config.enabled {
+                self.start_listener(&config_id, &config, sink.clone());
+            }
+            self.configs.insert(config_id, config);
+        }
+    }
+
+    /// 设置数字人是否回复直播间弹幕；不回复时弹幕仍照常存储和统计
+    pub fn set_responding(&mut self, room_id: &str, respond: bool) {
+        if respond {
+            self.silent_rooms.remove(room_id);
+        } else {
+            self.silent_rooms.insert(room_id.to_string());
+        }
     }
 
     pub fn remove_platform_config(&mut self, config_id: &str) {
@@ -47,55 +311,300 @@ impl LiveStreamManager {
         }
     }
 
-    fn start_listener(&mut self, config_id: &str, config: &LiveStreamConfig) {
+    fn start_listener(
+        &mut self,
+        config_id: &str,
+        config: &LiveStreamConfig,
+        sink: Recipient<ProcessDanmaku>,
+    ) {
         info!("Starting listener for: {}", config_id);
 
-        let listener: Box<dyn PlatformListener> = match config.platform {
-            Platform::Douyin => Box::new(DouyinListener::new(config.clone())),
-            Platform::Bilibili => Box::new(BilibiliListener::new(config.clone())),
+        let mut listener: Box<dyn PlatformListener> = match config.platform {
+            Platform::Douyin => Box::new(DouyinListener::new(
+                config.clone(),
+                self.douyin_source.clone(),
+                self.douyin.clone(),
+                sink,
+            )),
+            Platform::Bilibili => Box::new(BilibiliListener::new(config.clone(), sink)),
             Platform::YouTube => Box::new(YouTubeListener::new(config.clone())),
             Platform::WebSocket => Box::new(WebSocketListener::new(config.clone())),
+            Platform::Replay => Box::new(ReplayListener::new(config.clone(), sink)),
         };
 
+        if let Err(e) = listener.start() {
+            warn!("Failed to start listener {}: {}", config_id, e);
+            self.listener_errors
+                .insert(config_id.to_string(), e.to_string());
+            return;
+        }
+
+        self.listener_errors.remove(config_id);
         self.active_listeners
             .insert(config_id.to_string(), listener);
     }
 
     fn stop_listener(&mut self, config_id: &str) {
-        if let Some(_listener) = self.active_listeners.remove(config_id) {
+        self.listener_errors.remove(config_id);
+        self.listener_traffic.remove(config_id);
+        if let Some(mut listener) = self.active_listeners.remove(config_id) {
+            listener.stop();
             info!("Stopped listener for: {}", config_id);
         }
     }
 
-    pub fn process_danmaku(&mut self, danmaku: DanmakuMessage) {
+    /// 记到负责该直播间的监听器上，webhook推送的弹幕也算
+    /// Counts a danmaku against the platform config it came through, for
+    /// listener health and throttle feedback.
+    fn record_listener_traffic(&mut self, danmaku: &DanmakuMessage) {
+        let config_id = self
+            .configs
+            .iter()
+            .find(|(_, config)| {
+                config.platform == danmaku.platform
+                    && config.rooms().contains(&danmaku.room_id.as_str())
+            })
+            .map(|(config_id, _)| config_id.clone());
+        // Unmatched danmaku still count towards the drop rate
+        self.throttle
+            .record_received(config_id.as_deref().unwrap_or_default());
+        let Some(config_id) = config_id else {
+            return;
+        };
+        let traffic = self
+            .listener_traffic
+            .entry(config_id)
+            .or_insert((0, danmaku.timestamp));
+        traffic.0 += 1;
+        traffic.1 = danmaku.timestamp;
+    }
+
+    fn check_throttle(&mut self) {
+        let Some(limiter) = &self.limiter else {
+            return;
+        };
+        let Some(rates) = self
+            .throttle
+            .evaluate(limiter.stats().shed, chrono::Utc::now())
+        else {
+            return;
+        };
+
+        let total: u32 = rates.iter().map(|(_, rate)| rate).sum();
+        warn!(
+            "Shedding danmaku, asking bridges to throttle to {}/min in total",
+            total
+        );
+        for (config_id, rate) in rates {
+            let Some(webhook_url) = self
+                .configs
+                .get(&config_id)
+                .and_then(|config| config.webhook_url.clone())
+            else {
+                continue;
+            };
+            actix::spawn(throttle::send_throttle(
+                self.http.clone(),
+                webhook_url,
+                rate,
+            ));
+        }
+    }
+
+    /// 弹幕过少且距上次提问足够久的直播间，请数字人向观众提问
+    fn check_engagement(&mut self) {
+        let received: HashMap<String, usize> = self
+            .quotas
+            .throughput(chrono::Utc::now())
+            .into_iter()
+            .map(|room| (room.room_id, room.received_last_minute))
+            .collect();
+        let rooms: Vec<String> = self
+            .configs
+            .values()
+            .filter(|config| config.enabled)
+            .flat_map(|config| config.rooms().into_iter().map(str::to_string))
+            .filter(|room_id| !self.silent_rooms.contains(room_id))
+            .collect();
+
+        let now = std::time::Instant::now();
+        for room_id in rooms {
+            let per_minute = received.get(&room_id).copied().unwrap_or(0);
+            if self.engagement.due(&room_id, per_minute, now) {
+                info!(
+                    "Room {} is quiet ({} danmaku in the last minute), asking the audience",
+                    room_id, per_minute
+                );
+                self.event_bus.do_send(AskAudience {
+                    session_id: Uuid::new_v4(),
+                    room_id,
+                });
+            }
+        }
+    }
+
+    pub fn process_danmaku(&mut self, danmaku: DanmakuMessage, ctx: &mut Context<Self>) {
         info!(
             "Processing danmaku from {:?}: {}",
-            danmaku.platform, danmaku.message
+            danmaku.platform,
+            redact::text(&danmaku.message)
         );
 
+        self.record_listener_traffic(&danmaku);
+        // 不论是否回复，收到的弹幕都先落盘
+        if let Some(store) = &self.store {
+            if let Err(e) = store.record(&danmaku) {
+                warn!("Failed to store danmaku: {}", e);
+            }
+        }
+
+        // 未开播或已下播的直播间，弹幕只存储不处理
+        if !self.streams.is_live(&danmaku.room_id) {
+            debug!(
+                "Ignoring danmaku for room {}: stream is not live",
+                danmaku.room_id
+            );
+            return;
+        }
+
+        // 开启合并时先暂存片段，说完一句或窗口内没有后续时再处理
+        let Some(window) = self.merger.window() else {
+            self.handle_danmaku(danmaku, ctx);
+            return;
+        };
+        match self.merger.offer(danmaku) {
+            Merged::Ready(danmaku) => self.handle_danmaku(danmaku, ctx),
+            Merged::Held { key, id } => {
+                ctx.run_later(window, move |act, ctx| {
+                    if let Some(danmaku) = act.merger.release(&key, id) {
+                        act.handle_danmaku(danmaku, ctx);
+                    }
+                });
+            }
+        }
+    }
+
+    fn handle_danmaku(&mut self, danmaku: DanmakuMessage, ctx: &mut Context<Self>) {
+        // 超出直播间配额的弹幕直接丢弃，不再计入情绪和FAQ
+        if !self
+            .quotas
+            .admit(&danmaku.room_id, chrono::Utc::now(), rand::random::<f64>())
+        {
+            let shed = self.quotas.shed(&danmaku.room_id);
+            if shed == 1 || shed.is_multiple_of(100) {
+                warn!(
+                    "Room {} is over its danmaku quota, {} shed so far",
+                    danmaku.room_id, shed
+                );
+            }
+            return;
+        }
+        self.event_bus.do_send(MonitorDanmaku {
+            danmaku: danmaku.clone(),
+        });
+        // 所有提问都计入FAQ，不受回复抽样影响
+        self.faq
+            .record(&danmaku.room_id, &danmaku.message, chrono::Utc::now());
+
+        let room_mood = self
+            .mood
+            .snapshot(&danmaku.room_id, chrono::Utc::now())
+            .map(|mood| mood.label.to_string());
+        let room_id = danmaku.room_id.clone();
+        let text = danmaku.message.clone();
+        let max_age_seconds = self
+            .room_max_age_seconds
+            .get(&room_id)
+            .copied()
+            .or(self.max_age_seconds);
+        let session_id = if self.viewer_sessions {
+            danmaku.session_id()
+        } else {
+            Uuid::new_v4()
+        };
+
         let text_event = TextInputEvent {
             metadata: EventMetadata {
-                session_id: Some(Uuid::new_v4()),
+                session_id: Some(session_id),
                 user_id: Some(format!(
                     "{}_{}",
                     danmaku.platform.to_string(),
                     danmaku.user_id
                 )),
+                // 以弹幕到达时间为准，排队等待的时间也计入时效
+                timestamp: danmaku.timestamp,
                 ..Default::default()
             },
             text: danmaku.message,
             language: Some("zh-CN".to_string()),
+            username: Some(danmaku.username),
+            room_mood,
+            priority: if danmaku.is_vip {
+                MessagePriority::High
+            } else {
+                MessagePriority::Low
+            },
+            intent: None,
+            viewer: Some(ViewerInfo {
+                room_id: danmaku.room_id,
+                user_level: danmaku.user_level,
+                is_vip: danmaku.is_vip,
+                gift_value: danmaku.gift_value,
+                similar_count: 0,
+                trusted: false,
+                trusted: danmaku.trusted,
+            }),
+            max_age_seconds,
+            operator: false,
         };
 
-        self.event_bus.do_send(text_event);
+        // 未回复的弹幕仍计入直播间情绪
+        if self.silent_rooms.contains(&room_id) {
+            debug!("Not answering danmaku in silent room {}", room_id);
+        } else {
+            self.dispatch(text_event, ctx);
+        }
+
+        // 统计直播间整体情绪：情感分析放到工作线程池，结果稍后计入
+        let scoring = self.workers.clone().run(move || sentiment::score(&text));
+        ctx.spawn(scoring.into_actor(self).map(move |score, act, _ctx| {
+            act.mood.record(&room_id, score, chrono::Utc::now());
+        }));
     }
 }
 
 impl Actor for LiveStreamManager {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("LiveStreamManager started");
+
+        if self.throttle.config().enabled && self.limiter.is_some() {
+            let interval = std::time::Duration::from_secs(self.throttle.config().interval_seconds);
+            ctx.run_interval(interval, |act, _ctx| act.check_throttle());
+        }
+
+        if self.engagement.config().is_enabled() {
+            ctx.run_interval(ENGAGEMENT_CHECK_INTERVAL, |act, _ctx| {
+                act.check_engagement()
+            });
+        }
+
+        if let Some(path) = self.faq.config().persist_path.clone() {
+            if std::path::Path::new(&path).exists() {
+                match self.faq.load(&path) {
+                    Ok(()) => info!("Loaded FAQ buffer from {}", path),
+                    Err(e) => warn!("Failed to load FAQ buffer from {}: {}", path, e),
+                }
+            }
+            let interval =
+                std::time::Duration::from_secs(self.faq.config().persist_interval_seconds.max(1));
+            ctx.run_interval(interval, |act, _ctx| act.save_faq());
+        }
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.save_faq();
     }
 }
 
@@ -109,8 +618,29 @@ pub struct AddPlatformConfig {
 impl Handler<AddPlatformConfig> for LiveStreamManager {
     type Result = ();
 
-    fn handle(&mut self, msg: AddPlatformConfig, _ctx: &mut Context<Self>) -> Self::Result {
-        self.add_platform_config(msg.config);
+    fn handle(&mut self, msg: AddPlatformConfig, ctx: &mut Context<Self>) -> Self::Result {
+        self.add_platform_config(msg.config, ctx.address().recipient());
+    }
+}
+
+/// 运行时开关直播间的数字人回复
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetRoomResponding {
+    pub room_id: String,
+    pub respond: bool,
+}
+
+impl Handler<SetRoomResponding> for LiveStreamManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetRoomResponding, _ctx: &mut Context<Self>) -> Self::Result {
+        info!(
+            "Room {} responding: {}",
+            msg.room_id,
+            if msg.respond { "on" } else { "off" }
+        );
+        self.set_responding(&msg.room_id, msg.respond);
     }
 }
 
@@ -128,10 +658,700 @@ impl Handler<RemovePlatformConfig> for LiveStreamManager {
     }
 }
 
+impl Handler<StreamStartedEvent> for LiveStreamManager {
+    type Result = ();
+
+    fn handle(&mut self, mut event: StreamStartedEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        info!("Stream started in room {}", event.room_id);
+        self.streams.set_live(&event.room_id, true);
+        event.silent = self.silent_rooms.contains(&event.room_id);
+        self.event_bus.do_send(event);
+    }
+}
+
+impl Handler<StreamEndedEvent> for LiveStreamManager {
+    type Result = ();
+
+    fn handle(&mut self, mut event: StreamEndedEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        info!("Stream ended in room {}", event.room_id);
+        self.streams.set_live(&event.room_id, false);
+        event.silent = self.silent_rooms.contains(&event.room_id);
+        self.event_bus.do_send(event);
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Option<MoodSnapshot>")]
+pub struct GetRoomMood {
+    pub room_id: String,
+}
+
+impl Handler<GetRoomMood> for LiveStreamManager {
+    type Result = Option<MoodSnapshot>;
+
+    fn handle(&mut self, msg: GetRoomMood, _ctx: &mut Context<Self>) -> Self::Result {
+        self.mood.snapshot(&msg.room_id, chrono::Utc::now())
+    }
+}
+
+/// 各直播间最近一分钟的弹幕量
+#[derive(Message)]
+#[rtype(result = "Vec<RoomThroughput>")]
+pub struct GetRoomThroughput;
+
+impl Handler<GetRoomThroughput> for LiveStreamManager {
+    type Result = Vec<RoomThroughput>;
+
+    fn handle(&mut self, _msg: GetRoomThroughput, _ctx: &mut Context<Self>) -> Self::Result {
+        self.quotas.throughput(chrono::Utc::now())
+    }
+}
+
+/// 监听器运行状态：收到的弹幕、重连与最近的错误，含心跳健康度
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenerStatus {
+    pub config_id: String,
+    pub running: bool,
+    pub messages: u64,
+    #[serde(serialize_with = "crate::timezone::serialize_opt")]
+    pub last_message_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub reconnects: u64,
+    pub last_error: Option<String>,
+    pub heartbeat: Option<HeartbeatStatus>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<ListenerStatus>")]
+pub struct GetListenerStatus;
+
+/// 查询回放进度；监听器不存在或不是回放时返回None
+#[derive(Message)]
+#[rtype(result = "Option<ReplayStatus>")]
+pub struct GetReplayStatus {
+    pub config_id: String,
+}
+
+impl Handler<GetReplayStatus> for LiveStreamManager {
+    type Result = Option<ReplayStatus>;
+
+    fn handle(&mut self, msg: GetReplayStatus, _ctx: &mut Context<Self>) -> Self::Result {
+        let control = self.active_listeners.get(&msg.config_id)?.replay()?;
+        Some(control.status())
+    }
+}
+
+/// 暂停、继续、跳转或调整回放速度；监听器不存在或不是回放时返回None
+#[derive(Message)]
+#[rtype(result = "Option<Result<ReplayStatus, String>>")]
+pub struct ControlReplay {
+    pub config_id: String,
+    pub update: ReplayUpdate,
+}
+
+impl Handler<ControlReplay> for LiveStreamManager {
+    type Result = Option<Result<ReplayStatus, String>>;
+
+    fn handle(&mut self, msg: ControlReplay, _ctx: &mut Context<Self>) -> Self::Result {
+        let control = self.active_listeners.get(&msg.config_id)?.replay()?;
+        info!("Updating replay {}: {:?}", msg.config_id, msg.update);
+        Some(control.update(&msg.update))
+    }
+}
+
+impl Handler<GetListenerStatus> for LiveStreamManager {
+    type Result = Vec<ListenerStatus>;
+
+    fn handle(&mut self, _msg: GetListenerStatus, _ctx: &mut Context<Self>) -> Self::Result {
+        let traffic = |config_id: &str| {
+            self.listener_traffic
+                .get(config_id)
+                .map_or((0, None), |&(messages, last)| (messages, Some(last)))
+        };
+        let mut statuses: Vec<ListenerStatus> = self
+            .active_listeners
+            .iter()
+            .map(|(config_id, listener)| {
+                let (messages, last_message_at) = traffic(config_id);
+                let health = listener.health().unwrap_or_default();
+                ListenerStatus {
+                    config_id: config_id.clone(),
+                    running: listener.is_running(),
+                    messages,
+                    last_message_at,
+                    reconnects: health.reconnects,
+                    last_error: health.last_error,
+                    heartbeat: listener.heartbeat(),
+                }
+            })
+            .collect();
+        // 启动失败的监听器也要报告
+        statuses.extend(self.listener_errors.iter().map(|(config_id, error)| {
+            let (messages, last_message_at) = traffic(config_id);
+            ListenerStatus {
+                config_id: config_id.clone(),
+                running: false,
+                messages,
+                last_message_at,
+                reconnects: 0,
+                last_error: Some(error.clone()),
+                heartbeat: None,
+            }
+        }));
+        statuses.sort_by(|a, b| a.config_id.cmp(&b.config_id));
+        statuses
+    }
+}
+
+/// 按提问次数排序的前 `limit` 个问题
+#[derive(Message)]
+#[rtype(result = "Vec<FaqEntry>")]
+pub struct GetFaq {
+    pub room_id: String,
+    pub limit: usize,
+}
+
+impl Handler<GetFaq> for LiveStreamManager {
+    type Result = Vec<FaqEntry>;
+
+    fn handle(&mut self, msg: GetFaq, _ctx: &mut Context<Self>) -> Self::Result {
+        self.faq.top(&msg.room_id, msg.limit)
+    }
+}
+
+/// 按直播间和时间范围查询已存储的弹幕；未启用存储时为None
+#[derive(Message)]
+#[rtype(result = "Option<Result<Vec<DanmakuMessage>, String>>")]
+pub struct QueryDanmaku {
+    pub room_id: String,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: usize,
+}
+
+impl Handler<QueryDanmaku> for LiveStreamManager {
+    type Result = ResponseFuture<Option<Result<Vec<DanmakuMessage>, String>>>;
+
+    fn handle(&mut self, msg: QueryDanmaku, _ctx: &mut Context<Self>) -> Self::Result {
+        let query = self
+            .store
+            .as_ref()
+            .map(|store| store.query(&msg.room_id, msg.from, msg.to, msg.limit));
+        Box::pin(async move {
+            match query {
+                Some(query) => Some(query.await),
+                None => None,
+            }
+        })
+    }
+}
+
+/// 弹幕存储当前写入的文件及轮转情况；未启用存储时为None
+#[derive(Message)]
+#[rtype(result = "Option<DanmakuStoreStatus>")]
+pub struct GetDanmakuStoreStatus;
+
+impl Handler<GetDanmakuStoreStatus> for LiveStreamManager {
+    type Result = Option<DanmakuStoreStatus>;
+
+    fn handle(&mut self, _msg: GetDanmakuStoreStatus, _ctx: &mut Context<Self>) -> Self::Result {
+        self.store.as_ref().map(DanmakuStore::status)
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<SamplingStats>")]
+pub struct GetSamplingStats;
+
+impl Handler<GetSamplingStats> for LiveStreamManager {
+    type Result = Vec<SamplingStats>;
+
+    fn handle(&mut self, _msg: GetSamplingStats, _ctx: &mut Context<Self>) -> Self::Result {
+        self.sampler.stats()
+    }
+}
+
 impl Handler<ProcessDanmaku> for LiveStreamManager {
     type Result = ();
 
-    fn handle(&mut self, msg: ProcessDanmaku, _ctx: &mut Context<Self>) -> Self::Result {
-        self.process_danmaku(msg.danmaku);
+    fn handle(&mut self, msg: ProcessDanmaku, ctx: &mut Context<Self>) -> Self::Result {
+        self.process_danmaku(msg.danmaku, ctx);
+    }
+}
+
+/// A danmaku delivered by a platform webhook, which may retry it under the
+/// same idempotency key. Returns false for a retry, which is not processed.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ProcessWebhookDanmaku {
+    pub danmaku: DanmakuMessage,
+    pub idempotency_key: Option<String>,
+}
+
+impl Handler<ProcessWebhookDanmaku> for LiveStreamManager {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ProcessWebhookDanmaku, ctx: &mut Context<Self>) -> Self::Result {
+        if let Some(key) = &msg.idempotency_key {
+            // 不同平台的键可能重复
+            let key = format!("{}:{}", msg.danmaku.platform.to_string(), key);
+            if !self.webhook_keys.insert(&key, std::time::Instant::now()) {
+                info!("Ignoring retried webhook delivery {}", key);
+                return false;
+            }
+        }
+        self.process_danmaku(msg.danmaku, ctx);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::DanmakuStoreConfig;
+    use crate::testing::Collect;
+
+    #[actix_web::test]
+    async fn test_every_danmaku_is_stored_even_when_not_answered() {
+        let path = std::env::temp_dir().join(format!("danmaku-{}.jsonl", Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        let config = DanmakuStoreConfig {
+            // Small enough to rotate partway through
+            max_bytes: 600,
+            max_files: 3,
+            ..DanmakuStoreConfig::new(&path)
+        };
+        let store = DanmakuStore::open(config).unwrap();
+        let event_bus = EventBus::new().start();
+        let manager = LiveStreamManager::new(event_bus)
+            .with_sampling(SamplingPolicy::Probability { rate: 0.0 })
+            .with_danmaku_store(store)
+            .start();
+
+        let start = chrono::Utc::now();
+        for i in 0..6 {
+            for room_id in ["1001", "2002"] {
+                manager
+                    .send(ProcessDanmaku {
+                        danmaku: DanmakuMessage {
+                            platform: Platform::Bilibili,
+                            room_id: room_id.to_string(),
+                            user_id: format!("{}", i),
+                            username: format!("观众{}", i),
+                            message: format!("第{}条弹幕", i),
+                            timestamp: chrono::Utc::now(),
+                            user_level: Some(i),
+                            is_vip: i % 2 == 0,
+                            gift_value: None,
+                            trusted: false,
+                        },
+                    })
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let stored = manager
+            .send(QueryDanmaku {
+                room_id: "1001".to_string(),
+                from: Some(start),
+                to: None,
+                limit: 100,
+            })
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let messages: Vec<_> = stored.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "第0条弹幕",
+                "第1条弹幕",
+                "第2条弹幕",
+                "第3条弹幕",
+                "第4条弹幕",
+                "第5条弹幕"
+            ]
+        );
+        assert!(std::path::Path::new(&format!("{}.1", path)).exists());
+
+        let stats = manager.send(GetSamplingStats).await.unwrap();
+        assert!(stats
+            .iter()
+            .all(|room| room.seen == 6 && room.selected == 0));
+
+        for file in [
+            path.clone(),
+            format!("{}.1", path),
+            format!("{}.2", path),
+            format!("{}.3", path),
+        ] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_rapid_fragments_are_merged_into_one_input() {
+        let event_bus = EventBus::new().start();
+        let inputs = Collect::<TextInputEvent>::default().start();
+        event_bus
+            .send(crate::event_bus::Subscribe::<TextInputEvent>::all(
+                inputs.clone().recipient(),
+            ))
+            .await
+            .unwrap();
+        let manager = LiveStreamManager::new(event_bus)
+            .with_merging(MergeConfig {
+                window_ms: Some(100),
+                max_parts: 4,
+            })
+            .start();
+        let danmaku = |user_id: &str, message: &str| ProcessDanmaku {
+            danmaku: DanmakuMessage {
+                platform: Platform::Bilibili,
+                room_id: "1001".to_string(),
+                user_id: user_id.to_string(),
+                username: format!("观众{}", user_id),
+                message: message.to_string(),
+                timestamp: chrono::Utc::now(),
+                user_level: None,
+                is_vip: false,
+                gift_value: None,
+                trusted: false,
+            },
+        };
+
+        // Another viewer's message in between does not split the thought
+        for (user_id, message) in [
+            ("42", "what do"),
+            ("7", "主播好"),
+            ("42", "you think"),
+            ("42", "about this?"),
+        ] {
+            manager.send(danmaku(user_id, message)).await.unwrap();
+            actix::clock::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let texts = |inputs: Vec<TextInputEvent>| -> Vec<String> {
+            inputs.into_iter().map(|event| event.text).collect()
+        };
+        assert_eq!(
+            texts(Collect::received(&inputs).await),
+            vec!["what do you think about this?"]
+        );
+
+        // Without a sentence ending, the held parts go out once the window passes
+        actix::clock::sleep(std::time::Duration::from_millis(150)).await;
+        assert_eq!(
+            texts(Collect::received(&inputs).await),
+            vec!["what do you think about this?", "主播好"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_similar_danmaku_are_collapsed_before_sampling() {
+        let event_bus = EventBus::new().start();
+        let inputs = Collect::<TextInputEvent>::default().start();
+        event_bus
+            .send(crate::event_bus::Subscribe::<TextInputEvent>::all(
+                inputs.clone().recipient(),
+            ))
+            .await
+            .unwrap();
+        let manager = LiveStreamManager::new(event_bus)
+            .with_dedup(DedupConfig {
+                window_seconds: Some(1),
+                ..Default::default()
+            })
+            .start();
+
+        for (i, message) in ["哈哈哈哈", "哈哈哈哈哈", "笑死我了哈哈"]
+            .iter()
+            .enumerate()
+        {
+            manager
+                .send(ProcessDanmaku {
+                    danmaku: DanmakuMessage {
+                        platform: Platform::Bilibili,
+                        room_id: "1001".to_string(),
+                        user_id: i.to_string(),
+                        username: format!("观众{}", i),
+                        message: message.to_string(),
+                        timestamp: chrono::Utc::now(),
+                        user_level: None,
+                        is_vip: false,
+                        gift_value: None,
+                        trusted: false,
+                    },
+                })
+                .await
+                .unwrap();
+        }
+        actix::clock::sleep(std::time::Duration::from_millis(1100)).await;
+
+        let received = Collect::received(&inputs).await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].viewer.as_ref().unwrap().similar_count, 2);
+        // The sampler saw the wave once, not once per danmaku
+        let stats = manager.send(GetSamplingStats).await.unwrap();
+        assert_eq!(stats[0].seen, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_danmaku_are_processed_only_while_the_stream_is_live() {
+        let event_bus = EventBus::new().start();
+        let inputs = Collect::<TextInputEvent>::default().start();
+        event_bus
+            .send(crate::event_bus::Subscribe::<TextInputEvent>::all(
+                inputs.clone().recipient(),
+            ))
+            .await
+            .unwrap();
+        let manager = LiveStreamManager::new(event_bus)
+            .with_stream_lifecycle(&LifecycleConfig {
+                require_start: true,
+                ..Default::default()
+            })
+            .start();
+        let danmaku = |message: &str| ProcessDanmaku {
+            danmaku: DanmakuMessage {
+                platform: Platform::Bilibili,
+                room_id: "1001".to_string(),
+                user_id: "42".to_string(),
+                username: "观众42".to_string(),
+                message: message.to_string(),
+                timestamp: chrono::Utc::now(),
+                user_level: None,
+                is_vip: false,
+                gift_value: None,
+                trusted: false,
+            },
+        };
+        let room = || "1001".to_string();
+
+        manager.send(danmaku("还没开播吗？")).await.unwrap();
+        manager
+            .send(StreamStartedEvent {
+                metadata: EventMetadata::default(),
+                room_id: room(),
+                silent: false,
+            })
+            .await
+            .unwrap();
+        manager.send(danmaku("开播啦！")).await.unwrap();
+        manager
+            .send(StreamEndedEvent {
+                metadata: EventMetadata::default(),
+                room_id: room(),
+                silent: false,
+            })
+            .await
+            .unwrap();
+        manager.send(danmaku("下次见！")).await.unwrap();
+
+        let texts: Vec<String> = Collect::received(&inputs)
+            .await
+            .into_iter()
+            .map(|event| event.text)
+            .collect();
+        assert_eq!(texts, vec!["开播啦！"]);
+    }
+
+    #[actix_web::test]
+    async fn test_silent_room_stream_changes_are_marked_silent() {
+        let event_bus = EventBus::new().start();
+        let started = Collect::<StreamStartedEvent>::default().start();
+        event_bus
+            .send(crate::event_bus::Subscribe::<StreamStartedEvent>::all(
+                started.clone().recipient(),
+            ))
+            .await
+            .unwrap();
+        let manager = LiveStreamManager::new(event_bus).start();
+        manager
+            .send(SetRoomResponding {
+                room_id: "1001".to_string(),
+                respond: false,
+            })
+            .await
+            .unwrap();
+
+        for room_id in ["1001", "1002"] {
+            manager
+                .send(StreamStartedEvent {
+                    metadata: EventMetadata::default(),
+                    room_id: room_id.to_string(),
+                    silent: false,
+                })
+                .await
+                .unwrap();
+        }
+        actix::clock::sleep(std::time::Duration::from_millis(20)).await;
+
+        // The persona gives no intro in a room it is not answering
+        let silent: Vec<(String, bool)> = Collect::received(&started)
+            .await
+            .into_iter()
+            .map(|event| (event.room_id, event.silent))
+            .collect();
+        assert_eq!(
+            silent,
+            vec![("1001".to_string(), true), ("1002".to_string(), false)]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_silent_room_records_danmaku_without_answering() {
+        let event_bus = EventBus::new().start();
+        let inputs = Collect::<TextInputEvent>::default().start();
+        event_bus
+            .send(crate::event_bus::Subscribe::<TextInputEvent>::all(
+                inputs.clone().recipient(),
+            ))
+            .await
+            .unwrap();
+        let manager = LiveStreamManager::new(event_bus).start();
+        manager
+            .send(AddPlatformConfig {
+                config: LiveStreamConfig {
+                    platform: Platform::Bilibili,
+                    room_id: "1001".to_string(),
+                    room_ids: Vec::new(),
+                    api_key: None,
+                    webhook_url: None,
+                    replay_path: None,
+                    enabled: false,
+                    sampling: None,
+                    quota: None,
+                    max_age_seconds: None,
+                    respond: false,
+                },
+            })
+            .await
+            .unwrap();
+        let danmaku = |message: &str| ProcessDanmaku {
+            danmaku: DanmakuMessage {
+                platform: Platform::Bilibili,
+                room_id: "1001".to_string(),
+                user_id: "42".to_string(),
+                username: "观众42".to_string(),
+                message: message.to_string(),
+                timestamp: chrono::Utc::now(),
+                user_level: None,
+                is_vip: false,
+                gift_value: None,
+                trusted: false,
+            },
+        };
+
+        manager.send(danmaku("主播今天好开心！")).await.unwrap();
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(Collect::received(&inputs).await.is_empty());
+        let mood = manager
+            .send(GetRoomMood {
+                room_id: "1001".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(mood.is_some());
+
+        manager
+            .send(SetRoomResponding {
+                room_id: "1001".to_string(),
+                respond: true,
+            })
+            .await
+            .unwrap();
+        manager.send(danmaku("可以回复了吗？")).await.unwrap();
+        let texts: Vec<String> = Collect::received(&inputs)
+            .await
+            .into_iter()
+            .map(|event| event.text)
+            .collect();
+        assert_eq!(texts, vec!["可以回复了吗？"]);
+    }
+
+    /// Refuses every fetch for room 2002; other rooms never get a batch.
+    struct FlakySource;
+
+    impl DanmakuSource for FlakySource {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn fetch(
+            &self,
+            room_id: &str,
+        ) -> futures_util::future::BoxFuture<'static, Result<Vec<serde_json::Value>, String>>
+        {
+            let refused = room_id == "2002";
+            Box::pin(async move {
+                if refused {
+                    return Err("connection refused".to_string());
+                }
+                futures_util::future::pending().await
+            })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_listener_status_tells_busy_from_idle() {
+        let event_bus = EventBus::new().start();
+        let manager = LiveStreamManager::new(event_bus)
+            .with_douyin_source(Arc::new(FlakySource))
+            .start();
+        manager
+            .send(AddPlatformConfig {
+                config: LiveStreamConfig {
+                    platform: Platform::Douyin,
+                    room_id: "1001".to_string(),
+                    room_ids: vec!["2002".to_string(), "3003".to_string()],
+                    api_key: None,
+                    webhook_url: None,
+                    replay_path: None,
+                    enabled: true,
+                    sampling: None,
+                    quota: None,
+                    max_age_seconds: None,
+                    respond: true,
+                },
+            })
+            .await
+            .unwrap();
+
+        for message in ["主播好", "今天播什么"] {
+            manager
+                .send(ProcessDanmaku {
+                    danmaku: DanmakuMessage {
+                        platform: Platform::Douyin,
+                        room_id: "1001".to_string(),
+                        user_id: "42".to_string(),
+                        username: "小明".to_string(),
+                        message: message.to_string(),
+                        timestamp: chrono::Utc::now(),
+                        user_level: None,
+                        is_vip: false,
+                        gift_value: None,
+                        trusted: false,
+                    },
+                })
+                .await
+                .unwrap();
+        }
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+
+        let statuses = manager.send(GetListenerStatus).await.unwrap();
+        let ids: Vec<_> = statuses.iter().map(|s| s.config_id.as_str()).collect();
+        assert_eq!(ids, vec!["Douyin_1001", "Douyin_2002", "Douyin_3003"]);
+        let (busy, failing, idle) = (&statuses[0], &statuses[1], &statuses[2]);
+
+        assert!(busy.running && idle.running);
+        assert_eq!(busy.messages, 2);
+        assert!(busy.last_message_at.is_some());
+        assert_eq!(idle.messages, 0);
+        assert!(idle.last_message_at.is_none());
+        assert!(idle.last_error.is_none());
+
+        assert_eq!(failing.reconnects, 1);
+        assert_eq!(failing.last_error.as_deref(), Some("connection refused"));
     }
 }
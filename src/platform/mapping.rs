@@ -0,0 +1,283 @@
+use crate::platform::{DanmakuMessage, Platform};
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// Where a value sits in a payload: dot-separated keys with `[n]` array
+/// indexes, optionally led by `$.`, e.g. `data.user.uid` or `info[2][0]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector(Vec<Step>);
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = s.trim();
+        let path = path
+            .strip_prefix("$.")
+            .or_else(|| path.strip_prefix('$'))
+            .unwrap_or(path);
+        let mut steps = Vec::new();
+        for part in path.split('.') {
+            let (key, mut indexes) = part.split_at(part.find('[').unwrap_or(part.len()));
+            if !key.is_empty() {
+                steps.push(Step::Key(key.to_string()));
+            }
+            while let Some(rest) = indexes.strip_prefix('[') {
+                let (index, rest) = rest
+                    .split_once(']')
+                    .ok_or_else(|| format!("unclosed index in selector '{}'", s))?;
+                let index = index
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid index '{}' in selector '{}'", index, s))?;
+                steps.push(Step::Index(index));
+                indexes = rest;
+            }
+            if !indexes.is_empty() {
+                return Err(format!("unexpected '{}' in selector '{}'", indexes, s));
+            }
+        }
+        if steps.is_empty() {
+            return Err(format!("empty selector '{}'", s));
+        }
+        Ok(Selector(steps))
+    }
+}
+
+impl Selector {
+    pub fn select<'a>(&self, payload: &'a Value) -> Option<&'a Value> {
+        self.0.iter().try_fold(payload, |value, step| match step {
+            Step::Key(key) => value.get(key),
+            Step::Index(index) => value.get(index),
+        })
+    }
+}
+
+/// Where each danmaku field is found in a platform's payloads, for bridges
+/// that emit their own JSON shape. Unset fields use the built-in location.
+#[derive(Debug, Clone, Default)]
+pub struct FieldMapping {
+    pub message: Option<Selector>,
+    pub user_id: Option<Selector>,
+    pub username: Option<Selector>,
+    pub room_id: Option<Selector>,
+    pub level: Option<Selector>,
+    pub vip: Option<Selector>,
+    pub gift: Option<Selector>,
+}
+
+fn selector(path: &str) -> Option<Selector> {
+    path.parse().ok()
+}
+
+impl FieldMapping {
+    /// Where the webhook payloads of `platform` carry each field.
+    pub fn built_in(platform: &Platform) -> Self {
+        match platform {
+            Platform::Bilibili => Self {
+                message: selector("info[1]"),
+                user_id: selector("info[2][0]"),
+                username: selector("info[2][1]"),
+                room_id: selector("roomid"),
+                level: None,
+                vip: None,
+                gift: None,
+            },
+            _ => Self {
+                message: selector("message"),
+                user_id: selector("user_id"),
+                username: selector("username"),
+                room_id: selector("room_id"),
+                level: selector("user_level"),
+                vip: selector("is_vip"),
+                gift: selector("gift_value"),
+            },
+        }
+    }
+
+    /// Applies `field=selector` pairs such as
+    /// `message=data.content,user_id=data.user.uid`.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (field, path) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected field=selector, got '{}'", entry))?;
+            let selector = Some(path.parse()?);
+            match field.trim() {
+                "message" => self.message = selector,
+                "user_id" => self.user_id = selector,
+                "username" => self.username = selector,
+                "room_id" => self.room_id = selector,
+                "level" => self.level = selector,
+                "vip" => self.vip = selector,
+                "gift" => self.gift = selector,
+                other => return Err(format!("unknown danmaku field: {}", other)),
+            }
+        }
+        Ok(())
+    }
+
+    /// This mapping with its unset fields taken from `defaults`.
+    fn or(&self, defaults: Self) -> Self {
+        Self {
+            message: self.message.clone().or(defaults.message),
+            user_id: self.user_id.clone().or(defaults.user_id),
+            username: self.username.clone().or(defaults.username),
+            room_id: self.room_id.clone().or(defaults.room_id),
+            level: self.level.clone().or(defaults.level),
+            vip: self.vip.clone().or(defaults.vip),
+            gift: self.gift.clone().or(defaults.gift),
+        }
+    }
+
+    /// Reads a danmaku from `payload`. Only the message is required.
+    pub fn parse(&self, platform: Platform, payload: &Value) -> Result<DanmakuMessage, String> {
+        let fields = self.or(Self::built_in(&platform));
+        let select = |selector: &Option<Selector>| {
+            selector
+                .as_ref()
+                .and_then(|selector| selector.select(payload))
+        };
+
+        let message = select(&fields.message)
+            .and_then(text)
+            .ok_or("Missing message field")?;
+        Ok(DanmakuMessage {
+            platform,
+            room_id: select(&fields.room_id)
+                .and_then(text)
+                .unwrap_or_else(|| "unknown".to_string()),
+            user_id: select(&fields.user_id)
+                .and_then(text)
+                .unwrap_or_else(|| "anonymous".to_string()),
+            username: select(&fields.username)
+                .and_then(text)
+                .unwrap_or_else(|| "用户".to_string()),
+            message,
+            timestamp: chrono::Utc::now(),
+            user_level: select(&fields.level).and_then(level),
+            is_vip: select(&fields.vip).is_some_and(flag),
+            gift_value: select(&fields.gift).and_then(amount),
+            trusted: false,
+        })
+    }
+}
+
+/// Strings as they are; numbers, which bridges often use for ids, as text.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn level(value: &Value) -> Option<u32> {
+    match value {
+        Value::Number(n) => n.as_u64().map(|l| l as u32),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn amount(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn flag(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => matches!(s.trim(), "true" | "1"),
+        _ => false,
+    }
+}
+
+/// Field mappings for the platforms that take webhook payloads.
+#[derive(Debug, Clone, Default)]
+pub struct FieldMappings {
+    pub douyin: FieldMapping,
+    pub bilibili: FieldMapping,
+}
+
+static MAPPINGS: OnceLock<FieldMappings> = OnceLock::new();
+
+/// Installs the process-wide field mappings. Only the first call takes effect.
+pub fn init_field_mappings(mappings: FieldMappings) {
+    let _ = MAPPINGS.set(mappings);
+}
+
+/// The configured mapping for `platform`; empty when none is installed.
+pub fn field_mapping(platform: &Platform) -> &'static FieldMapping {
+    static EMPTY: FieldMapping = FieldMapping {
+        message: None,
+        user_id: None,
+        username: None,
+        room_id: None,
+        level: None,
+        vip: None,
+        gift: None,
+    };
+    let mappings = MAPPINGS.get_or_init(FieldMappings::default);
+    match platform {
+        Platform::Douyin => &mappings.douyin,
+        Platform::Bilibili => &mappings.bilibili,
+        _ => &EMPTY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_payload_is_parsed_with_mapping() {
+        let mut mapping = FieldMapping::default();
+        mapping
+            .apply(
+                "message=$.data.content, user_id=data.user.uid, username=data.user.nick, \
+                 level=data.user.badges[0].level, vip=data.user.member, gift=data.gift.price",
+            )
+            .unwrap();
+        let payload = serde_json::json!({
+            "data": {
+                "content": "主播好！",
+                "user": {
+                    "uid": 42,
+                    "nick": "小明",
+                    "badges": [{"level": "7"}],
+                    "member": 1
+                },
+                "gift": {"price": "9.9"}
+            },
+            // Unmapped, so read from the built-in location
+            "room_id": "1001"
+        });
+
+        let danmaku = mapping.parse(Platform::Douyin, &payload).unwrap();
+        assert_eq!(danmaku.message, "主播好！");
+        assert_eq!(danmaku.user_id, "42");
+        assert_eq!(danmaku.username, "小明");
+        assert_eq!(danmaku.room_id, "1001");
+        assert_eq!(danmaku.user_level, Some(7));
+        assert!(danmaku.is_vip);
+        assert_eq!(danmaku.gift_value, Some(9.9));
+
+        assert!(mapping
+            .parse(Platform::Douyin, &serde_json::json!({"message": "x"}))
+            .is_err());
+        assert!(mapping.apply("mood=data.mood").is_err());
+        assert!(mapping.apply("message=info[x]").is_err());
+    }
+}
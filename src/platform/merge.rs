@@ -0,0 +1,129 @@
+use crate::platform::DanmakuMessage;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// 句末标点，以此结尾的弹幕视为一句话说完
+const SENTENCE_ENDINGS: &[char] = &['。', '！', '？', '!', '?', '.', '~', '～', '…'];
+
+/// Merges a viewer's quick successive danmaku ("主播你觉得" "这首歌"
+/// "怎么样？") into one message. Off unless `window_ms` is set.
+#[derive(Debug, Clone)]
+pub struct MergeConfig {
+    /// Milliseconds to wait for the next part after each fragment.
+    pub window_ms: Option<u64>,
+    /// Parts merged at most; reaching it sends the message at once.
+    pub max_parts: usize,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: None,
+            max_parts: 4,
+        }
+    }
+}
+
+/// The result of offering a danmaku to the merger.
+#[derive(Debug)]
+pub enum Merged {
+    /// The message is complete and can be processed now.
+    Ready(DanmakuMessage),
+    /// Held for more parts; `release` it by `key` and `id` once the window
+    /// passes.
+    Held { key: String, id: u64 },
+}
+
+#[derive(Debug)]
+struct Pending {
+    /// Id of the latest part; each part restarts the window.
+    id: u64,
+    parts: Vec<DanmakuMessage>,
+}
+
+/// Fragments held per viewer and room until the viewer finishes the thought.
+#[derive(Debug, Default)]
+pub struct DanmakuMerger {
+    config: MergeConfig,
+    pending: HashMap<String, Pending>,
+    next_id: u64,
+}
+
+impl DanmakuMerger {
+    pub fn new(config: MergeConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn window(&self) -> Option<Duration> {
+        self.config.window_ms.map(Duration::from_millis)
+    }
+
+    /// Adds `danmaku` to its viewer's held parts. The merged message is ready
+    /// once a part ends a sentence or `max_parts` is reached.
+    pub fn offer(&mut self, danmaku: DanmakuMessage) -> Merged {
+        let key = format!(
+            "{}:{}:{}",
+            danmaku.platform.to_string(),
+            danmaku.room_id,
+            danmaku.user_id
+        );
+        let ends_sentence = danmaku.message.trim_end().ends_with(SENTENCE_ENDINGS);
+        self.next_id += 1;
+        let pending = self.pending.entry(key.clone()).or_insert(Pending {
+            id: self.next_id,
+            parts: Vec::new(),
+        });
+        pending.id = self.next_id;
+        pending.parts.push(danmaku);
+
+        if ends_sentence || pending.parts.len() >= self.config.max_parts.max(1) {
+            let parts = self
+                .pending
+                .remove(&key)
+                .map(|p| p.parts)
+                .unwrap_or_default();
+            return Merged::Ready(merge(parts));
+        }
+        Merged::Held {
+            key,
+            id: self.next_id,
+        }
+    }
+
+    /// The merged message, unless another part arrived after `id` or it was
+    /// already sent.
+    pub fn release(&mut self, key: &str, id: u64) -> Option<DanmakuMessage> {
+        if self.pending.get(key)?.id != id {
+            return None;
+        }
+        self.pending.remove(key).map(|p| merge(p.parts))
+    }
+}
+
+/// 合并为一条，沿用第一段的到达时间；拉丁字母之间补空格，中文直接相连
+fn merge(parts: Vec<DanmakuMessage>) -> DanmakuMessage {
+    let mut parts = parts.into_iter();
+    let mut merged = parts.next().expect("merged danmaku has at least one part");
+    merged.message = merged.message.trim().to_string();
+    for part in parts {
+        let text = part.message.trim();
+        let spaced = merged
+            .message
+            .ends_with(|c: char| c.is_ascii_alphanumeric() || c.is_ascii_punctuation())
+            && text.starts_with(|c: char| c.is_ascii_alphanumeric());
+        if spaced {
+            merged.message.push(' ');
+        }
+        merged.message.push_str(text);
+        merged.user_level = part.user_level.or(merged.user_level);
+        merged.is_vip |= part.is_vip;
+        merged.trusted &= part.trusted;
+        if let Some(value) = part.gift_value {
+            *merged.gift_value.get_or_insert(0.0) += value;
+        }
+    }
+    merged
+}
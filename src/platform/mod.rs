@@ -1,21 +1,66 @@
 mod bilibili;
+mod dedup;
 mod douyin;
+mod faq;
+mod health;
+mod heartbeat;
+mod idempotency;
 mod manager;
+mod mapping;
+mod merge;
+mod mood;
+mod quota;
+mod replay;
+mod sampling;
+mod store;
+mod throttle;
 mod websocket;
 mod youtube;
 
 use actix::prelude::*;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[allow(unused)]
 pub use {
-    bilibili::BilibiliListener, douyin::DouyinListener, manager::AddPlatformConfig,
-    manager::LiveStreamManager, manager::RemovePlatformConfig, websocket::WebSocketListener,
+    bilibili::{parse_bilibili_danmaku, BilibiliListener},
+    dedup::DedupConfig,
+    douyin::{
+        parse_douyin_danmaku, DanmakuSource, DouyinConfig, DouyinListener, DouyinSourceKind,
+        HttpPollSource, WebhookBridgeSource,
+    },
+    faq::{FaqConfig, FaqEntry},
+    health::{ConnectionHealth, ListenerHealth},
+    heartbeat::{Heartbeat, HeartbeatConfig, HeartbeatSend, HeartbeatStatus, ReconnectHook},
+    idempotency::IdempotencyConfig,
+    manager::AddPlatformConfig,
+    manager::ControlReplay,
+    manager::GetDanmakuStoreStatus,
+    manager::GetFaq,
+    manager::GetListenerStatus,
+    manager::GetReplayStatus,
+    manager::GetRoomMood,
+    manager::GetRoomThroughput,
+    manager::GetSamplingStats,
+    manager::ListenerStatus,
+    manager::LiveStreamManager,
+    manager::ProcessWebhookDanmaku,
+    manager::QueryDanmaku,
+    manager::RemovePlatformConfig,
+    manager::SetRoomResponding,
+    mapping::{init_field_mappings, FieldMapping, FieldMappings, Selector},
+    merge::MergeConfig,
+    quota::{RoomQuota, RoomThroughput},
+    replay::{ReplayControl, ReplayListener, ReplayState, ReplayStatus, ReplayUpdate},
+    sampling::SamplingPolicy,
+    store::{DanmakuStore, DanmakuStoreConfig, DanmakuStoreStatus},
+    throttle::ThrottleConfig,
+    websocket::WebSocketListener,
     youtube::YouTubeListener,
 };
 
-#[derive(Message)]
+#[derive(Message, Clone)]
 #[rtype(result = "()")]
 pub struct ProcessDanmaku {
     pub danmaku: DanmakuMessage,
@@ -28,26 +73,106 @@ pub struct DanmakuMessage {
     pub user_id: String,
     pub username: String,
     pub message: String,
+    /// When the danmaku reached us, not when the viewer sent it.
     pub timestamp: DateTime<Utc>,
     pub user_level: Option<u32>,
     pub is_vip: bool,
+    /// Value of a gift sent with the danmaku, in the platform's units.
+    #[serde(default)]
+    pub gift_value: Option<f64>,
+    /// Delivered by a listener connected to the platform itself, which
+    /// vouches for `user_id`. Never set for webhook deliveries or danmaku
+    /// read back from storage.
+    #[serde(skip)]
+    pub trusted: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl DanmakuMessage {
+    /// The same for every danmaku from this viewer in this room, so their
+    /// follow-ups land in one conversation.
+    pub fn session_id(&self) -> Uuid {
+        let name = format!(
+            "danmaku:{}:{}:{}",
+            self.platform.to_string(),
+            self.room_id,
+            self.user_id
+        );
+        let hash = openssl::sha::sha1(name.as_bytes());
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hash[..16]);
+        uuid::Builder::from_sha1_bytes(bytes).into_uuid()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Platform {
     Douyin,
     Bilibili,
     YouTube,
     WebSocket,
+    /// Danmaku recorded earlier, played back from `replay_path`.
+    Replay,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LiveStreamConfig {
     pub platform: Platform,
     pub room_id: String,
+    /// Further rooms to listen to. Platforms that can share a connection
+    /// between rooms use one listener; the rest get a listener per room.
+    #[serde(default)]
+    pub room_ids: Vec<String>,
     pub api_key: Option<String>,
     pub webhook_url: Option<String>,
+    /// Recording in the danmaku store's format, for the replay platform.
+    #[serde(default)]
+    pub replay_path: Option<String>,
     pub enabled: bool,
+    /// Overrides the default response sampling for this room.
+    #[serde(default)]
+    pub sampling: Option<SamplingPolicy>,
+    /// Overrides the default danmaku quota for each of the rooms.
+    #[serde(default)]
+    pub quota: Option<RoomQuota>,
+    /// Overrides the default max danmaku age, in seconds, for each of the rooms.
+    #[serde(default)]
+    pub max_age_seconds: Option<u64>,
+    /// When false the rooms' danmaku are still recorded and counted towards
+    /// mood and FAQ, but the digital human does not answer them.
+    #[serde(default = "respond_by_default")]
+    pub respond: bool,
+}
+
+fn respond_by_default() -> bool {
+    true
+}
+
+impl LiveStreamConfig {
+    /// `room_id` followed by `room_ids`, without blanks or duplicates.
+    pub fn rooms(&self) -> Vec<&str> {
+        let mut rooms: Vec<&str> = Vec::new();
+        for room in std::iter::once(&self.room_id).chain(&self.room_ids) {
+            let room = room.trim();
+            if !room.is_empty() && !rooms.contains(&room) {
+                rooms.push(room);
+            }
+        }
+        rooms
+    }
+
+    /// Identifies the listener, e.g. `Bilibili_1001` or `Bilibili_1001+1002`.
+    pub fn config_id(&self) -> String {
+        format!("{:?}_{}", self.platform, self.rooms().join("+"))
+    }
+
+    /// This config narrowed to one of its rooms.
+    pub fn for_room(&self, room_id: &str) -> Self {
+        Self {
+            room_id: room_id.to_string(),
+            room_ids: Vec::new(),
+            ..self.clone()
+        }
+    }
 }
 
 #[allow(unused)]
@@ -55,15 +180,36 @@ pub trait PlatformListener: Send {
     fn start(&mut self) -> Result<(), Box<dyn std::error::Error>>;
     fn stop(&mut self);
     fn is_running(&self) -> bool;
+
+    /// Keep-alive health, for listeners holding a connection that needs heartbeats.
+    fn heartbeat(&self) -> Option<HeartbeatStatus> {
+        None
+    }
+
+    /// Connection failures, for listeners that retry their connection.
+    fn health(&self) -> Option<ConnectionHealth> {
+        None
+    }
+
+    /// Playback controls, for listeners replaying a recording.
+    fn replay(&self) -> Option<ReplayControl> {
+        None
+    }
 }
 
 impl Platform {
+    /// Whether one connection can carry danmaku from several rooms.
+    pub fn supports_multi_room(&self) -> bool {
+        matches!(self, Platform::Bilibili)
+    }
+
     pub fn to_string(&self) -> String {
         match self {
             Platform::Douyin => "douyin".to_string(),
             Platform::Bilibili => "bilibili".to_string(),
             Platform::YouTube => "youtube".to_string(),
             Platform::WebSocket => "websocket".to_string(),
+            Platform::Replay => "replay".to_string(),
         }
     }
 }
@@ -0,0 +1,153 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How much a single danmaku moves the room mood toward its own sentiment.
+const SAMPLE_WEIGHT: f64 = 0.2;
+/// Scores beyond this are reported as happy or negative rather than neutral.
+const LABEL_THRESHOLD: f64 = 0.2;
+
+#[derive(Debug, Clone)]
+struct RoomMood {
+    score: f64,
+    samples: u64,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MoodSnapshot {
+    pub room_id: String,
+    /// Exponentially weighted sentiment, from -1.0 to 1.0.
+    pub score: f64,
+    pub label: &'static str,
+    pub samples: u64,
+    #[serde(serialize_with = "crate::timezone::serialize")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Rolls up danmaku sentiment per room into a score that decays back to
+/// neutral when chat goes quiet.
+#[derive(Debug)]
+pub struct MoodTracker {
+    half_life_seconds: f64,
+    rooms: HashMap<String, RoomMood>,
+}
+
+impl MoodTracker {
+    pub fn new(half_life_seconds: u64) -> Self {
+        Self {
+            half_life_seconds: half_life_seconds.max(1) as f64,
+            rooms: HashMap::new(),
+        }
+    }
+
+    fn decayed(&self, mood: &RoomMood, now: DateTime<Utc>) -> f64 {
+        let elapsed = now
+            .signed_duration_since(mood.updated_at)
+            .num_milliseconds() as f64
+            / 1000.0;
+        mood.score * 0.5f64.powf(elapsed.max(0.0) / self.half_life_seconds)
+    }
+
+    pub fn record(&mut self, room_id: &str, sentiment: f32, now: DateTime<Utc>) {
+        let current = self
+            .rooms
+            .get(room_id)
+            .map_or(0.0, |mood| self.decayed(mood, now));
+        let score = current + SAMPLE_WEIGHT * (sentiment as f64 - current);
+
+        let mood = self.rooms.entry(room_id.to_string()).or_insert(RoomMood {
+            score: 0.0,
+            samples: 0,
+            updated_at: now,
+        });
+        mood.score = score;
+        mood.samples += 1;
+        mood.updated_at = now;
+    }
+
+    pub fn snapshot(&self, room_id: &str, now: DateTime<Utc>) -> Option<MoodSnapshot> {
+        let mood = self.rooms.get(room_id)?;
+        let score = self.decayed(mood, now);
+        let label = if score >= LABEL_THRESHOLD {
+            "happy"
+        } else if score <= -LABEL_THRESHOLD {
+            "negative"
+        } else {
+            "neutral"
+        };
+
+        Some(MoodSnapshot {
+            room_id: room_id.to_string(),
+            score,
+            label,
+            samples: mood.samples,
+            updated_at: mood.updated_at,
+        })
+    }
+}
+
+impl Default for MoodTracker {
+    fn default() -> Self {
+        Self::new(120)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sentiment;
+    use chrono::Duration;
+
+    #[test]
+    fn test_mood_shifts_with_chat_and_decays() {
+        let mut tracker = MoodTracker::new(60);
+        let start = Utc::now();
+
+        for (i, text) in ["主播太厉害了", "哈哈哈哈", "awesome stream", "好可爱"]
+            .iter()
+            .enumerate()
+        {
+            tracker.record(
+                "room",
+                sentiment::score(text),
+                start + Duration::seconds(i as i64),
+            );
+        }
+        let happy = tracker
+            .snapshot("room", start + Duration::seconds(4))
+            .unwrap();
+        assert!(happy.score > LABEL_THRESHOLD);
+        assert_eq!(happy.label, "happy");
+
+        for (i, text) in [
+            "好无聊",
+            "退钱",
+            "垃圾节目",
+            "so boring",
+            "无聊死了",
+            "失望",
+        ]
+        .iter()
+        .enumerate()
+        {
+            tracker.record(
+                "room",
+                sentiment::score(text),
+                start + Duration::seconds(5 + i as i64),
+            );
+        }
+        let negative = tracker
+            .snapshot("room", start + Duration::seconds(11))
+            .unwrap();
+        assert!(negative.score < happy.score);
+        assert_eq!(negative.label, "negative");
+
+        // Quiet chat drifts back toward neutral
+        let later = tracker
+            .snapshot("room", start + Duration::seconds(600))
+            .unwrap();
+        assert_eq!(later.label, "neutral");
+        assert!(tracker.snapshot("other", start).is_none());
+    }
+}
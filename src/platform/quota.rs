@@ -0,0 +1,173 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+
+/// Danmaku per minute a room may send before the rest are shed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomQuota {
+    pub per_minute: u32,
+    /// Fraction of danmaku over the quota still let through; 0 drops them all.
+    #[serde(default)]
+    pub overflow_rate: f64,
+}
+
+impl FromStr for RoomQuota {
+    type Err = String;
+
+    /// Parses `<per_minute>` or `<per_minute>:<overflow_rate>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("invalid room quota: {}", s);
+        let (per_minute, overflow_rate) = s.split_once(':').unwrap_or((s, "0"));
+        Ok(RoomQuota {
+            per_minute: per_minute.trim().parse().map_err(|_| invalid())?,
+            overflow_rate: overflow_rate.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomThroughput {
+    pub room_id: String,
+    pub quota: Option<RoomQuota>,
+    /// Danmaku received in the last minute, shed or not.
+    pub received_last_minute: usize,
+    /// Danmaku shed since start.
+    pub shed: u64,
+}
+
+#[derive(Debug, Default)]
+struct RoomWindow {
+    quota: Option<RoomQuota>,
+    received: VecDeque<DateTime<Utc>>,
+    admitted: VecDeque<DateTime<Utc>>,
+    shed: u64,
+}
+
+impl RoomWindow {
+    fn expire(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - Duration::minutes(1);
+        for times in [&mut self.received, &mut self.admitted] {
+            while times.front().is_some_and(|&t| t <= cutoff) {
+                times.pop_front();
+            }
+        }
+    }
+}
+
+/// Caps how many danmaku each room feeds in per minute, so one flooding
+/// room cannot crowd out the others.
+#[derive(Debug, Default)]
+pub struct RoomQuotas {
+    default_quota: Option<RoomQuota>,
+    rooms: HashMap<String, RoomWindow>,
+}
+
+impl RoomQuotas {
+    /// Unlimited rooms when `default_quota` is unset.
+    pub fn new(default_quota: Option<RoomQuota>) -> Self {
+        Self {
+            default_quota,
+            rooms: HashMap::new(),
+        }
+    }
+
+    /// Overrides the quota for one room.
+    pub fn set_quota(&mut self, room_id: &str, quota: RoomQuota) {
+        self.rooms.entry(room_id.to_string()).or_default().quota = Some(quota);
+    }
+
+    fn quota(&self, room_id: &str) -> Option<&RoomQuota> {
+        self.rooms
+            .get(room_id)
+            .and_then(|room| room.quota.as_ref())
+            .or(self.default_quota.as_ref())
+    }
+
+    /// Records a danmaku and decides whether it may be processed. Over the
+    /// quota, it is let through only when `roll` (uniform in 0..1) falls
+    /// under the overflow rate.
+    pub fn admit(&mut self, room_id: &str, now: DateTime<Utc>, roll: f64) -> bool {
+        let quota = self.quota(room_id).cloned();
+        let room = self.rooms.entry(room_id.to_string()).or_default();
+        room.expire(now);
+        room.received.push_back(now);
+
+        let admitted = match quota {
+            Some(quota) if room.admitted.len() >= quota.per_minute as usize => {
+                roll < quota.overflow_rate
+            }
+            _ => true,
+        };
+        if admitted {
+            room.admitted.push_back(now);
+        } else {
+            room.shed += 1;
+        }
+        admitted
+    }
+
+    /// Danmaku shed in a room so far.
+    pub fn shed(&self, room_id: &str) -> u64 {
+        self.rooms.get(room_id).map_or(0, |room| room.shed)
+    }
+
+    pub fn throughput(&mut self, now: DateTime<Utc>) -> Vec<RoomThroughput> {
+        let default_quota = self.default_quota.clone();
+        let mut stats: Vec<RoomThroughput> = self
+            .rooms
+            .iter_mut()
+            .map(|(room_id, room)| {
+                room.expire(now);
+                RoomThroughput {
+                    room_id: room_id.clone(),
+                    quota: room.quota.clone().or(default_quota.clone()),
+                    received_last_minute: room.received.len(),
+                    shed: room.shed,
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flooding_room_is_shed_while_quiet_room_is_not() {
+        let mut quotas = RoomQuotas::default();
+        quotas.set_quota(
+            "busy",
+            RoomQuota {
+                per_minute: 3,
+                overflow_rate: 0.0,
+            },
+        );
+        let now = Utc::now();
+
+        let busy = (0..5).filter(|_| quotas.admit("busy", now, 0.5)).count();
+        let quiet = (0..5).filter(|_| quotas.admit("quiet", now, 0.5)).count();
+        assert_eq!((busy, quiet), (3, 5));
+
+        let stats = quotas.throughput(now);
+        let counts: Vec<_> = stats
+            .iter()
+            .map(|s| (s.room_id.as_str(), s.received_last_minute, s.shed))
+            .collect();
+        assert_eq!(counts, vec![("busy", 5, 2), ("quiet", 5, 0)]);
+
+        // The window slides: a minute later the busy room is admitted again
+        assert!(quotas.admit("busy", now + Duration::seconds(61), 0.5));
+        assert_eq!(
+            "20:0.1".parse::<RoomQuota>(),
+            Ok(RoomQuota {
+                per_minute: 20,
+                overflow_rate: 0.1
+            })
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use crate::platform::{
+    DanmakuMessage, LiveStreamConfig, Platform, PlatformListener, ProcessDanmaku,
+};
+use actix::prelude::*;
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use futures_util::future::{self, Either};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Gaps in a recording are cut to this, so a break in the stream does not
+/// stall its replay.
+const MAX_GAP: Duration = Duration::from_secs(30);
+/// Slowest speed a replay plays at.
+const MIN_SPEED: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayState {
+    /// The recording is still being read.
+    Loading,
+    /// The recording could not be read; see the log.
+    Failed,
+    Playing,
+    Paused,
+    /// Every danmaku was emitted; seeking back plays on from there.
+    Finished,
+}
+
+/// Where a replay is and how fast it plays.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayStatus {
+    pub state: ReplayState,
+    /// Index of the next danmaku to emit.
+    pub position: usize,
+    pub total: usize,
+    pub speed: f64,
+}
+
+/// Changes to a running replay; unset fields stay as they are.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReplayUpdate {
+    pub paused: Option<bool>,
+    /// Multiplier on the recorded pace, e.g. 2.0 for twice as fast.
+    pub speed: Option<f64>,
+    /// Index of the danmaku to continue from.
+    pub position: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Playback {
+    position: usize,
+    total: usize,
+    speed: f64,
+    paused: bool,
+    loading: bool,
+    failed: bool,
+}
+
+/// Steers a replay while its task plays it.
+#[derive(Debug, Clone)]
+pub struct ReplayControl {
+    playback: Arc<Mutex<Playback>>,
+    changed: Arc<Notify>,
+}
+
+impl ReplayControl {
+    fn new() -> Self {
+        Self {
+            playback: Arc::new(Mutex::new(Playback {
+                position: 0,
+                total: 0,
+                speed: 1.0,
+                paused: false,
+                loading: true,
+                failed: false,
+            })),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    fn playback(&self) -> MutexGuard<'_, Playback> {
+        self.playback.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        let playback = self.playback();
+        let state = if playback.loading {
+            ReplayState::Loading
+        } else if playback.failed {
+            ReplayState::Failed
+        } else if playback.position >= playback.total {
+            ReplayState::Finished
+        } else if playback.paused {
+            ReplayState::Paused
+        } else {
+            ReplayState::Playing
+        };
+        ReplayStatus {
+            state,
+            position: playback.position,
+            total: playback.total,
+            speed: playback.speed,
+        }
+    }
+
+    pub fn update(&self, update: &ReplayUpdate) -> Result<ReplayStatus, String> {
+        if let Some(speed) = update.speed {
+            if !(speed.is_finite() && speed >= MIN_SPEED) {
+                return Err(format!("invalid speed: {} (at least {})", speed, MIN_SPEED));
+            }
+        }
+        {
+            let mut playback = self.playback();
+            if let Some(speed) = update.speed {
+                playback.speed = speed;
+            }
+            if let Some(paused) = update.paused {
+                playback.paused = paused;
+            }
+            if let Some(position) = update.position {
+                playback.position = position.min(playback.total);
+            }
+        }
+        // The wait in progress starts over under the new settings
+        self.changed.notify_one();
+        Ok(self.status())
+    }
+}
+
+/// The wait before emitting `danmaku[position]`: its recorded gap after the
+/// one before, divided by `speed` (at least [`MIN_SPEED`]).
+pub fn delay_before(danmaku: &[DanmakuMessage], position: usize, speed: f64) -> Duration {
+    if position == 0 || position >= danmaku.len() {
+        return Duration::ZERO;
+    }
+    let gap = (danmaku[position].timestamp - danmaku[position - 1].timestamp)
+        .to_std()
+        .unwrap_or_default()
+        .min(MAX_GAP);
+    gap.div_f64(speed.max(MIN_SPEED))
+}
+
+/// Danmaku from `rooms` in a file written by the danmaku store, gzipped or
+/// not, oldest first.
+pub fn load_recording(path: &str, rooms: &[&str]) -> Result<Vec<DanmakuMessage>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let reader: Box<dyn Read> = if path.ends_with(".gz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut danmaku = Vec::new();
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        match serde_json::from_str::<DanmakuMessage>(&line) {
+            Ok(d) if rooms.contains(&d.room_id.as_str()) => danmaku.push(d),
+            Ok(_) => {}
+            Err(e) => warn!("Skipping unreadable line in {}: {}", path, e),
+        }
+    }
+    danmaku.sort_by_key(|d| d.timestamp);
+    Ok(danmaku)
+}
+
+/// Reads the recording off the actor threads, then plays it.
+async fn load_and_play(
+    path: String,
+    rooms: Vec<String>,
+    control: ReplayControl,
+    sink: Recipient<ProcessDanmaku>,
+) {
+    let loaded = actix_web::rt::task::spawn_blocking(move || {
+        let rooms: Vec<&str> = rooms.iter().map(String::as_str).collect();
+        load_recording(&path, &rooms)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|loaded| loaded);
+
+    let danmaku = match loaded {
+        Ok(danmaku) => danmaku,
+        Err(e) => {
+            warn!("Failed to load replay: {}", e);
+            let mut playback = control.playback();
+            playback.loading = false;
+            playback.failed = true;
+            return;
+        }
+    };
+    info!("Replaying {} danmaku", danmaku.len());
+    {
+        let mut playback = control.playback();
+        playback.loading = false;
+        playback.total = danmaku.len();
+    }
+    play(danmaku, control, sink).await;
+}
+
+async fn play(
+    danmaku: Vec<DanmakuMessage>,
+    control: ReplayControl,
+    sink: Recipient<ProcessDanmaku>,
+) {
+    loop {
+        let (position, speed, paused) = {
+            let playback = control.playback();
+            (playback.position, playback.speed, playback.paused)
+        };
+        if paused || position >= danmaku.len() {
+            control.changed.notified().await;
+            continue;
+        }
+
+        let wait = std::pin::pin!(actix::clock::sleep(delay_before(&danmaku, position, speed)));
+        let changed = std::pin::pin!(control.changed.notified());
+        if let Either::Right(_) = future::select(wait, changed).await {
+            continue;
+        }
+
+        control.playback().position = position + 1;
+        // Replayed danmaku arrive now, so they are not dropped as stale
+        let mut replayed = danmaku[position].clone();
+        replayed.platform = Platform::Replay;
+        replayed.timestamp = Utc::now();
+        sink.do_send(ProcessDanmaku { danmaku: replayed });
+    }
+}
+
+/// Plays back recorded danmaku at their recorded pace, for debugging the
+/// pipeline against a real stream.
+pub struct ReplayListener {
+    config: LiveStreamConfig,
+    sink: Recipient<ProcessDanmaku>,
+    control: Option<ReplayControl>,
+    handle: Option<actix_web::rt::task::JoinHandle<()>>,
+}
+
+impl ReplayListener {
+    pub fn new(config: LiveStreamConfig, sink: Recipient<ProcessDanmaku>) -> Self {
+        Self {
+            config,
+            sink,
+            control: None,
+            handle: None,
+        }
+    }
+}
+
+impl PlatformListener for ReplayListener {
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.handle.is_some() {
+            return Ok(());
+        }
+        let path = self
+            .config
+            .replay_path
+            .as_deref()
+            .ok_or("replay needs a replay_path")?;
+        info!(
+            "Loading replay for {} from {}",
+            self.config.config_id(),
+            path
+        );
+
+        let rooms = self
+            .config
+            .rooms()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let control = ReplayControl::new();
+        self.handle = Some(actix::spawn(load_and_play(
+            path.to_string(),
+            rooms,
+            control.clone(),
+            self.sink.clone(),
+        )));
+        self.control = Some(control);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        info!("Stopping replay of {}", self.config.config_id());
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    fn replay(&self) -> Option<ReplayControl> {
+        self.control.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Collect;
+
+    #[actix_web::test]
+    async fn test_speed_scales_gaps_and_pause_halts_emission() {
+        let start = Utc::now();
+        let recorded: Vec<DanmakuMessage> = (0..4)
+            .map(|i| DanmakuMessage {
+                platform: Platform::Bilibili,
+                room_id: "1001".to_string(),
+                user_id: format!("{}", i),
+                username: format!("观众{}", i),
+                message: format!("第{}条弹幕", i),
+                timestamp: start + chrono::Duration::milliseconds(200 * i),
+                user_level: None,
+                is_vip: false,
+                gift_value: None,
+                trusted: false,
+            })
+            .collect();
+        assert_eq!(delay_before(&recorded, 1, 1.0), Duration::from_millis(200));
+        assert_eq!(delay_before(&recorded, 1, 2.0), Duration::from_millis(100));
+        assert_eq!(delay_before(&recorded, 1, 1e-300), Duration::from_secs(20));
+
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        let lines: Vec<String> = recorded
+            .iter()
+            .map(|d| serde_json::to_string(d).unwrap())
+            .collect();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let arrivals = Collect::<ProcessDanmaku>::default().start();
+        let mut listener = ReplayListener::new(
+            LiveStreamConfig {
+                platform: Platform::Replay,
+                room_id: "1001".to_string(),
+                room_ids: Vec::new(),
+                api_key: None,
+                webhook_url: None,
+                replay_path: Some(path.clone()),
+                enabled: true,
+                sampling: None,
+                quota: None,
+                max_age_seconds: None,
+                respond: true,
+            },
+            arrivals.clone().recipient(),
+        );
+        listener.start().unwrap();
+        let control = listener.replay().unwrap();
+        assert!(control
+            .update(&ReplayUpdate {
+                speed: Some(1e-300),
+                ..Default::default()
+            })
+            .is_err());
+        control
+            .update(&ReplayUpdate {
+                speed: Some(2.0),
+                ..Default::default()
+            })
+            .unwrap();
+
+        actix::clock::sleep(Duration::from_millis(150)).await;
+        // Replayed danmaku are stamped with the time they are emitted
+        let times: Vec<_> = Collect::received(&arrivals)
+            .await
+            .into_iter()
+            .map(|msg| msg.danmaku.timestamp)
+            .collect();
+        assert_eq!(times.len(), 2);
+        let gap = (times[1] - times[0]).to_std().unwrap();
+        assert!(gap >= Duration::from_millis(90) && gap < Duration::from_millis(150));
+
+        // Nothing is emitted while paused
+        let paused = control
+            .update(&ReplayUpdate {
+                paused: Some(true),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(paused.state, ReplayState::Paused);
+        assert_eq!(paused.position, 2);
+        actix::clock::sleep(Duration::from_millis(300)).await;
+        assert_eq!(Collect::received(&arrivals).await.len(), 2);
+
+        control
+            .update(&ReplayUpdate {
+                paused: Some(false),
+                ..Default::default()
+            })
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(300)).await;
+        assert_eq!(Collect::received(&arrivals).await.len(), 4);
+        assert_eq!(control.status().state, ReplayState::Finished);
+
+        listener.stop();
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Danmaku length at which the length part of the interest score saturates.
+const INTERESTING_LENGTH: f64 = 30.0;
+
+/// Which danmaku get a response; the rest only feed the room mood.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum SamplingPolicy {
+    /// Answer everything.
+    #[default]
+    All,
+    /// Answer every `n`th danmaku.
+    EveryNth { n: u32 },
+    /// Answer each danmaku with probability `rate`.
+    Probability { rate: f64 },
+    /// Answer danmaku whose interest score reaches `threshold`.
+    MostInteresting { threshold: f64 },
+}
+
+impl FromStr for SamplingPolicy {
+    type Err = String;
+
+    /// Parses `all`, `every:<n>`, `probability:<rate>` or `interesting:<threshold>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (kind, value) = s.split_once(':').unwrap_or((s.as_str(), ""));
+        let invalid = || format!("invalid sampling policy: {}", s);
+
+        match kind {
+            "all" => Ok(SamplingPolicy::All),
+            "every" => {
+                let n = value.parse().map_err(|_| invalid())?;
+                Ok(SamplingPolicy::EveryNth { n })
+            }
+            "probability" => {
+                let rate = value.parse().map_err(|_| invalid())?;
+                Ok(SamplingPolicy::Probability { rate })
+            }
+            "interesting" => {
+                let threshold = value.parse().map_err(|_| invalid())?;
+                Ok(SamplingPolicy::MostInteresting { threshold })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl SamplingPolicy {
+    /// Whether selection needs the danmaku's interest score.
+    pub fn needs_interest(&self) -> bool {
+        matches!(self, SamplingPolicy::MostInteresting { .. })
+    }
+}
+
+/// How worth answering a danmaku is, from 0.0 to 1.0: longer and more
+/// emotional messages score higher.
+pub fn interest(text: &str, sentiment: f32) -> f64 {
+    let length = (text.chars().count() as f64 / INTERESTING_LENGTH).min(1.0);
+    (length + sentiment.abs() as f64) / 2.0
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SamplingStats {
+    pub room_id: String,
+    pub policy: SamplingPolicy,
+    pub seen: u64,
+    pub selected: u64,
+    /// Fraction of danmaku answered so far.
+    pub selection_rate: f64,
+}
+
+#[derive(Debug, Default)]
+struct RoomSampling {
+    policy: Option<SamplingPolicy>,
+    seen: u64,
+    selected: u64,
+}
+
+/// Applies each room's sampling policy and counts what it selects.
+#[derive(Debug, Default)]
+pub struct ResponseSampler {
+    default_policy: SamplingPolicy,
+    rooms: HashMap<String, RoomSampling>,
+}
+
+impl ResponseSampler {
+    pub fn new(default_policy: SamplingPolicy) -> Self {
+        Self {
+            default_policy,
+            rooms: HashMap::new(),
+        }
+    }
+
+    /// Overrides the policy for one room.
+    pub fn set_policy(&mut self, room_id: &str, policy: SamplingPolicy) {
+        self.rooms.entry(room_id.to_string()).or_default().policy = Some(policy);
+    }
+
+    pub fn policy(&self, room_id: &str) -> &SamplingPolicy {
+        self.rooms
+            .get(room_id)
+            .and_then(|room| room.policy.as_ref())
+            .unwrap_or(&self.default_policy)
+    }
+
+    /// Decides whether to answer the next danmaku in a room. `interest` is
+    /// only used by `MostInteresting`, `roll` (uniform in 0..1) only by
+    /// `Probability`.
+    pub fn select(&mut self, room_id: &str, interest: f64, roll: f64) -> bool {
+        let policy = self.policy(room_id).clone();
+        let room = self.rooms.entry(room_id.to_string()).or_default();
+        room.seen += 1;
+
+        let selected = match policy {
+            SamplingPolicy::All => true,
+            SamplingPolicy::EveryNth { n } => room.seen.is_multiple_of(n.max(1) as u64),
+            SamplingPolicy::Probability { rate } => roll < rate,
+            SamplingPolicy::MostInteresting { threshold } => interest >= threshold,
+        };
+        if selected {
+            room.selected += 1;
+        }
+        selected
+    }
+
+    pub fn stats(&self) -> Vec<SamplingStats> {
+        let mut stats: Vec<SamplingStats> = self
+            .rooms
+            .iter()
+            .map(|(room_id, room)| SamplingStats {
+                room_id: room_id.clone(),
+                policy: self.policy(room_id).clone(),
+                seen: room.seen,
+                selected: room.selected,
+                selection_rate: if room.seen == 0 {
+                    0.0
+                } else {
+                    room.selected as f64 / room.seen as f64
+                },
+            })
+            .collect();
+        stats.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_nth_policy() {
+        let mut sampler = ResponseSampler::new(SamplingPolicy::EveryNth { n: 3 });
+
+        let selected: Vec<bool> = (0..7).map(|_| sampler.select("room", 0.0, 0.0)).collect();
+        assert_eq!(selected, [false, false, true, false, false, true, false]);
+
+        let stats = &sampler.stats()[0];
+        assert_eq!((stats.seen, stats.selected), (7, 2));
+        assert!((stats.selection_rate - 2.0 / 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_probability_policy_per_room() {
+        let mut sampler = ResponseSampler::default();
+        sampler.set_policy("busy", SamplingPolicy::Probability { rate: 0.25 });
+
+        let rolls = [0.1, 0.3, 0.24, 0.9, 0.5, 0.0, 0.75, 0.26];
+        let selected = rolls
+            .iter()
+            .filter(|&&roll| sampler.select("busy", 0.0, roll))
+            .count();
+        assert_eq!(selected, 3);
+
+        // Other rooms keep the default of answering everything
+        assert!(sampler.select("quiet", 0.0, 0.99));
+        assert_eq!(
+            "probability:0.25".parse::<SamplingPolicy>(),
+            Ok(SamplingPolicy::Probability { rate: 0.25 })
+        );
+        assert!("every:x".parse::<SamplingPolicy>().is_err());
+    }
+}
@@ -0,0 +1,422 @@
+use super::DanmakuMessage;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::warn;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::oneshot;
+
+/// How often an idle store checks whether its file is due for rotation and
+/// its rotated files for pruning.
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where raw danmaku are kept for post-stream analysis, and how much.
+#[derive(Debug, Clone)]
+pub struct DanmakuStoreConfig {
+    /// JSONL file danmaku are appended to; rotated files get `.1`, `.2`, ...
+    pub path: String,
+    /// Size in bytes at which the file is rotated.
+    pub max_bytes: u64,
+    /// Rotated files kept besides the current one; older ones are deleted.
+    pub max_files: usize,
+    /// Age at which a non-empty file is rotated even below `max_bytes`;
+    /// None rotates by size only.
+    pub max_age: Option<Duration>,
+    /// How long a rotated file is kept after its last write; None keeps it
+    /// until `max_files` pushes it out.
+    pub retention: Option<Duration>,
+    /// Whether rotated files are gzip-compressed to `.1.gz`, `.2.gz`, ...
+    pub gzip: bool,
+}
+
+impl DanmakuStoreConfig {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            max_bytes: 64 * 1024 * 1024,
+            max_files: 5,
+            max_age: None,
+            retention: None,
+            gzip: false,
+        }
+    }
+
+    fn rotated(&self, n: usize) -> String {
+        if self.gzip {
+            format!("{}.{}.gz", self.path, n)
+        } else {
+            format!("{}.{}", self.path, n)
+        }
+    }
+}
+
+/// The file currently written and how the store has rotated so far.
+#[derive(Debug, Clone, Serialize)]
+pub struct DanmakuStoreStatus {
+    pub path: String,
+    pub bytes: u64,
+    pub opened_at: DateTime<Utc>,
+    pub rotations: u64,
+    pub last_rotated_at: Option<DateTime<Utc>>,
+    /// Rotated files deleted for being past retention.
+    pub pruned: u64,
+    pub write_errors: u64,
+}
+
+enum Command {
+    Record(String),
+    Query {
+        room_id: String,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: usize,
+        reply: oneshot::Sender<Result<Vec<DanmakuMessage>, String>>,
+    },
+}
+
+/// Appends every danmaku received, one JSON object per line, rotating the
+/// file by size and age. Writes, rotation and pruning happen on a thread of
+/// the store's own, so a slow disk never holds up the live pipeline; queries
+/// go through the same thread and see every danmaku recorded before them.
+#[derive(Debug)]
+pub struct DanmakuStore {
+    commands: mpsc::Sender<Command>,
+    status: Arc<Mutex<DanmakuStoreStatus>>,
+}
+
+fn open_append(path: &str) -> Result<(File, u64), String> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+    Ok((file, size))
+}
+
+/// Copies `from` into a gzip file at `to` that keeps its modification
+/// time, so retention still counts from the last danmaku written.
+fn compress(from: &str, to: &str) -> io::Result<()> {
+    let mut source = File::open(from)?;
+    let modified = source.metadata()?.modified()?;
+    let mut encoder = GzEncoder::new(File::create(to)?, Compression::default());
+    io::copy(&mut source, &mut encoder)?;
+    encoder.finish()?.set_modified(modified)
+}
+
+impl DanmakuStore {
+    pub fn open(config: DanmakuStoreConfig) -> Result<Self, String> {
+        let (file, bytes) = open_append(&config.path)?;
+        // A reopened file keeps aging from when it was started
+        let opened_at = file
+            .metadata()
+            .and_then(|m| m.created())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        let status = Arc::new(Mutex::new(DanmakuStoreStatus {
+            path: config.path.clone(),
+            bytes,
+            opened_at,
+            rotations: 0,
+            last_rotated_at: None,
+            pruned: 0,
+            write_errors: 0,
+        }));
+        let mut writer = StoreWriter {
+            config,
+            file,
+            status: status.clone(),
+        };
+        let (commands, inbox) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("danmaku-store".to_string())
+            .spawn(move || writer.run(inbox))
+            .map_err(|e| e.to_string())?;
+        Ok(Self { commands, status })
+    }
+
+    /// Queues `danmaku` to be appended; fails only if the store has stopped.
+    pub fn record(&self, danmaku: &DanmakuMessage) -> Result<(), String> {
+        let mut line = serde_json::to_string(danmaku).map_err(|e| e.to_string())?;
+        line.push('\n');
+        self.commands
+            .send(Command::Record(line))
+            .map_err(|_| "danmaku store stopped".to_string())
+    }
+
+    /// Danmaku from `room_id` received between `from` and `to`, oldest
+    /// first, at most `limit`.
+    pub fn query(
+        &self,
+        room_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<DanmakuMessage>, String>> + 'static {
+        let (reply, found) = oneshot::channel();
+        let sent = self.commands.send(Command::Query {
+            room_id: room_id.to_string(),
+            from,
+            to,
+            limit,
+            reply,
+        });
+        async move {
+            sent.map_err(|_| "danmaku store stopped".to_string())?;
+            found
+                .await
+                .map_err(|_| "danmaku store stopped".to_string())?
+        }
+    }
+
+    pub fn status(&self) -> DanmakuStoreStatus {
+        self.status.lock().clone()
+    }
+}
+
+/// Owns the store's files on its thread.
+struct StoreWriter {
+    config: DanmakuStoreConfig,
+    file: File,
+    status: Arc<Mutex<DanmakuStoreStatus>>,
+}
+
+impl StoreWriter {
+    fn run(&mut self, inbox: mpsc::Receiver<Command>) {
+        let mut next_housekeeping = Instant::now() + HOUSEKEEPING_INTERVAL;
+        loop {
+            let wait = next_housekeeping.saturating_duration_since(Instant::now());
+            match inbox.recv_timeout(wait) {
+                Ok(Command::Record(line)) => {
+                    if let Err(e) = self.record(&line) {
+                        warn!("Failed to store danmaku: {}", e);
+                        self.status.lock().write_errors += 1;
+                    }
+                }
+                Ok(Command::Query {
+                    room_id,
+                    from,
+                    to,
+                    limit,
+                    reply,
+                }) => {
+                    let _ = reply.send(self.query(&room_id, from, to, limit));
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            // Checked after every command too, so a busy store still prunes
+            if Instant::now() >= next_housekeeping {
+                if let Err(e) = self.housekeep() {
+                    warn!("Failed to rotate danmaku store: {}", e);
+                }
+                next_housekeeping = Instant::now() + HOUSEKEEPING_INTERVAL;
+            }
+        }
+    }
+
+    fn due(&self) -> bool {
+        let status = self.status.lock();
+        if status.bytes >= self.config.max_bytes {
+            return true;
+        }
+        // An empty file is left alone however old it gets
+        status.bytes > 0
+            && self.config.max_age.is_some_and(|max_age| {
+                (Utc::now() - status.opened_at)
+                    .to_std()
+                    .is_ok_and(|age| age >= max_age)
+            })
+    }
+
+    fn housekeep(&mut self) -> Result<(), String> {
+        if self.due() {
+            self.rotate()
+        } else {
+            self.prune();
+            Ok(())
+        }
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        let config = &self.config;
+        if config.max_files == 0 {
+            fs::remove_file(&config.path).map_err(|e| e.to_string())?;
+        } else {
+            let _ = fs::remove_file(config.rotated(config.max_files));
+            for n in (1..config.max_files).rev() {
+                let _ = fs::rename(config.rotated(n), config.rotated(n + 1));
+            }
+            if config.gzip {
+                compress(&config.path, &config.rotated(1)).map_err(|e| e.to_string())?;
+                fs::remove_file(&config.path).map_err(|e| e.to_string())?;
+            } else {
+                fs::rename(&config.path, config.rotated(1)).map_err(|e| e.to_string())?;
+            }
+        }
+        let (file, bytes) = open_append(&config.path)?;
+        self.file = file;
+        {
+            let mut status = self.status.lock();
+            let now = Utc::now();
+            status.bytes = bytes;
+            status.opened_at = now;
+            status.rotations += 1;
+            status.last_rotated_at = Some(now);
+        }
+        self.prune();
+        Ok(())
+    }
+
+    /// Deletes rotated files last written longer ago than the retention.
+    fn prune(&mut self) {
+        let Some(retention) = self.config.retention else {
+            return;
+        };
+        let now = SystemTime::now();
+        for n in 1..=self.config.max_files {
+            let path = self.config.rotated(n);
+            let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if now
+                .duration_since(modified)
+                .is_ok_and(|age| age > retention)
+            {
+                match fs::remove_file(&path) {
+                    Ok(()) => self.status.lock().pruned += 1,
+                    Err(e) => warn!("Failed to prune {}: {}", path, e),
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, line: &str) -> Result<(), String> {
+        if self.due() {
+            self.rotate()?;
+        }
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|e| e.to_string())?;
+        self.status.lock().bytes += line.len() as u64;
+        Ok(())
+    }
+
+    fn query(
+        &self,
+        room_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<DanmakuMessage>, String> {
+        let mut paths: Vec<String> = (1..=self.config.max_files)
+            .rev()
+            .map(|n| self.config.rotated(n))
+            .collect();
+        paths.push(self.config.path.clone());
+
+        let mut found = Vec::new();
+        for path in paths {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            let reader: Box<dyn Read> = if path.ends_with(".gz") {
+                Box::new(GzDecoder::new(file))
+            } else {
+                Box::new(file)
+            };
+            for line in BufReader::new(reader).lines() {
+                let line = line.map_err(|e| e.to_string())?;
+                // A line cut short by a crash is skipped rather than failing the query
+                let Ok(danmaku) = serde_json::from_str::<DanmakuMessage>(&line) else {
+                    continue;
+                };
+                if danmaku.room_id == room_id
+                    && from.is_none_or(|from| danmaku.timestamp >= from)
+                    && to.is_none_or(|to| danmaku.timestamp <= to)
+                {
+                    found.push(danmaku);
+                    if found.len() >= limit {
+                        return Ok(found);
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    fn danmaku(i: u32) -> DanmakuMessage {
+        DanmakuMessage {
+            platform: Platform::Bilibili,
+            room_id: "1001".to_string(),
+            user_id: format!("{}", i),
+            username: format!("观众{}", i),
+            message: format!("第{}条弹幕", i),
+            timestamp: Utc::now(),
+            user_level: None,
+            is_vip: false,
+            gift_value: None,
+            trusted: false,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_oversized_file_rotates_and_expired_files_are_pruned() {
+        let path = std::env::temp_dir().join(format!("danmaku-{}.jsonl", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        let store = DanmakuStore::open(DanmakuStoreConfig {
+            // Every danmaku after the first goes to a new file
+            max_bytes: 100,
+            retention: Some(Duration::from_secs(3600)),
+            gzip: true,
+            ..DanmakuStoreConfig::new(&path)
+        })
+        .unwrap();
+
+        store.record(&danmaku(0)).unwrap();
+        store.record(&danmaku(1)).unwrap();
+        // Queries run after every earlier write
+        store.query("1001", None, None, 10).await.unwrap();
+        let first = format!("{}.1.gz", path);
+        assert!(fs::metadata(&first).is_ok());
+        let status = store.status();
+        assert_eq!(status.rotations, 1);
+        assert_eq!(status.bytes, fs::metadata(&path).unwrap().len());
+
+        // Backdate the segment holding danmaku 0 past retention
+        File::options()
+            .write(true)
+            .open(&first)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(7200))
+            .unwrap();
+        store.record(&danmaku(2)).unwrap();
+
+        let stored = store.query("1001", None, None, 10).await.unwrap();
+        let messages: Vec<_> = stored.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["第1条弹幕", "第2条弹幕"]);
+        assert!(fs::metadata(format!("{}.2.gz", path)).is_err());
+        let status = store.status();
+        assert_eq!(status.rotations, 2);
+        assert_eq!(status.pruned, 1);
+
+        for file in [path.clone(), first] {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
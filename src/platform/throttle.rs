@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+
+#[derive(Debug, Clone)]
+pub struct ThrottleConfig {
+    pub enabled: bool,
+    /// Fraction of danmaku shed in one interval that triggers a throttle request.
+    pub drop_rate_threshold: f64,
+    /// Minimum time between two throttle requests.
+    pub debounce_seconds: u64,
+    /// How often the drop rate is evaluated.
+    pub interval_seconds: u64,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            drop_rate_threshold: 0.2,
+            debounce_seconds: 30,
+            interval_seconds: 10,
+        }
+    }
+}
+
+/// Watches how much danmaku is being shed and decides when to ask upstream
+/// bridges to slow down.
+#[derive(Debug)]
+pub struct ThrottleMonitor {
+    config: ThrottleConfig,
+    /// Danmaku received this interval, by the platform config it came through.
+    received: HashMap<String, u64>,
+    last_shed_total: u64,
+    last_sent: Option<DateTime<Utc>>,
+}
+
+impl ThrottleMonitor {
+    pub fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config,
+            received: HashMap::new(),
+            last_shed_total: 0,
+            last_sent: None,
+        }
+    }
+
+    pub fn config(&self) -> &ThrottleConfig {
+        &self.config
+    }
+
+    pub fn record_received(&mut self, config_id: &str) {
+        *self.received.entry(config_id.to_string()).or_default() += 1;
+    }
+
+    /// Closes the current interval given the limiter's running shed total.
+    /// When a throttle request is due, returns the rate (danmaku per minute)
+    /// each platform config should send at: what was handled this interval,
+    /// split by each config's share of the traffic, so the bridges together
+    /// stay within it.
+    pub fn evaluate(&mut self, shed_total: u64, now: DateTime<Utc>) -> Option<Vec<(String, u32)>> {
+        let received_by = std::mem::take(&mut self.received);
+        let received: u64 = received_by.values().sum();
+        let shed = shed_total.saturating_sub(self.last_shed_total);
+        self.last_shed_total = shed_total;
+
+        if received == 0 {
+            return None;
+        }
+        let drop_rate = shed as f64 / received as f64;
+        if drop_rate < self.config.drop_rate_threshold {
+            return None;
+        }
+
+        let debounce = Duration::seconds(self.config.debounce_seconds as i64);
+        if self.last_sent.is_some_and(|sent| now - sent < debounce) {
+            return None;
+        }
+        self.last_sent = Some(now);
+
+        // Ask for roughly what we managed to handle this interval
+        let accepted = received.saturating_sub(shed);
+        let per_minute = accepted * 60 / self.config.interval_seconds.max(1);
+        let mut rates: Vec<(String, u32)> = received_by
+            .into_iter()
+            .map(|(config_id, count)| {
+                let rate = per_minute * count / received;
+                (config_id, rate.max(1) as u32)
+            })
+            .collect();
+        rates.sort();
+        Some(rates)
+    }
+}
+
+/// POSTs a throttle request to a bridge's webhook.
+pub async fn send_throttle(client: reqwest::Client, webhook_url: String, rate: u32) {
+    let body = serde_json::json!({
+        "action": "throttle",
+        "rate": rate
+    });
+
+    match client.post(&webhook_url).json(&body).send().await {
+        Ok(response) if response.status().is_success() => {
+            info!("Sent throttle request (rate {}) to {}", rate, webhook_url);
+        }
+        Ok(response) => warn!(
+            "Throttle request to {} failed with status {}",
+            webhook_url,
+            response.status()
+        ),
+        Err(e) => warn!("Throttle request to {} failed: {}", webhook_url, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor() -> ThrottleMonitor {
+        ThrottleMonitor::new(ThrottleConfig {
+            enabled: true,
+            drop_rate_threshold: 0.25,
+            debounce_seconds: 30,
+            interval_seconds: 10,
+        })
+    }
+
+    fn receive(monitor: &mut ThrottleMonitor, config_id: &str, count: usize) {
+        for _ in 0..count {
+            monitor.record_received(config_id);
+        }
+    }
+
+    fn rates(pairs: &[(&str, u32)]) -> Option<Vec<(String, u32)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(config_id, rate)| (config_id.to_string(), *rate))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_throttle_fires_when_drop_rate_crosses_threshold() {
+        let mut monitor = monitor();
+        let start = Utc::now();
+
+        // 1 of 10 dropped: below threshold
+        receive(&mut monitor, "douyin", 10);
+        assert_eq!(monitor.evaluate(1, start), None);
+
+        // 5 of 10 dropped: ask for the 5 per 10s we could handle
+        receive(&mut monitor, "douyin", 10);
+        assert_eq!(
+            monitor.evaluate(6, start + Duration::seconds(10)),
+            rates(&[("douyin", 30)])
+        );
+
+        // Still overloaded, but debounced
+        receive(&mut monitor, "douyin", 10);
+        assert_eq!(monitor.evaluate(11, start + Duration::seconds(20)), None);
+
+        receive(&mut monitor, "douyin", 10);
+        assert_eq!(
+            monitor.evaluate(16, start + Duration::seconds(50)),
+            rates(&[("douyin", 30)])
+        );
+    }
+
+    #[test]
+    fn test_handled_rate_is_split_between_configs() {
+        let mut monitor = monitor();
+
+        // 20 of 40 handled: 120/min in total, shared 3:1
+        receive(&mut monitor, "bilibili", 10);
+        receive(&mut monitor, "douyin", 30);
+        assert_eq!(
+            monitor.evaluate(20, Utc::now()),
+            rates(&[("bilibili", 30), ("douyin", 90)])
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::llm::{ChatMessage, LlmError, LlmProvider, LlmRequest};
+use crate::tts::{TextToSpeech, TtsError};
+use log::{error, info, warn};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long each provider has to answer the preflight.
+pub const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a preflight that did not pass is tried again.
+pub const PREFLIGHT_RETRY: Duration = Duration::from_secs(30);
+
+/// A provider rejected its credentials during the startup preflight.
+#[derive(Debug, Clone)]
+pub enum PreflightError {
+    Llm(LlmError),
+    Tts(TtsError),
+}
+
+impl fmt::Display for PreflightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreflightError::Llm(e) => write!(f, "LLM preflight failed: {}", e),
+            PreflightError::Tts(e) => write!(f, "TTS preflight failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PreflightError {}
+
+/// Whether the startup preflight has passed, or was skipped, shared with the
+/// readiness probe.
+#[derive(Debug, Clone, Default)]
+pub struct PreflightStatus(Arc<AtomicBool>);
+
+impl PreflightStatus {
+    pub fn passed(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn mark_passed(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Sends a throwaway request to each provider to open connections and
+/// check credentials before the first viewer is answered. Credential
+/// failures are returned; other failures, and providers that do not answer
+/// within `timeout`, are logged, since they may recover later. Returns
+/// whether every provider answered.
+pub async fn run(
+    llm: Option<&dyn LlmProvider>,
+    tts: Option<&dyn TextToSpeech>,
+    timeout: Duration,
+) -> Result<bool, PreflightError> {
+    let mut answered = true;
+    if let Some(provider) = llm {
+        let request = LlmRequest {
+            messages: vec![ChatMessage::new("user", "ping")],
+            max_tokens: Some(1),
+            ..Default::default()
+        };
+        match actix::clock::timeout(timeout, provider.complete(request)).await {
+            Ok(Ok(_)) => info!("LLM preflight to {} succeeded", provider.model()),
+            Ok(Err(e @ LlmError::Unauthorized(_))) => return Err(PreflightError::Llm(e)),
+            Ok(Err(e)) => {
+                warn!("LLM preflight to {} failed: {}", provider.model(), e);
+                answered = false;
+            }
+            Err(_) => {
+                warn!(
+                    "LLM preflight to {} timed out after {:?}",
+                    provider.model(),
+                    timeout
+                );
+                answered = false;
+            }
+        }
+    }
+    if let Some(tts) = tts {
+        match actix::clock::timeout(timeout, tts.synthesize("好")).await {
+            Ok(Ok(_)) => info!("TTS preflight with voice '{}' succeeded", tts.voice()),
+            Ok(Err(e @ TtsError::Unauthorized(_))) => return Err(PreflightError::Tts(e)),
+            Ok(Err(e)) => {
+                warn!("TTS preflight with voice '{}' failed: {}", tts.voice(), e);
+                answered = false;
+            }
+            Err(_) => {
+                warn!(
+                    "TTS preflight with voice '{}' timed out after {:?}",
+                    tts.voice(),
+                    timeout
+                );
+                answered = false;
+            }
+        }
+    }
+    Ok(answered)
+}
+
+/// Runs the preflight every `interval` until every provider answers, then
+/// marks `status` passed. Gives up if a provider rejects its credentials.
+pub async fn retry(
+    llm: Option<Arc<dyn LlmProvider>>,
+    tts: Option<Arc<dyn TextToSpeech>>,
+    status: PreflightStatus,
+    interval: Duration,
+) {
+    loop {
+        actix::clock::sleep(interval).await;
+        match run(llm.as_deref(), tts.as_deref(), PREFLIGHT_TIMEOUT).await {
+            Ok(true) => {
+                info!("Preflight passed on retry");
+                status.mark_passed();
+                return;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                error!("{}; the service stays not ready", e);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::llm::LlmResponse;
+    use crate::DigitalHumanService;
+    use futures_util::future::BoxFuture;
+    use std::sync::Arc;
+
+    struct Rejecting;
+
+    impl LlmProvider for Rejecting {
+        fn model(&self) -> &str {
+            "rejecting"
+        }
+
+        fn complete(&self, _: LlmRequest) -> BoxFuture<'static, Result<LlmResponse, LlmError>> {
+            Box::pin(async { Err(LlmError::from_status(401, "invalid API key")) })
+        }
+    }
+
+    struct Hanging;
+
+    impl LlmProvider for Hanging {
+        fn model(&self) -> &str {
+            "hanging"
+        }
+
+        fn complete(&self, _: LlmRequest) -> BoxFuture<'static, Result<LlmResponse, LlmError>> {
+            Box::pin(futures_util::future::pending())
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_unresponsive_provider_does_not_pass() {
+        let answered = run(Some(&Hanging), None, Duration::from_millis(20)).await;
+        assert!(!answered.unwrap());
+    }
+
+    /// Fails its first request, then answers.
+    #[derive(Default)]
+    struct Recovering(std::sync::atomic::AtomicUsize);
+
+    impl LlmProvider for Recovering {
+        fn model(&self) -> &str {
+            "recovering"
+        }
+
+        fn complete(&self, _: LlmRequest) -> BoxFuture<'static, Result<LlmResponse, LlmError>> {
+            let attempt = self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                if attempt == 0 {
+                    return Err(LlmError::from_status(503, "unavailable"));
+                }
+                Ok(LlmResponse {
+                    content: "pong".to_string(),
+                    model: "recovering".to_string(),
+                    tokens_used: None,
+                    refused: false,
+                })
+            })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_not_ready_until_providers_answer() {
+        let llm: Arc<dyn LlmProvider> = Arc::new(Recovering::default());
+        assert!(!run(Some(llm.as_ref()), None, PREFLIGHT_TIMEOUT)
+            .await
+            .unwrap());
+
+        let status = PreflightStatus::default();
+        retry(Some(llm), None, status.clone(), Duration::from_millis(1)).await;
+        assert!(status.passed());
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_credentials_fail_startup() {
+        let config = AppConfig {
+            preflight: true,
+            ..AppConfig::default()
+        };
+        let service = DigitalHumanService::new(config).with_llm_provider(Arc::new(Rejecting));
+
+        let error = service.preflight().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "LLM preflight failed: LLM provider rejected its credentials: invalid API key"
+        );
+
+        // Skipped unless enabled
+        let service =
+            DigitalHumanService::new(AppConfig::default()).with_llm_provider(Arc::new(Rejecting));
+        assert!(service.preflight().await.is_ok());
+    }
+}
@@ -0,0 +1,395 @@
+use crate::redis_conn::RedisConnector;
+use actix::clock::timeout;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::future::BoxFuture;
+use log::warn;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Wall-clock time that only moves forward: the system time when created
+/// plus the monotonic time since, so stepping the system clock back does
+/// not make intervals measured by this process negative.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    wall: DateTime<Utc>,
+    started: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self {
+            wall: Utc::now(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.wall + Duration::from_std(self.started.elapsed()).unwrap_or_default()
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Backing store for per-user rate-limit state.
+///
+/// Implementations must be safe to share between service instances when they
+/// are backed by external storage; the in-memory store is per-process only.
+///
+/// Checks call the methods below synchronously. A store that needs a round
+/// trip must not make it there: it reads the user's state in `load`, which is
+/// awaited before the check, and writes the changes in `flush`, awaited after.
+pub trait RateLimitStore: Send + fmt::Debug {
+    /// Reads `user_id`'s state ahead of a check; None when the store answers
+    /// directly.
+    fn load(&mut self, _user_id: &str) -> Option<BoxFuture<'static, ()>> {
+        None
+    }
+
+    /// Writes what the checks since the last flush changed; None when there
+    /// is nothing to write.
+    fn flush(&mut self) -> Option<BoxFuture<'static, ()>> {
+        None
+    }
+
+    /// Time of the user's last accepted message; None for a user the store
+    /// has no record of, and an error when the store cannot be read.
+    fn last_seen(&mut self, user_id: &str) -> Result<Option<DateTime<Utc>>, String>;
+
+    /// Counts a message in the user's current window and returns the new count.
+    /// The window starts with the first message and expires after `window`.
+    fn increment(
+        &mut self,
+        user_id: &str,
+        now: DateTime<Utc>,
+        window: Duration,
+    ) -> Result<u32, String>;
+
+    /// Messages counted in the user's current window, without counting a new one.
+    fn count(&mut self, user_id: &str, now: DateTime<Utc>, window: Duration)
+        -> Result<u32, String>;
+
+    /// Records `now` as the time of the user's last accepted message.
+    fn touch(&mut self, user_id: &str, now: DateTime<Utc>);
+
+    /// Forgets the user's state, e.g. once it is found to be stamped ahead
+    /// of the clock.
+    fn reset(&mut self, user_id: &str);
+}
+
+#[derive(Debug, Clone)]
+pub struct UserStats {
+    pub last_message_time: Option<DateTime<Utc>>,
+    pub window_start: DateTime<Utc>,
+    pub message_count: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryRateLimitStore {
+    users: HashMap<String, UserStats>,
+}
+
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RateLimitStore for InMemoryRateLimitStore {
+    fn last_seen(&mut self, user_id: &str) -> Result<Option<DateTime<Utc>>, String> {
+        Ok(self.users.get(user_id).and_then(|s| s.last_message_time))
+    }
+
+    fn increment(
+        &mut self,
+        user_id: &str,
+        now: DateTime<Utc>,
+        window: Duration,
+    ) -> Result<u32, String> {
+        let stats = self.users.entry(user_id.to_string()).or_insert(UserStats {
+            last_message_time: None,
+            window_start: now,
+            message_count: 0,
+        });
+
+        // A window starting after `now` was stamped before the clock went back
+        let elapsed = now.signed_duration_since(stats.window_start);
+        if elapsed >= window || elapsed < Duration::zero() {
+            stats.window_start = now;
+            stats.message_count = 0;
+        }
+        stats.message_count += 1;
+        Ok(stats.message_count)
+    }
+
+    fn count(
+        &mut self,
+        user_id: &str,
+        now: DateTime<Utc>,
+        window: Duration,
+    ) -> Result<u32, String> {
+        match self.users.get(user_id) {
+            Some(stats)
+                if (Duration::zero()..window)
+                    .contains(&now.signed_duration_since(stats.window_start)) =>
+            {
+                Ok(stats.message_count)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn touch(&mut self, user_id: &str, now: DateTime<Utc>) {
+        if let Some(stats) = self.users.get_mut(user_id) {
+            stats.last_message_time = Some(now);
+        }
+    }
+
+    fn reset(&mut self, user_id: &str) {
+        self.users.remove(user_id);
+    }
+}
+
+const KEY_PREFIX: &str = "live_streamer:rate_limit";
+/// How long a user's last-seen timestamp is kept once they go quiet.
+const LAST_SEEN_TTL_SECONDS: u64 = 3600;
+/// Longest a read or write may take; a user whose read times out is
+/// treated like one Redis could not be reached for.
+const REDIS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// INCR and PEXPIRE in one round-trip so the window TTL is never lost.
+const INCREMENT_SCRIPT: &str = r"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[1])
+end
+return count
+";
+
+/// A user's state as read from Redis, with the checks' changes applied.
+#[derive(Debug)]
+struct LoadedState {
+    loaded_at: Instant,
+    state: Result<(Option<DateTime<Utc>>, u32), String>,
+}
+
+/// Redis-backed store so limits survive restarts and are shared across instances.
+///
+/// Nothing blocks on Redis: state is read in `load` and written in `flush`
+/// over an async connection that reconnects after failures. A user that
+/// could not be loaded reads as an error, so the rate limit rule decides
+/// whether to let their messages through.
+pub struct RedisRateLimitStore {
+    connector: RedisConnector,
+    loaded: Arc<Mutex<HashMap<String, LoadedState>>>,
+    pending: redis::Pipeline,
+}
+
+impl RedisRateLimitStore {
+    pub fn connect(url: &str) -> redis::RedisResult<Self> {
+        Ok(Self {
+            connector: RedisConnector::open(url)?,
+            loaded: Arc::new(Mutex::new(HashMap::new())),
+            pending: redis::pipe(),
+        })
+    }
+
+    fn count_key(user_id: &str) -> String {
+        format!("{}:count:{}", KEY_PREFIX, user_id)
+    }
+
+    fn last_seen_key(user_id: &str) -> String {
+        format!("{}:last_seen:{}", KEY_PREFIX, user_id)
+    }
+
+    fn loaded(&self) -> MutexGuard<'_, HashMap<String, LoadedState>> {
+        self.loaded.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The user's loaded state, for reading or changing.
+    fn with_state<T>(
+        &self,
+        user_id: &str,
+        f: impl FnOnce(&mut (Option<DateTime<Utc>>, u32)) -> T,
+    ) -> Result<T, String> {
+        match self.loaded().get_mut(user_id) {
+            Some(LoadedState {
+                state: Ok(state), ..
+            }) => Ok(f(state)),
+            Some(LoadedState { state: Err(e), .. }) => Err(e.clone()),
+            None => Err(format!("rate limit state of {} was not loaded", user_id)),
+        }
+    }
+}
+
+impl fmt::Debug for RedisRateLimitStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisRateLimitStore")
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimitStore for RedisRateLimitStore {
+    fn load(&mut self, user_id: &str) -> Option<BoxFuture<'static, ()>> {
+        let connector = self.connector.clone();
+        let loaded = self.loaded.clone();
+        let user_id = user_id.to_string();
+        Some(Box::pin(async move {
+            let result = timeout(REDIS_TIMEOUT, async {
+                let mut connection = connector.connection().await?;
+                redis::pipe()
+                    .get(Self::last_seen_key(&user_id))
+                    .get(Self::count_key(&user_id))
+                    .query_async::<(Option<i64>, Option<u32>)>(&mut connection)
+                    .await
+            })
+            .await;
+            let state = match result {
+                Ok(Ok((millis, count))) => Ok((
+                    millis.and_then(DateTime::from_timestamp_millis),
+                    count.unwrap_or(0),
+                )),
+                Ok(Err(e)) => Err(format!("Redis rate limit lookup failed: {}", e)),
+                Err(_) => Err("Redis rate limit lookup timed out".to_string()),
+            };
+
+            let mut loaded = loaded.lock().unwrap_or_else(|e| e.into_inner());
+            // Entries are only needed for the check they were loaded for
+            let now = Instant::now();
+            loaded.retain(|_, entry| {
+                now.duration_since(entry.loaded_at) < std::time::Duration::from_secs(60)
+            });
+            loaded.insert(
+                user_id,
+                LoadedState {
+                    loaded_at: now,
+                    state,
+                },
+            );
+        }))
+    }
+
+    fn flush(&mut self) -> Option<BoxFuture<'static, ()>> {
+        let pending = std::mem::replace(&mut self.pending, redis::pipe());
+        pending.cmd_iter().next()?;
+        let connector = self.connector.clone();
+        Some(Box::pin(async move {
+            let result = timeout(REDIS_TIMEOUT, async {
+                let mut connection = connector.connection().await?;
+                pending.query_async::<()>(&mut connection).await
+            })
+            .await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Redis rate limit update failed: {}", e),
+                Err(_) => warn!("Redis rate limit update timed out"),
+            }
+        }))
+    }
+
+    fn last_seen(&mut self, user_id: &str) -> Result<Option<DateTime<Utc>>, String> {
+        self.with_state(user_id, |(last_seen, _)| *last_seen)
+    }
+
+    fn increment(
+        &mut self,
+        user_id: &str,
+        _now: DateTime<Utc>,
+        window: Duration,
+    ) -> Result<u32, String> {
+        let count = self.with_state(user_id, |(_, count)| {
+            *count += 1;
+            *count
+        })?;
+        self.pending
+            .cmd("EVAL")
+            .arg(INCREMENT_SCRIPT)
+            .arg(1)
+            .arg(Self::count_key(user_id))
+            .arg(window.num_milliseconds().max(1))
+            .ignore();
+        Ok(count)
+    }
+
+    fn count(
+        &mut self,
+        user_id: &str,
+        _now: DateTime<Utc>,
+        _window: Duration,
+    ) -> Result<u32, String> {
+        // The counter key expires with the window, so a missing key means zero
+        self.with_state(user_id, |(_, count)| *count)
+    }
+
+    fn touch(&mut self, user_id: &str, now: DateTime<Utc>) {
+        let _ = self.with_state(user_id, |(last_seen, _)| *last_seen = Some(now));
+        self.pending
+            .cmd("SET")
+            .arg(Self::last_seen_key(user_id))
+            .arg(now.timestamp_millis())
+            .arg("EX")
+            .arg(LAST_SEEN_TTL_SECONDS)
+            .ignore();
+    }
+
+    fn reset(&mut self, user_id: &str) {
+        let _ = self.with_state(user_id, |state| *state = (None, 0));
+        self.pending
+            .cmd("DEL")
+            .arg(Self::count_key(user_id))
+            .arg(Self::last_seen_key(user_id))
+            .ignore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_memory_window_resets() {
+        let mut store = InMemoryRateLimitStore::new();
+        let start = Utc::now();
+        let window = Duration::seconds(60);
+
+        assert_eq!(store.last_seen("alice"), Ok(None));
+        assert_eq!(store.increment("alice", start, window), Ok(1));
+        assert_eq!(store.increment("alice", start, window), Ok(2));
+        assert_eq!(store.count("alice", start, window), Ok(2));
+        assert_eq!(store.count("bob", start, window), Ok(0));
+        store.touch("alice", start);
+        assert_eq!(store.last_seen("alice"), Ok(Some(start)));
+
+        let later = start + Duration::seconds(61);
+        assert_eq!(store.count("alice", later, window), Ok(0));
+        assert_eq!(store.increment("alice", later, window), Ok(1));
+    }
+
+    /// Requires a running Redis: `REDIS_URL=redis://127.0.0.1/ cargo test --features redis-tests`
+    #[cfg(feature = "redis-tests")]
+    #[actix_web::test]
+    async fn test_redis_store_shares_state_between_instances() {
+        let url = std::env::var("REDIS_URL").unwrap_or("redis://127.0.0.1/".to_string());
+        let user_id = format!("test_{}", uuid::Uuid::new_v4());
+        let now = Utc::now();
+        let window = Duration::seconds(5);
+
+        let mut first = RedisRateLimitStore::connect(&url).unwrap();
+        let mut second = RedisRateLimitStore::connect(&url).unwrap();
+
+        first.load(&user_id).unwrap().await;
+        assert_eq!(first.increment(&user_id, now, window), Ok(1));
+        first.touch(&user_id, now);
+        first.flush().unwrap().await;
+
+        second.load(&user_id).unwrap().await;
+        assert_eq!(second.increment(&user_id, now, window), Ok(2));
+        let seen = second.last_seen(&user_id).unwrap().unwrap();
+        assert_eq!(seen.timestamp_millis(), now.timestamp_millis());
+    }
+}
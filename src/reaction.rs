@@ -0,0 +1,78 @@
+use serde::Deserialize;
+
+/// Emotions a nonverbal reaction may show.
+pub const EMOTIONS: &[&str] = &["happy", "shy", "surprised", "sad", "angry", "thinking"];
+
+/// Shown for a reaction whose emotion is missing or not one of [`EMOTIONS`],
+/// so the JSON is never spoken as a reply.
+const FALLBACK_EMOTION: &str = "neutral";
+
+/// Appended to the request for inputs whose intent is set to `react`.
+pub const REACTION_PROMPT: &str = "If a facial expression alone suits the viewer's message better than words, for example when they call you cute, reply with only {\"type\":\"reaction\",\"emotion\":\"<emotion>\"}, where <emotion> is one of: happy, shy, surprised, sad, angry, thinking. Otherwise reply normally.";
+
+/// A nonverbal reaction chosen by the LLM in place of a reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reaction {
+    pub emotion: String,
+}
+
+#[derive(Deserialize)]
+struct Structured {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    emotion: String,
+}
+
+/// Reads `{"type":"reaction","emotion":...}` from a response, allowing a
+/// code fence around it. None for ordinary replies.
+pub fn parse(response: &str) -> Option<Reaction> {
+    let body = response
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    if !body.starts_with('{') {
+        return None;
+    }
+    let structured: Structured = serde_json::from_str(body).ok()?;
+    if structured.kind != "reaction" {
+        return None;
+    }
+    let emotion = structured.emotion.trim().to_lowercase();
+    let emotion = if EMOTIONS.contains(&emotion.as_str()) {
+        emotion
+    } else {
+        FALLBACK_EMOTION.to_string()
+    };
+    Some(Reaction { emotion })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reaction() {
+        assert_eq!(
+            parse("```json\n{\"type\":\"reaction\",\"emotion\":\"Shy\"}\n```"),
+            Some(Reaction {
+                emotion: "shy".to_string()
+            })
+        );
+        assert_eq!(
+            parse("{\"type\":\"reaction\",\"emotion\":\"smug\"}"),
+            Some(Reaction {
+                emotion: "neutral".to_string()
+            })
+        );
+        assert_eq!(
+            parse("{\"type\":\"reaction\"}"),
+            Some(Reaction {
+                emotion: "neutral".to_string()
+            })
+        );
+        assert_eq!(parse("谢谢夸奖！"), None);
+    }
+}
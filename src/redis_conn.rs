@@ -0,0 +1,40 @@
+use redis::aio::ConnectionManager;
+use redis::RedisResult;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// An async Redis connection shared by clones, made on first use so no
+/// caller blocks its thread on Redis. Once made it reconnects by itself
+/// after failures; a first connection that fails is tried again next use.
+#[derive(Clone)]
+pub struct RedisConnector {
+    client: redis::Client,
+    manager: Arc<Mutex<Option<ConnectionManager>>>,
+}
+
+impl RedisConnector {
+    /// Checks the URL; nothing is connected yet.
+    pub fn open(url: &str) -> RedisResult<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            manager: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub async fn connection(&self) -> RedisResult<ConnectionManager> {
+        let mut manager = self.manager.lock().await;
+        if let Some(connection) = manager.as_ref() {
+            return Ok(connection.clone());
+        }
+        let connection = self.client.get_connection_manager().await?;
+        *manager = Some(connection.clone());
+        Ok(connection)
+    }
+}
+
+impl fmt::Debug for RedisConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisConnector").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,163 @@
+use crate::llm::LlmResponse;
+use serde::{Deserialize, Serialize};
+
+/// How policy refusals from the LLM ("I can't help with that") are replaced
+/// with an in-character deflection, so the persona never breaks character.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RefusalConfig {
+    /// Off by default: an answer that merely opens like a refusal would be
+    /// replaced too, and streamed tokens have already shown the refusal.
+    pub enabled: bool,
+    /// A response opening with one of these is a refusal. Matching ignores
+    /// case, apostrophes and punctuation, and needs a word boundary after
+    /// the phrase, so "I can't help with" does not catch "I can't help without".
+    pub openings: Vec<String>,
+    /// Said instead, in turn; `{name}` is the persona's name.
+    pub deflections: Vec<String>,
+}
+
+impl Default for RefusalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            openings: strings(&[
+                "I can't help with",
+                "I cannot help with",
+                "I can't assist with",
+                "I cannot assist with",
+                "I'm sorry, but I can't",
+                "I'm sorry, but I cannot",
+                "Sorry, but I can't",
+                "I'm unable to help",
+                "I am unable to help",
+                "I won't be able to help",
+                "As an AI language model",
+                "As an AI, I can't",
+                "As an AI, I cannot",
+                "As a language model",
+                "抱歉，我无法",
+                "抱歉，我不能",
+                "很抱歉，我无法",
+                "很抱歉，我不能",
+                "对不起，我无法",
+                "对不起，我不能",
+                "我无法协助",
+                "我无法提供这",
+                "作为一个AI语言模型",
+                "作为AI语言模型",
+                "作为一个人工智能语言模型",
+                "作为人工智能助手",
+            ]),
+            deflections: strings(&[
+                "哎呀，这个话题{name}就不展开啦，我们聊点别的吧！",
+                "嘿嘿，这个{name}可答不上来，换个问题考考我吧～",
+                "这个嘛……{name}先保密啦！弹幕里还有什么想聊的？",
+            ]),
+        }
+    }
+}
+
+impl RefusalConfig {
+    /// Loads openings and deflections from a JSON file; missing fields keep
+    /// their defaults.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    /// Whether the provider flagged `response` as a refusal or it opens
+    /// like one.
+    pub fn is_refusal(&self, response: &LlmResponse) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if response.refused {
+            return true;
+        }
+        let opening = normalize(&response.content);
+        self.openings.iter().any(|phrase| {
+            let phrase = normalize(phrase);
+            !phrase.is_empty()
+                && opening.starts_with(&phrase)
+                && !opening[phrase.len()..].starts_with(|c: char| c.is_ascii_alphanumeric())
+        })
+    }
+
+    /// The `turn`-th deflection for the persona `name`; None when there are
+    /// none configured.
+    pub fn deflection(&self, turn: usize, name: &str) -> Option<String> {
+        if self.deflections.is_empty() {
+            return None;
+        }
+        let template = &self.deflections[turn % self.deflections.len()];
+        Some(template.replace("{name}", name))
+    }
+}
+
+/// Lowercases and turns punctuation into single spaces; apostrophes are
+/// dropped so "can't" and "can’t" both read "cant".
+fn normalize(text: &str) -> String {
+    let spaced: String = text
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '’'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    spaced
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content: &str) -> LlmResponse {
+        LlmResponse {
+            content: content.to_string(),
+            model: "test".to_string(),
+            tokens_used: None,
+            refused: false,
+        }
+    }
+
+    #[test]
+    fn test_refusals_are_told_apart_from_answers() {
+        let config = RefusalConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert!(!RefusalConfig::default().is_refusal(&response("I cannot assist with this.")));
+        for refusal in [
+            "I’m sorry, but I can’t help with that request.",
+            "  I cannot assist with this.",
+            "很抱歉，我无法回答这个问题。",
+            "作为一个AI语言模型，我不能这样做",
+        ] {
+            assert!(config.is_refusal(&response(refusal)), "{}", refusal);
+        }
+        for answer in [
+            "I can't wait for tonight's stream!",
+            "I can't help without laughing, haha",
+            "Sorry I'm late! I can't help with the raid but I'll cheer.",
+            "哈哈，我无法抗拒这首歌！",
+            "作为AI主播，我今天超开心！",
+        ] {
+            assert!(!config.is_refusal(&response(answer)), "{}", answer);
+        }
+
+        let flagged = LlmResponse {
+            refused: true,
+            ..response("Let's talk about something else.")
+        };
+        assert!(config.is_refusal(&flagged));
+        assert!(config.deflection(1, "Maya").unwrap().contains("Maya"));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::similarity::{bigrams, dice, fold};
+use std::str::FromStr;
+
+/// Sent after a repeated answer to ask the LLM for a fresh wording.
+pub const REWORD_PROMPT: &str =
+    "You just gave that same answer. Say it differently, without repeating your previous wording.";
+
+const ZH_VARIATIONS: &[&str] = &["（刚才也说过哦）", "（再说一次～）", "（还是这个答案啦）"];
+const EN_VARIATIONS: &[&str] = &[
+    " (as I mentioned)",
+    " (same answer as before!)",
+    " (still true!)",
+];
+
+/// What to do when a response nearly repeats one of the session's recent ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    /// Ask the LLM once more for different wording; falls back to `Vary`
+    /// when streaming tokens or if the new wording still repeats.
+    Reword,
+    /// Append a short remark acknowledging the repetition.
+    Vary,
+}
+
+impl FromStr for RepeatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => Ok(RepeatMode::Off),
+            "reword" => Ok(RepeatMode::Reword),
+            "vary" => Ok(RepeatMode::Vary),
+            other => Err(format!("unknown repeat mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RepeatPolicy {
+    pub mode: RepeatMode,
+    /// Bigram overlap (Dice coefficient, 0-1) at which two responses count as the same.
+    pub similarity: f64,
+    /// How many of the session's latest responses are compared.
+    pub window: usize,
+}
+
+impl Default for RepeatPolicy {
+    fn default() -> Self {
+        Self {
+            mode: RepeatMode::Off,
+            similarity: 0.85,
+            window: 5,
+        }
+    }
+}
+
+impl RepeatPolicy {
+    /// How many of `recent` (newest first) `response` nearly repeats, among
+    /// the first `window`; always 0 when off.
+    pub fn repeats<'a>(&self, response: &str, recent: impl Iterator<Item = &'a str>) -> usize {
+        if self.mode == RepeatMode::Off {
+            return 0;
+        }
+        let grams = bigrams(&fold(response));
+        recent
+            .take(self.window)
+            .filter(|previous| dice(&grams, &bigrams(&fold(previous))) >= self.similarity)
+            .count()
+    }
+}
+
+/// Appends a remark to a repeated response, a different one for each repeat.
+pub fn vary(response: &str, repeats: usize) -> String {
+    let chinese = response
+        .chars()
+        .any(|c| ('\u{4e00}'..='\u{9fff}').contains(&c));
+    let variations = if chinese {
+        ZH_VARIATIONS
+    } else {
+        EN_VARIATIONS
+    };
+    let variation = variations[repeats.saturating_sub(1) % variations.len()];
+    format!("{}{}", response.trim_end(), variation)
+}
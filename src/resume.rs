@@ -0,0 +1,292 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use uuid::Uuid;
+
+/// Frames worth replaying after a reconnect; transient frames such as
+/// animations, streamed tokens and stats are dropped while detached.
+const REPLAYED_FRAME_TYPES: &[&str] = &["llm_response", "tts_response", "retract"];
+
+#[derive(Debug, Clone)]
+pub struct ResumeConfig {
+    /// How long a dropped session can be resumed; 0 disables resumption.
+    pub window_seconds: u64,
+    /// Frames kept per detached session; the oldest are dropped first.
+    pub max_buffered_frames: usize,
+    /// Detached sessions kept at once; the longest-detached are evicted first.
+    pub max_detached_sessions: usize,
+    /// How often sessions past their window are swept.
+    pub sweep_interval_seconds: u64,
+}
+
+impl Default for ResumeConfig {
+    fn default() -> Self {
+        Self {
+            window_seconds: 30,
+            max_buffered_frames: 50,
+            max_detached_sessions: 1000,
+            sweep_interval_seconds: 5,
+        }
+    }
+}
+
+impl ResumeConfig {
+    pub fn enabled(&self) -> bool {
+        self.window_seconds > 0
+    }
+
+    pub fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.window_seconds)
+    }
+
+    pub fn sweep_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.sweep_interval_seconds.max(1))
+    }
+}
+
+#[derive(Debug)]
+struct DetachedSession {
+    user_id: String,
+    resume_token: Uuid,
+    detached_at: DateTime<Utc>,
+    frames: VecDeque<String>,
+}
+
+/// A session picked up again by a reconnecting client.
+#[derive(Debug)]
+pub struct ResumedSession {
+    pub session_id: Uuid,
+    /// Frames generated while disconnected, in delivery order.
+    pub frames: Vec<String>,
+}
+
+/// Sessions whose socket dropped but which may still be resumed.
+#[derive(Debug, Default)]
+pub struct DetachedSessions {
+    config: ResumeConfig,
+    sessions: HashMap<Uuid, DetachedSession>,
+}
+
+impl DetachedSessions {
+    pub fn new(config: ResumeConfig) -> Self {
+        Self {
+            config,
+            sessions: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ResumeConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Keeps a session resumable, evicting the longest-detached sessions
+    /// beyond `max_detached_sessions`. Returns the evicted session ids and
+    /// their user ids.
+    pub fn detach(
+        &mut self,
+        session_id: Uuid,
+        user_id: String,
+        resume_token: Uuid,
+        now: DateTime<Utc>,
+    ) -> Vec<(Uuid, String)> {
+        self.sessions.insert(
+            session_id,
+            DetachedSession {
+                user_id,
+                resume_token,
+                detached_at: now,
+                frames: VecDeque::new(),
+            },
+        );
+
+        let mut evicted = Vec::new();
+        while self.sessions.len() > self.config.max_detached_sessions {
+            let Some(oldest) = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, session)| session.detached_at)
+                .map(|(id, _)| *id)
+            else {
+                break;
+            };
+            if let Some(session) = self.sessions.remove(&oldest) {
+                evicted.push((oldest, session.user_id));
+            }
+        }
+        evicted
+    }
+
+    /// Buffers a frame for a detached session. Returns false if the session
+    /// is not detached, so the caller can treat it as unknown.
+    pub fn buffer(&mut self, session_id: &Uuid, frame: &serde_json::Value) -> bool {
+        let Some(session) = self.sessions.get_mut(session_id) else {
+            return false;
+        };
+
+        let frame_type = frame.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        if REPLAYED_FRAME_TYPES.contains(&frame_type) {
+            session.frames.push_back(frame.to_string());
+            while session.frames.len() > self.config.max_buffered_frames {
+                session.frames.pop_front();
+            }
+        }
+        true
+    }
+
+    /// Claims a detached session by its resume token, if it belongs to
+    /// `user_id` and its window has not passed.
+    pub fn resume(
+        &mut self,
+        resume_token: &Uuid,
+        user_id: &str,
+        now: DateTime<Utc>,
+    ) -> Option<ResumedSession> {
+        let window = Duration::seconds(self.config.window_seconds as i64);
+        let session_id = self.sessions.iter().find_map(|(id, session)| {
+            (session.resume_token == *resume_token
+                && session.user_id == user_id
+                && now - session.detached_at < window)
+                .then_some(*id)
+        })?;
+
+        let session = self.sessions.remove(&session_id)?;
+        Some(ResumedSession {
+            session_id,
+            frames: session.frames.into(),
+        })
+    }
+
+    /// Drops a session whose window has passed, returning its user id.
+    pub fn expire(&mut self, session_id: &Uuid, now: DateTime<Utc>) -> Option<String> {
+        let window = Duration::seconds(self.config.window_seconds as i64);
+        let expired = self
+            .sessions
+            .get(session_id)
+            .is_some_and(|session| now - session.detached_at >= window);
+
+        if expired {
+            self.sessions
+                .remove(session_id)
+                .map(|session| session.user_id)
+        } else {
+            None
+        }
+    }
+
+    /// Drops every session whose window has passed, returning their session
+    /// ids and user ids.
+    pub fn sweep(&mut self, now: DateTime<Utc>) -> Vec<(Uuid, String)> {
+        let ids: Vec<Uuid> = self.sessions.keys().copied().collect();
+        ids.into_iter()
+            .filter_map(|id| self.expire(&id, now).map(|user_id| (id, user_id)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame_type: &str, text: &str) -> serde_json::Value {
+        serde_json::json!({"type": frame_type, "data": {"text": text}})
+    }
+
+    #[test]
+    fn test_reconnect_replays_buffered_frames() {
+        let mut detached = DetachedSessions::new(ResumeConfig {
+            window_seconds: 30,
+            max_buffered_frames: 2,
+            ..Default::default()
+        });
+        let session_id = Uuid::new_v4();
+        let token = Uuid::new_v4();
+        let start = Utc::now();
+
+        // Disconnect, then the bot answers while the client is away
+        detached.detach(session_id, "alice".to_string(), token, start);
+        assert!(detached.buffer(&session_id, &frame("llm_response", "first")));
+        assert!(detached.buffer(&session_id, &frame("animation", "wave")));
+        assert!(detached.buffer(&session_id, &frame("llm_response", "second")));
+        assert!(detached.buffer(&session_id, &frame("tts_response", "third")));
+        assert!(!detached.buffer(&Uuid::new_v4(), &frame("llm_response", "x")));
+
+        // Wrong user cannot claim the session
+        assert!(detached.resume(&token, "mallory", start).is_none());
+
+        let resumed = detached
+            .resume(&token, "alice", start + Duration::seconds(5))
+            .unwrap();
+        assert_eq!(resumed.session_id, session_id);
+        assert_eq!(
+            resumed.frames,
+            [
+                frame("llm_response", "second").to_string(),
+                frame("tts_response", "third").to_string()
+            ]
+        );
+        assert!(detached.resume(&token, "alice", start).is_none());
+    }
+
+    #[test]
+    fn test_sessions_expire_after_window() {
+        let mut detached = DetachedSessions::new(ResumeConfig::default());
+        let session_id = Uuid::new_v4();
+        let token = Uuid::new_v4();
+        let start = Utc::now();
+
+        detached.detach(session_id, "alice".to_string(), token, start);
+        assert_eq!(detached.expire(&session_id, start), None);
+
+        let later = start + Duration::seconds(31);
+        assert!(detached.resume(&token, "alice", later).is_none());
+        assert_eq!(
+            detached.expire(&session_id, later),
+            Some("alice".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sweep_and_cap_evict_resume_tokens() {
+        let mut detached = DetachedSessions::new(ResumeConfig {
+            window_seconds: 30,
+            max_detached_sessions: 2,
+            ..Default::default()
+        });
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let tokens: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let start = Utc::now();
+
+        assert!(detached
+            .detach(ids[0], "alice".to_string(), tokens[0], start)
+            .is_empty());
+        let later = start + Duration::seconds(20);
+        assert!(detached
+            .detach(ids[1], "bob".to_string(), tokens[1], later)
+            .is_empty());
+
+        // Over the cap, the longest-detached session goes
+        assert_eq!(
+            detached.detach(ids[2], "carol".to_string(), tokens[2], later),
+            [(ids[0], "alice".to_string())]
+        );
+        assert!(detached.resume(&tokens[0], "alice", later).is_none());
+
+        // Past the window, the sweep removes it and its token stops working
+        let expired = later + Duration::seconds(30);
+        let mut swept = detached.sweep(expired);
+        swept.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(
+            swept,
+            [(ids[1], "bob".to_string()), (ids[2], "carol".to_string())]
+        );
+        assert!(detached.is_empty());
+        assert!(detached.resume(&tokens[1], "bob", later).is_none());
+    }
+}
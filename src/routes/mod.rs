@@ -1,24 +1,110 @@
+use crate::actor::{
+    DigitalHumanActor, ExportHistory, GetBudgetStats, GetLlmStats, ImportHistory, SessionHistory,
+    SummarizeForHandoff,
+};
+use crate::auth::AuthConfig;
+use crate::channels::{ChannelsUpdate, GlobalChannels};
+use crate::diagnostics;
+use crate::event_bus::{
+    BanUser, EventBus, ExemptFromRateLimit, GetRuleTriggerStats, GetWiring,
+    ListRateLimitExemptions, ListValidationRules, RemoveRateLimitExemption, ResetRuleTriggerStats,
+    SetDigitalHumanPaused, SetRoomGate, SetValidationRuleEnabled, SubscribeMonitor, UnbanUser,
+    UnsubscribeMonitor,
+};
+use crate::events::{EventMetadata, RetractResponse, StreamEndedEvent, StreamStartedEvent};
 use crate::platform::*;
+use crate::preflight::PreflightStatus;
+use crate::redact;
+use crate::timezone;
+use crate::validator::ValidationRule;
 use crate::websocket::*;
 use actix::prelude::*;
 use actix_web::{web, HttpRequest, HttpResponse, Result};
 use actix_ws;
-use futures_util::StreamExt as _;
-use log::{info, warn};
+use futures_util::{Stream, StreamExt as _};
+use log::{info, trace, warn};
+use serde::Deserialize;
 use uuid::Uuid;
 
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api/v1")
             .route("/health", web::get().to(health_check))
+            .route("/ready", web::get().to(readiness_check))
+            .route("/stats", web::get().to(get_stats))
+            .route("/diagnostics", web::get().to(run_diagnostics))
+            .route("/ws/monitor", web::get().to(monitor_handler))
             .route(
                 "/ws/{channel_id}/{user_id}",
                 web::get().to(websocket_handler),
             )
             .route("/digital-human/info", web::get().to(get_digital_human_info))
+            .route(
+                "/digital-human/paused",
+                web::put().to(set_digital_human_paused),
+            )
+            .route("/output/channels", web::get().to(get_output_channels))
+            .route("/output/channels", web::patch().to(set_output_channels))
             .route("/danmaku/douyin", web::post().to(handle_douyin_danmaku))
             .route("/danmaku/bilibili", web::post().to(handle_bilibili_danmaku))
-            .route("/platform/config", web::post().to(add_platform_config)),
+            .route("/platform/config", web::post().to(add_platform_config))
+            .route("/platform/status", web::get().to(get_platform_status))
+            .route("/replay/{config_id}", web::get().to(get_replay_status))
+            .route("/replay/{config_id}", web::patch().to(control_replay))
+            .route("/faq", web::get().to(get_faq))
+            .route("/rooms/{room_id}/mood", web::get().to(get_room_mood))
+            .route(
+                "/rooms/{room_id}/danmaku",
+                web::get().to(query_room_danmaku),
+            )
+            .route("/rooms/{room_id}/gate", web::put().to(set_room_gate))
+            .route(
+                "/rooms/{room_id}/respond",
+                web::put().to(set_room_responding),
+            )
+            .route("/stream/{room_id}/start", web::post().to(start_stream))
+            .route("/stream/{room_id}/end", web::post().to(end_stream))
+            .route("/users/{user_id}/ban", web::post().to(ban_user))
+            .route("/users/{user_id}/ban", web::delete().to(unban_user))
+            .route("/users/rate-limit-exempt", web::get().to(list_exempt_users))
+            .route(
+                "/users/{user_id}/rate-limit-exempt",
+                web::put().to(exempt_user),
+            )
+            .route(
+                "/users/{user_id}/rate-limit-exempt",
+                web::delete().to(unexempt_user),
+            )
+            .route("/validation/rules", web::get().to(list_validation_rules))
+            // 须在 {rule_id} 之前注册
+            .route(
+                "/validation/rules/stats",
+                web::get().to(get_rule_trigger_stats),
+            )
+            .route(
+                "/validation/rules/stats",
+                web::delete().to(reset_rule_trigger_stats),
+            )
+            .route(
+                "/validation/rules/{rule_id}",
+                web::patch().to(update_validation_rule),
+            )
+            .route(
+                "/responses/{response_id}/retract",
+                web::post().to(retract_response),
+            )
+            .route(
+                "/sessions/{session_id}/export",
+                web::get().to(export_session_history),
+            )
+            .route(
+                "/sessions/{session_id}/import",
+                web::post().to(import_session_history),
+            )
+            .route(
+                "/sessions/{session_id}/summary",
+                web::get().to(summarize_session),
+            ),
     );
 }
 
@@ -26,68 +112,271 @@ async fn health_check() -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "status": "healthy",
         "service": "digital-human",
-        "timestamp": chrono::Utc::now()
+        "timestamp": timezone::now()
     })))
 }
 
+/// 就绪探针：事件总线已接好数字人和WebSocket管理器，且启动预检已通过
+async fn readiness_check(
+    event_bus: web::Data<Addr<EventBus>>,
+    preflight: web::Data<PreflightStatus>,
+) -> Result<HttpResponse> {
+    let wiring = event_bus
+        .send(GetWiring)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let digital_human = wiring.digital_human.is_some();
+    let preflight = preflight.passed();
+    let ready = digital_human && wiring.websocket_manager && preflight;
+
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    Ok(response.json(serde_json::json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": {
+            "digital_human": digital_human,
+            "websocket_manager": wiring.websocket_manager,
+            "preflight": preflight
+        },
+        "timestamp": timezone::now()
+    })))
+}
+
+async fn get_stats(
+    digital_human: web::Data<Addr<DigitalHumanActor>>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+    event_bus: web::Data<Addr<EventBus>>,
+    retries: web::Data<SendRetries>,
+    queue: web::Data<OutboundQueue>,
+    channels: web::Data<GlobalChannels>,
+) -> Result<HttpResponse> {
+    let llm = digital_human
+        .send(GetLlmStats)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let budget = digital_human
+        .send(GetBudgetStats)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let sampling = live_manager
+        .send(GetSamplingStats)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let rooms = live_manager
+        .send(GetRoomThroughput)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let listeners = live_manager
+        .send(GetListenerStatus)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let danmaku_store = live_manager
+        .send(GetDanmakuStoreStatus)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let validation = event_bus
+        .send(GetRuleTriggerStats)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "llm": llm,
+        "budget": budget,
+        "sampling": sampling,
+        "rooms": rooms,
+        "listeners": listeners,
+        "danmaku_store": danmaku_store,
+        "validation": validation,
+        "output_channels": channels.get(),
+        "websocket": {
+            "send_retries": retries.retried(),
+            "queue_overflows": queue.overflows()
+        },
+        "timestamp": timezone::now()
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct AdminQuery {
+    /// JWT carrying the `admin` claim.
+    token: Option<String>,
+}
+
+/// Checks an operator token and returns who it was issued to; refused
+/// outright when authentication is off.
+fn require_admin(auth: &AuthConfig, token: Option<&str>, what: &str) -> Result<String> {
+    if !auth.enabled() {
+        return Err(actix_web::error::ErrorForbidden(format!(
+            "{} requires authentication",
+            what
+        )));
+    }
+    let token = token.ok_or_else(|| actix_web::error::ErrorUnauthorized("missing token"))?;
+    auth.verify_admin(token).map_err(|e| {
+        warn!("Rejecting {} token: {}", what, e);
+        actix_web::error::ErrorUnauthorized("invalid token")
+    })
+}
+
+/// 用合成弹幕走一遍事件总线、校验和LLM，不影响真实会话
+async fn run_diagnostics(
+    query: web::Query<AdminQuery>,
+    event_bus: web::Data<Addr<EventBus>>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    // 每次自检都会真实调用一次LLM，只对管理员开放
+    let operator = require_admin(&auth, query.token.as_deref(), "diagnostics")?;
+    info!("Running diagnostics for {}", operator);
+    let report = diagnostics::run(&event_bus).await;
+    let mut response = if report.ok {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    Ok(response.json(report))
+}
+
+#[derive(Debug, Deserialize)]
+struct WebSocketQuery {
+    resume_token: Option<Uuid>,
+    /// JWT issued to the user; required when authentication is enabled.
+    token: Option<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn websocket_handler(
     req: HttpRequest,
     path: web::Path<(String, String)>,
+    query: web::Query<WebSocketQuery>,
     stream: web::Payload,
     ws_manager: web::Data<Addr<WebSocketManager>>,
+    auth: web::Data<AuthConfig>,
+    limits: web::Data<MessageLimits>,
+    retries: web::Data<SendRetries>,
+    queue: web::Data<OutboundQueue>,
 ) -> Result<HttpResponse> {
     let (_channel_id, user_id) = path.into_inner();
-    info!("WebSocket connection request from user: {}", user_id);
+    info!(
+        "WebSocket connection request from user: {}",
+        redact::user(&user_id)
+    );
+
+    // 启用鉴权时必须携带有效 token，记录其过期时间以便后续轮换；
+    // 带 admin 声明的 token 才能执行主持人指令
+    let (token_expires_at, operator) = if auth.enabled() {
+        let token = query
+            .token
+            .as_deref()
+            .ok_or_else(|| actix_web::error::ErrorUnauthorized("missing token"))?;
+        let expires_at = auth.verify(token, &user_id).map_err(|e| {
+            warn!(
+                "Rejecting WebSocket token for user {}: {}",
+                redact::user(&user_id),
+                e
+            );
+            actix_web::error::ErrorUnauthorized("invalid token")
+        })?;
+        (Some(expires_at), auth.verify_admin(token).is_ok())
+    } else {
+        (None, false)
+    };
 
     let (response, session, stream) = actix_ws::handle(&req, stream)?;
 
-    let session_id = Uuid::new_v4();
+    // 断线重连时尝试恢复原会话；握手成功后再认领，失败的握手不会吞掉它
+    let resumed = match query.resume_token {
+        Some(resume_token) => ws_manager
+            .send(ResumeSession {
+                resume_token,
+                user_id: user_id.clone(),
+            })
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        None => None,
+    };
+
+    let (session_id, replay) = match resumed {
+        Some(resumed) => (resumed.session_id, Some(resumed.frames)),
+        None => (Uuid::new_v4(), None),
+    };
     let ws_manager_addr = ws_manager.get_ref().clone();
 
+    let start = SessionStart {
+        session_id,
+        user_id,
+        replay,
+        token_expires_at,
+        operator,
+    };
     actix_web::rt::spawn(handle_websocket_session(
         session,
         stream,
-        session_id,
-        user_id,
+        start,
+        MessageAssembler::new(&limits),
+        retries.get_ref().clone(),
+        queue.get_ref().clone(),
         ws_manager_addr,
     ));
 
     Ok(response)
 }
 
-async fn handle_websocket_session(
-    mut session: actix_ws::Session,
-    mut stream: actix_ws::MessageStream,
+/// Identity of a newly upgraded socket and what it carries over.
+struct SessionStart {
     session_id: Uuid,
     user_id: String,
+    /// Frames to replay when resuming an earlier session.
+    replay: Option<Vec<String>>,
+    token_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Whether the token carries the admin claim.
+    operator: bool,
+}
+
+async fn handle_websocket_session(
+    mut session: actix_ws::Session,
+    mut stream: impl Stream<Item = Result<actix_ws::Message, actix_ws::ProtocolError>> + Unpin,
+    start: SessionStart,
+    mut assembler: MessageAssembler,
+    retries: SendRetries,
+    queue: OutboundQueue,
     ws_manager: Addr<WebSocketManager>,
 ) {
+    let SessionStart {
+        session_id,
+        user_id,
+        replay,
+        token_expires_at,
+        operator,
+    } = start;
+
     // Create WebSocket session actor
-    let session_actor =
-        WebSocketSessionActor::new(session.clone(), session_id, user_id.clone()).start();
+    let session_actor = WebSocketSessionActor::new(session.clone(), session_id, user_id.clone())
+        .with_send_retries(retries)
+        .with_outbound_queue(queue)
+        .start();
 
     // Send connection event
     ws_manager.do_send(HandleUserConnect {
         session_id,
         user_id: user_id.clone(),
         session_actor: session_actor.clone(),
+        replay,
+        token_expires_at,
+        operator,
     });
 
     while let Some(msg) = stream.next().await {
         match msg {
             Ok(actix_ws::Message::Text(text)) => {
-                info!("Received text: {}", text);
-                // Handle text message through WebSocketManager
-                ws_manager.do_send(HandleTextMessage {
-                    session_id,
-                    user_id: user_id.clone(),
-                    text: text.to_string(),
-                });
-            }
-            Ok(actix_ws::Message::Binary(bin)) => {
-                info!("Received binary data: {} bytes", bin.len());
-                // Handle binary message (audio)
+                dispatch_text(&ws_manager, session_id, &user_id, text.to_string());
             }
+            Ok(actix_ws::Message::Binary(bin)) => match assembler.check_audio(bin.len()) {
+                Ok(()) => dispatch_binary(&bin),
+                Err(reason) => reject_audio(&session_actor, session_id, &reason),
+            },
             Ok(actix_ws::Message::Ping(bytes)) => {
                 if let Err(e) = session.pong(&bytes).await {
                     warn!("Failed to send pong: {}", e);
@@ -101,11 +390,41 @@ async fn handle_websocket_session(
                 info!("WebSocket closed: {:?}", reason);
                 break;
             }
-            Ok(actix_ws::Message::Continuation(_)) => {
-                // Handle continuation frames
+            Ok(actix_ws::Message::Continuation(item)) => {
+                // 分片消息拼装完整后按普通消息处理
+                match assembler.push(item) {
+                    Ok(Some(AssembledMessage::Text(text))) => {
+                        dispatch_text(&ws_manager, session_id, &user_id, text);
+                    }
+                    Ok(Some(AssembledMessage::Binary(bin))) => dispatch_binary(&bin),
+                    Ok(Some(AssembledMessage::AudioRejected(reason))) => {
+                        reject_audio(&session_actor, session_id, &reason);
+                    }
+                    Ok(None) => {}
+                    Err(reason) => {
+                        warn!(
+                            "Dropping fragmented message on session {}: {:?}",
+                            session_id, reason
+                        );
+                        session_actor.do_send(CloseSession { reason });
+                        break;
+                    }
+                }
             }
             Ok(actix_ws::Message::Nop) => {
-                // Handle nop frames
+                trace!("Ignoring nop frame on session {}", session_id);
+            }
+            // Frames added by future actix-ws versions are skipped, not fatal
+            #[allow(unreachable_patterns)]
+            Ok(other) => {
+                warn!(
+                    "Ignoring unexpected frame on session {}: {:?}",
+                    session_id, other
+                );
+            }
+            // 文本帧不是合法UTF-8时只丢弃该帧，连接仍可继续使用
+            Err(actix_ws::ProtocolError::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData => {
+                warn!("Dropping malformed frame on session {}: {}", session_id, e);
             }
             Err(e) => {
                 warn!("WebSocket error: {}", e);
@@ -118,10 +437,114 @@ async fn handle_websocket_session(
     ws_manager.do_send(HandleUserDisconnect {
         session_id,
         user_id,
+        session_actor,
     });
     info!("WebSocket session ended");
 }
 
+/// 运营监控面板：只读接收所有会话的事件流，不作为观众参与对话
+async fn monitor_handler(
+    req: HttpRequest,
+    query: web::Query<AdminQuery>,
+    stream: web::Payload,
+    event_bus: web::Data<Addr<EventBus>>,
+    auth: web::Data<AuthConfig>,
+    retries: web::Data<SendRetries>,
+    queue: web::Data<OutboundQueue>,
+) -> Result<HttpResponse> {
+    // 事件流包含所有观众的消息，必须持有管理员 token
+    let operator = require_admin(&auth, query.token.as_deref(), "monitor")?;
+
+    let (response, session, stream) = actix_ws::handle(&req, stream)?;
+    actix_web::rt::spawn(handle_monitor_session(
+        session,
+        stream,
+        operator,
+        retries.get_ref().clone(),
+        queue.get_ref().clone(),
+        event_bus.get_ref().clone(),
+    ));
+
+    Ok(response)
+}
+
+async fn handle_monitor_session(
+    mut session: actix_ws::Session,
+    mut stream: actix_ws::MessageStream,
+    operator: String,
+    retries: SendRetries,
+    queue: OutboundQueue,
+    event_bus: Addr<EventBus>,
+) {
+    let monitor_id = Uuid::new_v4();
+    info!(
+        "Monitor {} connected for {}",
+        monitor_id,
+        redact::user(&operator)
+    );
+    let session_actor = WebSocketSessionActor::new(session.clone(), monitor_id, operator)
+        .with_send_retries(retries)
+        .with_outbound_queue(queue)
+        .start();
+    event_bus.do_send(SubscribeMonitor {
+        monitor_id,
+        recipient: session_actor.recipient(),
+    });
+
+    // 只读：客户端消息一律忽略，只处理心跳和关闭
+    while let Some(msg) = stream.next().await {
+        match msg {
+            Ok(actix_ws::Message::Ping(bytes)) => {
+                if let Err(e) = session.pong(&bytes).await {
+                    warn!("Failed to send pong: {}", e);
+                    break;
+                }
+            }
+            Ok(actix_ws::Message::Close(reason)) => {
+                info!("Monitor closed: {:?}", reason);
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Monitor WebSocket error: {}", e);
+                break;
+            }
+        }
+    }
+
+    event_bus.do_send(UnsubscribeMonitor { monitor_id });
+    info!("Monitor {} disconnected", monitor_id);
+}
+
+fn dispatch_text(
+    ws_manager: &Addr<WebSocketManager>,
+    session_id: Uuid,
+    user_id: &str,
+    text: String,
+) {
+    info!("Received text: {}", redact::text(&text));
+    // Handle text message through WebSocketManager
+    ws_manager.do_send(HandleTextMessage {
+        session_id,
+        user_id: user_id.to_string(),
+        text,
+    });
+}
+
+fn dispatch_binary(bin: &[u8]) {
+    info!("Received binary data: {} bytes", bin.len());
+    // Handle binary message (audio)
+}
+
+/// 超限音频直接丢弃并告知客户端，连接保持
+fn reject_audio(session_actor: &Addr<WebSocketSessionActor>, session_id: Uuid, reason: &str) {
+    warn!("Rejecting audio on session {}: {}", session_id, reason);
+    session_actor.do_send(SendMessage {
+        message: error_frame("audio_too_large", reason),
+        kind: FrameKind::Message,
+    });
+}
+
 async fn get_digital_human_info() -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "name": "Digital Human Assistant",
@@ -137,17 +560,68 @@ async fn get_digital_human_info() -> Result<HttpResponse> {
     })))
 }
 
+#[derive(Debug, Deserialize)]
+struct PauseRequest {
+    paused: bool,
+}
+
+// 暂停或恢复数字人；暂停期间的弹幕在配置了输入队列时排队，恢复后按顺序处理
+async fn set_digital_human_paused(
+    body: web::Json<PauseRequest>,
+    event_bus: web::Data<Addr<EventBus>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "pause")?;
+    let paused = body.into_inner().paused;
+    info!(
+        "{} {} the digital human",
+        operator,
+        if paused { "paused" } else { "resumed" }
+    );
+    event_bus
+        .send(SetDigitalHumanPaused { paused })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "paused": paused })))
+}
+
+// 所有会话共用的输出通道开关
+async fn get_output_channels(channels: web::Data<GlobalChannels>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(channels.get()))
+}
+
+// 全局关闭或打开某个输出通道，如TTS故障时关闭音频，无需重启
+async fn set_output_channels(
+    body: web::Json<ChannelsUpdate>,
+    channels: web::Data<GlobalChannels>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "output channels")?;
+    let updated = channels.update(&body.into_inner());
+    info!(
+        "{} set output channels on for every session: {:?}",
+        operator,
+        updated.list()
+    );
+    Ok(HttpResponse::Ok().json(updated))
+}
+
 // 处理抖音弹幕的HTTP回调
 async fn handle_douyin_danmaku(
+    req: HttpRequest,
     json: web::Json<serde_json::Value>,
     live_manager: web::Data<Addr<LiveStreamManager>>,
 ) -> Result<HttpResponse> {
-    info!("Received Douyin danmaku: {:?}", json);
+    info!(
+        "Received Douyin danmaku: {}",
+        redact::text(&json.to_string())
+    );
 
     // 解析抖音弹幕数据
     if let Ok(danmaku) = parse_douyin_danmaku(&json) {
-        live_manager.do_send(ProcessDanmaku { danmaku });
-        Ok(HttpResponse::Ok().json(serde_json::json!({"status": "success"})))
+        process_webhook_danmaku(&req, &json, danmaku, &live_manager).await
     } else {
         Ok(HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid danmaku format"})))
     }
@@ -155,26 +629,61 @@ async fn handle_douyin_danmaku(
 
 // 处理B站弹幕的HTTP回调
 async fn handle_bilibili_danmaku(
+    req: HttpRequest,
     json: web::Json<serde_json::Value>,
     live_manager: web::Data<Addr<LiveStreamManager>>,
 ) -> Result<HttpResponse> {
-    info!("Received Bilibili danmaku: {:?}", json);
+    info!(
+        "Received Bilibili danmaku: {}",
+        redact::text(&json.to_string())
+    );
 
     // 解析B站弹幕数据
     if let Ok(danmaku) = parse_bilibili_danmaku(&json) {
-        live_manager.do_send(ProcessDanmaku { danmaku });
-        Ok(HttpResponse::Ok().json(serde_json::json!({"status": "success"})))
+        process_webhook_danmaku(&req, &json, danmaku, &live_manager).await
     } else {
         Ok(HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid danmaku format"})))
     }
 }
 
+/// Webhook 重试时带相同的 `Idempotency-Key` 头或 `event_id` 字段，
+/// 重复的投递也返回 200，让平台停止重试
+async fn process_webhook_danmaku(
+    req: &HttpRequest,
+    json: &serde_json::Value,
+    danmaku: DanmakuMessage,
+    live_manager: &Addr<LiveStreamManager>,
+) -> Result<HttpResponse> {
+    let idempotency_key = req
+        .headers()
+        .get("idempotency-key")
+        .and_then(|key| key.to_str().ok())
+        .or_else(|| json.get("event_id").and_then(|id| id.as_str()))
+        .map(str::to_string);
+    let accepted = live_manager
+        .send(ProcessWebhookDanmaku {
+            danmaku,
+            idempotency_key,
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let status = if accepted { "success" } else { "duplicate" };
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": status })))
+}
+
 // 添加平台配置
 async fn add_platform_config(
     json: web::Json<LiveStreamConfig>,
     live_manager: web::Data<Addr<LiveStreamManager>>,
 ) -> Result<HttpResponse> {
     info!("Adding platform config: {:?}", json);
+    if json
+        .max_age_seconds
+        .is_some_and(|max_age| expiry_after(max_age).is_none())
+    {
+        return Ok(HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "max_age_seconds is out of range"})));
+    }
 
     live_manager.do_send(AddPlatformConfig {
         config: json.into_inner(),
@@ -183,83 +692,1641 @@ async fn add_platform_config(
     Ok(HttpResponse::Ok().json(serde_json::json!({"status": "success"})))
 }
 
-fn parse_douyin_danmaku(data: &serde_json::Value) -> Result<DanmakuMessage, String> {
-    let message = data
-        .get("message")
-        .and_then(|m| m.as_str())
-        .ok_or("Missing message field")?;
+/// 各平台监听器的健康状态
+async fn get_platform_status(
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+) -> Result<HttpResponse> {
+    let listeners = live_manager
+        .send(GetListenerStatus)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let user_id = data
-        .get("user_id")
-        .and_then(|u| u.as_str())
-        .unwrap_or("anonymous");
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "listeners": listeners,
+        "timestamp": timezone::now()
+    })))
+}
 
-    let username = data
-        .get("username")
-        .and_then(|u| u.as_str())
-        .unwrap_or("用户");
+// 回放的进度和状态
+async fn get_replay_status(
+    path: web::Path<String>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+) -> Result<HttpResponse> {
+    let config_id = path.into_inner();
+    let status = live_manager
+        .send(GetReplayStatus {
+            config_id: config_id.clone(),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let room_id = data
-        .get("room_id")
-        .and_then(|r| r.as_str())
-        .unwrap_or("unknown");
+    match status {
+        Some(status) => Ok(HttpResponse::Ok().json(status)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No running replay",
+            "config_id": config_id
+        }))),
+    }
+}
 
-    Ok(DanmakuMessage {
-        platform: Platform::Douyin,
-        room_id: room_id.to_string(),
-        user_id: user_id.to_string(),
-        username: username.to_string(),
-        message: message.to_string(),
-        timestamp: chrono::Utc::now(),
-        user_level: data
-            .get("user_level")
-            .and_then(|l| l.as_u64())
-            .map(|l| l as u32),
-        is_vip: data
-            .get("is_vip")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false),
-    })
+// 暂停、继续、跳转或调整回放速度
+async fn control_replay(
+    path: web::Path<String>,
+    body: web::Json<ReplayUpdate>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+) -> Result<HttpResponse> {
+    let config_id = path.into_inner();
+    let status = live_manager
+        .send(ControlReplay {
+            config_id: config_id.clone(),
+            update: body.into_inner(),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match status {
+        Some(Ok(status)) => Ok(HttpResponse::Ok().json(status)),
+        Some(Err(e)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No running replay",
+            "config_id": config_id
+        }))),
+    }
 }
 
-fn parse_bilibili_danmaku(data: &serde_json::Value) -> Result<DanmakuMessage, String> {
-    let info = data
-        .get("info")
-        .and_then(|i| i.as_array())
-        .ok_or("Missing info array")?;
+// 查询直播间情绪
+const DEFAULT_FAQ_LIMIT: usize = 10;
+const MAX_FAQ_LIMIT: usize = 100;
 
-    let message = info
-        .get(1)
-        .and_then(|m| m.as_str())
-        .ok_or("Missing message")?;
+#[derive(Debug, Deserialize)]
+struct FaqQuery {
+    room: String,
+    limit: Option<usize>,
+}
 
-    let user_info = info
-        .get(2)
-        .and_then(|u| u.as_array())
-        .ok_or("Missing user info")?;
+/// 直播间观众最常问的问题
+async fn get_faq(
+    query: web::Query<FaqQuery>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let questions = live_manager
+        .send(GetFaq {
+            room_id: query.room.clone(),
+            limit: query.limit.unwrap_or(DEFAULT_FAQ_LIMIT).min(MAX_FAQ_LIMIT),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let user_id = user_info
-        .get(0)
-        .and_then(|u| u.as_u64())
-        .map(|u| u.to_string())
-        .unwrap_or("anonymous".to_string());
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "room_id": query.room,
+        "questions": questions
+    })))
+}
 
-    let username = user_info.get(1).and_then(|u| u.as_str()).unwrap_or("用户");
+const DEFAULT_DANMAKU_LIMIT: usize = 100;
+const MAX_DANMAKU_LIMIT: usize = 1000;
 
-    let room_id = data
-        .get("roomid")
-        .and_then(|r| r.as_u64())
-        .map(|r| r.to_string())
-        .unwrap_or("unknown".to_string());
+#[derive(Debug, Deserialize)]
+struct DanmakuQuery {
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<usize>,
+}
 
-    Ok(DanmakuMessage {
-        platform: Platform::Bilibili,
-        room_id,
-        user_id,
-        username: username.to_string(),
-        message: message.to_string(),
-        timestamp: chrono::Utc::now(),
-        user_level: None,
-        is_vip: false,
+/// 查询直播间存储的原始弹幕，按时间范围筛选
+async fn query_room_danmaku(
+    path: web::Path<String>,
+    query: web::Query<DanmakuQuery>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+) -> Result<HttpResponse> {
+    let room_id = path.into_inner();
+    let query = query.into_inner();
+    let stored = live_manager
+        .send(QueryDanmaku {
+            room_id: room_id.clone(),
+            from: query.from,
+            to: query.to,
+            limit: query
+                .limit
+                .unwrap_or(DEFAULT_DANMAKU_LIMIT)
+                .min(MAX_DANMAKU_LIMIT),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match stored {
+        Some(Ok(danmaku)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "room_id": room_id,
+            "danmaku": danmaku
+        }))),
+        Some(Err(e)) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e,
+            "room_id": room_id
+        }))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Danmaku store is not enabled",
+            "room_id": room_id
+        }))),
+    }
+}
+
+async fn get_room_mood(
+    path: web::Path<String>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+) -> Result<HttpResponse> {
+    let room_id = path.into_inner();
+    let mood = live_manager
+        .send(GetRoomMood {
+            room_id: room_id.clone(),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match mood {
+        Some(mood) => Ok(HttpResponse::Ok().json(mood)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No danmaku received for room",
+            "room_id": room_id
+        }))),
+    }
+}
+
+/// 从现在起 `seconds` 秒后的时间；超出时间可表示的范围时为None
+fn expiry_after(seconds: u64) -> Option<chrono::DateTime<chrono::Utc>> {
+    let delta = chrono::TimeDelta::try_seconds(i64::try_from(seconds).ok()?)?;
+    chrono::Utc::now().checked_add_signed(delta)
+}
+
+fn invalid_duration() -> HttpResponse {
+    HttpResponse::BadRequest()
+        .json(serde_json::json!({"error": "duration_seconds is out of range"}))
+}
+
+#[derive(Debug, Deserialize)]
+struct RoomGateRequest {
+    min_level: Option<u32>,
+    #[serde(default)]
+    vip_only: bool,
+    duration_seconds: Option<u64>,
+}
+
+// 设置直播间准入门槛；不带min_level且vip_only为false时清除
+async fn set_room_gate(
+    path: web::Path<String>,
+    body: web::Json<RoomGateRequest>,
+    event_bus: web::Data<Addr<EventBus>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "room gate")?;
+    let room_id = path.into_inner();
+    info!("{} setting the gate of room {}", operator, room_id);
+    let body = body.into_inner();
+    let expires_at = match body.duration_seconds.map(expiry_after) {
+        Some(None) => return Ok(invalid_duration()),
+        expires_at => expires_at.flatten(),
+    };
+
+    let gate = event_bus
+        .send(SetRoomGate {
+            room_id: room_id.clone(),
+            min_level: body.min_level,
+            vip_only: body.vip_only,
+            expires_at,
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match gate {
+        Some(rule) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "room_id": room_id,
+            "gate": rule.parameters
+        }))),
+        None => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "room_id": room_id,
+            "gate": null
+        }))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RespondRequest {
+    respond: bool,
+}
+
+// 开关直播间的数字人回复；关闭后弹幕仍存储并计入情绪和FAQ
+async fn set_room_responding(
+    path: web::Path<String>,
+    body: web::Json<RespondRequest>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+) -> Result<HttpResponse> {
+    let room_id = path.into_inner();
+    let respond = body.into_inner().respond;
+    live_manager
+        .send(SetRoomResponding {
+            room_id: room_id.clone(),
+            respond,
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "room_id": room_id,
+        "respond": respond
+    })))
+}
+
+/// 开播：开始处理直播间弹幕，数字人致开场白
+async fn start_stream(
+    path: web::Path<String>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "stream start")?;
+    let room_id = path.into_inner();
+    info!("{} starting the stream of room {}", operator, room_id);
+    live_manager
+        .send(StreamStartedEvent {
+            metadata: EventMetadata {
+                session_id: Some(Uuid::new_v4()),
+                ..Default::default()
+            },
+            room_id: room_id.clone(),
+            silent: false,
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "room_id": room_id,
+        "live": true
+    })))
+}
+
+/// 下播：数字人致结束语，之后忽略直播间弹幕
+async fn end_stream(
+    path: web::Path<String>,
+    live_manager: web::Data<Addr<LiveStreamManager>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "stream end")?;
+    let room_id = path.into_inner();
+    info!("{} ending the stream of room {}", operator, room_id);
+    live_manager
+        .send(StreamEndedEvent {
+            metadata: EventMetadata {
+                session_id: Some(Uuid::new_v4()),
+                ..Default::default()
+            },
+            room_id: room_id.clone(),
+            silent: false,
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "room_id": room_id,
+        "live": false
+    })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BanRequest {
+    reason: Option<String>,
+    /// 临时封禁的时长；不填为永久封禁
+    duration_seconds: Option<u64>,
+}
+
+// 封禁用户：忽略其消息并断开其WebSocket会话
+async fn ban_user(
+    path: web::Path<String>,
+    body: Option<web::Json<BanRequest>>,
+    event_bus: web::Data<Addr<EventBus>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "ban")?;
+    let user_id = path.into_inner();
+    info!("{} banning user {}", operator, redact::user(&user_id));
+    let body = body.map(web::Json::into_inner).unwrap_or_default();
+    let expires_at = match body.duration_seconds.map(expiry_after) {
+        Some(None) => return Ok(invalid_duration()),
+        expires_at => expires_at.flatten(),
+    };
+
+    event_bus
+        .send(BanUser {
+            user_id: user_id.clone(),
+            reason: body.reason,
+            expires_at,
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "banned": true,
+        "expires_at": expires_at
+    })))
+}
+
+// 解除封禁
+async fn unban_user(
+    path: web::Path<String>,
+    event_bus: web::Data<Addr<EventBus>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "unban")?;
+    let user_id = path.into_inner();
+    info!("{} unbanning user {}", operator, redact::user(&user_id));
+    let unbanned = event_bus
+        .send(UnbanUser {
+            user_id: user_id.clone(),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if unbanned {
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "user_id": user_id,
+            "banned": false
+        })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "User is not banned",
+            "user_id": user_id
+        })))
+    }
+}
+
+// 豁免用户的频率限制，封禁仍然生效
+async fn exempt_user(
+    path: web::Path<String>,
+    event_bus: web::Data<Addr<EventBus>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "rate-limit exemption")?;
+    let user_id = path.into_inner();
+    info!(
+        "{} exempting user {} from rate limits",
+        operator,
+        redact::user(&user_id)
+    );
+    event_bus
+        .send(ExemptFromRateLimit {
+            user_id: user_id.clone(),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "rate_limit_exempt": true
+    })))
+}
+
+// 取消频率限制豁免
+async fn unexempt_user(
+    path: web::Path<String>,
+    event_bus: web::Data<Addr<EventBus>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "rate-limit exemption")?;
+    let user_id = path.into_inner();
+    info!(
+        "{} removing rate-limit exemption of user {}",
+        operator,
+        redact::user(&user_id)
+    );
+    let removed = event_bus
+        .send(RemoveRateLimitExemption {
+            user_id: user_id.clone(),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if removed {
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "user_id": user_id,
+            "rate_limit_exempt": false
+        })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "User is not exempt",
+            "user_id": user_id
+        })))
+    }
+}
+
+// 频率限制豁免名单
+async fn list_exempt_users(event_bus: web::Data<Addr<EventBus>>) -> Result<HttpResponse> {
+    let user_ids = event_bus
+        .send(ListRateLimitExemptions)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "user_ids": user_ids })))
+}
+
+fn rule_summary(rule: &ValidationRule) -> serde_json::Value {
+    serde_json::json!({
+        "id": rule.id,
+        "name": rule.name,
+        "rule_type": rule.rule_type,
+        "enabled": rule.enabled
     })
 }
+
+// 列出校验规则
+async fn list_validation_rules(event_bus: web::Data<Addr<EventBus>>) -> Result<HttpResponse> {
+    let rules = event_bus
+        .send(ListValidationRules)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let rules: Vec<_> = rules.iter().map(rule_summary).collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "rules": rules })))
+}
+
+// 各校验规则的触发次数
+async fn get_rule_trigger_stats(event_bus: web::Data<Addr<EventBus>>) -> Result<HttpResponse> {
+    let stats = event_bus
+        .send(GetRuleTriggerStats)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+// 清零规则触发次数
+async fn reset_rule_trigger_stats(event_bus: web::Data<Addr<EventBus>>) -> Result<HttpResponse> {
+    event_bus
+        .send(ResetRuleTriggerStats)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({"status": "reset"})))
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateRuleRequest {
+    enabled: bool,
+}
+
+// 启用/停用单条校验规则
+async fn update_validation_rule(
+    path: web::Path<String>,
+    body: web::Json<UpdateRuleRequest>,
+    event_bus: web::Data<Addr<EventBus>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "validation rules")?;
+    let rule_id = path.into_inner();
+    info!(
+        "{} setting validation rule {} enabled={}",
+        operator, rule_id, body.enabled
+    );
+
+    let rule = event_bus
+        .send(SetValidationRuleEnabled {
+            rule_id: rule_id.clone(),
+            enabled: body.enabled,
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match rule {
+        Some(rule) => Ok(HttpResponse::Ok().json(rule_summary(&rule))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Unknown validation rule",
+            "rule_id": rule_id
+        }))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RetractRequest {
+    reason: Option<String>,
+}
+
+// 撤回已发送的回复
+async fn retract_response(
+    path: web::Path<Uuid>,
+    body: Option<web::Json<RetractRequest>>,
+    event_bus: web::Data<Addr<EventBus>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "retraction")?;
+    let response_id = path.into_inner();
+    info!("{} retracting response {}", operator, response_id);
+
+    let retracted = event_bus
+        .send(RetractResponse {
+            metadata: EventMetadata::default(),
+            response_id,
+            reason: body.and_then(|b| b.into_inner().reason),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match retracted {
+        Some(_) => Ok(HttpResponse::Ok().json(serde_json::json!({"status": "success"}))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Unknown response",
+            "response_id": response_id
+        }))),
+    }
+}
+
+// 导出会话的完整对话历史
+async fn export_session_history(
+    path: web::Path<Uuid>,
+    digital_human: web::Data<Addr<DigitalHumanActor>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "history export")?;
+    let session_id = path.into_inner();
+    info!(
+        "{} exporting the history of session {}",
+        operator, session_id
+    );
+    let history = digital_human
+        .send(ExportHistory { session_id })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match history {
+        Some(history) => Ok(HttpResponse::Ok().json(history)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Unknown session",
+            "session_id": session_id
+        }))),
+    }
+}
+
+// 为接手的主持人生成会话摘要和要点
+async fn summarize_session(
+    path: web::Path<Uuid>,
+    digital_human: web::Data<Addr<DigitalHumanActor>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    // 每次生成摘要都会真实调用一次LLM，只对管理员开放
+    let operator = require_admin(&auth, query.token.as_deref(), "session summary")?;
+    let session_id = path.into_inner();
+    info!("{} summarizing session {}", operator, session_id);
+    let handoff = digital_human
+        .send(SummarizeForHandoff { session_id })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match handoff {
+        Some(Ok(handoff)) => Ok(HttpResponse::Ok().json(handoff)),
+        Some(Err(e)) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": e.to_string(),
+            "session_id": session_id
+        }))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Unknown session",
+            "session_id": session_id
+        }))),
+    }
+}
+
+// 导入对话历史到新会话或替换已有会话的历史
+async fn import_session_history(
+    path: web::Path<Uuid>,
+    body: web::Json<SessionHistory>,
+    digital_human: web::Data<Addr<DigitalHumanActor>>,
+    query: web::Query<AdminQuery>,
+    auth: web::Data<AuthConfig>,
+) -> Result<HttpResponse> {
+    let operator = require_admin(&auth, query.token.as_deref(), "history import")?;
+    let session_id = path.into_inner();
+    info!("{} importing history into session {}", operator, session_id);
+    let imported = digital_human
+        .send(ImportHistory {
+            session_id,
+            history: body.into_inner(),
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match imported {
+        Ok(count) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "status": "success",
+            "session_id": session_id,
+            "imported": count
+        }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e,
+            "session_id": session_id
+        }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_bus::{RegisterDigitalHuman, RegisterWebSocketManager, Subscribe};
+    use crate::events::TextInputEvent;
+    use crate::moderation::{AckChannel, ModerationConfig};
+    use crate::overlay::DanmakuDelivery;
+    use crate::testing::{self, received_frames, Collect};
+    use actix_web::http::Method;
+    use actix_web::FromRequest;
+
+    async fn upgraded_session() -> actix_ws::Session {
+        upgraded_socket().await.1
+    }
+
+    /// The upgraded session and the response whose body carries what the
+    /// server writes to the socket.
+    async fn upgraded_socket() -> (HttpResponse, actix_ws::Session) {
+        let (req, mut payload) = actix_web::test::TestRequest::get()
+            .insert_header(("upgrade", "websocket"))
+            .insert_header(("connection", "upgrade"))
+            .insert_header(("sec-websocket-version", "13"))
+            .insert_header(("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="))
+            .to_http_parts();
+        let body = web::Payload::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        let (response, session, _stream) = actix_ws::handle(&req, body).unwrap();
+        (response, session)
+    }
+
+    /// Everything written to the socket so far. Server frames are unmasked,
+    /// so text frames appear verbatim.
+    async fn written(body: &mut actix_web::body::BoxBody) -> String {
+        use actix_web::body::MessageBody;
+
+        let mut written = Vec::new();
+        while let Ok(Some(Ok(chunk))) = actix::clock::timeout(
+            std::time::Duration::from_millis(20),
+            futures_util::future::poll_fn(|cx| std::pin::Pin::new(&mut *body).poll_next(cx)),
+        )
+        .await
+        {
+            written.extend_from_slice(&chunk);
+        }
+        String::from_utf8_lossy(&written).into_owned()
+    }
+
+    #[actix_web::test]
+    async fn test_nop_frame_does_not_disrupt_session() {
+        let event_bus = EventBus::new().start();
+        let ws_manager = WebSocketManager::new(event_bus.clone()).start();
+        event_bus
+            .send(RegisterWebSocketManager {
+                addr: ws_manager.clone(),
+            })
+            .await
+            .unwrap();
+        let monitor = Collect::<SendMessage>::default().start();
+        event_bus
+            .send(SubscribeMonitor {
+                monitor_id: Uuid::new_v4(),
+                recipient: monitor.clone().recipient(),
+            })
+            .await
+            .unwrap();
+
+        let frames = futures_util::stream::iter(vec![
+            Ok(actix_ws::Message::Nop),
+            Ok(actix_ws::Message::Text("第一条".into())),
+            Ok(actix_ws::Message::Nop),
+            Ok(actix_ws::Message::Text("第二条".into())),
+            Ok(actix_ws::Message::Close(None)),
+        ]);
+        let start = SessionStart {
+            session_id: Uuid::new_v4(),
+            user_id: "viewer".to_string(),
+            replay: None,
+            token_expires_at: None,
+            operator: false,
+        };
+        handle_websocket_session(
+            upgraded_session().await,
+            frames,
+            start,
+            MessageAssembler::new(&MessageLimits::default()),
+            SendRetries::default(),
+            OutboundQueue::default(),
+            ws_manager,
+        )
+        .await;
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+
+        // Both messages after the nop frames reached the event bus
+        let inputs: Vec<serde_json::Value> = received_frames(&monitor)
+            .await
+            .into_iter()
+            .filter(|f| f["type"] == "text_input")
+            .map(|f| f["data"]["text"].clone())
+            .collect();
+        assert_eq!(inputs, vec!["第一条", "第二条"]);
+    }
+
+    #[actix_web::test]
+    async fn test_banned_user_is_disconnected_and_ignored() {
+        let event_bus = EventBus::new().start();
+        let ws_manager = WebSocketManager::new(event_bus.clone()).start();
+        event_bus
+            .send(RegisterWebSocketManager {
+                addr: ws_manager.clone(),
+            })
+            .await
+            .unwrap();
+        let monitor = Collect::<SendMessage>::default().start();
+        event_bus
+            .send(SubscribeMonitor {
+                monitor_id: Uuid::new_v4(),
+                recipient: monitor.clone().recipient(),
+            })
+            .await
+            .unwrap();
+
+        // Keep the socket open until the server closes it
+        let frames = futures_util::stream::pending();
+        let session_id = Uuid::new_v4();
+        let start = SessionStart {
+            session_id,
+            user_id: "troll".to_string(),
+            replay: None,
+            token_expires_at: None,
+            operator: false,
+        };
+        let session = upgraded_session().await;
+        actix::spawn(handle_websocket_session(
+            session,
+            frames,
+            start,
+            MessageAssembler::new(&MessageLimits::default()),
+            SendRetries::default(),
+            OutboundQueue::default(),
+            ws_manager,
+        ));
+        actix::clock::sleep(std::time::Duration::from_millis(20)).await;
+
+        event_bus
+            .send(BanUser {
+                user_id: "troll".to_string(),
+                reason: Some("spam".to_string()),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        event_bus
+            .send(crate::events::TextInputEvent {
+                metadata: EventMetadata {
+                    session_id: Some(session_id),
+                    user_id: Some("troll".to_string()),
+                    ..Default::default()
+                },
+                text: "还在刷屏".to_string(),
+                language: None,
+                username: None,
+                room_mood: None,
+                priority: crate::events::MessagePriority::Normal,
+                intent: None,
+                viewer: None,
+                max_age_seconds: None,
+                operator: false,
+            })
+            .await
+            .unwrap();
+        actix::clock::sleep(std::time::Duration::from_millis(20)).await;
+
+        let frames = received_frames(&monitor).await;
+        assert!(frames.iter().any(|f| f["type"] == "user_disconnected"
+            && f["data"]["session_id"] == serde_json::json!(session_id)));
+        let outcomes: Vec<_> = frames
+            .iter()
+            .filter(|f| f["type"] == "validation")
+            .map(|f| f["data"]["outcome"].clone())
+            .collect();
+        assert_eq!(outcomes, vec!["ignore"]);
+    }
+
+    #[actix_web::test]
+    async fn test_danmaku_response_reaches_room_overlay() {
+        let event_bus = EventBus::new().start();
+        let ws_manager = WebSocketManager::new(event_bus.clone())
+            .with_danmaku_delivery(DanmakuDelivery::Room)
+            .start();
+        event_bus
+            .send(RegisterWebSocketManager {
+                addr: ws_manager.clone(),
+            })
+            .await
+            .unwrap();
+        let digital_human = DigitalHumanActor::new(
+            "Maya".to_string(),
+            "cheerful".to_string(),
+            event_bus.clone(),
+        )
+        .start();
+        event_bus
+            .send(RegisterDigitalHuman {
+                addr: digital_human,
+            })
+            .await
+            .unwrap();
+
+        // An overlay for room 1001 and a client for room 2002
+        let mut sockets = Vec::new();
+        for room_id in ["1001", "2002"] {
+            let session_id = Uuid::new_v4();
+            let (response, session) = upgraded_socket().await;
+            actix::spawn(handle_websocket_session(
+                session,
+                futures_util::stream::pending(),
+                SessionStart {
+                    session_id,
+                    user_id: format!("overlay_{}", room_id),
+                    replay: None,
+                    token_expires_at: None,
+                    operator: false,
+                },
+                MessageAssembler::new(&MessageLimits::default()),
+                SendRetries::default(),
+                OutboundQueue::default(),
+                ws_manager.clone(),
+            ));
+            actix::clock::sleep(std::time::Duration::from_millis(20)).await;
+            ws_manager
+                .send(HandleTextMessage {
+                    session_id,
+                    user_id: format!("overlay_{}", room_id),
+                    text: serde_json::json!({"type": "subscribe_room", "room_id": room_id})
+                        .to_string(),
+                })
+                .await
+                .unwrap();
+            sockets.push(response.into_body());
+        }
+
+        event_bus
+            .send(crate::events::TextInputEvent {
+                metadata: EventMetadata {
+                    session_id: Some(Uuid::new_v4()),
+                    user_id: Some("bilibili_42".to_string()),
+                    ..Default::default()
+                },
+                text: "主播今天唱什么歌".to_string(),
+                language: Some("zh-CN".to_string()),
+                username: Some("观众42".to_string()),
+                room_mood: None,
+                priority: crate::events::MessagePriority::Low,
+                intent: None,
+                viewer: Some(crate::events::ViewerInfo {
+                    room_id: "1001".to_string(),
+                    user_level: None,
+                    is_vip: false,
+                    gift_value: None,
+                    similar_count: 0,
+                    trusted: false,
+                }),
+                max_age_seconds: None,
+                operator: false,
+            })
+            .await
+            .unwrap();
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+
+        let overlay = written(&mut sockets[0]).await;
+        assert!(overlay.contains(r#""type":"llm_response""#));
+        assert!(overlay.contains("主播今天唱什么歌"));
+        let other_room = written(&mut sockets[1]).await;
+        assert!(!other_room.contains("llm_response"));
+    }
+
+    #[actix_web::test]
+    async fn test_response_frame_names_the_message_answered() {
+        let event_bus = EventBus::new().start();
+        let ws_manager = WebSocketManager::new(event_bus.clone())
+            .with_danmaku_delivery(DanmakuDelivery::Room)
+            .start();
+        event_bus
+            .send(RegisterWebSocketManager {
+                addr: ws_manager.clone(),
+            })
+            .await
+            .unwrap();
+        let digital_human = DigitalHumanActor::new(
+            "Maya".to_string(),
+            "cheerful".to_string(),
+            event_bus.clone(),
+        )
+        .with_response_attribution(true)
+        .start();
+        event_bus
+            .send(RegisterDigitalHuman {
+                addr: digital_human,
+            })
+            .await
+            .unwrap();
+
+        let overlay = Uuid::new_v4();
+        let (response, session) = upgraded_socket().await;
+        actix::spawn(handle_websocket_session(
+            session,
+            futures_util::stream::pending(),
+            SessionStart {
+                session_id: overlay,
+                user_id: "overlay_1001".to_string(),
+                replay: None,
+                token_expires_at: None,
+                operator: false,
+            },
+            MessageAssembler::new(&MessageLimits::default()),
+            SendRetries::default(),
+            OutboundQueue::default(),
+            ws_manager.clone(),
+        ));
+        actix::clock::sleep(std::time::Duration::from_millis(20)).await;
+        ws_manager
+            .send(HandleTextMessage {
+                session_id: overlay,
+                user_id: "overlay_1001".to_string(),
+                text: serde_json::json!({"type": "subscribe_room", "room_id": "1001"}).to_string(),
+            })
+            .await
+            .unwrap();
+
+        event_bus
+            .send(TextInputEvent {
+                metadata: EventMetadata {
+                    session_id: Some(Uuid::new_v4()),
+                    user_id: Some("bilibili_42".to_string()),
+                    ..Default::default()
+                },
+                text: "主播今天唱什么歌".to_string(),
+                language: Some("zh-CN".to_string()),
+                username: Some("观众42".to_string()),
+                room_mood: None,
+                priority: crate::events::MessagePriority::Low,
+                intent: None,
+                viewer: Some(crate::events::ViewerInfo {
+                    room_id: "1001".to_string(),
+                    user_level: None,
+                    is_vip: false,
+                    gift_value: None,
+                    similar_count: 0,
+                    trusted: false,
+                }),
+                max_age_seconds: None,
+                operator: false,
+            })
+            .await
+            .unwrap();
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+
+        let mut body = response.into_body();
+        let frames = written(&mut body).await;
+        assert!(frames.contains(r#""type":"llm_response""#));
+        assert!(frames.contains(r#""replying_to":{"#));
+        assert!(frames.contains(r#""username":"观众42""#));
+        assert!(frames.contains(r#""message":"主播今天唱什么歌""#));
+    }
+
+    #[actix_web::test]
+    async fn test_text_only_session_skips_audio_and_animation() {
+        let ws_manager = WebSocketManager::new(EventBus::new().start()).start();
+        let session_id = Uuid::new_v4();
+        let (response, session) = upgraded_socket().await;
+        actix::spawn(handle_websocket_session(
+            session,
+            futures_util::stream::pending(),
+            SessionStart {
+                session_id,
+                user_id: "user_1".to_string(),
+                replay: None,
+                token_expires_at: None,
+                operator: false,
+            },
+            MessageAssembler::new(&MessageLimits::default()),
+            SendRetries::default(),
+            OutboundQueue::default(),
+            ws_manager.clone(),
+        ));
+        actix::clock::sleep(std::time::Duration::from_millis(20)).await;
+        ws_manager
+            .send(HandleTextMessage {
+                session_id,
+                user_id: "user_1".to_string(),
+                text: serde_json::json!({"type": "set_channels", "channels": ["text"]}).to_string(),
+            })
+            .await
+            .unwrap();
+
+        let metadata = EventMetadata {
+            session_id: Some(session_id),
+            ..Default::default()
+        };
+        ws_manager.do_send(crate::events::LLMResponseEvent {
+            metadata: metadata.clone(),
+            response: "你好呀".to_string(),
+            model: "test".to_string(),
+            tokens_used: None,
+            length_limit: None,
+            sampling: None,
+            language: None,
+            translation_of: None,
+            replying_to: None,
+            segments: None,
+        });
+        ws_manager.do_send(crate::events::TTSResponseEvent {
+            metadata: metadata.clone(),
+            audio_data: vec![0; 16],
+            text: "你好呀".to_string(),
+            voice: "default".to_string(),
+            style: None,
+            voice_fallback: None,
+        });
+        ws_manager
+            .send(crate::events::AnimationEvent {
+                metadata,
+                animation_type: "wave".to_string(),
+                duration: Some(1.0),
+                parameters: serde_json::json!({}),
+            })
+            .await
+            .unwrap();
+
+        let mut body = response.into_body();
+        let frames = written(&mut body).await;
+        assert!(frames.contains(r#""type":"channels""#));
+        assert!(frames.contains(r#""type":"llm_response""#));
+        assert!(!frames.contains("tts_response"));
+        assert!(!frames.contains(r#""type":"animation""#));
+    }
+
+    #[actix_web::test]
+    async fn test_globally_muted_audio_stops_tts_frames() {
+        let channels = GlobalChannels::default();
+        let ws_manager = WebSocketManager::new(EventBus::new().start())
+            .with_global_channels(channels.clone())
+            .start();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(channels.clone()))
+                .app_data(web::Data::new(testing::auth()))
+                .configure(configure_routes),
+        )
+        .await;
+        let session_id = Uuid::new_v4();
+        let (response, session) = upgraded_socket().await;
+        actix::spawn(handle_websocket_session(
+            session,
+            futures_util::stream::pending(),
+            SessionStart {
+                session_id,
+                user_id: "user_1".to_string(),
+                replay: None,
+                token_expires_at: None,
+                operator: false,
+            },
+            MessageAssembler::new(&MessageLimits::default()),
+            SendRetries::default(),
+            OutboundQueue::default(),
+            ws_manager.clone(),
+        ));
+        actix::clock::sleep(std::time::Duration::from_millis(20)).await;
+
+        let metadata = EventMetadata {
+            session_id: Some(session_id),
+            ..Default::default()
+        };
+        let speech = || crate::events::TTSResponseEvent {
+            metadata: metadata.clone(),
+            audio_data: vec![0; 16],
+            text: "你好呀".to_string(),
+            voice: "default".to_string(),
+            style: None,
+            voice_fallback: None,
+        };
+        ws_manager.send(speech()).await.unwrap();
+
+        // Only operators switch channels for everyone
+        let forged = actix_web::test::TestRequest::patch()
+            .uri(&format!(
+                "/api/v1/output/channels?token={}",
+                testing::token("user_1", false)
+            ))
+            .set_json(serde_json::json!({"audio": false}))
+            .to_request();
+        let response = actix_web::test::call_service(&app, forged).await;
+        assert_eq!(response.status(), 401);
+        let mute = actix_web::test::TestRequest::patch()
+            .uri(&format!(
+                "/api/v1/output/channels?token={}",
+                testing::token("ops", true)
+            ))
+            .set_json(serde_json::json!({"audio": false}))
+            .to_request();
+        let body: serde_json::Value =
+            actix_web::test::read_body_json(actix_web::test::call_service(&app, mute).await).await;
+        assert_eq!(
+            body,
+            serde_json::json!({"text": true, "audio": false, "animation": true})
+        );
+
+        ws_manager.do_send(crate::events::LLMResponseEvent {
+            metadata: metadata.clone(),
+            response: "你好呀".to_string(),
+            model: "test".to_string(),
+            tokens_used: None,
+            length_limit: None,
+            sampling: None,
+            language: None,
+            translation_of: None,
+            replying_to: None,
+            segments: None,
+        });
+        ws_manager.send(speech()).await.unwrap();
+
+        let mut body = response.into_body();
+        let frames = written(&mut body).await;
+        assert_eq!(frames.matches(r#""type":"tts_response""#).count(), 1);
+        assert!(frames.contains(r#""type":"llm_response""#));
+    }
+
+    #[actix_web::test]
+    async fn test_retried_webhook_is_processed_once() {
+        let event_bus = EventBus::new().start();
+        let inputs = Collect::<TextInputEvent>::default().start();
+        event_bus
+            .send(Subscribe::<TextInputEvent>::all(inputs.clone().recipient()))
+            .await
+            .unwrap();
+        let live_manager = LiveStreamManager::new(event_bus).start();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(live_manager))
+                .configure(configure_routes),
+        )
+        .await;
+
+        let danmaku = serde_json::json!({
+            "message": "主播好！",
+            "user_id": "42",
+            "username": "观众42",
+            "room_id": "1001"
+        });
+        let mut statuses = Vec::new();
+        for _ in 0..2 {
+            let req = actix_web::test::TestRequest::post()
+                .uri("/api/v1/danmaku/douyin")
+                .insert_header(("Idempotency-Key", "evt-1"))
+                .set_json(&danmaku)
+                .to_request();
+            let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+            statuses.push(body["status"].clone());
+        }
+        // The same key from another platform is a different delivery
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/v1/danmaku/bilibili")
+            .set_json(serde_json::json!({
+                "event_id": "evt-1",
+                "info": [[], "B站弹幕", [7, "观众7"]],
+                "roomid": 2002
+            }))
+            .to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        statuses.push(body["status"].clone());
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+
+        assert_eq!(statuses, vec!["success", "duplicate", "success"]);
+        let texts: Vec<String> = Collect::received(&inputs)
+            .await
+            .into_iter()
+            .map(|event| event.text)
+            .collect();
+        assert_eq!(texts, vec!["主播好！", "B站弹幕"]);
+    }
+
+    #[actix_web::test]
+    async fn test_forged_webhook_moderator_command_is_refused() {
+        let moderation = ModerationConfig {
+            moderators: ["douyin_mod".to_string()].into(),
+            ack_channel: AckChannel::Monitor,
+            sessions_authenticated: true,
+            ..Default::default()
+        };
+        let event_bus = EventBus::new().with_moderation(moderation).start();
+        let monitor = Collect::<SendMessage>::default().start();
+        event_bus
+            .send(SubscribeMonitor {
+                monitor_id: Uuid::new_v4(),
+                recipient: monitor.clone().recipient(),
+            })
+            .await
+            .unwrap();
+        let live_manager = LiveStreamManager::new(event_bus).start();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(live_manager.clone()))
+                .configure(configure_routes),
+        )
+        .await;
+
+        // Anyone can post a webhook claiming to be the moderator
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/v1/danmaku/douyin")
+            .set_json(serde_json::json!({
+                "message": "!pause",
+                "user_id": "mod",
+                "room_id": "1001"
+            }))
+            .to_request();
+        actix_web::test::call_service(&app, req).await;
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+        // The platform listener vouches for the same moderator
+        let danmaku = parse_douyin_danmaku(&serde_json::json!({
+            "message": "!pause",
+            "user_id": "mod",
+            "room_id": "1001"
+        }))
+        .unwrap();
+        live_manager
+            .send(ProcessDanmaku {
+                danmaku: DanmakuMessage {
+                    trusted: true,
+                    ..danmaku
+                },
+            })
+            .await
+            .unwrap();
+        actix::clock::sleep(std::time::Duration::from_millis(50)).await;
+
+        let acks: Vec<serde_json::Value> = received_frames(&monitor)
+            .await
+            .into_iter()
+            .filter(|f| f["type"] == "command_ack")
+            .map(|f| f["data"].clone())
+            .collect();
+        assert_eq!(acks.len(), 2);
+        assert_eq!(acks[0]["accepted"], false);
+        assert_eq!(acks[0]["detail"], "commands need an authenticated source");
+        assert_eq!(acks[1]["accepted"], true);
+    }
+
+    #[actix_web::test]
+    async fn test_ready_only_once_wired_and_preflight_passed() {
+        let event_bus = EventBus::new().start();
+        let preflight = PreflightStatus::default();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(event_bus.clone()))
+                .app_data(web::Data::new(preflight.clone()))
+                .configure(configure_routes),
+        )
+        .await;
+        let ready = || {
+            actix_web::test::TestRequest::get()
+                .uri("/api/v1/ready")
+                .to_request()
+        };
+
+        let response = actix_web::test::call_service(&app, ready()).await;
+        assert_eq!(response.status(), 503);
+
+        let ws_manager = WebSocketManager::new(event_bus.clone()).start();
+        event_bus
+            .send(RegisterWebSocketManager { addr: ws_manager })
+            .await
+            .unwrap();
+        let digital_human = DigitalHumanActor::new(
+            "Maya".to_string(),
+            "cheerful".to_string(),
+            event_bus.clone(),
+        )
+        .start();
+        event_bus
+            .send(RegisterDigitalHuman {
+                addr: digital_human,
+            })
+            .await
+            .unwrap();
+        // Wired, but the preflight has not passed yet
+        let response = actix_web::test::call_service(&app, ready()).await;
+        assert_eq!(response.status(), 503);
+        let body: serde_json::Value = actix_web::test::read_body_json(response).await;
+        assert_eq!(body["checks"]["preflight"], false);
+        assert_eq!(body["checks"]["digital_human"], true);
+
+        preflight.mark_passed();
+        let response = actix_web::test::call_service(&app, ready()).await;
+        assert_eq!(response.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_health_stays_up_while_not_ready() {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(EventBus::new().start()))
+                .app_data(web::Data::new(PreflightStatus::default()))
+                .configure(configure_routes),
+        )
+        .await;
+
+        let health = actix_web::test::TestRequest::get()
+            .uri("/api/v1/health")
+            .to_request();
+        let response = actix_web::test::call_service(&app, health).await;
+        assert_eq!(response.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_session_summary_covers_several_turns() {
+        let event_bus = EventBus::new().start();
+        let digital_human = DigitalHumanActor::new(
+            "Maya".to_string(),
+            "cheerful".to_string(),
+            event_bus.clone(),
+        )
+        .start();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(event_bus))
+                .app_data(web::Data::new(digital_human))
+                .app_data(web::Data::new(PreflightStatus::default()))
+                .app_data(web::Data::new(testing::auth()))
+                .configure(configure_routes),
+        )
+        .await;
+        let token = testing::token("ops", true);
+        let session_id = Uuid::new_v4();
+        let summary = || {
+            actix_web::test::TestRequest::get()
+                .uri(&format!(
+                    "/api/v1/sessions/{}/summary?token={}",
+                    session_id, token
+                ))
+                .to_request()
+        };
+
+        let response = actix_web::test::call_service(&app, summary()).await;
+        assert_eq!(response.status(), 404);
+
+        let start = chrono::Utc::now() - chrono::Duration::minutes(5);
+        let turns = ["我想退款", "订单号是12345", "已经等了一周了"];
+        let history: Vec<serde_json::Value> = turns
+            .iter()
+            .enumerate()
+            .flat_map(|(i, text)| {
+                let at = start + chrono::Duration::seconds(20 * i as i64);
+                [
+                    serde_json::json!({"role": "user", "content": text, "timestamp": at}),
+                    serde_json::json!({
+                        "role": "assistant",
+                        "content": "收到，我帮你看看",
+                        "timestamp": at + chrono::Duration::seconds(5)
+                    }),
+                ]
+            })
+            .collect();
+        let import = actix_web::test::TestRequest::post()
+            .uri(&format!(
+                "/api/v1/sessions/{}/import?token={}",
+                session_id, token
+            ))
+            .set_json(serde_json::json!({"user_id": "viewer1", "history": history}))
+            .to_request();
+        assert_eq!(
+            actix_web::test::call_service(&app, import).await.status(),
+            200
+        );
+
+        let response = actix_web::test::call_service(&app, summary()).await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = actix_web::test::read_body_json(response).await;
+        assert_eq!(body["user_id"], "viewer1");
+        assert_eq!(body["messages"], 6);
+        // The echo provider repeats the transcript it was asked to summarize
+        let text = body["summary"].as_str().unwrap();
+        assert!(!text.is_empty() && text.contains("订单号是12345"));
+
+        // Reused until the history changes
+        let again: serde_json::Value =
+            actix_web::test::read_body_json(actix_web::test::call_service(&app, summary()).await)
+                .await;
+        assert_eq!(again["generated_at"], body["generated_at"]);
+    }
+
+    #[actix_web::test]
+    async fn test_retracting_unknown_response_is_not_found() {
+        let event_bus = EventBus::new().start();
+        let digital_human = DigitalHumanActor::new(
+            "Maya".to_string(),
+            "cheerful".to_string(),
+            event_bus.clone(),
+        )
+        .start();
+        event_bus
+            .send(RegisterDigitalHuman {
+                addr: digital_human.clone(),
+            })
+            .await
+            .unwrap();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(event_bus))
+                .app_data(web::Data::new(digital_human))
+                .app_data(web::Data::new(PreflightStatus::default()))
+                .app_data(web::Data::new(testing::auth()))
+                .configure(configure_routes),
+        )
+        .await;
+        let token = testing::token("ops", true);
+        let session_id = Uuid::new_v4();
+        let response_id = Uuid::new_v4();
+        let import = actix_web::test::TestRequest::post()
+            .uri(&format!(
+                "/api/v1/sessions/{}/import?token={}",
+                session_id, token
+            ))
+            .set_json(serde_json::json!({
+                "user_id": "viewer1",
+                "history": [{
+                    "role": "assistant",
+                    "content": "收到",
+                    "timestamp": chrono::Utc::now(),
+                    "response_id": response_id
+                }]
+            }))
+            .to_request();
+        assert_eq!(
+            actix_web::test::call_service(&app, import).await.status(),
+            200
+        );
+        let retract = |response_id: Uuid| {
+            actix_web::test::TestRequest::post()
+                .uri(&format!(
+                    "/api/v1/responses/{}/retract?token={}",
+                    response_id, token
+                ))
+                .to_request()
+        };
+
+        let response = actix_web::test::call_service(&app, retract(Uuid::new_v4())).await;
+        assert_eq!(response.status(), 404);
+        let response = actix_web::test::call_service(&app, retract(response_id)).await;
+        assert_eq!(response.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_admin_endpoints_refuse_viewer_tokens() {
+        let event_bus = EventBus::new().start();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(event_bus.clone()))
+                .app_data(web::Data::new(testing::auth()))
+                .configure(configure_routes),
+        )
+        .await;
+        let viewer = testing::token("troll", false);
+
+        let requests = [
+            (
+                Method::POST,
+                "/api/v1/responses/00000000-0000-0000-0000-000000000000/retract",
+                serde_json::json!({}),
+            ),
+            (
+                Method::GET,
+                "/api/v1/sessions/00000000-0000-0000-0000-000000000000/summary",
+                serde_json::json!({}),
+            ),
+            (
+                Method::GET,
+                "/api/v1/sessions/00000000-0000-0000-0000-000000000000/export",
+                serde_json::json!({}),
+            ),
+            (
+                Method::POST,
+                "/api/v1/sessions/00000000-0000-0000-0000-000000000000/import",
+                serde_json::json!({"user_id": "victim", "history": []}),
+            ),
+            (
+                Method::PATCH,
+                "/api/v1/validation/rules/blacklist",
+                serde_json::json!({"enabled": false}),
+            ),
+            (
+                Method::POST,
+                "/api/v1/stream/1001/start",
+                serde_json::json!({}),
+            ),
+            (
+                Method::POST,
+                "/api/v1/stream/1001/end",
+                serde_json::json!({}),
+            ),
+            (
+                Method::PUT,
+                "/api/v1/rooms/1001/gate",
+                serde_json::json!({"vip_only": true}),
+            ),
+            (
+                Method::PUT,
+                "/api/v1/digital-human/paused",
+                serde_json::json!({"paused": true}),
+            ),
+            (
+                Method::POST,
+                "/api/v1/users/victim/ban",
+                serde_json::json!({}),
+            ),
+            (
+                Method::DELETE,
+                "/api/v1/users/troll/ban",
+                serde_json::json!({}),
+            ),
+            (
+                Method::PUT,
+                "/api/v1/users/troll/rate-limit-exempt",
+                serde_json::json!({}),
+            ),
+            (
+                Method::DELETE,
+                "/api/v1/users/victim/rate-limit-exempt",
+                serde_json::json!({}),
+            ),
+        ];
+        for (method, path, body) in requests {
+            for uri in [path.to_string(), format!("{}?token={}", path, viewer)] {
+                let request = actix_web::test::TestRequest::default()
+                    .method(method.clone())
+                    .uri(&uri)
+                    .set_json(&body)
+                    .to_request();
+                let response = actix_web::test::call_service(&app, request).await;
+                assert_eq!(response.status(), 401, "{} {}", method, uri);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_out_of_range_durations_are_rejected() {
+        let event_bus = EventBus::new().start();
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(event_bus.clone()))
+                .app_data(web::Data::new(
+                    LiveStreamManager::new(event_bus.clone()).start(),
+                ))
+                .app_data(web::Data::new(testing::auth()))
+                .configure(configure_routes),
+        )
+        .await;
+        let token = testing::token("ops", true);
+
+        for duration in [u64::MAX, i64::MAX as u64] {
+            let req = actix_web::test::TestRequest::put()
+                .uri(&format!("/api/v1/rooms/1001/gate?token={}", token))
+                .set_json(serde_json::json!({"vip_only": true, "duration_seconds": duration}))
+                .to_request();
+            let response = actix_web::test::call_service(&app, req).await;
+            assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        }
+
+        let req = actix_web::test::TestRequest::post()
+            .uri(&format!("/api/v1/users/troll/ban?token={}", token))
+            .set_json(serde_json::json!({"duration_seconds": u64::MAX}))
+            .to_request();
+        let response = actix_web::test::call_service(&app, req).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/v1/platform/config")
+            .set_json(serde_json::json!({
+                "platform": "Douyin",
+                "room_id": "1001",
+                "enabled": false,
+                "max_age_seconds": u64::MAX
+            }))
+            .to_request();
+        let response = actix_web::test::call_service(&app, req).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        // The event bus is still alive and takes a sensible gate
+        let req = actix_web::test::TestRequest::put()
+            .uri(&format!("/api/v1/rooms/1001/gate?token={}", token))
+            .set_json(serde_json::json!({"vip_only": true, "duration_seconds": 600}))
+            .to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert!(body["gate"]["expires_at"].is_string());
+    }
+}
@@ -0,0 +1,118 @@
+const ZH_POSITIVE: &[&str] = &[
+    "哈哈", "好看", "好听", "好玩", "喜欢", "爱", "棒", "厉害", "牛", "666", "开心", "太强", "赞",
+    "漂亮", "可爱", "谢谢", "感谢", "笑死", "精彩",
+];
+const ZH_NEGATIVE: &[&str] = &[
+    "无聊",
+    "垃圾",
+    "难看",
+    "讨厌",
+    "烦",
+    "差",
+    "生气",
+    "恶心",
+    "失望",
+    "退钱",
+    "难过",
+    "没意思",
+    "菜",
+    "难听",
+];
+const ZH_NEGATIONS: &[char] = &['不', '没', '别'];
+const EN_POSITIVE: &[&str] = &[
+    "love",
+    "great",
+    "awesome",
+    "nice",
+    "good",
+    "lol",
+    "haha",
+    "amazing",
+    "cool",
+    "happy",
+    "thanks",
+    "pog",
+    "fun",
+    "beautiful",
+    "wow",
+];
+const EN_NEGATIVE: &[&str] = &[
+    "boring", "bad", "hate", "terrible", "awful", "sad", "angry", "worst", "trash", "lame",
+    "cringe", "annoying",
+];
+const EN_NEGATIONS: &[&str] = &["not", "no", "never", "don't", "isn't", "wasn't", "aren't"];
+const POSITIVE_EMOJI: &[char] = &['😂', '🤣', '😊', '😄', '❤', '👍', '🎉', '🥰'];
+const NEGATIVE_EMOJI: &[char] = &['😡', '😠', '😭', '😢', '👎', '💢', '🙄'];
+
+/// Lexicon-based sentiment of a chat message, from -1.0 (negative) to 1.0
+/// (positive); 0.0 when no sentiment-bearing terms are found.
+pub fn score(text: &str) -> f32 {
+    let mut positive = 0u32;
+    let mut negative = 0u32;
+    let mut tally = |is_positive: bool, negated: bool| {
+        if is_positive != negated {
+            positive += 1;
+        } else {
+            negative += 1;
+        }
+    };
+
+    // 中文：按子串匹配，前一个字是否定词时反转
+    for (terms, is_positive) in [(ZH_POSITIVE, true), (ZH_NEGATIVE, false)] {
+        for term in terms.iter() {
+            for (index, _) in text.match_indices(term) {
+                let negated = text[..index]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| ZH_NEGATIONS.contains(&c));
+                tally(is_positive, negated);
+            }
+        }
+    }
+
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        let is_positive = if EN_POSITIVE.contains(word) {
+            true
+        } else if EN_NEGATIVE.contains(word) {
+            false
+        } else {
+            continue;
+        };
+        let negated = i > 0 && EN_NEGATIONS.contains(&words[i - 1]);
+        tally(is_positive, negated);
+    }
+
+    for c in text.chars() {
+        if POSITIVE_EMOJI.contains(&c) {
+            tally(true, false);
+        } else if NEGATIVE_EMOJI.contains(&c) {
+            tally(false, false);
+        }
+    }
+
+    let total = positive + negative;
+    if total == 0 {
+        return 0.0;
+    }
+    (positive as f32 - negative as f32) / total as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentiment_score() {
+        assert!(score("主播太厉害了哈哈") > 0.5);
+        assert!(score("好无聊，退钱") < -0.5);
+        assert!(score("这首歌不好听") < 0.0);
+        assert!(score("this is awesome 😂") > 0.5);
+        assert!(score("not good, so boring") < -0.5);
+        assert_eq!(score("what game is this"), 0.0);
+    }
+}
@@ -0,0 +1,457 @@
+use crate::actor::DigitalHumanActor;
+use crate::audit::AuditLog;
+use crate::ban::BanList;
+use crate::channels::GlobalChannels;
+use crate::cluster::{EventTransport, RedisTransport};
+use crate::config::AppConfig;
+use crate::escalation::EscalationWebhook;
+use crate::event_bus::{
+    EventBus, RegisterDigitalHuman, RegisterWebSocketManager, SubscribeResponses,
+};
+use crate::events::{ResponseBundle, TextInputEvent};
+use crate::input_queue::{
+    FileInputQueue, InMemoryInputQueue, InputQueue, InputQueueStore, QueueBackend, RedisInputQueue,
+};
+use crate::llm::{EchoProvider, FallbackProvider, HiddenReasoning, LlmLimiter, LlmProvider};
+use crate::mask::ResponseMask;
+use crate::platform::{DanmakuStore, LiveStreamManager};
+use crate::preflight::{self, PreflightError, PreflightStatus};
+use crate::rate_limit::{RateLimitStore, RedisRateLimitStore};
+use crate::stt::SpeechToText;
+use crate::translate::{LlmTranslator, Translator};
+use crate::tts::{SilenceTts, TextToSpeech};
+use crate::websocket::WebSocketManager;
+use crate::worker::{self, WorkerPool};
+use actix::prelude::*;
+use log::{info, warn};
+use std::sync::Arc;
+
+/// Builds and starts the digital human engine: the event bus with its
+/// validator, the digital human, and the WebSocket and live stream managers.
+/// The HTTP server is optional; embedders can feed input and subscribe to
+/// responses through the returned handles instead.
+///
+/// Must be started from within a running actix system.
+pub struct DigitalHumanService {
+    config: AppConfig,
+    rate_limit_store: Option<Box<dyn RateLimitStore>>,
+    llm_provider: Option<Arc<dyn LlmProvider>>,
+    cheap_llm_provider: Option<Arc<dyn LlmProvider>>,
+    tts: Option<Arc<dyn TextToSpeech>>,
+    /// Voices for language-tagged spans, by language.
+    language_voices: Vec<(String, Arc<dyn TextToSpeech>)>,
+    stt: Option<Arc<dyn SpeechToText>>,
+    translator: Option<Arc<dyn Translator>>,
+    event_transport: Option<Box<dyn EventTransport>>,
+    preflight: PreflightStatus,
+}
+
+impl DigitalHumanService {
+    pub fn new(config: AppConfig) -> Self {
+        Self {
+            config,
+            rate_limit_store: None,
+            llm_provider: None,
+            cheap_llm_provider: None,
+            tts: None,
+            language_voices: Vec::new(),
+            stt: None,
+            translator: None,
+            event_transport: None,
+            preflight: PreflightStatus::default(),
+        }
+    }
+
+    /// Overrides the rate-limit store; otherwise Redis is used when
+    /// `redis_url` is configured, and memory when it is not.
+    pub fn with_rate_limit_store(mut self, store: Box<dyn RateLimitStore>) -> Self {
+        self.rate_limit_store = Some(store);
+        self
+    }
+
+    /// Answers with `provider`, stripping the reasoning blocks configured for
+    /// its model in `llm.reasoning_delimiters`.
+    pub fn with_llm_provider(mut self, provider: Arc<dyn LlmProvider>) -> Self {
+        self.llm_provider = Some(self.hide_reasoning(provider));
+        self
+    }
+
+    /// Answers rooms over their daily budget when `budget.action` is
+    /// `cheap_model`.
+    pub fn with_cheap_llm_provider(mut self, provider: Arc<dyn LlmProvider>) -> Self {
+        self.cheap_llm_provider = Some(self.hide_reasoning(provider));
+        self
+    }
+
+    /// Uses `providers` in order, falling back to the next when one fails or
+    /// exceeds its `llm.provider_timeout_seconds`. Each keeps the reasoning
+    /// delimiters of its own model.
+    pub fn with_llm_providers(mut self, providers: Vec<Box<dyn LlmProvider>>) -> Self {
+        let timeouts = self.config.llm.provider_timeouts();
+        let providers = providers
+            .into_iter()
+            .map(
+                |provider| match self.config.llm.reasoning_delimiters_for(provider.model()) {
+                    Some(delimiters) => Box::new(HiddenReasoning::new(
+                        Arc::from(provider),
+                        delimiters.clone(),
+                    )) as Box<dyn LlmProvider>,
+                    None => provider,
+                },
+            )
+            .collect();
+        self.llm_provider = Some(Arc::new(FallbackProvider::new(providers, &timeouts)));
+        self
+    }
+
+    fn hide_reasoning(&self, provider: Arc<dyn LlmProvider>) -> Arc<dyn LlmProvider> {
+        match self.config.llm.reasoning_delimiters_for(provider.model()) {
+            Some(delimiters) => Arc::new(HiddenReasoning::new(provider, delimiters.clone())),
+            None => provider,
+        }
+    }
+
+    /// Speaks responses with `tts`; otherwise the offline silent voice is
+    /// used when `tts.enabled` is set.
+    pub fn with_tts(mut self, tts: Arc<dyn TextToSpeech>) -> Self {
+        self.tts = Some(tts);
+        self
+    }
+
+    /// Speaks responses in `language` with `tts`, or only such spans when
+    /// `language_segments` is set; other languages fall back through
+    /// `tts.families` to the main voice.
+    pub fn with_language_voice(mut self, language: &str, tts: Arc<dyn TextToSpeech>) -> Self {
+        self.language_voices.push((language.to_string(), tts));
+        self
+    }
+
+    /// Transcribes audio input with `stt`, gated by voice activity
+    /// detection; audio input is ignored without one.
+    pub fn with_stt(mut self, stt: Arc<dyn SpeechToText>) -> Self {
+        self.stt = Some(stt);
+        self
+    }
+
+    /// Translates responses with `translator`; otherwise the LLM provider
+    /// translates when `translation` has target languages.
+    pub fn with_translator(mut self, translator: Arc<dyn Translator>) -> Self {
+        self.translator = Some(translator);
+        self
+    }
+
+    /// Shares events with other instances through `transport`; otherwise
+    /// Redis pub/sub is used when `cluster.redis_url` is configured.
+    pub fn with_event_transport(mut self, transport: Box<dyn EventTransport>) -> Self {
+        self.event_transport = Some(transport);
+        self
+    }
+
+    /// The voice responses are spoken with, if any.
+    fn tts(&self) -> Option<Arc<dyn TextToSpeech>> {
+        self.tts.clone().or_else(|| {
+            self.config
+                .tts
+                .enabled
+                .then(|| Arc::new(SilenceTts) as Arc<dyn TextToSpeech>)
+        })
+    }
+
+    /// Warms up the LLM and TTS providers when `preflight` is set, failing
+    /// when either rejects its credentials. Call before `start`; the service
+    /// is not ready until every provider has answered, which is retried in
+    /// the background when one does not at first.
+    pub async fn preflight(&self) -> Result<(), PreflightError> {
+        if !self.config.preflight {
+            return Ok(());
+        }
+        let tts = self.tts();
+        let answered = preflight::run(
+            self.llm_provider.as_deref(),
+            tts.as_deref(),
+            preflight::PREFLIGHT_TIMEOUT,
+        )
+        .await?;
+        if answered {
+            self.preflight.mark_passed();
+        } else {
+            warn!(
+                "Preflight incomplete, not ready until it passes; retrying every {:?}",
+                preflight::PREFLIGHT_RETRY
+            );
+            actix::spawn(preflight::retry(
+                self.llm_provider.clone(),
+                tts,
+                self.preflight.clone(),
+                preflight::PREFLIGHT_RETRY,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn start(self) -> ServiceHandles {
+        let tts = self.tts();
+        let config = self.config;
+        if !config.preflight {
+            self.preflight.mark_passed();
+        }
+
+        let mut event_bus = EventBus::new().with_injection_guard(&config.injection);
+        let profanity_mask = config.profanity_mask.clone().map(ResponseMask::new);
+        if let Some(mask) = profanity_mask.clone() {
+            event_bus = event_bus.with_profanity_mask(mask);
+        }
+        let store = self.rate_limit_store.or_else(|| {
+            let redis_url = config.redis_url.as_ref()?;
+            match RedisRateLimitStore::connect(redis_url) {
+                Ok(store) => {
+                    info!("Using Redis rate limit store");
+                    Some(Box::new(store) as Box<dyn RateLimitStore>)
+                }
+                Err(e) => {
+                    warn!("Invalid REDIS_URL, rate limits stay in memory: {}", e);
+                    None
+                }
+            }
+        });
+        if let Some(store) = store {
+            event_bus = event_bus.with_rate_limit_store(store);
+        }
+        if let Some(path) = &config.ban_list_path {
+            match BanList::open(path) {
+                Ok(bans) => event_bus = event_bus.with_ban_list(bans),
+                Err(e) => warn!(
+                    "Failed to open ban list {}, bans stay in memory: {}",
+                    path, e
+                ),
+            }
+        }
+        if !config.rate_limit_exempt.is_empty() {
+            event_bus = event_bus.with_rate_limit_exemptions(config.rate_limit_exempt.clone());
+        }
+        let mut moderation = config.moderation.clone();
+        moderation.sessions_authenticated = config.auth.enabled();
+        if !moderation.moderators.is_empty()
+            && !moderation.sessions_authenticated
+            && !moderation.allow_unauthenticated
+        {
+            warn!("Moderator commands are refused: WS_JWT_SECRET is not set");
+        }
+        event_bus = event_bus.with_moderation(moderation);
+        if let Some(sink) = &config.audit_sink {
+            match AuditLog::open(sink) {
+                Ok(audit) => event_bus = event_bus.with_audit_log(audit),
+                Err(e) => warn!("Failed to open audit log {:?}: {}", sink, e),
+            }
+        }
+        if let Some(escalation) = EscalationWebhook::new(config.escalation.clone()) {
+            event_bus = event_bus.with_escalation_webhook(escalation);
+        }
+        let queue_store = open_input_queue(&config);
+        if let Some(store) = queue_store {
+            info!("Queuing input while the digital human is unavailable");
+            event_bus = event_bus.with_input_queue(InputQueue::new(store, &config.input_queue));
+        }
+        let transport = self.event_transport.or_else(|| {
+            let redis_url = config.cluster.redis_url.as_ref()?;
+            match RedisTransport::connect(redis_url) {
+                Ok(transport) => Some(Box::new(transport) as Box<dyn EventTransport>),
+                Err(e) => {
+                    warn!("Invalid CLUSTER_REDIS_URL, running standalone: {}", e);
+                    None
+                }
+            }
+        });
+        if let Some(transport) = transport {
+            event_bus = event_bus.with_cluster(transport, config.cluster.topics.clone());
+        }
+        let event_bus = event_bus.start();
+
+        // Shared by the digital human, the danmaku throttle feedback and the
+        // WebSocket load signal
+        let llm_limiter = LlmLimiter::new(&config.llm);
+
+        // Switched at runtime by operators, respected by delivery and synthesis
+        let channels = GlobalChannels::new(config.output_channels);
+
+        let ws_manager = WebSocketManager::new(event_bus.clone())
+            .with_session_limit(config.session_limit.clone())
+            .with_client_stats(config.client_stats)
+            .with_strict_json(!config.lenient_json)
+            .with_default_channels(config.default_channels)
+            .with_global_channels(channels.clone())
+            .with_audio_codecs(config.audio_codecs.clone())
+            .with_danmaku_delivery(config.danmaku_delivery)
+            .with_resume(config.resume.clone())
+            .with_auth(config.auth.clone())
+            .with_load_signal(config.load.clone(), llm_limiter.clone())
+            .start();
+
+        // Dedicated pool for CPU-bound work such as sentiment analysis
+        let workers = WorkerPool::new(
+            config
+                .worker_pool_size
+                .unwrap_or_else(worker::default_pool_size),
+        );
+        info!("Worker pool started with {} threads", workers.size());
+
+        let mut live_manager = LiveStreamManager::new(event_bus.clone())
+            .with_throttle_feedback(config.throttle.clone(), llm_limiter.clone())
+            .with_worker_pool(workers)
+            .with_sampling(config.sampling.clone())
+            .with_merging(config.merge.clone())
+            .with_dedup(config.dedup.clone())
+            .with_douyin(config.douyin.clone())
+            .with_webhook_idempotency(config.webhook_idempotency.clone())
+            .with_faq(config.faq.clone())
+            .with_engagement(config.engagement.clone())
+            .with_stream_lifecycle(&config.lifecycle)
+            .with_viewer_sessions(config.viewer_context.enabled);
+        if let Some(quota) = config.room_quota.clone() {
+            live_manager = live_manager.with_room_quota(quota);
+        }
+        if let Some(store_config) = config.danmaku_store.clone() {
+            let path = store_config.path.clone();
+            match DanmakuStore::open(store_config) {
+                Ok(store) => live_manager = live_manager.with_danmaku_store(store),
+                Err(e) => warn!("Failed to open danmaku store {}: {}", path, e),
+            }
+        }
+        if let Some(max_age) = config.max_danmaku_age_seconds {
+            live_manager = live_manager.with_max_danmaku_age(max_age);
+        }
+        if let Some(half_life) = config.mood_half_life_seconds {
+            live_manager = live_manager.with_mood_half_life(half_life);
+        }
+        let live_manager = live_manager.start();
+
+        let persona = &config.digital_human;
+        let mut digital_human = DigitalHumanActor::new(
+            persona.name.clone(),
+            persona.personality.clone(),
+            event_bus.clone(),
+        )
+        .with_system_prompt(persona.system_prompt.clone())
+        .with_llm_limiter(llm_limiter.clone())
+        .with_token_streaming(config.llm.stream_tokens)
+        .with_stream_recovery(config.llm.stream_recovery.clone())
+        .with_outage(config.outage.clone())
+        .with_prompt_debugging(config.llm.debug_prompts)
+        .with_intent_policy(config.intent_policy.clone())
+        .with_length_policy(config.length_policy.clone())
+        .with_intent_sampling(config.sampling_by_intent.clone())
+        .with_templates(config.templates.clone())
+        .with_wake_words(persona.wake_words.clone())
+        .with_username_display(config.username_display.clone())
+        .with_animation_scaling(config.animation_scaling.clone())
+        .with_emotion_transitions(config.emotion_transitions.clone())
+        .with_danmaku_delivery(config.danmaku_delivery)
+        .with_global_channels(channels.clone())
+        .with_repeat_policy(config.repeat_policy.clone())
+        .with_response_attribution(config.response_attribution)
+        .with_language_segments(config.language_segments)
+        .with_summary(config.summary.clone())
+        .with_refusals(config.refusals.clone())
+        .with_budget(config.budget.clone())
+        .with_stream_lifecycle(config.lifecycle.clone())
+        .with_viewer_context(config.viewer_context.clone())
+        .with_idle(config.idle.clone());
+        if let Some(mask) = profanity_mask {
+            digital_human = digital_human.with_profanity_mask(mask);
+        }
+        if let Some(provider) = self.llm_provider.clone() {
+            if let Some(tokens) = config.llm.context_limit(provider.model()) {
+                digital_human = digital_human.with_context_limit(tokens);
+            }
+            digital_human = digital_human.with_llm_provider(provider);
+        }
+        if let Some(provider) = self.cheap_llm_provider {
+            digital_human = digital_human.with_cheap_llm_provider(provider);
+        }
+        if config.translation.is_enabled() {
+            let translator = self.translator.unwrap_or_else(|| {
+                let provider = self
+                    .llm_provider
+                    .unwrap_or_else(|| Arc::new(EchoProvider::new(persona.name.clone())));
+                Arc::new(LlmTranslator::new(provider, llm_limiter.clone()))
+            });
+            info!("Translating responses with '{}'", translator.name());
+            digital_human = digital_human.with_translator(translator, config.translation.clone());
+        }
+        if let Some(tts) = tts {
+            info!("Speaking responses with voice '{}'", tts.voice());
+            digital_human = digital_human.with_tts(tts, &config.tts);
+        }
+        for (language, tts) in self.language_voices {
+            info!("Speaking {} spans with voice '{}'", language, tts.voice());
+            digital_human = digital_human.with_language_voice(&language, tts);
+        }
+        if let Some(stt) = self.stt {
+            digital_human = digital_human.with_stt(stt, config.vad.clone());
+        }
+        let digital_human = digital_human.start();
+        info!("DigitalHumanActor '{}' started", persona.name);
+
+        event_bus.do_send(RegisterDigitalHuman {
+            addr: digital_human.clone(),
+        });
+        event_bus.do_send(RegisterWebSocketManager {
+            addr: ws_manager.clone(),
+        });
+
+        ServiceHandles {
+            event_bus,
+            digital_human,
+            ws_manager,
+            live_manager,
+            preflight: self.preflight,
+            channels,
+        }
+    }
+}
+
+/// Opens the configured input queue backend; input is dropped while the
+/// digital human is unavailable if it cannot be opened.
+fn open_input_queue(config: &AppConfig) -> Option<Box<dyn InputQueueStore>> {
+    let opened: Result<Box<dyn InputQueueStore>, String> =
+        match config.input_queue.backend.as_ref()? {
+            QueueBackend::Memory => Ok(Box::new(InMemoryInputQueue::new())),
+            QueueBackend::File(path) => FileInputQueue::open(path).map(|q| Box::new(q) as _),
+            QueueBackend::Redis => match config.redis_url.as_deref() {
+                Some(url) => RedisInputQueue::connect(url)
+                    .map(|q| Box::new(q) as _)
+                    .map_err(|e| e.to_string()),
+                None => Err("REDIS_URL is not set".to_string()),
+            },
+        };
+    opened
+        .inspect_err(|e| warn!("Failed to open input queue, running without one: {}", e))
+        .ok()
+}
+
+/// Addresses of a running service's actors.
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct ServiceHandles {
+    pub event_bus: Addr<EventBus>,
+    pub digital_human: Addr<DigitalHumanActor>,
+    pub ws_manager: Addr<WebSocketManager>,
+    pub live_manager: Addr<LiveStreamManager>,
+    /// Whether the startup preflight passed, for the readiness probe.
+    pub preflight: PreflightStatus,
+    /// Output channels on for every session.
+    pub channels: GlobalChannels,
+}
+
+impl ServiceHandles {
+    /// Feeds user input through validation to the digital human.
+    pub fn send_text(&self, event: TextInputEvent) {
+        self.event_bus.do_send(event);
+    }
+
+    /// Delivers every response bundle to `recipient`, in addition to
+    /// connected WebSocket clients.
+    pub fn subscribe(&self, recipient: Recipient<ResponseBundle>) {
+        self.event_bus.do_send(SubscribeResponses { recipient });
+    }
+}
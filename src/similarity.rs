@@ -0,0 +1,47 @@
+//! Character-bigram similarity, which works for unspaced Chinese as well as
+//! spaced text.
+
+use std::collections::HashSet;
+
+/// The adjacent character pairs of a text.
+pub type Bigrams = HashSet<(char, char)>;
+
+/// Lowercases `text` and keeps only letters and digits, so punctuation and
+/// spacing do not affect similarity.
+pub fn fold(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The bigrams of `text`; a single character pairs with itself.
+pub fn bigrams(text: &str) -> Bigrams {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() == 1 {
+        return HashSet::from([(chars[0], chars[0])]);
+    }
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+/// Dice coefficient of two bigram sets, from 0.0 (disjoint) to 1.0.
+pub fn dice(a: &Bigrams, b: &Bigrams) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dice_of_folded_text() {
+        let a = bigrams(&fold("今天 玩什么？"));
+        assert_eq!(dice(&a, &bigrams(&fold("今天玩什么"))), 1.0);
+        assert_eq!(dice(&a, &bigrams("")), 0.0);
+        assert_eq!(bigrams("好"), HashSet::from([('好', '好')]));
+        assert!(dice(&a, &bigrams("今天吃什么")) > 0.0);
+    }
+}
@@ -0,0 +1,27 @@
+use futures_util::future::BoxFuture;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum SttError {
+    Transcription(String),
+}
+
+impl fmt::Display for SttError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SttError::Transcription(msg) => write!(f, "transcription failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SttError {}
+
+/// Turns an utterance of 16-bit little-endian mono PCM into text. No
+/// transcriber is built in; embedders supply one to accept audio input.
+pub trait SpeechToText: Send + Sync {
+    fn transcribe(
+        &self,
+        pcm: Vec<u8>,
+        sample_rate: u32,
+    ) -> BoxFuture<'static, Result<String, SttError>>;
+}
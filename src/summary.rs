@@ -0,0 +1,131 @@
+use crate::actor::ConversationMessage;
+use crate::llm::{ChatMessage, LlmRequest};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Asks the LLM to condense the older part of a conversation.
+pub const SUMMARY_PROMPT: &str = "Summarize this conversation between a live stream host and a viewer in a few sentences, keeping names, facts the viewer shared and open questions. Reply with the summary only.";
+
+/// When a session's older turns are replaced by a summary. Off unless
+/// `max_turns` is set.
+#[derive(Debug, Clone)]
+pub struct SummaryConfig {
+    /// Viewer messages a session may hold before it is summarized.
+    pub max_turns: Option<usize>,
+    /// Latest viewer messages, with their replies, kept word for word.
+    pub keep_turns: usize,
+    /// Most key points a handoff summary lists.
+    pub handoff_key_points: usize,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            max_turns: None,
+            keep_turns: 4,
+            handoff_key_points: 5,
+        }
+    }
+}
+
+impl SummaryConfig {
+    /// How many of the oldest messages to summarize, once `history` holds
+    /// `max_turns` viewer messages; the rest, from the `keep_turns`-th latest
+    /// viewer message on, stay as they are.
+    pub fn split_point(&self, history: &[ConversationMessage]) -> Option<usize> {
+        let max_turns = self.max_turns?;
+        let turns: Vec<usize> = history
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.role == "user")
+            .map(|(i, _)| i)
+            .collect();
+        if turns.len() < max_turns.max(1) {
+            return None;
+        }
+        let split = match turns.len().checked_sub(self.keep_turns) {
+            Some(0) | None => return None,
+            Some(first_kept) => turns.get(first_kept).copied().unwrap_or(history.len()),
+        };
+        (split > 0).then_some(split)
+    }
+}
+
+/// The request condensing `messages`, folding in the summary of what came
+/// before them.
+pub fn request(previous: Option<&str>, messages: &[ConversationMessage]) -> LlmRequest {
+    LlmRequest {
+        messages: vec![
+            ChatMessage::new("system", SUMMARY_PROMPT),
+            ChatMessage::new("user", transcript(previous, messages)),
+        ],
+        max_tokens: Some(200),
+        ..Default::default()
+    }
+}
+
+/// A session summarized for the moderator taking it over.
+#[derive(Debug, Clone, Serialize)]
+pub struct Handoff {
+    pub session_id: Uuid,
+    pub user_id: String,
+    pub summary: String,
+    pub key_points: Vec<String>,
+    /// Messages in the history it covers, besides any earlier summary.
+    pub messages: usize,
+    #[serde(serialize_with = "crate::timezone::serialize")]
+    pub generated_at: DateTime<Utc>,
+}
+
+/// The request for a handoff summary of the whole conversation, with up to
+/// `key_points` points listed after it.
+pub fn handoff_request(
+    previous: Option<&str>,
+    messages: &[ConversationMessage],
+    key_points: usize,
+) -> LlmRequest {
+    let prompt = format!(
+        "Summarize this conversation between a live stream host and a viewer for the moderator taking it over. Write the summary in one or two sentences, then list up to {} key points (who the viewer is, what they want, anything unresolved), one per line starting with \"- \".",
+        key_points
+    );
+    LlmRequest {
+        messages: vec![
+            ChatMessage::new("system", prompt),
+            ChatMessage::new("user", transcript(previous, messages)),
+        ],
+        max_tokens: Some(300),
+        ..Default::default()
+    }
+}
+
+/// Splits a handoff reply into its summary and at most `key_points`
+/// bulleted points.
+pub fn parse_handoff(reply: &str, key_points: usize) -> (String, Vec<String>) {
+    let mut summary = Vec::new();
+    let mut points = Vec::new();
+    for line in reply.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+            Some(point) => points.push(point.trim().to_string()),
+            None => summary.push(line),
+        }
+    }
+    points.truncate(key_points);
+    (summary.join(" "), points)
+}
+
+fn transcript(previous: Option<&str>, messages: &[ConversationMessage]) -> String {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str(&format!("Earlier: {}\n", previous));
+    }
+    for message in messages.iter().filter(|m| !m.retracted) {
+        let speaker = if message.role == "user" {
+            "Viewer"
+        } else {
+            "Host"
+        };
+        transcript.push_str(&format!("{}: {}\n", speaker, message.content));
+    }
+    transcript
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerMatch {
+    /// The whole message equals the trigger (case-insensitive).
+    Exact,
+    /// The message starts with the trigger, e.g. `!discord`.
+    Prefix,
+    /// The trigger appears anywhere in the message.
+    #[default]
+    Contains,
+}
+
+/// A canned response sent instead of calling the LLM.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseTemplate {
+    pub trigger: String,
+    #[serde(default, rename = "match")]
+    pub match_type: TriggerMatch,
+    /// Response text; `{name}` placeholders are filled from the input context.
+    pub response: String,
+}
+
+impl ResponseTemplate {
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.trim().to_lowercase();
+        let trigger = self.trigger.to_lowercase();
+        match self.match_type {
+            TriggerMatch::Exact => text == trigger,
+            TriggerMatch::Prefix => text.starts_with(&trigger),
+            TriggerMatch::Contains => text.contains(&trigger),
+        }
+    }
+
+    /// Substitutes `{key}` placeholders; unknown placeholders are left untouched.
+    pub fn render(&self, vars: &HashMap<&str, String>) -> String {
+        substitute(&self.response, |key| {
+            vars.get(key)
+                .cloned()
+                .or_else(|| Some(format!("{{{}}}", key)))
+        })
+    }
+}
+
+/// Replaces each `{key}` with `lookup(key)`, or nothing when it returns None.
+fn substitute(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => {
+                if let Some(value) = lookup(&after[..end]) {
+                    output.push_str(&value);
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Keys of all `{key}` placeholders in a template, in order of appearance.
+fn placeholders(template: &str) -> Vec<&str> {
+    let mut keys = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        keys.push(&after[..end]);
+        rest = &after[end + 1..];
+    }
+    keys
+}
+
+/// Variables available to the system prompt template.
+pub const SYSTEM_PROMPT_VARIABLES: &[&str] = &[
+    "name",
+    "personality",
+    "language",
+    "current_time",
+    "room_mood",
+];
+
+/// LLM system prompt rendered per request. Unknown or unavailable
+/// variables render as empty strings.
+#[derive(Debug, Clone)]
+pub struct SystemPromptTemplate {
+    template: String,
+}
+
+impl SystemPromptTemplate {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+        }
+    }
+
+    /// Loads the template from a text file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map(Self::new)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Referenced variables that are not in `SYSTEM_PROMPT_VARIABLES`.
+    pub fn unknown_variables(&self) -> Vec<&str> {
+        placeholders(&self.template)
+            .into_iter()
+            .filter(|key| !SYSTEM_PROMPT_VARIABLES.contains(key))
+            .collect()
+    }
+
+    pub fn render(&self, vars: &HashMap<&str, String>) -> String {
+        substitute(&self.template, |key| vars.get(key).cloned())
+    }
+}
+
+impl Default for SystemPromptTemplate {
+    /// The bare personality, matching the original behaviour.
+    fn default() -> Self {
+        Self::new("{personality}")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResponseTemplates {
+    templates: Vec<ResponseTemplate>,
+}
+
+impl ResponseTemplates {
+    pub fn new(templates: Vec<ResponseTemplate>) -> Self {
+        Self { templates }
+    }
+
+    /// Loads templates from a JSON array file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let templates = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+        Ok(Self::new(templates))
+    }
+
+    /// First template whose trigger matches, in configuration order.
+    pub fn find(&self, text: &str) -> Option<&ResponseTemplate> {
+        self.templates.iter().find(|t| t.matches(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates() -> ResponseTemplates {
+        serde_json::from_value::<Vec<ResponseTemplate>>(serde_json::json!([
+            {"trigger": "!discord", "match": "prefix", "response": "Join us at discord.gg/example, {username}!"},
+            {"trigger": "关注", "response": "感谢{username}的关注！"},
+            {"trigger": "gg", "match": "exact", "response": "GG {username}"}
+        ]))
+        .map(ResponseTemplates::new)
+        .unwrap()
+    }
+
+    #[test]
+    fn test_trigger_matching() {
+        let templates = templates();
+
+        assert_eq!(
+            templates.find("!Discord please").unwrap().trigger,
+            "!discord"
+        );
+        assert_eq!(templates.find("刚刚关注了主播").unwrap().trigger, "关注");
+        assert_eq!(templates.find("GG").unwrap().trigger, "gg");
+        assert!(templates.find("eggs").is_none());
+        assert!(templates.find("where is the discord").is_none());
+    }
+
+    #[test]
+    fn test_placeholder_substitution() {
+        let template = templates().find("关注").unwrap().clone();
+        let vars = HashMap::from([("username", "小明".to_string())]);
+
+        assert_eq!(template.render(&vars), "感谢小明的关注！");
+
+        let template = ResponseTemplate {
+            trigger: "gift".to_string(),
+            match_type: TriggerMatch::Contains,
+            response: "Thanks {username} for the {gift_name}! {".to_string(),
+        };
+        assert_eq!(template.render(&vars), "Thanks 小明 for the {gift_name}! {");
+    }
+
+    #[test]
+    fn test_system_prompt_unknown_variables() {
+        let prompt = SystemPromptTemplate::new("You are {name}. Mood: {room_mood}. {secret}");
+        assert_eq!(prompt.unknown_variables(), ["secret"]);
+
+        let vars = HashMap::from([("name", "Maya".to_string())]);
+        assert_eq!(prompt.render(&vars), "You are Maya. Mood: . ");
+    }
+}
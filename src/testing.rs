@@ -0,0 +1,86 @@
+//! Helpers shared by the unit tests.
+
+use crate::auth::{AuthConfig, Claims};
+use crate::websocket::SendMessage;
+use actix::prelude::*;
+use jsonwebtoken::{EncodingKey, Header};
+use std::marker::PhantomData;
+
+/// Keeps every `M` it is sent, so a test can subscribe it in place of a real
+/// recipient and check what arrived with [`Collect::received`].
+pub struct Collect<M>(Vec<M>);
+
+impl<M> Default for Collect<M> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<M: Unpin + 'static> Actor for Collect<M> {
+    type Context = Context<Self>;
+}
+
+impl<M> Handler<M> for Collect<M>
+where
+    M: Message<Result = ()> + Unpin + 'static,
+{
+    type Result = ();
+
+    fn handle(&mut self, msg: M, _ctx: &mut Context<Self>) {
+        self.0.push(msg);
+    }
+}
+
+struct Received<M>(PhantomData<M>);
+
+impl<M: 'static> Message for Received<M> {
+    type Result = Vec<M>;
+}
+
+impl<M: Clone + Unpin + 'static> Handler<Received<M>> for Collect<M> {
+    type Result = MessageResult<Received<M>>;
+
+    fn handle(&mut self, _msg: Received<M>, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.0.clone())
+    }
+}
+
+impl<M: Clone + Unpin + Send + 'static> Collect<M> {
+    /// Everything `collector` has been sent so far, oldest first.
+    pub async fn received(collector: &Addr<Self>) -> Vec<M> {
+        collector.send(Received(PhantomData)).await.unwrap()
+    }
+}
+
+/// The JSON frames a [`Collect`] standing in for a WebSocket session has been
+/// sent, such as a monitor's.
+pub async fn received_frames(collector: &Addr<Collect<SendMessage>>) -> Vec<serde_json::Value> {
+    Collect::received(collector)
+        .await
+        .iter()
+        .map(|msg| serde_json::from_str(&msg.message).unwrap())
+        .collect()
+}
+
+/// Auth with a fixed secret, for tests that sign tokens with [`token`].
+pub fn auth() -> AuthConfig {
+    AuthConfig {
+        jwt_secret: Some("secret".to_string()),
+        ..Default::default()
+    }
+}
+
+/// A token for `sub` signed for [`auth`], valid for an hour.
+pub fn token(sub: &str, admin: bool) -> String {
+    let claims = Claims {
+        sub: sub.to_string(),
+        exp: (chrono::Utc::now() + chrono::Duration::hours(1)).timestamp(),
+        admin,
+    };
+    jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(b"secret"),
+    )
+    .unwrap()
+}
@@ -0,0 +1,86 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Tz;
+use serde::{Serialize, Serializer};
+use std::sync::OnceLock;
+
+static DISPLAY: OnceLock<Tz> = OnceLock::new();
+
+/// Installs the process-wide display timezone. Only the first call takes effect.
+pub fn init(tz: Tz) {
+    let _ = DISPLAY.set(tz);
+}
+
+/// The timezone timestamps are shown in; UTC until `init` is called.
+pub fn display() -> Tz {
+    DISPLAY.get().copied().unwrap_or(Tz::UTC)
+}
+
+/// `dt` in the display timezone, with a numeric offset such as `+08:00`.
+/// Timestamps are stored in UTC and only converted for output.
+pub fn local(dt: DateTime<Utc>) -> DateTime<FixedOffset> {
+    in_timezone(dt, display())
+}
+
+/// `dt` in `tz`, with a numeric offset.
+pub fn in_timezone(dt: DateTime<Utc>, tz: Tz) -> DateTime<FixedOffset> {
+    dt.with_timezone(&tz).fixed_offset()
+}
+
+pub fn now() -> DateTime<FixedOffset> {
+    local(Utc::now())
+}
+
+/// For `#[serde(serialize_with = "crate::timezone::serialize")]` on output
+/// fields; the value still deserializes into a UTC timestamp.
+pub fn serialize<S: Serializer>(dt: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    local(*dt).serialize(serializer)
+}
+
+/// `serialize` for optional timestamps.
+pub fn serialize_opt<S: Serializer>(
+    dt: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    dt.map(local).serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::{ConversationMessage, SessionHistory};
+
+    #[test]
+    fn test_export_round_trips_through_display_timezone() {
+        // `init` is process-wide, so this test only relies on the UTC default
+        // and checks the conversion itself through `in_timezone`.
+        let timestamp = DateTime::parse_from_rfc3339("2024-05-01T12:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            in_timezone(timestamp, chrono_tz::Asia::Shanghai).to_rfc3339(),
+            "2024-05-01T20:30:00+08:00"
+        );
+
+        let history = SessionHistory {
+            user_id: "alice".to_string(),
+            history: vec![ConversationMessage {
+                role: "user".to_string(),
+                content: "你好".to_string(),
+                timestamp,
+                response_id: None,
+                retracted: false,
+            }],
+            summary: None,
+        };
+
+        let exported = serde_json::to_value(&history).unwrap();
+        assert_eq!(
+            exported["history"][0]["timestamp"],
+            serde_json::to_value(local(timestamp)).unwrap()
+        );
+
+        // Importing it back yields the same instant in UTC
+        let imported: SessionHistory = serde_json::from_value(exported).unwrap();
+        assert_eq!(imported.history[0].timestamp, timestamp);
+    }
+}
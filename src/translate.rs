@@ -0,0 +1,245 @@
+use crate::events::{EventMetadata, LLMResponseEvent, MessagePriority};
+use crate::llm::{ChatMessage, LlmLimiter, LlmProvider, LlmRequest};
+use futures_util::future::BoxFuture;
+use log::warn;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Languages responses are also sent in, besides the one they were written in.
+#[derive(Debug, Clone, Default)]
+pub struct TranslationConfig {
+    /// Targets for every room, e.g. `en`.
+    pub languages: Vec<String>,
+    /// Per-room targets replacing `languages`; an empty list turns translation off.
+    pub by_room: HashMap<String, Vec<String>>,
+}
+
+impl TranslationConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.languages.is_empty() || self.by_room.values().any(|l| !l.is_empty())
+    }
+
+    /// Languages to translate a response into, skipping the one the input
+    /// was written in.
+    pub fn targets(&self, room_id: Option<&str>, source_language: Option<&str>) -> Vec<String> {
+        let languages = room_id
+            .and_then(|room| self.by_room.get(room))
+            .unwrap_or(&self.languages);
+        languages
+            .iter()
+            .filter(|target| !source_language.is_some_and(|source| same_language(source, target)))
+            .cloned()
+            .collect()
+    }
+
+    /// Applies per-room targets such as `12345=en|ja,67890=`.
+    pub fn apply_room_overrides(&mut self, spec: &str) -> Result<(), String> {
+        for pair in spec.split(',').filter(|p| !p.trim().is_empty()) {
+            let (room, languages) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected room=languages, got: {}", pair))?;
+            self.by_room
+                .insert(room.trim().to_string(), parse_languages(languages, '|'));
+        }
+        Ok(())
+    }
+}
+
+/// Splits a list such as `en,ja` into language tags.
+pub fn parse_languages(spec: &str, separator: char) -> Vec<String> {
+    spec.split(separator)
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Compares primary subtags, so `zh-CN` and `zh` match.
+fn same_language(a: &str, b: &str) -> bool {
+    let primary = |tag: &str| tag.split(['-', '_']).next().unwrap_or("").to_lowercase();
+    primary(a) == primary(b)
+}
+
+#[derive(Debug, Clone)]
+pub enum TranslateError {
+    Provider(String),
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranslateError::Provider(msg) => write!(f, "translation failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for TranslateError {}
+
+pub trait Translator: Send + Sync {
+    /// Name reported as the model of translated responses.
+    fn name(&self) -> &str;
+
+    fn translate(
+        &self,
+        text: &str,
+        target_language: &str,
+    ) -> BoxFuture<'static, Result<String, TranslateError>>;
+}
+
+/// Translates with the configured LLM, sharing its concurrency limit.
+pub struct LlmTranslator {
+    provider: Arc<dyn LlmProvider>,
+    limiter: Arc<LlmLimiter>,
+}
+
+impl LlmTranslator {
+    pub fn new(provider: Arc<dyn LlmProvider>, limiter: Arc<LlmLimiter>) -> Self {
+        Self { provider, limiter }
+    }
+}
+
+impl Translator for LlmTranslator {
+    fn name(&self) -> &str {
+        self.provider.model()
+    }
+
+    fn translate(
+        &self,
+        text: &str,
+        target_language: &str,
+    ) -> BoxFuture<'static, Result<String, TranslateError>> {
+        let request = LlmRequest {
+            messages: vec![
+                ChatMessage::new(
+                    "system",
+                    format!(
+                        "Translate the user's message into {}. Reply with the translation only.",
+                        target_language
+                    ),
+                ),
+                ChatMessage::new("user", text),
+            ],
+            max_tokens: None,
+            ..Default::default()
+        };
+        let completion = self.provider.complete(request);
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            limiter
+                .run(MessagePriority::Normal, completion)
+                .await
+                .map(|response| response.content)
+                .map_err(|e| TranslateError::Provider(e.to_string()))
+        })
+    }
+}
+
+/// Translates `original` into each target language, returning one tagged
+/// response per successful translation.
+pub async fn translate_response(
+    translator: Arc<dyn Translator>,
+    original: LLMResponseEvent,
+    response_id: Uuid,
+    targets: Vec<String>,
+) -> Vec<LLMResponseEvent> {
+    let mut translations = Vec::new();
+    for language in targets {
+        match translator.translate(&original.response, &language).await {
+            Ok(response) => translations.push(LLMResponseEvent {
+                metadata: EventMetadata {
+                    session_id: original.metadata.session_id,
+                    user_id: original.metadata.user_id.clone(),
+                    ..Default::default()
+                },
+                response,
+                model: translator.name().to_string(),
+                tokens_used: None,
+                length_limit: None,
+                sampling: None,
+                language: Some(language),
+                translation_of: Some(response_id),
+                replying_to: original.replying_to.clone(),
+                segments: None,
+            }),
+            Err(e) => warn!(
+                "Skipping {} translation of response {}: {}",
+                language, response_id, e
+            ),
+        }
+    }
+    translations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockTranslator;
+
+    impl Translator for MockTranslator {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn translate(
+            &self,
+            text: &str,
+            target_language: &str,
+        ) -> BoxFuture<'static, Result<String, TranslateError>> {
+            let result = match target_language {
+                "en" => Ok(format!("[en] {}", text)),
+                other => Err(TranslateError::Provider(format!("unsupported: {}", other))),
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_translation_is_emitted_as_tagged_response() {
+        let mut config = TranslationConfig {
+            languages: vec!["en".to_string(), "ja".to_string()],
+            ..Default::default()
+        };
+        config.apply_room_overrides("room1=en|zh").unwrap();
+        let targets = config.targets(Some("room1"), Some("zh-CN"));
+        assert_eq!(targets, vec!["en".to_string()]);
+        assert_eq!(config.targets(Some("room2"), None), vec!["en", "ja"]);
+
+        let session_id = Uuid::new_v4();
+        let response_id = Uuid::new_v4();
+        let original = LLMResponseEvent {
+            metadata: EventMetadata {
+                session_id: Some(session_id),
+                ..Default::default()
+            },
+            response: "大家好".to_string(),
+            model: "digital_human".to_string(),
+            tokens_used: None,
+            length_limit: None,
+            sampling: None,
+            language: None,
+            translation_of: None,
+            replying_to: None,
+            segments: None,
+        };
+
+        // The failing "ja" translation is skipped
+        let translations = translate_response(
+            Arc::new(MockTranslator),
+            original,
+            response_id,
+            vec!["en".to_string(), "ja".to_string()],
+        )
+        .await;
+        assert_eq!(translations.len(), 1);
+        let translated = &translations[0];
+        assert_eq!(translated.response, "[en] 大家好");
+        assert_eq!(translated.language.as_deref(), Some("en"));
+        assert_eq!(translated.translation_of, Some(response_id));
+        assert_eq!(translated.metadata.session_id, Some(session_id));
+        assert_eq!(translated.model, "mock");
+    }
+}
@@ -0,0 +1,608 @@
+use crate::events::MessagePriority;
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self as futures_stream, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// PCM format produced by the built-in providers: 16 kHz, 16-bit mono.
+const BYTES_PER_MILLISECOND: usize = 16 * 2;
+/// Rough speaking time per character.
+const MILLISECONDS_PER_CHAR: usize = 150;
+
+#[derive(Debug, Clone)]
+pub struct TtsConfig {
+    pub enabled: bool,
+    /// Largest audio payload per binary frame.
+    pub chunk_bytes: usize,
+    /// Utterances synthesized at once.
+    pub max_concurrent: usize,
+    /// Normal and high-priority utterances that may wait for a free slot.
+    pub max_queued: usize,
+    /// Voice and prosody for each emotion of a response.
+    pub styles: VoiceStyles,
+    /// Where languages without a voice of their own look for one.
+    pub families: VoiceFamilies,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chunk_bytes: 16 * 1024,
+            max_concurrent: 4,
+            max_queued: 8,
+            styles: VoiceStyles::default(),
+            families: VoiceFamilies::default(),
+        }
+    }
+}
+
+/// Delivery relative to the voice's own, 1.0 leaving it unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Prosody {
+    pub rate: f32,
+    pub pitch: f32,
+    pub volume: f32,
+}
+
+impl Default for Prosody {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            pitch: 1.0,
+            volume: 1.0,
+        }
+    }
+}
+
+/// How a response is spoken.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VoiceStyle {
+    /// The provider's voice to use instead of its default.
+    pub voice: Option<String>,
+    pub prosody: Prosody,
+}
+
+impl FromStr for VoiceStyle {
+    type Err = String;
+
+    /// `|`-separated settings such as `voice:bright|rate:1.2|pitch:1.1`;
+    /// unset ones keep the voice's own.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = VoiceStyle::default();
+        for setting in s.split('|').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting
+                .split_once(':')
+                .ok_or_else(|| format!("expected key:value, got '{}'", setting))?;
+            let value = value.trim();
+            let factor = || {
+                value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|f| *f > 0.0)
+                    .ok_or_else(|| format!("invalid {}: {}", key.trim(), value))
+            };
+            match key.trim() {
+                "voice" => style.voice = Some(value.to_string()),
+                "rate" => style.prosody.rate = factor()?,
+                "pitch" => style.prosody.pitch = factor()?,
+                "volume" => style.prosody.volume = factor()?,
+                other => return Err(format!("unknown voice setting: {}", other)),
+            }
+        }
+        Ok(style)
+    }
+}
+
+/// `text` with the characters XML gives meaning to escaped, for both
+/// element content and attribute values.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn percent(factor: f32) -> String {
+    format!("{:+.0}%", (factor - 1.0) * 100.0)
+}
+
+impl VoiceStyle {
+    /// `text` as SSML, for providers that take prosody that way.
+    pub fn ssml(&self, text: &str) -> String {
+        let prosody = format!(
+            r#"<prosody rate="{}" pitch="{}" volume="{}">{}</prosody>"#,
+            percent(self.prosody.rate),
+            percent(self.prosody.pitch),
+            percent(self.prosody.volume),
+            xml_escape(text)
+        );
+        match &self.voice {
+            Some(voice) => format!(
+                r#"<speak><voice name="{}">{}</voice></speak>"#,
+                xml_escape(voice),
+                prosody
+            ),
+            None => format!("<speak>{}</speak>", prosody),
+        }
+    }
+}
+
+/// Voice styles by the emotion shown with a response, so the voice matches
+/// the avatar's expression.
+#[derive(Debug, Clone)]
+pub struct VoiceStyles {
+    pub enabled: bool,
+    pub by_emotion: HashMap<String, VoiceStyle>,
+}
+
+impl Default for VoiceStyles {
+    fn default() -> Self {
+        let style = |rate, pitch, volume| VoiceStyle {
+            voice: None,
+            prosody: Prosody {
+                rate,
+                pitch,
+                volume,
+            },
+        };
+        Self {
+            enabled: true,
+            by_emotion: HashMap::from([
+                ("excited".to_string(), style(1.15, 1.1, 1.1)),
+                ("curious".to_string(), style(1.0, 1.05, 1.0)),
+                ("friendly".to_string(), style(1.0, 1.0, 1.0)),
+            ]),
+        }
+    }
+}
+
+impl VoiceStyles {
+    /// Applies styles such as `excited=voice:bright|rate:1.2,shy=volume:0.8`.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (emotion, style) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected emotion=style, got '{}'", entry))?;
+            self.by_emotion
+                .insert(emotion.trim().to_string(), style.parse()?);
+        }
+        Ok(())
+    }
+
+    /// The style for `emotion`; emotions without one use the voice as it is.
+    pub fn style_for(&self, emotion: &str) -> Option<VoiceStyle> {
+        self.enabled
+            .then(|| self.by_emotion.get(emotion).cloned().unwrap_or_default())
+    }
+}
+
+/// How a voice was found for a language that has none of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoiceFallback {
+    /// A related language's voice, e.g. `zh` for `zh-TW`.
+    Family,
+    /// The main voice, as nothing in the language's family has one.
+    Default,
+}
+
+/// The next language to try for one without a voice, beyond its primary
+/// subtag (`zh-TW` → `zh`), e.g. `yue` → `zh`.
+#[derive(Debug, Clone, Default)]
+pub struct VoiceFamilies {
+    pub by_language: HashMap<String, String>,
+}
+
+impl VoiceFamilies {
+    /// Applies fallbacks such as `yue=zh,nn=no`.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (language, family) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected language=family, got '{}'", entry))?;
+            self.by_language
+                .insert(language.trim().to_lowercase(), family.trim().to_lowercase());
+        }
+        Ok(())
+    }
+
+    /// Languages to try after `language`, nearest first.
+    pub fn chain(&self, language: &str) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut current = language.to_lowercase();
+        loop {
+            let next = match self.by_language.get(&current) {
+                Some(family) => family.clone(),
+                None => match current.split_once('-') {
+                    Some((primary, _)) => primary.to_string(),
+                    None => break,
+                },
+            };
+            if next == language.to_lowercase() || chain.contains(&next) {
+                break;
+            }
+            chain.push(next.clone());
+            current = next;
+        }
+        chain
+    }
+}
+
+/// The voice a language is spoken in.
+pub struct ResolvedVoice<'a> {
+    /// None for the main voice.
+    pub tts: Option<&'a Arc<dyn TextToSpeech>>,
+    /// Unset when the language has a voice of its own, or no language
+    /// voices are configured.
+    pub fallback: Option<VoiceFallback>,
+}
+
+/// Voices by language. A language without one is spoken in the nearest
+/// voice of its family, then in the main voice.
+#[derive(Clone, Default)]
+pub struct LanguageVoices {
+    voices: HashMap<String, Arc<dyn TextToSpeech>>,
+    families: VoiceFamilies,
+}
+
+impl LanguageVoices {
+    pub fn insert(&mut self, language: &str, tts: Arc<dyn TextToSpeech>) {
+        self.voices.insert(language.to_lowercase(), tts);
+    }
+
+    pub fn set_families(&mut self, families: VoiceFamilies) {
+        self.families = families;
+    }
+
+    pub fn resolve(&self, language: &str) -> ResolvedVoice<'_> {
+        if self.voices.is_empty() {
+            return ResolvedVoice {
+                tts: None,
+                fallback: None,
+            };
+        }
+        if let Some(tts) = self.voices.get(&language.to_lowercase()) {
+            return ResolvedVoice {
+                tts: Some(tts),
+                fallback: None,
+            };
+        }
+        let family = self
+            .families
+            .chain(language)
+            .iter()
+            .find_map(|related| self.voices.get(related));
+        ResolvedVoice {
+            tts: family,
+            fallback: Some(match family {
+                Some(_) => VoiceFallback::Family,
+                None => VoiceFallback::Default,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TtsError {
+    Synthesis(String),
+    /// The service rejected its credentials.
+    Unauthorized(String),
+}
+
+impl fmt::Display for TtsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TtsError::Synthesis(msg) => write!(f, "TTS synthesis failed: {}", msg),
+            TtsError::Unauthorized(msg) => {
+                write!(f, "TTS service rejected its credentials: {}", msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TtsError {}
+
+pub trait TextToSpeech: Send + Sync {
+    /// Voice name reported on responses.
+    fn voice(&self) -> &str;
+
+    fn synthesize(&self, text: &str) -> BoxFuture<'static, Result<Vec<u8>, TtsError>>;
+
+    /// Streams audio as it is synthesized so playback can start early.
+    /// Providers without native streaming split the finished audio into
+    /// `chunk_bytes` pieces.
+    fn synthesize_stream(
+        &self,
+        text: &str,
+        chunk_bytes: usize,
+    ) -> BoxStream<'static, Result<Vec<u8>, TtsError>> {
+        let synthesis = self.synthesize(text);
+        let chunk_bytes = chunk_bytes.max(1);
+        futures_stream::once(synthesis)
+            .flat_map(move |result| {
+                let chunks: Vec<_> = match result {
+                    Ok(audio) => audio
+                        .chunks(chunk_bytes)
+                        .map(|chunk| Ok(chunk.to_vec()))
+                        .collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                futures_stream::iter(chunks)
+            })
+            .boxed()
+    }
+
+    /// Streams `text` spoken in `style`. Providers that cannot change voice
+    /// or prosody speak it as usual.
+    fn synthesize_styled(
+        &self,
+        text: &str,
+        _style: &VoiceStyle,
+        chunk_bytes: usize,
+    ) -> BoxStream<'static, Result<Vec<u8>, TtsError>> {
+        self.synthesize_stream(text, chunk_bytes)
+    }
+}
+
+/// Offline provider that renders silence as long as the text would take to
+/// speak, so clients can exercise audio playback without a TTS service.
+pub struct SilenceTts;
+
+impl SilenceTts {
+    /// Silence as long as `text` takes to say at `rate` times normal speed.
+    fn silence(text: &str, rate: f32) -> Vec<u8> {
+        let milliseconds = text.chars().count() * MILLISECONDS_PER_CHAR;
+        let milliseconds = (milliseconds as f32 / rate.max(0.1)) as usize;
+        vec![0u8; milliseconds * BYTES_PER_MILLISECOND]
+    }
+}
+
+impl TextToSpeech for SilenceTts {
+    fn voice(&self) -> &str {
+        "silence"
+    }
+
+    fn synthesize(&self, text: &str) -> BoxFuture<'static, Result<Vec<u8>, TtsError>> {
+        let audio = Self::silence(text, 1.0);
+        Box::pin(async move { Ok(audio) })
+    }
+
+    /// Follows the style's rate, so clients see faster speech end sooner.
+    fn synthesize_styled(
+        &self,
+        text: &str,
+        style: &VoiceStyle,
+        chunk_bytes: usize,
+    ) -> BoxStream<'static, Result<Vec<u8>, TtsError>> {
+        let audio = Self::silence(text, style.prosody.rate);
+        let chunks: Vec<_> = audio
+            .chunks(chunk_bytes.max(1))
+            .map(|chunk| Ok(chunk.to_vec()))
+            .collect();
+        futures_stream::iter(chunks).boxed()
+    }
+}
+
+/// Caps concurrent TTS jobs so a burst of responses cannot overwhelm the
+/// voice service. Low-priority responses are only spoken when a slot is free;
+/// others wait in a bounded queue. Responses that get no slot go out as text
+/// only.
+#[derive(Debug)]
+pub struct TtsLimiter {
+    semaphore: Arc<Semaphore>,
+    max_queued: usize,
+    queued: AtomicUsize,
+    skipped: AtomicU64,
+}
+
+impl TtsLimiter {
+    pub fn new(config: &TtsConfig) -> Arc<Self> {
+        Arc::new(Self {
+            semaphore: Arc::new(Semaphore::new(config.max_concurrent.max(1))),
+            max_queued: config.max_queued,
+            queued: AtomicUsize::new(0),
+            skipped: AtomicU64::new(0),
+        })
+    }
+
+    /// A slot for one utterance, held until the permit is dropped; None when
+    /// the response should be sent without audio.
+    pub async fn acquire(&self, priority: MessagePriority) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        if priority > MessagePriority::Low && self.queued.load(Ordering::SeqCst) < self.max_queued {
+            self.queued.fetch_add(1, Ordering::SeqCst);
+            let permit = self.semaphore.clone().acquire_owned().await.ok();
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return permit;
+        }
+        self.skipped.fetch_add(1, Ordering::SeqCst);
+        None
+    }
+
+    /// Responses sent without audio because every slot was taken.
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::SeqCst)
+    }
+}
+
+/// One piece of a streamed utterance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioChunk {
+    pub seq: u32,
+    pub is_last: bool,
+    pub audio: Vec<u8>,
+}
+
+/// Drives a synthesis stream, numbering chunks and flagging the final one.
+/// A failed synthesis still ends with an empty `is_last` chunk so clients
+/// stop waiting for more audio.
+pub async fn stream_chunks(
+    stream: BoxStream<'static, Result<Vec<u8>, TtsError>>,
+    mut on_chunk: impl FnMut(AudioChunk),
+) -> Result<(), TtsError> {
+    let mut stream = stream.peekable();
+    let mut seq = 0;
+
+    while let Some(result) = stream.next().await {
+        let audio = match result {
+            Ok(audio) => audio,
+            Err(e) => {
+                on_chunk(AudioChunk {
+                    seq,
+                    is_last: true,
+                    audio: Vec::new(),
+                });
+                return Err(e);
+            }
+        };
+        let is_last = std::pin::Pin::new(&mut stream).peek().await.is_none();
+        on_chunk(AudioChunk {
+            seq,
+            is_last,
+            audio,
+        });
+        seq += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_chunks_arrive_in_order_with_terminal_flag() {
+        let text = "大家好，欢迎来到直播间";
+        let full = SilenceTts.synthesize(text).await.unwrap();
+        assert_eq!(full.len(), 11 * 4800);
+
+        let mut chunks = Vec::new();
+        stream_chunks(SilenceTts.synthesize_stream(text, 16 * 1024), |chunk| {
+            chunks.push(chunk)
+        })
+        .await
+        .unwrap();
+
+        let seqs: Vec<u32> = chunks.iter().map(|c| c.seq).collect();
+        assert_eq!(seqs, [0, 1, 2, 3]);
+        let flags: Vec<bool> = chunks.iter().map(|c| c.is_last).collect();
+        assert_eq!(flags, [false, false, false, true]);
+        assert_eq!(
+            chunks.iter().map(|c| c.audio.len()).sum::<usize>(),
+            full.len()
+        );
+
+        // A failure still terminates the stream for the client
+        let failing = futures_stream::iter(vec![
+            Ok(vec![1u8; 4]),
+            Err(TtsError::Synthesis("voice unavailable".to_string())),
+        ])
+        .boxed();
+        let mut chunks = Vec::new();
+        assert!(stream_chunks(failing, |chunk| chunks.push(chunk))
+            .await
+            .is_err());
+        assert_eq!(
+            chunks.last(),
+            Some(&AudioChunk {
+                seq: 1,
+                is_last: true,
+                audio: Vec::new()
+            })
+        );
+    }
+
+    #[test]
+    fn test_voice_styles_parse_and_render_as_ssml() {
+        let mut styles = VoiceStyles::default();
+        styles
+            .apply("excited=voice:bright|rate:1.2, shy=volume:0.8")
+            .unwrap();
+        let excited = styles.style_for("excited").unwrap();
+        assert_eq!(excited.voice.as_deref(), Some("bright"));
+        assert_eq!(excited.prosody.rate, 1.2);
+        assert_eq!(excited.prosody.pitch, 1.0);
+        assert_eq!(
+            styles.style_for("shy").unwrap().ssml("a<b"),
+            r#"<speak><prosody rate="+0%" pitch="+0%" volume="-20%">a&lt;b</prosody></speak>"#
+        );
+        assert_eq!(styles.style_for("bored"), Some(VoiceStyle::default()));
+        let injected = VoiceStyle {
+            voice: Some(r#"x"><audio src="evil"/><voice name="y"#.to_string()),
+            prosody: Prosody::default(),
+        };
+        assert!(injected
+            .ssml("hi")
+            .starts_with(r#"<speak><voice name="x&quot;&gt;&lt;audio src=&quot;evil&quot;/&gt;"#));
+
+        assert!(styles.apply("excited=rate:fast").is_err());
+        assert!(styles.apply("excited=timbre:warm").is_err());
+        styles.enabled = false;
+        assert_eq!(styles.style_for("excited"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_silent_voice_follows_the_style_rate() {
+        let length = |style: VoiceStyle| async move {
+            let chunks: Vec<_> = SilenceTts
+                .synthesize_styled("你好呀", &style, 1024)
+                .collect()
+                .await;
+            chunks.into_iter().map(|c| c.unwrap().len()).sum::<usize>()
+        };
+        let normal = length(VoiceStyle::default()).await;
+        let fast = length("rate:2".parse().unwrap()).await;
+        assert_eq!(normal, 3 * MILLISECONDS_PER_CHAR * BYTES_PER_MILLISECOND);
+        assert_eq!(fast, normal / 2);
+    }
+
+    struct NamedVoice(&'static str);
+
+    impl TextToSpeech for NamedVoice {
+        fn voice(&self) -> &str {
+            self.0
+        }
+
+        fn synthesize(&self, text: &str) -> BoxFuture<'static, Result<Vec<u8>, TtsError>> {
+            SilenceTts.synthesize(text)
+        }
+    }
+
+    #[test]
+    fn test_language_without_voice_falls_back_to_its_family() {
+        let mut voices = LanguageVoices::default();
+        voices.insert("zh", Arc::new(NamedVoice("xiaoxiao")));
+        voices.insert("en", Arc::new(NamedVoice("jenny")));
+        let mut families = VoiceFamilies::default();
+        families.apply("yue=zh-HK").unwrap();
+        voices.set_families(families);
+
+        let voice_of = |language| {
+            let resolved = voices.resolve(language);
+            (
+                resolved.tts.map(|tts| tts.voice().to_string()),
+                resolved.fallback,
+            )
+        };
+        assert_eq!(voice_of("zh"), (Some("xiaoxiao".to_string()), None));
+        assert_eq!(
+            voice_of("zh-TW"),
+            (Some("xiaoxiao".to_string()), Some(VoiceFallback::Family))
+        );
+        // Cantonese has no voice; its configured family leads through zh-HK to zh
+        assert_eq!(
+            voice_of("yue"),
+            (Some("xiaoxiao".to_string()), Some(VoiceFallback::Family))
+        );
+        assert_eq!(voice_of("fr"), (None, Some(VoiceFallback::Default)));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::injection;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// How one platform's usernames are reworded before the LLM sees them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsernameTransform {
+    Keep,
+    /// Drops the leading `@` of handles such as YouTube's `@name`.
+    StripHandle,
+}
+
+impl FromStr for UsernameTransform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "keep" => Ok(UsernameTransform::Keep),
+            "strip_handle" => Ok(UsernameTransform::StripHandle),
+            other => Err(format!("unknown username transform: {}", other)),
+        }
+    }
+}
+
+/// Whether the LLM is told who sent each message, so it can address viewers
+/// by name. Off by default.
+#[derive(Debug, Clone)]
+pub struct UsernameDisplay {
+    pub enabled: bool,
+    /// Longer names are cut to this many characters.
+    pub max_chars: usize,
+    /// Transform per platform (`douyin`, `bilibili`, `youtube`, ...);
+    /// names from other platforms are kept as they are.
+    pub transforms: HashMap<String, UsernameTransform>,
+}
+
+impl Default for UsernameDisplay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_chars: 16,
+            transforms: HashMap::from([("youtube".to_string(), UsernameTransform::StripHandle)]),
+        }
+    }
+}
+
+impl UsernameDisplay {
+    /// Applies per-platform transforms like `youtube=strip_handle,douyin=keep`.
+    pub fn apply_transforms(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (platform, transform) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected platform=transform, got '{}'", entry))?;
+            self.transforms
+                .insert(platform.trim().to_lowercase(), transform.parse()?);
+        }
+        Ok(())
+    }
+
+    /// The name to show the LLM for `username` from `platform`; None when
+    /// disabled or when nothing safe to show remains.
+    pub fn display(&self, platform: Option<&str>, username: &str) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        let transform = platform
+            .and_then(|platform| self.transforms.get(platform))
+            .copied()
+            .unwrap_or(UsernameTransform::Keep);
+        let name = match transform {
+            UsernameTransform::Keep => username.trim(),
+            UsernameTransform::StripHandle => username.trim().trim_start_matches('@'),
+        };
+        sanitize(name, self.max_chars)
+    }
+}
+
+/// Keeps letters, digits, spaces and `_-.`, so a name cannot break out of the
+/// prompt line it is put on. Names that read as instructions are dropped.
+fn sanitize(name: &str, max_chars: usize) -> Option<String> {
+    let kept: String = name
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-' | '.'))
+        .collect();
+    let name = kept.split_whitespace().collect::<Vec<_>>().join(" ");
+    // Checked before truncating, which could cut a pattern short
+    if name.is_empty() || injection::detect(&name, None, &injection::default_patterns()).is_some() {
+        return None;
+    }
+    Some(
+        name.chars()
+            .take(max_chars)
+            .collect::<String>()
+            .trim_end()
+            .to_string(),
+    )
+}
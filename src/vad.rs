@@ -0,0 +1,171 @@
+/// Decides whether a frame of 16-bit PCM samples holds speech.
+/// `EnergyVad` is the built-in detector; model-based ones can be plugged in
+/// with `SpeechSegmenter::with_detector`.
+pub trait VoiceActivityDetector: Send {
+    fn is_speech(&mut self, samples: &[i16]) -> bool;
+}
+
+/// Largest audio accepted in one binary message or audio input by default,
+/// 16 seconds of 16 kHz mono PCM.
+pub const DEFAULT_MAX_AUDIO_BYTES: usize = 512 * 1024;
+
+/// How audio input is split into utterances before transcription.
+#[derive(Debug, Clone)]
+pub struct VadConfig {
+    /// When off, every audio chunk is transcribed as it arrives.
+    pub enabled: bool,
+    /// Frame loudness (RMS, in dBFS) at or above which a frame is speech.
+    pub threshold_dbfs: f64,
+    /// Length of the frames audio is judged in.
+    pub frame_ms: u32,
+    /// Silence after speech that ends an utterance.
+    pub endpoint_silence_ms: u32,
+    /// Utterances with less speech than this (clicks, coughs) are dropped.
+    pub min_speech_ms: u32,
+    /// Audio inputs larger than this are rejected before transcription, and
+    /// utterances are cut once they reach it.
+    pub max_audio_bytes: usize,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_dbfs: -40.0,
+            frame_ms: 20,
+            endpoint_silence_ms: 500,
+            min_speech_ms: 200,
+            max_audio_bytes: DEFAULT_MAX_AUDIO_BYTES,
+        }
+    }
+}
+
+/// Treats frames louder than a fixed level as speech.
+#[derive(Debug, Clone)]
+pub struct EnergyVad {
+    /// RMS threshold as a fraction of full scale.
+    threshold: f64,
+}
+
+impl EnergyVad {
+    pub fn new(threshold_dbfs: f64) -> Self {
+        Self {
+            threshold: 10f64.powf(threshold_dbfs / 20.0),
+        }
+    }
+}
+
+impl VoiceActivityDetector for EnergyVad {
+    fn is_speech(&mut self, samples: &[i16]) -> bool {
+        if samples.is_empty() {
+            return false;
+        }
+        let energy: f64 = samples
+            .iter()
+            .map(|&s| (s as f64 / i16::MAX as f64).powi(2))
+            .sum();
+        (energy / samples.len() as f64).sqrt() >= self.threshold
+    }
+}
+
+/// Splits one session's audio stream into utterances. Silence before
+/// speech is dropped; pauses inside an utterance are kept until they last
+/// `endpoint_silence_ms`, which ends it.
+pub struct SpeechSegmenter {
+    config: VadConfig,
+    detector: Box<dyn VoiceActivityDetector>,
+    /// Bytes short of a whole frame, kept for the next chunk.
+    partial: Vec<u8>,
+    utterance: Vec<u8>,
+    speech_ms: u32,
+    silence_ms: u32,
+}
+
+impl SpeechSegmenter {
+    pub fn new(config: VadConfig) -> Self {
+        let detector = Box::new(EnergyVad::new(config.threshold_dbfs));
+        Self::with_detector(config, detector)
+    }
+
+    pub fn with_detector(config: VadConfig, detector: Box<dyn VoiceActivityDetector>) -> Self {
+        Self {
+            config,
+            detector,
+            partial: Vec::new(),
+            utterance: Vec::new(),
+            speech_ms: 0,
+            silence_ms: 0,
+        }
+    }
+
+    /// Feeds 16-bit little-endian mono PCM and returns the utterances it
+    /// completes.
+    pub fn push(&mut self, pcm: &[u8], sample_rate: u32) -> Vec<Vec<u8>> {
+        let frame_ms = self.config.frame_ms.max(1);
+        // Widened so a bogus sample rate cannot overflow, and capped so it
+        // cannot hold back more audio than an utterance may have
+        let frame_samples = u64::from(sample_rate) * u64::from(frame_ms) / 1000;
+        let frame_bytes = usize::try_from(frame_samples * 2)
+            .unwrap_or(usize::MAX)
+            .min(self.config.max_audio_bytes & !1)
+            .max(2);
+        self.partial.extend_from_slice(pcm);
+        let whole = self.partial.len() - self.partial.len() % frame_bytes;
+        let frames: Vec<u8> = self.partial.drain(..whole).collect();
+
+        let mut utterances = Vec::new();
+        for frame in frames.chunks(frame_bytes) {
+            // Long speech is sent in pieces rather than held without bound
+            if self.utterance.len() + frame.len() > self.config.max_audio_bytes {
+                utterances.extend(self.finish());
+            }
+            let samples: Vec<i16> = frame
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                .collect();
+            if self.detector.is_speech(&samples) {
+                self.utterance.extend_from_slice(frame);
+                self.speech_ms = self.speech_ms.saturating_add(frame_ms);
+                self.silence_ms = 0;
+            } else if !self.utterance.is_empty() {
+                self.utterance.extend_from_slice(frame);
+                self.silence_ms = self.silence_ms.saturating_add(frame_ms);
+                if self.silence_ms >= self.config.endpoint_silence_ms {
+                    utterances.extend(self.finish());
+                }
+            }
+        }
+        utterances
+    }
+
+    /// Ends the current utterance, returning it if it holds enough speech.
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        let utterance = std::mem::take(&mut self.utterance);
+        let speech_ms = std::mem::take(&mut self.speech_ms);
+        self.silence_ms = 0;
+        (speech_ms > 0 && speech_ms >= self.config.min_speech_ms).then_some(utterance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bogus_sample_rate_does_not_overflow() {
+        let mut segmenter = SpeechSegmenter::new(VadConfig {
+            max_audio_bytes: 64,
+            ..Default::default()
+        });
+        let loud: Vec<u8> = [i16::MAX / 2, i16::MIN / 2]
+            .iter()
+            .cycle()
+            .take(64)
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+
+        // Frames are cut to the utterance limit instead of waiting for more
+        assert!(segmenter.push(&loud, u32::MAX).is_empty());
+        assert!(segmenter.partial.is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+/// Separators between a wake word and the rest of the message.
+const SEPARATORS: &[char] = &[',', '，', ':', '：', '、', '~', '～'];
+
+/// Names the persona answers to. When any are set, only messages that
+/// address it get a response.
+#[derive(Debug, Clone, Default)]
+pub struct WakeWords {
+    /// Matched in messages of every language.
+    pub words: Vec<String>,
+    /// Matched only in messages of that language, keyed by primary subtag
+    /// such as `zh`. Messages without a language match all of them.
+    pub by_language: HashMap<String, Vec<String>>,
+}
+
+impl WakeWords {
+    pub fn is_enabled(&self) -> bool {
+        !self.words.is_empty() || self.by_language.values().any(|w| !w.is_empty())
+    }
+
+    /// Adds wake words such as `Maya,en=Hey Maya,zh=小美|美美`; entries
+    /// without a language apply to every language.
+    pub fn apply_spec(&mut self, spec: &str) {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.split_once('=') {
+                Some((language, words)) => self
+                    .by_language
+                    .entry(primary_subtag(language))
+                    .or_default()
+                    .extend(split_words(words)),
+                None => self.words.push(entry.to_string()),
+            }
+        }
+    }
+
+    fn candidates(&self, language: Option<&str>) -> Vec<&String> {
+        let by_language: Vec<&String> = match language {
+            Some(language) => self
+                .by_language
+                .get(&primary_subtag(language))
+                .into_iter()
+                .flatten()
+                .collect(),
+            None => self.by_language.values().flatten().collect(),
+        };
+        self.words.iter().chain(by_language).collect()
+    }
+
+    /// Returns `text` without the wake word if it contains one, ignoring
+    /// ASCII case; `None` if the message does not address the persona. Always
+    /// `Some(text)` when no wake words are configured.
+    pub fn strip(&self, text: &str, language: Option<&str>) -> Option<String> {
+        if !self.is_enabled() {
+            return Some(text.to_string());
+        }
+        let lowered = text.to_ascii_lowercase();
+        // Longest first, so "Hey Maya" wins over "Maya"
+        let mut candidates = self.candidates(language);
+        candidates.sort_by_key(|word| std::cmp::Reverse(word.len()));
+
+        let (start, end) = candidates.iter().find_map(|word| {
+            let word = word.to_ascii_lowercase();
+            lowered
+                .match_indices(&word)
+                .map(|(start, matched)| (start, start + matched.len()))
+                .find(|&(start, end)| is_whole_word(text, start, end))
+        })?;
+
+        let is_separator = |c: char| c.is_whitespace() || SEPARATORS.contains(&c);
+        let before = text[..start].trim_end_matches(is_separator);
+        let after = text[end..].trim_start_matches(is_separator);
+        // "what do you think, Maya?" keeps its question mark; Latin words stay apart
+        let stripped = if !before.is_empty()
+            && before.ends_with(|c: char| c.is_ascii())
+            && after.starts_with(|c: char| c.is_ascii_alphanumeric())
+        {
+            format!("{} {}", before, after)
+        } else {
+            format!("{}{}", before, after)
+        };
+
+        // A bare "Maya!" still reaches the LLM as written
+        if !stripped.chars().any(char::is_alphanumeric) {
+            Some(text.to_string())
+        } else {
+            Some(stripped)
+        }
+    }
+}
+
+/// Latin wake words must not be part of a longer word ("Maya" in "Mayan").
+fn is_whole_word(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    let joins = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+    let first = text[start..end].chars().next();
+    let last = text[start..end].chars().next_back();
+    let splits_start = joins(first) && joins(before);
+    let splits_end = joins(last) && joins(after);
+    !splits_start && !splits_end
+}
+
+fn split_words(words: &str) -> impl Iterator<Item = String> + '_ {
+    words
+        .split('|')
+        .map(str::trim)
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+}
+
+fn primary_subtag(tag: &str) -> String {
+    tag.trim()
+        .split(['-', '_'])
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_addressed_messages_pass_without_wake_word() {
+        let mut wake = WakeWords::default();
+        wake.apply_spec("Maya,zh=小美|美美");
+
+        assert_eq!(
+            wake.strip("Maya, what's the weather?", Some("en"))
+                .as_deref(),
+            Some("what's the weather?")
+        );
+        assert_eq!(
+            wake.strip("what do you think, MAYA?", Some("en"))
+                .as_deref(),
+            Some("what do you think?")
+        );
+        assert_eq!(
+            wake.strip("hey maya tell me a joke", None).as_deref(),
+            Some("hey tell me a joke")
+        );
+        assert_eq!(
+            wake.strip("小美，今天玩什么游戏？", Some("zh-CN"))
+                .as_deref(),
+            Some("今天玩什么游戏？")
+        );
+
+        // Not addressed
+        assert_eq!(wake.strip("what's the weather?", Some("en")), None);
+        assert_eq!(wake.strip("The Mayan calendar", Some("en")), None);
+        // Chinese wake words only apply to Chinese messages
+        assert_eq!(wake.strip("小美 hello", Some("en")), None);
+
+        // A bare wake word is kept as the message
+        assert_eq!(wake.strip("Maya!", Some("en")).as_deref(), Some("Maya!"));
+
+        // Disabled: everything passes unchanged
+        let off = WakeWords::default();
+        assert_eq!(off.strip("hi", None).as_deref(), Some("hi"));
+    }
+}
@@ -1,12 +1,425 @@
-use crate::event_bus::EventBus;
+use crate::auth::{AuthConfig, SessionTokens, TokenAction, TokenExpiryPolicy};
+use crate::channels::{Channel, Channels, GlobalChannels};
+use crate::codec::{AudioCodec, CodecConfig};
+use crate::event_bus::{EventBus, GetRateLimitBudget};
 use crate::events::*;
+use crate::llm::LlmLimiter;
+use crate::load::{LoadConfig, LoadMonitor, LoadSample, LoadSignal};
+use crate::overlay::{DanmakuDelivery, RoomOverlays};
+use crate::redact;
+use crate::resume::{DetachedSessions, ResumeConfig, ResumedSession};
 use actix::prelude::*;
-use log::{info, warn};
-use std::collections::HashMap;
+use futures_util::future::LocalBoxFuture;
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+/// What to do when a user opens more sessions than allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionOverflowPolicy {
+    /// Close the new connection.
+    Reject,
+    /// Close the user's oldest connection to make room.
+    EvictOldest,
+}
+
+impl FromStr for SessionOverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "reject" => Ok(SessionOverflowPolicy::Reject),
+            "evict_oldest" => Ok(SessionOverflowPolicy::EvictOldest),
+            other => Err(format!("unknown session overflow policy: {}", other)),
+        }
+    }
+}
+
+/// What to do with a user's open sessions when they connect again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconnectPolicy {
+    /// Keep the earlier sessions open alongside the new one.
+    Allow,
+    /// Close the earlier sessions and start a fresh conversation.
+    Replace,
+    /// Close the earlier sessions and carry their history into the new one.
+    Merge,
+}
+
+impl FromStr for ReconnectPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "allow" => Ok(ReconnectPolicy::Allow),
+            "replace" => Ok(ReconnectPolicy::Replace),
+            "merge" => Ok(ReconnectPolicy::Merge),
+            other => Err(format!("unknown reconnect policy: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionLimitConfig {
+    pub max_sessions_per_user: usize,
+    pub overflow_policy: SessionOverflowPolicy,
+    pub reconnect_policy: ReconnectPolicy,
+}
+
+impl Default for SessionLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions_per_user: 5,
+            overflow_policy: SessionOverflowPolicy::Reject,
+            reconnect_policy: ReconnectPolicy::Allow,
+        }
+    }
+}
+
+/// Why the server closed a session. Each reason maps to a standard close
+/// code, with a short description the client can show to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionCloseReason {
+    /// The server is shutting down (1001 going away).
+    Shutdown,
+    /// Authentication failure or abuse (1008 policy violation).
+    PolicyViolation(String),
+    /// A capacity limit was hit; the client may retry later (1013 try again later).
+    Capacity(String),
+    /// A message exceeded the size limit (1009 message too big).
+    MessageTooBig(String),
+}
+
+impl SessionCloseReason {
+    pub fn to_close_reason(&self) -> actix_ws::CloseReason {
+        let (code, description) = match self {
+            SessionCloseReason::Shutdown => (actix_ws::CloseCode::Away, "server shutting down"),
+            SessionCloseReason::PolicyViolation(reason) => {
+                (actix_ws::CloseCode::Policy, reason.as_str())
+            }
+            SessionCloseReason::Capacity(reason) => (actix_ws::CloseCode::Again, reason.as_str()),
+            SessionCloseReason::MessageTooBig(reason) => {
+                (actix_ws::CloseCode::Size, reason.as_str())
+            }
+        };
+        actix_ws::CloseReason {
+            code,
+            description: Some(description.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Admission {
+    Accepted,
+    Rejected,
+    /// Accepted after making room by evicting this older session.
+    Evicted(Uuid),
+    /// Accepted in place of the user's earlier sessions.
+    Replaced(Vec<Uuid>),
+}
+
+/// Tracks each user's active sessions in connection order.
+#[derive(Debug, Default)]
+struct UserSessions {
+    config: SessionLimitConfig,
+    sessions: HashMap<String, VecDeque<Uuid>>,
+}
+
+impl Admission {
+    /// Close reason for the session that loses out, if any.
+    fn close_reason(&self) -> Option<SessionCloseReason> {
+        match self {
+            Admission::Accepted => None,
+            Admission::Rejected => Some(SessionCloseReason::Capacity(
+                "too many sessions".to_string(),
+            )),
+            Admission::Evicted(_) | Admission::Replaced(_) => Some(
+                SessionCloseReason::PolicyViolation("replaced by a newer session".to_string()),
+            ),
+        }
+    }
+}
+
+impl UserSessions {
+    fn new(config: SessionLimitConfig) -> Self {
+        Self {
+            config,
+            sessions: HashMap::new(),
+        }
+    }
+
+    fn admit(&mut self, user_id: &str, session_id: Uuid) -> Admission {
+        let sessions = self.sessions.entry(user_id.to_string()).or_default();
+        let mut admission = Admission::Accepted;
+
+        // A session registering twice keeps a single slot
+        sessions.retain(|id| *id != session_id);
+
+        if self.config.reconnect_policy != ReconnectPolicy::Allow && !sessions.is_empty() {
+            let earlier = sessions.drain(..).collect();
+            sessions.push_back(session_id);
+            return Admission::Replaced(earlier);
+        }
+
+        if sessions.len() >= self.config.max_sessions_per_user.max(1) {
+            match self.config.overflow_policy {
+                SessionOverflowPolicy::Reject => return Admission::Rejected,
+                SessionOverflowPolicy::EvictOldest => {
+                    if let Some(oldest) = sessions.pop_front() {
+                        admission = Admission::Evicted(oldest);
+                    }
+                }
+            }
+        }
+
+        sessions.push_back(session_id);
+        admission
+    }
+
+    fn release(&mut self, user_id: &str, session_id: &Uuid) {
+        if let Some(sessions) = self.sessions.get_mut(user_id) {
+            sessions.retain(|id| id != session_id);
+            if sessions.is_empty() {
+                self.sessions.remove(user_id);
+            }
+        }
+    }
+
+    fn count(&self, user_id: &str) -> usize {
+        self.sessions.get(user_id).map_or(0, |s| s.len())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageLimits {
+    /// Largest message accepted after reassembling fragmented frames.
+    pub max_message_bytes: usize,
+    /// Largest binary (audio) message accepted. Larger ones are dropped with
+    /// an error frame while the session stays open.
+    pub max_audio_bytes: usize,
+}
+
+impl Default for MessageLimits {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: 1024 * 1024,
+            max_audio_bytes: crate::vad::DEFAULT_MAX_AUDIO_BYTES,
+        }
+    }
+}
+
+/// A complete message reassembled from continuation frames.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AssembledMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    /// A binary message dropped for exceeding the audio limit; the reason is
+    /// for the client.
+    AudioRejected(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FragmentKind {
+    Text,
+    Binary,
+}
+
+/// Reassembles messages that clients split across continuation frames.
+#[derive(Debug)]
+pub struct MessageAssembler {
+    max_message_bytes: usize,
+    max_audio_bytes: usize,
+    kind: Option<FragmentKind>,
+    buffer: Vec<u8>,
+    /// Set once an oversized binary message is rejected; its remaining
+    /// fragments are skipped.
+    discarding: bool,
+}
+
+impl MessageAssembler {
+    pub fn new(limits: &MessageLimits) -> Self {
+        Self {
+            max_message_bytes: limits.max_message_bytes,
+            max_audio_bytes: limits.max_audio_bytes,
+            kind: None,
+            buffer: Vec::new(),
+            discarding: false,
+        }
+    }
+
+    /// Checks a single-frame binary message against the audio limit.
+    pub fn check_audio(&self, len: usize) -> Result<(), String> {
+        if len > self.max_audio_bytes {
+            return Err(self.audio_too_large());
+        }
+        Ok(())
+    }
+
+    fn audio_too_large(&self) -> String {
+        format!("audio exceeds {} bytes", self.max_audio_bytes)
+    }
+
+    /// Adds a fragment, returning the message once its last fragment
+    /// arrives. Errors carry the reason to close the session with.
+    pub fn push(
+        &mut self,
+        item: actix_ws::Item,
+    ) -> Result<Option<AssembledMessage>, SessionCloseReason> {
+        let (kind, chunk, last) = match item {
+            actix_ws::Item::FirstText(chunk) => (Some(FragmentKind::Text), chunk, false),
+            actix_ws::Item::FirstBinary(chunk) => (Some(FragmentKind::Binary), chunk, false),
+            actix_ws::Item::Continue(chunk) => (None, chunk, false),
+            actix_ws::Item::Last(chunk) => (None, chunk, true),
+        };
+
+        match (kind, self.kind) {
+            (Some(_), Some(_)) => {
+                return Err(Self::protocol_error("fragmented message interrupted"))
+            }
+            (None, None) => return Err(Self::protocol_error("continuation without a first frame")),
+            (Some(kind), None) => self.kind = Some(kind),
+            (None, Some(_)) => {}
+        }
+
+        if self.discarding {
+            if last {
+                self.reset();
+            }
+            return Ok(None);
+        }
+        // Oversized audio is dropped as soon as it crosses the limit
+        if self.kind == Some(FragmentKind::Binary)
+            && self.buffer.len() + chunk.len() > self.max_audio_bytes
+        {
+            let reason = self.audio_too_large();
+            self.reset();
+            if !last {
+                self.kind = Some(FragmentKind::Binary);
+                self.discarding = true;
+            }
+            return Ok(Some(AssembledMessage::AudioRejected(reason)));
+        }
+        if self.buffer.len() + chunk.len() > self.max_message_bytes {
+            self.reset();
+            return Err(SessionCloseReason::MessageTooBig(format!(
+                "message exceeds {} bytes",
+                self.max_message_bytes
+            )));
+        }
+        self.buffer.extend_from_slice(&chunk);
+
+        if !last {
+            return Ok(None);
+        }
+        let buffer = std::mem::take(&mut self.buffer);
+        match self.kind.take() {
+            Some(FragmentKind::Text) => String::from_utf8(buffer)
+                .map(|text| Some(AssembledMessage::Text(text)))
+                .map_err(|_| Self::protocol_error("fragmented text is not valid UTF-8")),
+            _ => Ok(Some(AssembledMessage::Binary(buffer))),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.kind = None;
+        self.buffer = Vec::new();
+        self.discarding = false;
+    }
+
+    fn protocol_error(reason: &str) -> SessionCloseReason {
+        SessionCloseReason::PolicyViolation(reason.to_string())
+    }
+}
+
+/// Per-session counters reported to clients that request `get_stats`.
+#[derive(Debug, Clone, Default)]
+struct SessionMetrics {
+    messages: u64,
+    responses: u64,
+    total_latency: Duration,
+    /// Arrival of the oldest input still waiting for a response.
+    awaiting_since: Option<Instant>,
+    /// Arrival of each input not yet answered, oldest first.
+    pending: VecDeque<Instant>,
+}
+
+/// Inputs unanswered for this long are assumed dropped (e.g. by validation)
+/// and no longer count towards the session's queue depth.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+impl SessionMetrics {
+    fn record_input(&mut self, now: Instant) {
+        self.messages += 1;
+        self.awaiting_since.get_or_insert(now);
+        self.pending.push_back(now);
+    }
+
+    fn record_response(&mut self, now: Instant) {
+        self.pending.pop_front();
+        if let Some(since) = self.awaiting_since.take() {
+            self.responses += 1;
+            self.total_latency += now.saturating_duration_since(since);
+        }
+    }
+
+    fn average_latency_ms(&self) -> Option<u128> {
+        (self.responses > 0).then(|| self.total_latency.as_millis() / self.responses as u128)
+    }
+
+    /// Inputs still awaiting a response at `now`.
+    fn pending_depth(&mut self, now: Instant) -> usize {
+        while self
+            .pending
+            .front()
+            .is_some_and(|since| now.saturating_duration_since(*since) >= PENDING_TIMEOUT)
+        {
+            self.pending.pop_front();
+        }
+        self.pending.len()
+    }
+}
+
 pub struct WebSocketManager {
     connections: HashMap<Uuid, (String, Addr<WebSocketSessionActor>)>,
+    user_sessions: UserSessions,
+    metrics: HashMap<Uuid, SessionMetrics>,
+    /// Resume token issued to each connected session.
+    resume_tokens: HashMap<Uuid, Uuid>,
+    detached: DetachedSessions,
+    /// Whether clients may request debug stats with `get_stats`.
+    client_stats: bool,
+    /// Whether text that looks like JSON but fails to parse is answered with
+    /// an error frame rather than taken as a question.
+    strict_json: bool,
+    auth: AuthConfig,
+    session_tokens: SessionTokens,
+    /// Sessions whose token carries the admin claim; their input may run
+    /// moderator commands.
+    operators: HashSet<Uuid>,
+    load: LoadMonitor,
+    /// Source of the global LLM queue depth for load signals.
+    llm_limiter: Option<Arc<LlmLimiter>>,
+    danmaku_delivery: DanmakuDelivery,
+    overlays: RoomOverlays,
+    /// Output channels sessions chose with `set_channels`.
+    channels: HashMap<Uuid, Channels>,
+    default_channels: Channels,
+    /// Channels operators left on for everyone; a channel off here is off
+    /// whatever sessions chose.
+    global_channels: GlobalChannels,
+    codecs: CodecConfig,
+    /// Codec negotiated with each session that declared the codecs it
+    /// plays; None for sessions that can play none and get text only.
+    audio_codecs: HashMap<Uuid, Option<AudioCodec>>,
+    /// Utterance each session is getting converted audio of, so the first
+    /// chunk it gets carries the stream header whatever its `seq`.
+    converted_utterances: HashMap<Uuid, Uuid>,
     event_bus: Addr<EventBus>,
 }
 
@@ -14,49 +427,874 @@ impl WebSocketManager {
     pub fn new(event_bus: Addr<EventBus>) -> Self {
         Self {
             connections: HashMap::new(),
+            user_sessions: UserSessions::default(),
+            metrics: HashMap::new(),
+            resume_tokens: HashMap::new(),
+            detached: DetachedSessions::default(),
+            client_stats: false,
+            strict_json: true,
+            auth: AuthConfig::default(),
+            session_tokens: SessionTokens::default(),
+            operators: HashSet::new(),
+            load: LoadMonitor::new(LoadConfig::default()),
+            llm_limiter: None,
+            danmaku_delivery: DanmakuDelivery::default(),
+            overlays: RoomOverlays::default(),
+            channels: HashMap::new(),
+            default_channels: Channels::default(),
+            global_channels: GlobalChannels::default(),
+            codecs: CodecConfig::default(),
+            audio_codecs: HashMap::new(),
+            converted_utterances: HashMap::new(),
             event_bus,
         }
     }
 
+    /// What TTS audio is encoded as, and which codecs clients are offered.
+    pub fn with_audio_codecs(mut self, codecs: CodecConfig) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    /// Tells clients when their responses are delayed by queued LLM work.
+    pub fn with_load_signal(mut self, config: LoadConfig, limiter: Arc<LlmLimiter>) -> Self {
+        self.load = LoadMonitor::new(config);
+        self.llm_limiter = Some(limiter);
+        self
+    }
+
+    pub fn with_resume(mut self, config: ResumeConfig) -> Self {
+        self.detached = DetachedSessions::new(config);
+        self
+    }
+
+    pub fn with_client_stats(mut self, enabled: bool) -> Self {
+        self.client_stats = enabled;
+        self
+    }
+
+    pub fn with_strict_json(mut self, enabled: bool) -> Self {
+        self.strict_json = enabled;
+        self
+    }
+
+    pub fn with_auth(mut self, config: AuthConfig) -> Self {
+        self.auth = config;
+        self
+    }
+
+    /// Sends danmaku responses to the overlay clients of the danmaku's room.
+    pub fn with_danmaku_delivery(mut self, delivery: DanmakuDelivery) -> Self {
+        self.danmaku_delivery = delivery;
+        self
+    }
+
+    /// Output channels sessions receive until they choose their own.
+    pub fn with_default_channels(mut self, channels: Channels) -> Self {
+        self.default_channels = channels;
+        self
+    }
+
+    /// Channels switched on for every session, changed at runtime through
+    /// the shared handle.
+    pub fn with_global_channels(mut self, channels: GlobalChannels) -> Self {
+        self.global_channels = channels;
+        self
+    }
+
+    pub fn with_session_limit(mut self, config: SessionLimitConfig) -> Self {
+        self.user_sessions = UserSessions::new(config);
+        self
+    }
+
+    /// Registers a connection, enforcing the per-user session cap and
+    /// reconnect policy. Returns None if the connection was rejected and
+    /// closed, otherwise the closed sessions whose history it should take over.
     fn add_connection(
         &mut self,
         session_id: Uuid,
         user_id: String,
         session_actor: Addr<WebSocketSessionActor>,
-    ) {
-        self.connections
-            .insert(session_id, (user_id.clone(), session_actor));
+    ) -> Option<Vec<Uuid>> {
+        let mut merged = Vec::new();
+        let admission = self.user_sessions.admit(&user_id, session_id);
+        match admission {
+            Admission::Accepted => {}
+            Admission::Rejected => {
+                warn!(
+                    "Rejecting session {}: user {} already has {} sessions",
+                    session_id,
+                    redact::user(&user_id),
+                    self.user_sessions.count(&user_id)
+                );
+                if let Some(reason) = admission.close_reason() {
+                    session_actor.do_send(CloseSession { reason });
+                }
+                return None;
+            }
+            Admission::Evicted(oldest) => {
+                info!(
+                    "Evicting oldest session {} for user {}",
+                    oldest,
+                    redact::user(&user_id)
+                );
+                if let Some(evicted_actor) = self.remove_connection(&oldest) {
+                    if let Some(reason) = admission.close_reason() {
+                        evicted_actor.do_send(CloseSession { reason });
+                    }
+                    self.publish_disconnect(oldest, user_id.clone());
+                }
+            }
+            Admission::Replaced(ref earlier) => {
+                for old_session in earlier {
+                    info!(
+                        "Session {} replaces session {} for user {}",
+                        session_id,
+                        old_session,
+                        redact::user(&user_id)
+                    );
+                    if let Some(old_actor) = self.remove_connection(old_session) {
+                        if let Some(reason) = admission.close_reason() {
+                            old_actor.do_send(CloseSession { reason });
+                        }
+                    }
+                    match self.user_sessions.config.reconnect_policy {
+                        ReconnectPolicy::Merge => merged.push(*old_session),
+                        _ => self.publish_disconnect(*old_session, user_id.clone()),
+                    }
+                }
+            }
+        }
+
+        // Close whatever socket previously held this session id rather than
+        // losing track of it
+        if let Some((_, stale_actor)) = self
+            .connections
+            .insert(session_id, (user_id.clone(), session_actor))
+        {
+            warn!(
+                "Session {} registered twice; closing the stale socket",
+                session_id
+            );
+            stale_actor.do_send(CloseSession {
+                reason: SessionCloseReason::PolicyViolation(
+                    "replaced by a newer session".to_string(),
+                ),
+            });
+        }
+        self.metrics.insert(session_id, SessionMetrics::default());
         info!(
             "Added WebSocket connection for session: {} user: {}",
-            session_id, user_id
+            session_id,
+            redact::user(&user_id)
+        );
+        Some(merged)
+    }
+
+    fn wants(&self, session_id: &Uuid, channel: Option<Channel>) -> bool {
+        if channel.is_some_and(|channel| !self.global_channels.includes(channel)) {
+            return false;
+        }
+        // A client that can play none of our codecs only gets text
+        if channel == Some(Channel::Audio) && self.audio_codecs.get(session_id) == Some(&None) {
+            return false;
+        }
+        channel.is_none_or(|channel| {
+            self.channels
+                .get(session_id)
+                .unwrap_or(&self.default_channels)
+                .includes(channel)
+        })
+    }
+
+    /// The binary frame for `chunk` in the codec negotiated with
+    /// `session_id`; sessions that never declared codecs get the source.
+    fn audio_frame_for(&mut self, session_id: &Uuid, chunk: &TTSChunkEvent) -> Vec<u8> {
+        match self.audio_codecs.get(session_id).copied().flatten() {
+            Some(codec) if codec != self.codecs.source => {
+                let first = self
+                    .converted_utterances
+                    .insert(*session_id, chunk.response_id)
+                    != Some(chunk.response_id);
+                audio_chunk_frame(&TTSChunkEvent {
+                    audio: self.codecs.convert(codec, first, &chunk.audio),
+                    ..chunk.clone()
+                })
+            }
+            _ => audio_chunk_frame(chunk),
+        }
+    }
+
+    /// Picks the codec for a session from those it can play, falling back
+    /// to text only with a notice when there is none.
+    fn negotiate_codec(&mut self, session_id: Uuid, supported: &[AudioCodec]) {
+        let codec = self.codecs.negotiate(supported);
+        match codec {
+            Some(codec) => info!("Session {} plays {:?} audio", session_id, codec),
+            None => info!(
+                "Session {} plays none of {:?}, sending text only",
+                session_id, self.codecs.preference
+            ),
+        }
+        self.audio_codecs.insert(session_id, codec);
+        self.send_frame(&session_id, "capabilities", capabilities_frame(codec));
+    }
+
+    fn send_frame(&mut self, session_id: &Uuid, label: &str, mut frame: serde_json::Value) {
+        frame["schema_version"] = EVENT_SCHEMA_VERSION.into();
+        let channel = frame["type"].as_str().and_then(Channel::of_frame);
+        let kind = if channel == Some(Channel::Animation) {
+            FrameKind::Animation
+        } else {
+            FrameKind::Message
+        };
+        if let Some((user_id, session_actor)) = self.connections.get(session_id) {
+            if !self.wants(session_id, channel) {
+                debug!("Session {} does not receive {}", session_id, label);
+                return;
+            }
+            let message_str = frame.to_string();
+            info!(
+                "Sending {} to session {} (user {}): {}",
+                label,
+                session_id,
+                redact::user(user_id),
+                redact::text(&message_str)
+            );
+
+            // Send the message through WebSocket session actor
+            session_actor.do_send(SendMessage {
+                message: message_str,
+                kind,
+            });
+        } else if let Some(overlays) = self.overlays.overlays_for(session_id) {
+            let message_str = frame.to_string();
+            debug!(
+                "Sending {} for session {} to {} room overlays",
+                label,
+                session_id,
+                overlays.len()
+            );
+            for overlay in overlays.iter().filter(|o| self.wants(o, channel)) {
+                if let Some((_, session_actor)) = self.connections.get(overlay) {
+                    session_actor.do_send(SendMessage {
+                        message: message_str.clone(),
+                        kind,
+                    });
+                }
+            }
+        } else if !self.detached.buffer(session_id, &frame) {
+            warn!("No active connection found for session {}", session_id);
+        }
+    }
+
+    /// Sends an audio chunk to the session, or its room's overlays, in each
+    /// one's codec. Audio is transient, so nothing is buffered for detached
+    /// sessions.
+    fn send_audio_chunk(&mut self, session_id: &Uuid, chunk: &TTSChunkEvent) {
+        match self.connections.get(session_id).map(|(_, a)| a.clone()) {
+            Some(_) if !self.wants(session_id, Some(Channel::Audio)) => {
+                debug!("Session {} does not receive audio chunks", session_id)
+            }
+            Some(session_actor) => {
+                let data = self.audio_frame_for(session_id, chunk);
+                debug!(
+                    "Sending audio chunk to session {} ({} bytes)",
+                    session_id,
+                    data.len()
+                );
+                session_actor.do_send(SendBinary { data });
+            }
+            None => match self.overlays.overlays_for(session_id) {
+                Some(overlays) => {
+                    let overlays: Vec<Uuid> = overlays
+                        .iter()
+                        .filter(|o| self.wants(o, Some(Channel::Audio)))
+                        .copied()
+                        .collect();
+                    for overlay in overlays {
+                        let session_actor = self.connections.get(&overlay).map(|(_, a)| a.clone());
+                        if let Some(session_actor) = session_actor {
+                            session_actor.do_send(SendBinary {
+                                data: self.audio_frame_for(&overlay, chunk),
+                            });
+                        }
+                    }
+                }
+                None => debug!(
+                    "Dropping audio chunk for disconnected session {}",
+                    session_id
+                ),
+            },
+        }
+    }
+
+    fn remove_connection(&mut self, session_id: &Uuid) -> Option<Addr<WebSocketSessionActor>> {
+        let (user_id, session_actor) = self.connections.remove(session_id)?;
+        self.metrics.remove(session_id);
+        self.resume_tokens.remove(session_id);
+        self.session_tokens.remove(session_id);
+        self.operators.remove(session_id);
+        self.load.remove(session_id);
+        self.overlays.unsubscribe(session_id);
+        self.channels.remove(session_id);
+        self.audio_codecs.remove(session_id);
+        self.converted_utterances.remove(session_id);
+        self.user_sessions.release(&user_id, session_id);
+        info!(
+            "Removed WebSocket connection for session: {} user: {}",
+            session_id,
+            redact::user(&user_id)
         );
+        Some(session_actor)
+    }
+
+    fn publish_text_input(&mut self, mut event: TextInputEvent) {
+        if let Some(session_id) = event.metadata.session_id {
+            if let Some(metrics) = self.metrics.get_mut(&session_id) {
+                metrics.record_input(Instant::now());
+            }
+            event.operator = self.operators.contains(&session_id);
+        }
+        self.event_bus.do_send(event);
+    }
+
+    /// Replies with a `stats` frame once the user's rate-limit budget is known.
+    fn send_session_stats(&self, session_id: Uuid, user_id: String, ctx: &mut Context<Self>) {
+        let Some(metrics) = self.metrics.get(&session_id).cloned() else {
+            return;
+        };
+
+        let budget = self.event_bus.send(GetRateLimitBudget { user_id });
+        ctx.spawn(budget.into_actor(self).map(move |budget, act, _ctx| {
+            let remaining = budget.unwrap_or_else(|e| {
+                warn!("Failed to query rate-limit budget: {}", e);
+                None
+            });
+            act.send_frame(
+                &session_id,
+                "session stats",
+                stats_frame(&metrics, remaining),
+            );
+        }));
+    }
+
+    /// Sends a `load` frame to each session whose load level changed.
+    fn check_load(&mut self) {
+        let now = Instant::now();
+        let (global_queued, max_concurrent) = self.llm_limiter.as_ref().map_or((0, 1), |limiter| {
+            let stats = limiter.stats();
+            (stats.queued, stats.max_concurrent)
+        });
+
+        let mut signals = Vec::new();
+        for (session_id, metrics) in self.metrics.iter_mut() {
+            let sample = LoadSample {
+                session_pending: metrics.pending_depth(now),
+                global_queued,
+                max_concurrent,
+                avg_response_ms: metrics.average_latency_ms(),
+            };
+            if let Some(signal) = self.load.evaluate(*session_id, sample, now) {
+                signals.push((*session_id, signal));
+            }
+        }
+        for (session_id, signal) in signals {
+            self.send_frame(&session_id, "load signal", load_frame(&signal));
+        }
+    }
+
+    /// Issues a fresh resume token and tells the client about it.
+    fn issue_resume_token(&mut self, session_id: Uuid) {
+        if !self.detached.config().enabled() {
+            return;
+        }
+
+        let resume_token = Uuid::new_v4();
+        self.resume_tokens.insert(session_id, resume_token);
+        let frame = session_frame(session_id, resume_token, self.detached.config());
+        self.send_frame(&session_id, "session info", frame);
     }
 
-    fn remove_connection(&mut self, session_id: &Uuid) {
-        if let Some((user_id, _)) = self.connections.remove(session_id) {
+    /// Keeps a dropped session resumable for the configured window. Its
+    /// disconnect is published once it is swept or evicted unclaimed.
+    fn detach_session(&mut self, session_id: Uuid, user_id: String, resume_token: Uuid) {
+        let evicted = self
+            .detached
+            .detach(session_id, user_id, resume_token, chrono::Utc::now());
+        if !evicted.is_empty() {
             info!(
-                "Removed WebSocket connection for session: {} user: {}",
-                session_id, user_id
+                "Evicted {} resumable session(s) over the cap of {}",
+                evicted.len(),
+                self.detached.config().max_detached_sessions
             );
         }
+        for (session_id, user_id) in evicted {
+            self.publish_disconnect(session_id, user_id);
+        }
+    }
+
+    /// Drops resumable sessions whose window has passed.
+    fn sweep_detached(&mut self) {
+        let expired = self.detached.sweep(chrono::Utc::now());
+        if expired.is_empty() {
+            return;
+        }
+        info!(
+            "Swept {} expired resume token(s), {} still resumable",
+            expired.len(),
+            self.detached.len()
+        );
+        for (session_id, user_id) in expired {
+            self.publish_disconnect(session_id, user_id);
+        }
+    }
+
+    /// Asks clients for fresh tokens before theirs expire, and applies the
+    /// expiry policy to those that did not refresh in time.
+    fn check_session_tokens(&mut self) {
+        let refresh_before = chrono::Duration::seconds(self.auth.refresh_before_seconds as i64);
+        for (session_id, action) in self
+            .session_tokens
+            .check(chrono::Utc::now(), refresh_before)
+        {
+            match action {
+                TokenAction::RequestRefresh(expires_at) => {
+                    self.send_frame(&session_id, "auth refresh", auth_refresh_frame(expires_at));
+                }
+                TokenAction::Expired => match self.auth.expiry_policy {
+                    TokenExpiryPolicy::Enforce => {
+                        warn!("Token for session {} expired, closing it", session_id);
+                        if let Some((user_id, _)) = self.connections.get(&session_id).cloned() {
+                            if let Some(session_actor) = self.remove_connection(&session_id) {
+                                session_actor.do_send(CloseSession {
+                                    reason: SessionCloseReason::PolicyViolation(
+                                        "token expired".to_string(),
+                                    ),
+                                });
+                            }
+                            self.publish_disconnect(session_id, user_id);
+                        }
+                    }
+                    TokenExpiryPolicy::Warn => {
+                        warn!("Token for session {} expired, keeping it open", session_id);
+                        // 令牌过期后不再保留管理员身份
+                        self.operators.remove(&session_id);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Replaces a session's token with one the client sent in reply to
+    /// `auth_refresh`. The session is an operator only if the new token is
+    /// an admin one.
+    fn refresh_session_token(&mut self, session_id: Uuid, user_id: &str, token: &str) {
+        match self.auth.verify(token, user_id) {
+            Ok(expires_at) => {
+                info!("Refreshed token for session {}", session_id);
+                self.session_tokens.track(session_id, expires_at);
+                if self.auth.verify_admin(token).is_ok() {
+                    self.operators.insert(session_id);
+                } else {
+                    self.operators.remove(&session_id);
+                }
+            }
+            Err(e) => warn!("Rejected token refresh for session {}: {}", session_id, e),
+        }
+    }
+
+    fn publish_disconnect(&self, session_id: Uuid, user_id: String) {
+        let event = UserDisconnectedEvent {
+            metadata: EventMetadata {
+                session_id: Some(session_id),
+                user_id: Some(user_id.clone()),
+                ..Default::default()
+            },
+            session_id,
+            user_id,
+        };
+
+        self.event_bus.do_send(event);
+    }
+}
+
+/// Why a frame could not be sent to a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendError {
+    /// The socket is closed; nothing more can be sent on it.
+    Closed,
+    /// A failure that may pass, worth retrying, e.g. a send stalled behind
+    /// a client that is not reading.
+    Transient(String),
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Closed => write!(f, "session closed"),
+            SendError::Transient(msg) => write!(f, "transient send failure: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// A frame on its way to a client.
+#[derive(Debug, Clone)]
+pub enum OutboundFrame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// What a queued frame carries, which decides what a full queue drops.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameKind {
+    /// Responses and control frames.
+    #[default]
+    Message,
+    /// Animation frames, the cheapest to lose.
+    Animation,
+    /// Binary TTS chunks, never dropped: a gap would corrupt the utterance.
+    Audio,
+}
+
+/// Where a session actor writes its frames: the client's socket, or a stub
+/// in tests.
+pub trait SessionSink {
+    fn send(&self, frame: OutboundFrame) -> LocalBoxFuture<'static, Result<(), SendError>>;
+    fn close(
+        &self,
+        reason: actix_ws::CloseReason,
+    ) -> LocalBoxFuture<'static, Result<(), SendError>>;
+}
+
+/// actix-ws only fails a send once the session is closed; a send stalled
+/// behind a full socket buffer is timed out by `send_with_retry` instead.
+impl SessionSink for actix_ws::Session {
+    fn send(&self, frame: OutboundFrame) -> LocalBoxFuture<'static, Result<(), SendError>> {
+        let mut session = self.clone();
+        Box::pin(async move {
+            match frame {
+                OutboundFrame::Text(text) => session.text(text).await,
+                OutboundFrame::Binary(data) => session.binary(data).await,
+            }
+            .map_err(|_| SendError::Closed)
+        })
+    }
+
+    fn close(
+        &self,
+        reason: actix_ws::CloseReason,
+    ) -> LocalBoxFuture<'static, Result<(), SendError>> {
+        let session = self.clone();
+        Box::pin(async move {
+            session
+                .close(Some(reason))
+                .await
+                .map_err(|_| SendError::Closed)
+        })
+    }
+}
+
+/// How sends that fail transiently are retried.
+#[derive(Debug, Clone)]
+pub struct SendRetryConfig {
+    /// Retries after the first attempt; 0 gives up at once.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each one after.
+    pub backoff_ms: u64,
+    /// A send still waiting for room in the client's buffer after this long
+    /// fails transiently; 0 waits as long as it takes.
+    pub timeout_ms: u64,
+}
+
+impl Default for SendRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            backoff_ms: 50,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+/// Retry settings shared by every session, counting the retries made.
+#[derive(Debug, Clone, Default)]
+pub struct SendRetries {
+    config: SendRetryConfig,
+    retried: Arc<AtomicU64>,
+}
+
+impl SendRetries {
+    pub fn new(config: SendRetryConfig) -> Self {
+        Self {
+            config,
+            retried: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Retries made since startup, across sessions.
+    pub fn retried(&self) -> u64 {
+        self.retried.load(Ordering::Relaxed)
+    }
+}
+
+/// Sends `frame`, retrying transient failures and sends that time out.
+/// Closed sockets are not retried.
+async fn send_with_retry(
+    sink: Rc<dyn SessionSink>,
+    frame: OutboundFrame,
+    retries: SendRetries,
+    session_id: Uuid,
+) -> Result<(), SendError> {
+    let timeout = Duration::from_millis(retries.config.timeout_ms);
+    let mut backoff = Duration::from_millis(retries.config.backoff_ms);
+    let mut attempt = 0;
+    loop {
+        let send = sink.send(frame.clone());
+        let result = if timeout.is_zero() {
+            send.await
+        } else {
+            actix::clock::timeout(timeout, send)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(SendError::Transient(format!(
+                        "timed out after {:?}",
+                        timeout
+                    )))
+                })
+        };
+        match result {
+            Err(SendError::Transient(e)) if attempt < retries.config.max_retries => {
+                attempt += 1;
+                retries.retried.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    "Retrying send to session {} in {:?} ({}/{}): {}",
+                    session_id, backoff, attempt, retries.config.max_retries, e
+                );
+                actix::clock::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// What a session does when its outbound queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drops the oldest queued animation frame, or the oldest frame when no
+    /// animation is queued; animations are the cheapest to lose. Audio
+    /// chunks are never dropped.
+    #[default]
+    DropOldestAnimation,
+    /// Drops the oldest queued frame other than an audio chunk.
+    DropOldestAny,
+    /// Closes the session; the client can reconnect and resume.
+    Disconnect,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "drop_oldest_animation" => Ok(OverflowPolicy::DropOldestAnimation),
+            "drop_oldest_any" => Ok(OverflowPolicy::DropOldestAny),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            other => Err(format!("unknown overflow policy: {}", other)),
+        }
+    }
+}
+
+/// Bounds on the frames a session holds while its client reads slowly.
+#[derive(Debug, Clone)]
+pub struct OutboundQueueConfig {
+    /// Frames waiting to be sent, not counting the one being sent.
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for OutboundQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// Queue settings shared by every session, counting the overflows.
+#[derive(Debug, Clone, Default)]
+pub struct OutboundQueue {
+    config: OutboundQueueConfig,
+    overflows: Arc<AtomicU64>,
+}
+
+impl OutboundQueue {
+    pub fn new(config: OutboundQueueConfig) -> Self {
+        Self {
+            config,
+            overflows: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Times a session's queue overflowed since startup, across sessions.
+    pub fn overflows(&self) -> u64 {
+        self.overflows.load(Ordering::Relaxed)
     }
 }
 
 // New WebSocket Session Actor
 pub struct WebSocketSessionActor {
-    session: actix_ws::Session,
+    sink: Rc<dyn SessionSink>,
     session_id: Uuid,
     user_id: String,
+    retries: SendRetries,
+    queue: OutboundQueue,
+    /// Frames waiting for the one in flight to be sent.
+    pending: VecDeque<(FrameKind, OutboundFrame)>,
+    sending: bool,
+    /// Close requested while frames were still queued.
+    closing: Option<SessionCloseReason>,
 }
 
 impl WebSocketSessionActor {
     pub fn new(session: actix_ws::Session, session_id: Uuid, user_id: String) -> Self {
+        Self::with_sink(Rc::new(session), session_id, user_id)
+    }
+
+    pub fn with_sink(sink: Rc<dyn SessionSink>, session_id: Uuid, user_id: String) -> Self {
         Self {
-            session,
+            sink,
             session_id,
             user_id,
+            retries: SendRetries::default(),
+            queue: OutboundQueue::default(),
+            pending: VecDeque::new(),
+            sending: false,
+            closing: None,
         }
     }
+
+    /// Retries transient send failures as configured, counting into `retries`.
+    pub fn with_send_retries(mut self, retries: SendRetries) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Bounds the frames queued for a slow client, counting into `queue`.
+    pub fn with_outbound_queue(mut self, queue: OutboundQueue) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    /// Queues a frame, applying the overflow policy when the queue is full.
+    fn deliver(&mut self, kind: FrameKind, frame: OutboundFrame, ctx: &mut Context<Self>) {
+        if self.pending.len() >= self.queue.config.capacity {
+            self.queue.overflows.fetch_add(1, Ordering::Relaxed);
+            let oldest = |wanted: fn(FrameKind) -> bool| {
+                self.pending.iter().position(|(kind, _)| wanted(*kind))
+            };
+            let droppable = |kind: FrameKind| kind != FrameKind::Audio;
+            let victim = match self.queue.config.overflow {
+                OverflowPolicy::DropOldestAnimation => {
+                    oldest(|kind| kind == FrameKind::Animation).or_else(|| oldest(droppable))
+                }
+                OverflowPolicy::DropOldestAny => oldest(droppable),
+                OverflowPolicy::Disconnect => {
+                    warn!(
+                        "Outbound queue of session {} is full, disconnecting",
+                        self.session_id
+                    );
+                    self.pending.clear();
+                    self.close_with(
+                        SessionCloseReason::Capacity("outbound queue full".to_string()),
+                        ctx,
+                    );
+                    return;
+                }
+            };
+            match victim {
+                Some(index) => {
+                    self.pending.remove(index);
+                    debug!(
+                        "Outbound queue of session {} is full, dropped its oldest frame",
+                        self.session_id
+                    );
+                }
+                // Only audio is queued: more audio goes past capacity, anything
+                // else is dropped
+                None if kind == FrameKind::Audio => debug!(
+                    "Outbound queue of session {} is full of audio, queueing past capacity",
+                    self.session_id
+                ),
+                None => {
+                    debug!(
+                        "Outbound queue of session {} is full of audio, dropped a new frame",
+                        self.session_id
+                    );
+                    return;
+                }
+            }
+        }
+        self.pending.push_back((kind, frame));
+        self.send_next(ctx);
+    }
+
+    /// Sends the oldest queued frame unless one is in flight, one at a time so
+    /// frames reach the client in order. Stops the actor once the socket turns
+    /// out closed.
+    fn send_next(&mut self, ctx: &mut Context<Self>) {
+        if self.sending {
+            return;
+        }
+        let Some((_, frame)) = self.pending.pop_front() else {
+            if let Some(reason) = self.closing.take() {
+                self.close_with(reason, ctx);
+            }
+            return;
+        };
+        self.sending = true;
+        let session_id = self.session_id;
+        let fut = send_with_retry(self.sink.clone(), frame, self.retries.clone(), session_id);
+
+        // Spawned rather than waited on, so the mailbox keeps draining into
+        // the bounded queue while the client is slow
+        ctx.spawn(
+            fut.into_actor(self)
+                .map(move |result, act, ctx| match result {
+                    Ok(()) => {
+                        act.sending = false;
+                        act.send_next(ctx);
+                    }
+                    Err(SendError::Closed) => {
+                        warn!("Session {} is closed, stopping its actor", session_id);
+                        ctx.stop();
+                    }
+                    Err(e) => {
+                        warn!("Failed to send to session {}: {}", session_id, e);
+                        act.sending = false;
+                        act.send_next(ctx);
+                    }
+                }),
+        );
+    }
+
+    /// Closes the socket with the reason's close code, then stops the actor.
+    fn close_with(&self, reason: SessionCloseReason, ctx: &mut Context<Self>) {
+        let session_id = self.session_id;
+
+        info!("Closing session {}: {:?}", session_id, reason);
+        let close = self.sink.close(reason.to_close_reason());
+        let fut = async move {
+            if let Err(e) = close.await {
+                warn!("Failed to close session {}: {}", session_id, e);
+            }
+        };
+        ctx.wait(fut.into_actor(self).map(|_, _, ctx| ctx.stop()));
+    }
 }
 
 impl Actor for WebSocketSessionActor {
@@ -65,40 +1303,136 @@ impl Actor for WebSocketSessionActor {
     fn started(&mut self, _ctx: &mut Self::Context) {
         info!(
             "WebSocket session actor started for user: {} session: {}",
-            self.user_id, self.session_id
+            redact::user(&self.user_id),
+            self.session_id
         );
     }
 }
 
-#[derive(Message)]
+#[derive(Message, Clone)]
 #[rtype(result = "()")]
 pub struct SendMessage {
     pub message: String,
+    pub kind: FrameKind,
 }
 
 impl Handler<SendMessage> for WebSocketSessionActor {
     type Result = ();
 
     fn handle(&mut self, msg: SendMessage, ctx: &mut Context<Self>) -> Self::Result {
-        let mut session = self.session.clone();
-        let message = msg.message;
-        let session_id = self.session_id;
+        self.deliver(msg.kind, OutboundFrame::Text(msg.message), ctx);
+    }
+}
 
-        // Use spawn to handle the async operation
-        let fut = async move {
-            if let Err(e) = session.text(message).await {
-                warn!("Failed to send message to session {}: {}", session_id, e);
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendBinary {
+    pub data: Vec<u8>,
+}
+
+impl Handler<SendBinary> for WebSocketSessionActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendBinary, ctx: &mut Context<Self>) -> Self::Result {
+        self.deliver(FrameKind::Audio, OutboundFrame::Binary(msg.data), ctx);
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseSession {
+    pub reason: SessionCloseReason,
+}
+
+/// Sends a frame to a connected session only, never to room overlays or a
+/// detached session's buffer.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendToSession {
+    pub session_id: Uuid,
+    pub frame: serde_json::Value,
+}
+
+/// Closes every session of a user, e.g. once they are banned.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseUserSessions {
+    pub user_id: String,
+    pub reason: String,
+}
+
+impl Handler<CloseSession> for WebSocketSessionActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseSession, ctx: &mut Context<Self>) -> Self::Result {
+        // Frames sent before the close still go out first
+        if self.sending || !self.pending.is_empty() {
+            self.closing = Some(msg.reason);
+        } else {
+            self.close_with(msg.reason, ctx);
+        }
+    }
+}
+
+impl Handler<SendToSession> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendToSession, _ctx: &mut Context<Self>) -> Self::Result {
+        if !self.connections.contains_key(&msg.session_id) {
+            debug!(
+                "Session {} is not connected, dropping frame",
+                msg.session_id
+            );
+            return;
+        }
+        let label = msg.frame["type"].as_str().unwrap_or("frame").to_string();
+        self.send_frame(&msg.session_id, &label, msg.frame);
+    }
+}
+
+impl Handler<CloseUserSessions> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseUserSessions, _ctx: &mut Context<Self>) -> Self::Result {
+        let sessions: Vec<Uuid> = self
+            .connections
+            .iter()
+            .filter(|(_, (user_id, _))| *user_id == msg.user_id)
+            .map(|(session_id, _)| *session_id)
+            .collect();
+        for session_id in sessions {
+            info!("Closing session {}: {}", session_id, msg.reason);
+            if let Some(session_actor) = self.remove_connection(&session_id) {
+                session_actor.do_send(CloseSession {
+                    reason: SessionCloseReason::PolicyViolation(msg.reason.clone()),
+                });
             }
-        };
-        ctx.spawn(fut.into_actor(self));
+            self.publish_disconnect(session_id, msg.user_id.clone());
+        }
     }
 }
 
 impl Actor for WebSocketManager {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("WebSocketManager started");
+
+        if self.auth.enabled() {
+            ctx.run_interval(self.auth.check_interval(), |act, _ctx| {
+                act.check_session_tokens();
+            });
+        }
+        if self.load.config().enabled {
+            ctx.run_interval(self.load.config().interval(), |act, _ctx| {
+                act.check_load();
+            });
+        }
+        if self.detached.config().enabled() {
+            ctx.run_interval(self.detached.config().sweep_interval(), |act, _ctx| {
+                act.sweep_detached();
+            });
+        }
     }
 }
 
@@ -114,7 +1448,35 @@ impl Handler<RegisterConnection> for WebSocketManager {
     type Result = ();
 
     fn handle(&mut self, msg: RegisterConnection, _ctx: &mut Context<Self>) -> Self::Result {
-        self.add_connection(msg.session_id, msg.user_id, msg.session_actor);
+        let user_id = msg.user_id.clone();
+        let merged = self
+            .add_connection(msg.session_id, msg.user_id, msg.session_actor)
+            .unwrap_or_default();
+        for old_session in merged {
+            self.publish_disconnect(old_session, user_id.clone());
+        }
+    }
+}
+
+/// Closes every open session, e.g. before the server shuts down.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseAllSessions {
+    pub reason: SessionCloseReason,
+}
+
+impl Handler<CloseAllSessions> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseAllSessions, _ctx: &mut Context<Self>) -> Self::Result {
+        info!("Closing {} WebSocket sessions", self.connections.len());
+        for session_id in self.connections.keys().copied().collect::<Vec<_>>() {
+            if let Some(session_actor) = self.remove_connection(&session_id) {
+                session_actor.do_send(CloseSession {
+                    reason: msg.reason.clone(),
+                });
+            }
+        }
     }
 }
 
@@ -132,38 +1494,281 @@ impl Handler<UnregisterConnection> for WebSocketManager {
     }
 }
 
+fn llm_response_frame(event: &LLMResponseEvent) -> serde_json::Value {
+    let mut frame = serde_json::json!({
+        "type": "llm_response",
+        "data": {
+            "response": event.response,
+            "model": event.model,
+            "timestamp": event.metadata.timestamp
+        }
+    });
+    // Translations say which language they are in and which response they belong to
+    if let Some(language) = &event.language {
+        frame["data"]["language"] = serde_json::json!(language);
+        frame["data"]["translation_of"] = serde_json::json!(event.translation_of);
+    }
+    if let Some(replying_to) = &event.replying_to {
+        frame["data"]["replying_to"] = serde_json::json!(replying_to);
+    }
+    if let Some(segments) = &event.segments {
+        frame["data"]["segments"] = serde_json::json!(segments);
+    }
+    frame
+}
+
+fn llm_token_frame(event: &LLMTokenEvent) -> serde_json::Value {
+    let mut frame = serde_json::json!({
+        "type": "llm_token",
+        "data": {
+            "delta": event.delta,
+            "response_id": event.response_id,
+            "finished": event.finished,
+            "timestamp": event.metadata.timestamp
+        }
+    });
+    if let Some(error) = &event.error {
+        frame["data"]["error"] = serde_json::json!(error);
+    }
+    frame
+}
+
+fn debug_prompt_frame(event: &LLMPromptEvent) -> serde_json::Value {
+    serde_json::json!({
+        "type": "debug_prompt",
+        "data": {
+            "messages": event.messages,
+            "response_id": event.response_id,
+            "timestamp": event.metadata.timestamp
+        }
+    })
+}
+
+fn tts_response_frame(event: &TTSResponseEvent) -> serde_json::Value {
+    serde_json::json!({
+        "type": "tts_response",
+        "data": {
+            "text": event.text,
+            "voice": event.voice,
+            "style": event.style,
+            "voice_fallback": event.voice_fallback,
+            "response_id": event.response_id,
+            "audio_data_length": event.audio_data.len(),
+            "timestamp": event.metadata.timestamp
+        }
+    })
+}
+
+/// Bytes before the audio payload in an audio chunk frame.
+pub const AUDIO_CHUNK_HEADER_BYTES: usize = 21;
+
+/// Binary frame for one audio chunk: the 16-byte `response_id`, `seq` as a
+/// big-endian u32, a flags byte (bit 0 = `is_last`), then the audio.
+pub fn audio_chunk_frame(event: &TTSChunkEvent) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(AUDIO_CHUNK_HEADER_BYTES + event.audio.len());
+    frame.extend_from_slice(event.response_id.as_bytes());
+    frame.extend_from_slice(&event.seq.to_be_bytes());
+    frame.push(event.is_last as u8);
+    frame.extend_from_slice(&event.audio);
+    frame
+}
+
+fn animation_frame(event: &AnimationEvent) -> serde_json::Value {
+    serde_json::json!({
+        "type": "animation",
+        "data": {
+            "animation_type": event.animation_type,
+            "duration": event.duration,
+            "parameters": event.parameters,
+            "timestamp": event.metadata.timestamp
+        }
+    })
+}
+
+fn stats_frame(metrics: &SessionMetrics, rate_limit_remaining: Option<u32>) -> serde_json::Value {
+    serde_json::json!({
+        "type": "stats",
+        "data": {
+            "message_count": metrics.messages,
+            "response_count": metrics.responses,
+            "avg_latency_ms": metrics.average_latency_ms(),
+            "rate_limit_remaining": rate_limit_remaining,
+            "timestamp": chrono::Utc::now()
+        }
+    })
+}
+
+fn load_frame(signal: &LoadSignal) -> serde_json::Value {
+    serde_json::json!({
+        "type": "load",
+        "data": {
+            "level": signal.level,
+            "eta_ms": signal.eta_ms
+        }
+    })
+}
+
+fn session_frame(session_id: Uuid, resume_token: Uuid, config: &ResumeConfig) -> serde_json::Value {
+    serde_json::json!({
+        "type": "session",
+        "data": {
+            "session_id": session_id,
+            "resume_token": resume_token,
+            "resume_window_seconds": config.window_seconds
+        }
+    })
+}
+
+fn auth_refresh_frame(expires_at: chrono::DateTime<chrono::Utc>) -> serde_json::Value {
+    serde_json::json!({
+        "type": "auth_refresh",
+        "data": {
+            "expires_at": expires_at
+        }
+    })
+}
+
+fn channels_frame(channels: &Channels) -> serde_json::Value {
+    serde_json::json!({
+        "type": "channels",
+        "data": {
+            "channels": channels.list(),
+        }
+    })
+}
+
+/// Answers a client's declared capabilities with the audio codec it will
+/// get, or a notice that it gets text only.
+fn capabilities_frame(codec: Option<AudioCodec>) -> serde_json::Value {
+    let notice = codec
+        .is_none()
+        .then_some("No supported audio codec; responses are sent as text only");
+    serde_json::json!({
+        "type": "capabilities",
+        "data": {
+            "audio_codec": codec,
+            "notice": notice,
+        }
+    })
+}
+
+fn retract_frame(event: &ResponseRetractedEvent) -> serde_json::Value {
+    serde_json::json!({
+        "type": "retract",
+        "data": {
+            "response_id": event.response_id,
+            "reason": event.reason,
+            "timestamp": event.metadata.timestamp
+        }
+    })
+}
+
+/// Tells a client its message was dropped; `code` is machine-readable.
+pub fn error_frame(code: &str, message: &str) -> String {
+    let mut frame = error_data_frame(code, message);
+    frame["schema_version"] = EVENT_SCHEMA_VERSION.into();
+    frame.to_string()
+}
+
+fn error_data_frame(code: &str, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "error",
+        "data": {
+            "code": code,
+            "message": message
+        }
+    })
+}
+
+/// Tells overlays a room's stream went live (`started`) or ended (`ended`).
+fn stream_frame(room_id: &str, state: &str, metadata: &EventMetadata) -> serde_json::Value {
+    serde_json::json!({
+        "type": "stream",
+        "data": {
+            "room_id": room_id,
+            "state": state,
+            "timestamp": metadata.timestamp
+        }
+    })
+}
+
+/// Frames for one turn in delivery order: text, animation, emotion, audio.
+/// Every frame carries the bundle's `response_id`.
+pub fn bundle_frames(bundle: &ResponseBundle) -> Vec<serde_json::Value> {
+    let mut frames = vec![llm_response_frame(&bundle.text)];
+    frames.extend(bundle.animation.iter().map(animation_frame));
+    frames.extend(bundle.emotion.iter().map(animation_frame));
+    frames.extend(bundle.audio.iter().map(tts_response_frame));
+
+    for frame in &mut frames {
+        frame["data"]["response_id"] = serde_json::json!(bundle.response_id);
+    }
+    frames
+}
+
 impl Handler<LLMResponseEvent> for WebSocketManager {
     type Result = ();
 
     fn handle(&mut self, event: LLMResponseEvent, _ctx: &mut Context<Self>) -> Self::Result {
         let session_id = event.metadata.session_id.unwrap_or_default();
+        self.send_frame(&session_id, "LLM response", llm_response_frame(&event));
+    }
+}
 
-        if let Some((user_id, session_actor)) = self.connections.get(&session_id) {
-            let message = serde_json::json!({
-                "type": "llm_response",
-                "data": {
-                    "response": event.response,
-                    "model": event.model,
-                    "timestamp": event.metadata.timestamp
-                }
-            });
+impl Handler<LLMTokenEvent> for WebSocketManager {
+    type Result = ();
 
-            let message_str = message.to_string();
-            info!(
-                "Sending LLM response to session {} (user {}): {}",
-                session_id, user_id, message_str
-            );
+    fn handle(&mut self, event: LLMTokenEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let session_id = event.metadata.session_id.unwrap_or_default();
+        self.send_frame(&session_id, "LLM token", llm_token_frame(&event));
+    }
+}
 
-            // Send the message through WebSocket session actor
-            session_actor.do_send(SendMessage {
-                message: message_str,
-            });
-        } else {
-            warn!("No active connection found for session {}", session_id);
+impl Handler<LLMPromptEvent> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, event: LLMPromptEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let session_id = event.metadata.session_id.unwrap_or_default();
+        self.send_frame(&session_id, "debug prompt", debug_prompt_frame(&event));
+    }
+}
+
+impl Handler<ResponseBundle> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, bundle: ResponseBundle, _ctx: &mut Context<Self>) -> Self::Result {
+        let session_id = bundle.metadata.session_id.unwrap_or_default();
+        if let Some(metrics) = self.metrics.get_mut(&session_id) {
+            metrics.record_response(Instant::now());
+        }
+        for frame in bundle_frames(&bundle) {
+            self.send_frame(&session_id, "response bundle frame", frame);
         }
     }
 }
 
+/// How a client's text message is read.
+#[derive(Debug)]
+enum ClientText {
+    Json(serde_json::Value),
+    /// Meant as JSON, judging by its opening bracket, but not valid.
+    Malformed(serde_json::Error),
+    Plain,
+}
+
+/// Text opening with `{` or `[` is read as JSON; anything else is a plain
+/// question. Without `strict`, malformed JSON is a plain question too.
+fn classify(text: &str, strict: bool) -> ClientText {
+    if !text.trim_start().starts_with(['{', '[']) {
+        return ClientText::Plain;
+    }
+    match serde_json::from_str(text) {
+        Ok(json) => ClientText::Json(json),
+        Err(e) if strict => ClientText::Malformed(e),
+        Err(_) => ClientText::Plain,
+    }
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct HandleTextMessage {
@@ -175,11 +1780,19 @@ pub struct HandleTextMessage {
 impl Handler<HandleTextMessage> for WebSocketManager {
     type Result = ();
 
-    fn handle(&mut self, msg: HandleTextMessage, _ctx: &mut Context<Self>) -> Self::Result {
-        info!("Received text message from {}: {}", msg.user_id, msg.text);
+    fn handle(&mut self, msg: HandleTextMessage, ctx: &mut Context<Self>) -> Self::Result {
+        info!(
+            "Received text message from {}: {}",
+            redact::user(&msg.user_id),
+            redact::text(&msg.text)
+        );
 
-        // Try to parse as JSON for structured messages
-        if let Ok(json_msg) = serde_json::from_str::<serde_json::Value>(&msg.text) {
+        let text = classify(&msg.text, self.strict_json);
+        if let ClientText::Malformed(e) = &text {
+            warn!("Malformed JSON from session {}: {}", msg.session_id, e);
+            let frame = error_data_frame("invalid_json", &e.to_string());
+            self.send_frame(&msg.session_id, "error", frame);
+        } else if let ClientText::Json(json_msg) = text {
             if let Some(msg_type) = json_msg.get("type").and_then(|t| t.as_str()) {
                 match msg_type {
                     "text_input" => {
@@ -195,8 +1808,97 @@ impl Handler<HandleTextMessage> for WebSocketManager {
                                     .get("language")
                                     .and_then(|l| l.as_str())
                                     .map(|s| s.to_string()),
+                                username: None,
+                                room_mood: None,
+                                priority: MessagePriority::Normal,
+                                intent: None,
+                                viewer: None,
+                                max_age_seconds: None,
+                                operator: false,
                             };
-                            self.event_bus.do_send(event);
+                            self.publish_text_input(event);
+                        }
+                    }
+                    "subscribe_room" => match json_msg.get("room_id").and_then(|r| r.as_str()) {
+                        Some(room_id) => {
+                            info!(
+                                "Session {} subscribed to room {} as an overlay",
+                                msg.session_id, room_id
+                            );
+                            self.overlays.subscribe(room_id, msg.session_id);
+                        }
+                        None => {
+                            warn!(
+                                "subscribe_room from session {} has no room_id",
+                                msg.session_id
+                            )
+                        }
+                    },
+                    "set_channels" => {
+                        let requested =
+                            json_msg
+                                .get("channels")
+                                .and_then(|c| c.as_array())
+                                .map(|channels| {
+                                    channels
+                                        .iter()
+                                        .map(|c| c.as_str().unwrap_or_default().parse())
+                                        .collect::<Result<Vec<Channel>, _>>()
+                                });
+                        match requested {
+                            Some(Ok(channels)) => {
+                                let channels = Channels::only(&channels);
+                                info!("Session {} receives {:?}", msg.session_id, channels.list());
+                                self.channels.insert(msg.session_id, channels);
+                                self.send_frame(
+                                    &msg.session_id,
+                                    "channels",
+                                    channels_frame(&channels),
+                                );
+                            }
+                            Some(Err(e)) => {
+                                warn!(
+                                    "Ignoring set_channels from session {}: {}",
+                                    msg.session_id, e
+                                )
+                            }
+                            None => {
+                                warn!(
+                                    "set_channels from session {} has no channels",
+                                    msg.session_id
+                                )
+                            }
+                        }
+                    }
+                    "capabilities" => {
+                        // Only a declared codec list is negotiated; other
+                        // capabilities leave the audio as it was
+                        match json_msg.get("audio_codecs").and_then(|c| c.as_array()) {
+                            Some(codecs) => {
+                                // Codecs we do not know are ones we cannot send anyway
+                                let supported: Vec<AudioCodec> = codecs
+                                    .iter()
+                                    .filter_map(|c| c.as_str()?.parse().ok())
+                                    .collect();
+                                self.negotiate_codec(msg.session_id, &supported);
+                            }
+                            None => debug!(
+                                "capabilities from session {} declare no audio codecs",
+                                msg.session_id
+                            ),
+                        }
+                    }
+                    "get_stats" if self.client_stats => {
+                        self.send_session_stats(msg.session_id, msg.user_id, ctx);
+                    }
+                    "auth_refresh" if self.auth.enabled() => {
+                        match json_msg.get("token").and_then(|t| t.as_str()) {
+                            Some(token) => {
+                                self.refresh_session_token(msg.session_id, &msg.user_id, token)
+                            }
+                            None => {
+                                warn!("auth_refresh from session {} has no token", msg.session_id)
+                            }
                         }
                     }
                     _ => {
@@ -214,9 +1916,63 @@ impl Handler<HandleTextMessage> for WebSocketManager {
                 },
                 text: msg.text.to_string(),
                 language: None,
+                username: None,
+                room_mood: None,
+                priority: MessagePriority::Normal,
+                intent: None,
+                viewer: None,
+                max_age_seconds: None,
+                operator: false,
             };
-            self.event_bus.do_send(event);
+            self.publish_text_input(event);
+        }
+    }
+}
+
+/// Tells the manager which room a danmaku session belongs to, so its
+/// responses can reach the room's overlays.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RouteDanmaku {
+    pub session_id: Uuid,
+    pub room_id: String,
+}
+
+impl Handler<RouteDanmaku> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: RouteDanmaku, _ctx: &mut Context<Self>) -> Self::Result {
+        if self.danmaku_delivery == DanmakuDelivery::Room {
+            self.overlays
+                .route(msg.session_id, msg.room_id, Instant::now());
+        }
+    }
+}
+
+/// Claims a detached session for a reconnecting client.
+#[derive(Message)]
+#[rtype(result = "Option<ResumedSession>")]
+pub struct ResumeSession {
+    pub resume_token: Uuid,
+    pub user_id: String,
+}
+
+impl Handler<ResumeSession> for WebSocketManager {
+    type Result = Option<ResumedSession>;
+
+    fn handle(&mut self, msg: ResumeSession, _ctx: &mut Context<Self>) -> Self::Result {
+        let resumed = self
+            .detached
+            .resume(&msg.resume_token, &msg.user_id, chrono::Utc::now());
+        if let Some(ref resumed) = resumed {
+            info!(
+                "Resuming session {} for user {} with {} buffered frames",
+                resumed.session_id,
+                redact::user(&msg.user_id),
+                resumed.frames.len()
+            );
         }
+        resumed
     }
 }
 
@@ -226,6 +1982,12 @@ pub struct HandleUserConnect {
     pub session_id: Uuid,
     pub user_id: String,
     pub session_actor: Addr<WebSocketSessionActor>,
+    /// Frames to replay when this connection resumes an earlier session.
+    pub replay: Option<Vec<String>>,
+    /// Expiry of the token the session authenticated with, if any.
+    pub token_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Whether that token carries the admin claim.
+    pub operator: bool,
 }
 
 impl Handler<HandleUserConnect> for WebSocketManager {
@@ -234,11 +1996,43 @@ impl Handler<HandleUserConnect> for WebSocketManager {
     fn handle(&mut self, msg: HandleUserConnect, _ctx: &mut Context<Self>) -> Self::Result {
         info!(
             "WebSocket connection started for user: {} session: {}",
-            msg.user_id, msg.session_id
+            redact::user(&msg.user_id),
+            msg.session_id
         );
 
         // Register this connection
-        self.add_connection(msg.session_id, msg.user_id.clone(), msg.session_actor);
+        let Some(merged) = self.add_connection(
+            msg.session_id,
+            msg.user_id.clone(),
+            msg.session_actor.clone(),
+        ) else {
+            // The resumed session was already claimed; end it for good
+            if msg.replay.is_some() {
+                self.publish_disconnect(msg.session_id, msg.user_id);
+            }
+            return;
+        };
+        if let Some(expires_at) = msg.token_expires_at {
+            self.session_tokens.track(msg.session_id, expires_at);
+        }
+        if msg.operator {
+            self.operators.insert(msg.session_id);
+        }
+        self.issue_resume_token(msg.session_id);
+
+        // A resumed session continues the existing conversation
+        if let Some(frames) = msg.replay {
+            for old_session in merged {
+                self.publish_disconnect(old_session, msg.user_id.clone());
+            }
+            for message in frames {
+                msg.session_actor.do_send(SendMessage {
+                    message,
+                    kind: FrameKind::Message,
+                });
+            }
+            return;
+        }
 
         // Publish user connected event
         let event = UserConnectedEvent {
@@ -249,6 +2043,7 @@ impl Handler<HandleUserConnect> for WebSocketManager {
             },
             session_id: msg.session_id,
             user_id: msg.user_id,
+            merged_sessions: merged,
         };
 
         self.event_bus.do_send(event);
@@ -260,6 +2055,7 @@ impl Handler<HandleUserConnect> for WebSocketManager {
 pub struct HandleUserDisconnect {
     pub session_id: Uuid,
     pub user_id: String,
+    pub session_actor: Addr<WebSocketSessionActor>,
 }
 
 impl Handler<HandleUserDisconnect> for WebSocketManager {
@@ -268,24 +2064,30 @@ impl Handler<HandleUserDisconnect> for WebSocketManager {
     fn handle(&mut self, msg: HandleUserDisconnect, _ctx: &mut Context<Self>) -> Self::Result {
         info!(
             "WebSocket connection ended for user: {} session: {}",
-            msg.user_id, msg.session_id
+            redact::user(&msg.user_id),
+            msg.session_id
         );
 
-        // Unregister this connection
-        self.remove_connection(&msg.session_id);
-
-        // Publish user disconnected event
-        let event = UserDisconnectedEvent {
-            metadata: EventMetadata {
-                session_id: Some(msg.session_id),
-                user_id: Some(msg.user_id.clone()),
-                ..Default::default()
-            },
-            session_id: msg.session_id,
-            user_id: msg.user_id,
-        };
+        // A socket replaced under the same session id must not unregister
+        // its successor
+        let owns_session = self
+            .connections
+            .get(&msg.session_id)
+            .is_some_and(|(_, addr)| *addr == msg.session_actor);
+        if !owns_session {
+            return;
+        }
 
-        self.event_bus.do_send(event);
+        // Unregister this connection; rejected or evicted sessions were never
+        // (or are no longer) registered and need no disconnect event
+        let resume_token = self.resume_tokens.get(&msg.session_id).copied();
+        if self.remove_connection(&msg.session_id).is_none() {
+            return;
+        }
+        match resume_token {
+            Some(token) => self.detach_session(msg.session_id, msg.user_id, token),
+            None => self.publish_disconnect(msg.session_id, msg.user_id),
+        }
     }
 }
 
@@ -294,31 +2096,16 @@ impl Handler<TTSResponseEvent> for WebSocketManager {
 
     fn handle(&mut self, event: TTSResponseEvent, _ctx: &mut Context<Self>) -> Self::Result {
         let session_id = event.metadata.session_id.unwrap_or_default();
+        self.send_frame(&session_id, "TTS response", tts_response_frame(&event));
+    }
+}
 
-        if let Some((user_id, session_actor)) = self.connections.get(&session_id) {
-            let message = serde_json::json!({
-                "type": "tts_response",
-                "data": {
-                    "text": event.text,
-                    "voice": event.voice,
-                    "audio_data_length": event.audio_data.len(),
-                    "timestamp": event.metadata.timestamp
-                }
-            });
-
-            let message_str = message.to_string();
-            info!(
-                "Sending TTS response to session {} (user {}): {}",
-                session_id, user_id, message_str
-            );
+impl Handler<TTSChunkEvent> for WebSocketManager {
+    type Result = ();
 
-            // Send the message through WebSocket session actor
-            session_actor.do_send(SendMessage {
-                message: message_str,
-            });
-        } else {
-            warn!("No active connection found for session {}", session_id);
-        }
+    fn handle(&mut self, event: TTSChunkEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let session_id = event.metadata.session_id.unwrap_or_default();
+        self.send_audio_chunk(&session_id, &event);
     }
 }
 
@@ -327,30 +2114,917 @@ impl Handler<AnimationEvent> for WebSocketManager {
 
     fn handle(&mut self, event: AnimationEvent, _ctx: &mut Context<Self>) -> Self::Result {
         let session_id = event.metadata.session_id.unwrap_or_default();
+        self.send_frame(&session_id, "animation event", animation_frame(&event));
+    }
+}
+
+impl Handler<ResponseRetractedEvent> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, event: ResponseRetractedEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let session_id = event.metadata.session_id.unwrap_or_default();
+        self.send_frame(&session_id, "retraction", retract_frame(&event));
+    }
+}
+
+impl Handler<StreamStartedEvent> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, event: StreamStartedEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let session_id = event.metadata.session_id.unwrap_or_default();
+        let frame = stream_frame(&event.room_id, "started", &event.metadata);
+        self.send_frame(&session_id, "stream start", frame);
+    }
+}
+
+impl Handler<StreamEndedEvent> for WebSocketManager {
+    type Result = ();
+
+    fn handle(&mut self, event: StreamEndedEvent, _ctx: &mut Context<Self>) -> Self::Result {
+        let session_id = event.metadata.session_id.unwrap_or_default();
+        let frame = stream_frame(&event.room_id, "ended", &event.metadata);
+        self.send_frame(&session_id, "stream end", frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Collect};
+
+    fn animation(animation_type: &str) -> AnimationEvent {
+        AnimationEvent {
+            metadata: EventMetadata::default(),
+            animation_type: animation_type.to_string(),
+            duration: Some(2.0),
+            parameters: serde_json::json!({}),
+        }
+    }
+
+    #[test]
+    fn test_sessions_beyond_cap_are_rejected() {
+        let mut sessions = UserSessions::new(SessionLimitConfig {
+            max_sessions_per_user: 2,
+            overflow_policy: SessionOverflowPolicy::Reject,
+            ..Default::default()
+        });
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+
+        assert_eq!(sessions.admit("alice", ids[0]), Admission::Accepted);
+        assert_eq!(sessions.admit("alice", ids[1]), Admission::Accepted);
+        assert_eq!(sessions.admit("alice", ids[2]), Admission::Rejected);
+        assert_eq!(sessions.count("alice"), 2);
+        assert_eq!(sessions.admit("bob", ids[2]), Admission::Accepted);
+
+        sessions.release("alice", &ids[0]);
+        assert_eq!(sessions.admit("alice", ids[2]), Admission::Accepted);
+    }
+
+    #[test]
+    fn test_sessions_beyond_cap_evict_oldest() {
+        let mut sessions = UserSessions::new(SessionLimitConfig {
+            max_sessions_per_user: 2,
+            overflow_policy: SessionOverflowPolicy::EvictOldest,
+            ..Default::default()
+        });
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+
+        sessions.admit("alice", ids[0]);
+        sessions.admit("alice", ids[1]);
+        assert_eq!(sessions.admit("alice", ids[2]), Admission::Evicted(ids[0]));
+        assert_eq!(sessions.count("alice"), 2);
+    }
+
+    #[test]
+    fn test_reconnect_replaces_earlier_session() {
+        let mut sessions = UserSessions::new(SessionLimitConfig {
+            reconnect_policy: ReconnectPolicy::Merge,
+            ..Default::default()
+        });
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+
+        assert_eq!(sessions.admit("alice", ids[0]), Admission::Accepted);
+        assert_eq!(
+            sessions.admit("alice", ids[1]),
+            Admission::Replaced(vec![ids[0]])
+        );
+        assert_eq!(sessions.count("alice"), 1);
+
+        // Registering the same session again does not take a second slot
+        assert_eq!(sessions.admit("alice", ids[1]), Admission::Accepted);
+        assert_eq!(sessions.count("alice"), 1);
+        assert_eq!(sessions.admit("bob", ids[2]), Admission::Accepted);
+        assert_eq!(
+            "replace".parse::<ReconnectPolicy>(),
+            Ok(ReconnectPolicy::Replace)
+        );
+    }
+
+    #[test]
+    fn test_close_codes_match_reason() {
+        let code = |reason: &SessionCloseReason| u16::from(reason.to_close_reason().code);
+
+        let rejected = Admission::Rejected.close_reason().unwrap();
+        assert_eq!(code(&rejected), 1013);
+        assert_eq!(
+            rejected.to_close_reason().description.as_deref(),
+            Some("too many sessions")
+        );
+
+        let evicted = Admission::Evicted(Uuid::new_v4()).close_reason().unwrap();
+        assert_eq!(code(&evicted), 1008);
+        assert_eq!(code(&SessionCloseReason::Shutdown), 1001);
+        assert_eq!(
+            code(&SessionCloseReason::MessageTooBig("too big".to_string())),
+            1009
+        );
+        assert_eq!(Admission::Accepted.close_reason(), None);
+    }
+
+    #[test]
+    fn test_fragmented_text_is_reassembled() {
+        use actix_web::web::Bytes;
+        use actix_ws::Item;
+
+        let mut assembler = MessageAssembler::new(&MessageLimits {
+            max_message_bytes: 64,
+            ..Default::default()
+        });
+        let text = r#"{"type":"text_input","content":"你好"}"#;
+        let bytes = text.as_bytes();
+        // The last split falls inside a multi-byte character
+        let (first, rest) = bytes.split_at(10);
+        let (middle, last) = rest.split_at(rest.len() - 4);
+
+        let chunk = Bytes::copy_from_slice;
+        assert_eq!(assembler.push(Item::FirstText(chunk(first))), Ok(None));
+        assert_eq!(assembler.push(Item::Continue(chunk(middle))), Ok(None));
+        assert_eq!(
+            assembler.push(Item::Last(chunk(last))),
+            Ok(Some(AssembledMessage::Text(text.to_string())))
+        );
+
+        // Over the limit, or continuing nothing, closes the session
+        assembler
+            .push(Item::FirstBinary(Bytes::from(vec![0u8; 32])))
+            .unwrap();
+        assert!(matches!(
+            assembler.push(Item::Last(Bytes::from(vec![0u8; 64]))),
+            Err(SessionCloseReason::MessageTooBig(_))
+        ));
+        assert!(matches!(
+            assembler.push(Item::Continue(Bytes::from_static(b"x"))),
+            Err(SessionCloseReason::PolicyViolation(_))
+        ));
+    }
 
-        if let Some((user_id, session_actor)) = self.connections.get(&session_id) {
-            let message = serde_json::json!({
-                "type": "animation",
-                "data": {
-                    "animation_type": event.animation_type,
-                    "duration": event.duration,
-                    "parameters": event.parameters,
-                    "timestamp": event.metadata.timestamp
+    #[test]
+    fn test_oversized_audio_is_rejected_without_buffering() {
+        use actix_web::web::Bytes;
+        use actix_ws::Item;
+
+        let mut assembler = MessageAssembler::new(&MessageLimits {
+            max_message_bytes: 1024 * 1024,
+            max_audio_bytes: 1024,
+        });
+        assert!(assembler.check_audio(1024).is_ok());
+        assert_eq!(
+            assembler.check_audio(1025),
+            Err("audio exceeds 1024 bytes".to_string())
+        );
+
+        // 64 MiB of fragments against a 1 KiB limit: rejected once, then skipped
+        let chunk = Bytes::from(vec![0u8; 64 * 1024]);
+        assert_eq!(
+            assembler.push(Item::FirstBinary(chunk.clone())),
+            Ok(Some(AssembledMessage::AudioRejected(
+                "audio exceeds 1024 bytes".to_string()
+            )))
+        );
+        for _ in 0..1024 {
+            assert_eq!(assembler.push(Item::Continue(chunk.clone())), Ok(None));
+            assert_eq!(assembler.buffer.capacity(), 0);
+        }
+        assert_eq!(assembler.push(Item::Last(chunk)), Ok(None));
+
+        // The session carries on with the next message
+        assert_eq!(
+            assembler.push(Item::FirstBinary(Bytes::from_static(b"ab"))),
+            Ok(None)
+        );
+        assert_eq!(
+            assembler.push(Item::Last(Bytes::from_static(b"cd"))),
+            Ok(Some(AssembledMessage::Binary(b"abcd".to_vec())))
+        );
+    }
+
+    #[test]
+    fn test_bundle_frames_are_ordered_and_tagged() {
+        let bundle = ResponseBundle {
+            metadata: EventMetadata::default(),
+            response_id: Uuid::new_v4(),
+            text: LLMResponseEvent {
+                metadata: EventMetadata::default(),
+                response: "Hello!".to_string(),
+                model: "digital_human".to_string(),
+                tokens_used: None,
+                length_limit: None,
+                sampling: None,
+                language: None,
+                translation_of: None,
+                replying_to: None,
+                segments: None,
+            },
+            animation: Some(animation("wave")),
+            emotion: Some(animation("expression_excited")),
+            audio: Some(TTSResponseEvent {
+                metadata: EventMetadata::default(),
+                audio_data: vec![0; 4],
+                text: "Hello!".to_string(),
+                voice: "default".to_string(),
+                style: None,
+                voice_fallback: None,
+                response_id: None,
+            }),
+        };
+
+        let frames = bundle_frames(&bundle);
+        let types: Vec<&str> = frames.iter().map(|f| f["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            ["llm_response", "animation", "animation", "tts_response"]
+        );
+        assert_eq!(frames[1]["data"]["animation_type"], "wave");
+        assert_eq!(frames[2]["data"]["animation_type"], "expression_excited");
+
+        let response_id = serde_json::json!(bundle.response_id);
+        assert!(frames
+            .iter()
+            .all(|f| f["data"]["response_id"] == response_id));
+    }
+
+    #[test]
+    fn test_session_metrics_and_stats_frame() {
+        let mut metrics = SessionMetrics::default();
+        let start = Instant::now();
+
+        assert_eq!(metrics.average_latency_ms(), None);
+        metrics.record_input(start);
+        metrics.record_input(start + Duration::from_millis(50));
+        metrics.record_response(start + Duration::from_millis(200));
+        metrics.record_input(start + Duration::from_millis(300));
+        metrics.record_response(start + Duration::from_millis(400));
+        // Responses without a pending input are not timed
+        metrics.record_response(start + Duration::from_millis(500));
+
+        let frame = stats_frame(&metrics, Some(7));
+        assert_eq!(frame["type"], "stats");
+        assert_eq!(frame["data"]["message_count"], 3);
+        assert_eq!(frame["data"]["response_count"], 2);
+        assert_eq!(frame["data"]["avg_latency_ms"], 150);
+        assert_eq!(frame["data"]["rate_limit_remaining"], 7);
+    }
+
+    #[test]
+    fn test_session_backlog_produces_load_frame() {
+        let mut monitor = LoadMonitor::new(LoadConfig {
+            enabled: true,
+            session_pending_high: 3,
+            ..Default::default()
+        });
+        let mut metrics = SessionMetrics::default();
+        let session_id = Uuid::new_v4();
+        let start = Instant::now();
+
+        for i in 0..3 {
+            metrics.record_input(start + Duration::from_millis(i * 100));
+        }
+        let now = start + Duration::from_secs(1);
+        let sample = LoadSample {
+            session_pending: metrics.pending_depth(now),
+            global_queued: 0,
+            max_concurrent: 4,
+            avg_response_ms: metrics.average_latency_ms(),
+        };
+        let signal = monitor.evaluate(session_id, sample, now).unwrap();
+
+        let frame = load_frame(&signal);
+        assert_eq!(frame["type"], "load");
+        assert_eq!(frame["data"]["level"], "high");
+        assert_eq!(frame["data"]["eta_ms"], 2000);
+
+        // Unanswered inputs eventually stop counting
+        assert_eq!(metrics.pending_depth(start + PENDING_TIMEOUT), 2);
+    }
+
+    #[test]
+    fn test_retract_frame_targets_response_id() {
+        let response_id = Uuid::new_v4();
+        let frame = retract_frame(&ResponseRetractedEvent {
+            metadata: EventMetadata::default(),
+            response_id,
+            reason: Some("moderation".to_string()),
+        });
+
+        assert_eq!(frame["type"], "retract");
+        assert_eq!(frame["data"]["response_id"], serde_json::json!(response_id));
+        assert_eq!(frame["data"]["reason"], "moderation");
+    }
+
+    #[test]
+    fn test_audio_chunk_frame_header() {
+        let response_id = Uuid::new_v4();
+        let frame = audio_chunk_frame(&TTSChunkEvent {
+            metadata: EventMetadata::default(),
+            response_id,
+            seq: 258,
+            is_last: true,
+            audio: vec![7, 8, 9],
+            style: None,
+            voice: None,
+            voice_fallback: None,
+        });
+
+        assert_eq!(frame.len(), AUDIO_CHUNK_HEADER_BYTES + 3);
+        assert_eq!(&frame[..16], response_id.as_bytes());
+        assert_eq!(&frame[16..20], &[0, 0, 1, 2]);
+        assert_eq!(frame[20], 1);
+        assert_eq!(&frame[21..], &[7, 8, 9]);
+    }
+
+    /// Fails the first `failures` sends transiently, then delivers; fails
+    /// every send once `closed` is set.
+    struct FlakySink {
+        failures: std::sync::Mutex<u32>,
+        closed: bool,
+        sent: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl SessionSink for FlakySink {
+        fn send(&self, frame: OutboundFrame) -> LocalBoxFuture<'static, Result<(), SendError>> {
+            let mut failures = self.failures.lock().unwrap();
+            let result = if self.closed {
+                Err(SendError::Closed)
+            } else if *failures > 0 {
+                *failures -= 1;
+                Err(SendError::Transient("write buffer full".to_string()))
+            } else {
+                if let OutboundFrame::Text(text) = frame {
+                    self.sent.lock().unwrap().push(text);
                 }
-            });
+                Ok(())
+            };
+            Box::pin(async move { result })
+        }
 
-            let message_str = message.to_string();
-            info!(
-                "Sending animation event to session {} (user {}): {}",
-                session_id, user_id, message_str
-            );
+        fn close(
+            &self,
+            _reason: actix_ws::CloseReason,
+        ) -> LocalBoxFuture<'static, Result<(), SendError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
 
-            // Send the message through WebSocket session actor
-            session_actor.do_send(SendMessage {
-                message: message_str,
+    #[actix_web::test]
+    async fn test_transient_send_failures_are_retried() {
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let retries = SendRetries::new(SendRetryConfig {
+            max_retries: 2,
+            backoff_ms: 1,
+            ..Default::default()
+        });
+        let sink = FlakySink {
+            failures: std::sync::Mutex::new(2),
+            closed: false,
+            sent: sent.clone(),
+        };
+        let session = WebSocketSessionActor::with_sink(Rc::new(sink), Uuid::new_v4(), "u".into())
+            .with_send_retries(retries.clone())
+            .start();
+
+        session
+            .send(SendMessage {
+                message: "hello".to_string(),
+                kind: FrameKind::Message,
+            })
+            .await
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*sent.lock().unwrap(), ["hello"]);
+        assert_eq!(retries.retried(), 2);
+        assert!(session.connected());
+
+        // A closed socket is not retried and ends the session actor
+        let closed = FlakySink {
+            failures: std::sync::Mutex::new(0),
+            closed: true,
+            sent: sent.clone(),
+        };
+        let session = WebSocketSessionActor::with_sink(Rc::new(closed), Uuid::new_v4(), "u".into())
+            .with_send_retries(retries.clone())
+            .start();
+        session
+            .send(SendMessage {
+                message: "lost".to_string(),
+                kind: FrameKind::Message,
+            })
+            .await
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(50)).await;
+        assert_eq!(retries.retried(), 2);
+        assert!(!session.connected());
+    }
+
+    /// Never accepts the first `stalls` frames, like a client that stopped
+    /// reading for a while.
+    struct StalledSink {
+        stalls: std::sync::Mutex<u32>,
+        sent: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl SessionSink for StalledSink {
+        fn send(&self, frame: OutboundFrame) -> LocalBoxFuture<'static, Result<(), SendError>> {
+            let mut stalls = self.stalls.lock().unwrap();
+            if *stalls > 0 {
+                *stalls -= 1;
+                return Box::pin(futures_util::future::pending());
+            }
+            if let OutboundFrame::Text(text) = frame {
+                self.sent.lock().unwrap().push(text);
+            }
+            Box::pin(async { Ok(()) })
+        }
+
+        fn close(
+            &self,
+            _reason: actix_ws::CloseReason,
+        ) -> LocalBoxFuture<'static, Result<(), SendError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_stalled_send_times_out_and_is_retried() {
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let retries = SendRetries::new(SendRetryConfig {
+            max_retries: 2,
+            backoff_ms: 1,
+            timeout_ms: 20,
+        });
+        let sink = StalledSink {
+            stalls: std::sync::Mutex::new(1),
+            sent: sent.clone(),
+        };
+        let session = WebSocketSessionActor::with_sink(Rc::new(sink), Uuid::new_v4(), "u".into())
+            .with_send_retries(retries.clone())
+            .start();
+
+        session
+            .send(SendMessage {
+                message: "hello".to_string(),
+                kind: FrameKind::Message,
+            })
+            .await
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(100)).await;
+        assert_eq!(*sent.lock().unwrap(), ["hello"]);
+        assert_eq!(retries.retried(), 1);
+        assert!(session.connected());
+    }
+
+    /// Takes `delay` to accept each frame, like a client reading slowly.
+    struct SlowSink {
+        delay: Duration,
+        sent: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl SessionSink for SlowSink {
+        fn send(&self, frame: OutboundFrame) -> LocalBoxFuture<'static, Result<(), SendError>> {
+            let (delay, sent) = (self.delay, self.sent.clone());
+            Box::pin(async move {
+                actix::clock::sleep(delay).await;
+                let frame = match frame {
+                    OutboundFrame::Text(text) => text,
+                    OutboundFrame::Binary(data) => format!("binary:{}", data.len()),
+                };
+                sent.lock().unwrap().push(frame);
+                Ok(())
+            })
+        }
+
+        fn close(
+            &self,
+            _reason: actix_ws::CloseReason,
+        ) -> LocalBoxFuture<'static, Result<(), SendError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_flooded_session_applies_overflow_policy() {
+        let flood = |policy| async move {
+            let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let queue = OutboundQueue::new(OutboundQueueConfig {
+                capacity: 3,
+                overflow: policy,
             });
-        } else {
-            warn!("No active connection found for session {}", session_id);
+            let sink = SlowSink {
+                delay: Duration::from_millis(50),
+                sent: sent.clone(),
+            };
+            let session =
+                WebSocketSessionActor::with_sink(Rc::new(sink), Uuid::new_v4(), "u".into())
+                    .with_outbound_queue(queue.clone())
+                    .start();
+            let frames = [
+                r#"{"type":"llm_response","n":0}"#,
+                r#"{"type":"animation","n":1}"#,
+                r#"{"type":"llm_response","n":2}"#,
+                r#"{"type":"animation","n":3}"#,
+                r#"{"type":"llm_response","n":4}"#,
+                r#"{"type":"llm_response","n":5}"#,
+            ];
+            for frame in frames {
+                let kind = if frame.contains("animation") {
+                    FrameKind::Animation
+                } else {
+                    FrameKind::Message
+                };
+                session.do_send(SendMessage {
+                    message: frame.to_string(),
+                    kind,
+                });
+            }
+            actix::clock::sleep(Duration::from_millis(400)).await;
+            let sent = sent.lock().unwrap().clone();
+            (sent, queue.overflows(), session.connected())
+        };
+
+        // Frame 0 is in flight; 1-3 fill the queue, so 4 and 5 overflow
+        let (sent, overflows, connected) = flood(OverflowPolicy::DropOldestAnimation).await;
+        assert_eq!(overflows, 2);
+        assert!(connected);
+        assert_eq!(
+            sent,
+            [
+                r#"{"type":"llm_response","n":0}"#,
+                r#"{"type":"llm_response","n":2}"#,
+                r#"{"type":"llm_response","n":4}"#,
+                r#"{"type":"llm_response","n":5}"#,
+            ]
+        );
+
+        let (sent, overflows, _) = flood(OverflowPolicy::DropOldestAny).await;
+        assert_eq!(overflows, 2);
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[1], r#"{"type":"animation","n":3}"#);
+
+        let (sent, overflows, connected) = flood(OverflowPolicy::Disconnect).await;
+        assert_eq!(overflows, 1);
+        assert!(!connected);
+        assert!(sent.len() <= 1);
+    }
+
+    #[actix_web::test]
+    async fn test_full_queue_never_drops_audio() {
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let queue = OutboundQueue::new(OutboundQueueConfig {
+            capacity: 2,
+            overflow: OverflowPolicy::DropOldestAny,
+        });
+        let sink = SlowSink {
+            delay: Duration::from_millis(30),
+            sent: sent.clone(),
+        };
+        let session = WebSocketSessionActor::with_sink(Rc::new(sink), Uuid::new_v4(), "u".into())
+            .with_outbound_queue(queue.clone())
+            .start();
+
+        // Chunk 1 is in flight and 2-3 fill the queue
+        for len in 1..=3 {
+            session.do_send(SendBinary { data: vec![0; len] });
+        }
+        session.do_send(SendMessage {
+            message: r#"{"type":"animation"}"#.to_string(),
+            kind: FrameKind::Animation,
+        });
+        session.do_send(SendBinary { data: vec![0; 4] });
+        actix::clock::sleep(Duration::from_millis(300)).await;
+
+        assert_eq!(queue.overflows(), 2);
+        assert_eq!(
+            *sent.lock().unwrap(),
+            ["binary:1", "binary:2", "binary:3", "binary:4"]
+        );
+    }
+
+    #[test]
+    fn test_client_text_is_classified() {
+        let json = r#"{"type":"text_input","content":"你好"}"#;
+        assert!(matches!(classify(json, true), ClientText::Json(v) if v["content"] == "你好"));
+        assert!(matches!(classify(" [1, 2]", true), ClientText::Json(_)));
+
+        let malformed = r#"{"type":"text_input","content":"你好""#;
+        assert!(matches!(
+            classify(malformed, true),
+            ClientText::Malformed(_)
+        ));
+        assert!(matches!(classify(malformed, false), ClientText::Plain));
+
+        // JSON scalars and text with brackets further in are questions
+        for plain in ["今天唱什么歌？", "42", "\"quoted\"", ":-{"] {
+            assert!(matches!(classify(plain, true), ClientText::Plain));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_malformed_json_gets_error_frame() {
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = SlowSink {
+            delay: Duration::ZERO,
+            sent: sent.clone(),
+        };
+        let session_id = Uuid::new_v4();
+        let session_actor =
+            WebSocketSessionActor::with_sink(Rc::new(sink), session_id, "u".into()).start();
+        let ws_manager = WebSocketManager::new(EventBus::new().start()).start();
+        ws_manager
+            .send(HandleUserConnect {
+                session_id,
+                user_id: "u".to_string(),
+                session_actor,
+                replay: None,
+                token_expires_at: None,
+                operator: false,
+            })
+            .await
+            .unwrap();
+
+        for text in [r#"{"type":"text_input","content":"#, "你好"] {
+            ws_manager
+                .send(HandleTextMessage {
+                    session_id,
+                    user_id: "u".to_string(),
+                    text: text.to_string(),
+                })
+                .await
+                .unwrap();
+        }
+        actix::clock::sleep(Duration::from_millis(20)).await;
+
+        let errors: Vec<serde_json::Value> = sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|frame| serde_json::from_str::<serde_json::Value>(frame).unwrap())
+            .filter(|frame| frame["type"] == "error")
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["data"]["code"], "invalid_json");
+        assert_eq!(errors[0]["schema_version"], EVENT_SCHEMA_VERSION);
+    }
+
+    #[actix_web::test]
+    async fn test_refresh_with_non_admin_token_drops_operator() {
+        let event_bus = EventBus::new().start();
+        let inputs = Collect::<TextInputEvent>::default().start();
+        event_bus
+            .send(crate::event_bus::Subscribe::<TextInputEvent>::all(
+                inputs.clone().recipient(),
+            ))
+            .await
+            .unwrap();
+        let ws_manager = WebSocketManager::new(event_bus)
+            .with_auth(testing::auth())
+            .start();
+        let session_id = Uuid::new_v4();
+        let sink = SlowSink {
+            delay: Duration::ZERO,
+            sent: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        let session_actor =
+            WebSocketSessionActor::with_sink(Rc::new(sink), session_id, "ops".into()).start();
+        ws_manager
+            .send(HandleUserConnect {
+                session_id,
+                user_id: "ops".to_string(),
+                session_actor,
+                replay: None,
+                token_expires_at: None,
+                operator: true,
+            })
+            .await
+            .unwrap();
+        let token = testing::token("ops", false);
+
+        let send = |text: &str| HandleTextMessage {
+            session_id,
+            user_id: "ops".to_string(),
+            text: text.to_string(),
+        };
+        ws_manager
+            .send(send(r#"{"type":"text_input","content":"before"}"#))
+            .await
+            .unwrap();
+        ws_manager
+            .send(send(
+                &serde_json::json!({"type": "auth_refresh", "token": token}).to_string(),
+            ))
+            .await
+            .unwrap();
+        ws_manager
+            .send(send(r#"{"type":"text_input","content":"after"}"#))
+            .await
+            .unwrap();
+        actix::clock::sleep(Duration::from_millis(20)).await;
+
+        let operator: Vec<bool> = Collect::received(&inputs)
+            .await
+            .iter()
+            .map(|event| event.operator)
+            .collect();
+        assert_eq!(operator, vec![true, false]);
+    }
+
+    #[actix_web::test]
+    async fn test_client_without_playable_codec_gets_text_only() {
+        let ws_manager = WebSocketManager::new(EventBus::new().start()).start();
+        let mut sessions = Vec::new();
+        for codecs in [r#"["aac"]"#, r#"["wav","aac"]"#] {
+            let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let sink = SlowSink {
+                delay: Duration::ZERO,
+                sent: sent.clone(),
+            };
+            let session_id = Uuid::new_v4();
+            let session_actor =
+                WebSocketSessionActor::with_sink(Rc::new(sink), session_id, "u".into()).start();
+            ws_manager
+                .send(HandleUserConnect {
+                    session_id,
+                    user_id: "u".to_string(),
+                    session_actor,
+                    replay: None,
+                    token_expires_at: None,
+                    operator: false,
+                })
+                .await
+                .unwrap();
+            ws_manager
+                .send(HandleTextMessage {
+                    session_id,
+                    user_id: "u".to_string(),
+                    text: format!(r#"{{"type":"capabilities","audio_codecs":{}}}"#, codecs),
+                })
+                .await
+                .unwrap();
+
+            let metadata = EventMetadata {
+                session_id: Some(session_id),
+                ..Default::default()
+            };
+            ws_manager
+                .send(LLMResponseEvent {
+                    metadata: metadata.clone(),
+                    response: "大家好".to_string(),
+                    model: "digital_human".to_string(),
+                    tokens_used: None,
+                    length_limit: None,
+                    sampling: None,
+                    language: None,
+                    translation_of: None,
+                    replying_to: None,
+                    segments: None,
+                })
+                .await
+                .unwrap();
+            ws_manager
+                .send(TTSResponseEvent {
+                    metadata: metadata.clone(),
+                    audio_data: vec![0; 64],
+                    text: "大家好".to_string(),
+                    voice: "default".to_string(),
+                    style: None,
+                    voice_fallback: None,
+                    response_id: None,
+                })
+                .await
+                .unwrap();
+            ws_manager
+                .send(TTSChunkEvent {
+                    metadata,
+                    response_id: Uuid::new_v4(),
+                    seq: 0,
+                    is_last: true,
+                    audio: vec![0; 64],
+                    style: None,
+                    voice: None,
+                    voice_fallback: None,
+                })
+                .await
+                .unwrap();
+            sessions.push(sent);
         }
+        actix::clock::sleep(Duration::from_millis(20)).await;
+
+        let kinds = |sent: &Arc<std::sync::Mutex<Vec<String>>>| -> Vec<String> {
+            sent.lock()
+                .unwrap()
+                .iter()
+                .map(
+                    |frame| match serde_json::from_str::<serde_json::Value>(frame) {
+                        Ok(json) => json["type"].as_str().unwrap().to_string(),
+                        Err(_) => frame.clone(),
+                    },
+                )
+                .filter(|kind| kind != "session")
+                .collect()
+        };
+        assert_eq!(kinds(&sessions[0]), vec!["capabilities", "llm_response"]);
+        let notice: serde_json::Value =
+            serde_json::from_str(&sessions[0].lock().unwrap()[1]).unwrap();
+        assert!(notice["data"]["audio_codec"].is_null());
+        assert!(notice["data"]["notice"].is_string());
+
+        // PCM is wrapped as WAV: the first chunk carries the 44-byte header
+        let wav_chunk = format!("binary:{}", AUDIO_CHUNK_HEADER_BYTES + 44 + 64);
+        assert_eq!(
+            kinds(&sessions[1]),
+            vec!["capabilities", "llm_response", "tts_response", &wav_chunk]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_codec_negotiated_mid_utterance_still_gets_wav_header() {
+        let ws_manager = WebSocketManager::new(EventBus::new().start()).start();
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = SlowSink {
+            delay: Duration::ZERO,
+            sent: sent.clone(),
+        };
+        let session_id = Uuid::new_v4();
+        let session_actor =
+            WebSocketSessionActor::with_sink(Rc::new(sink), session_id, "u".into()).start();
+        ws_manager
+            .send(HandleUserConnect {
+                session_id,
+                user_id: "u".to_string(),
+                session_actor,
+                replay: None,
+                token_expires_at: None,
+                operator: false,
+            })
+            .await
+            .unwrap();
+        let response_id = Uuid::new_v4();
+        let chunk = |seq| TTSChunkEvent {
+            metadata: EventMetadata {
+                session_id: Some(session_id),
+                ..Default::default()
+            },
+            response_id,
+            seq,
+            is_last: false,
+            audio: vec![0; 64],
+            style: None,
+            voice: None,
+            voice_fallback: None,
+        };
+        let capabilities = |text: &str| HandleTextMessage {
+            session_id,
+            user_id: "u".to_string(),
+            text: text.to_string(),
+        };
+
+        // Capabilities without a codec list leave the session's audio alone
+        ws_manager
+            .send(capabilities(r#"{"type":"capabilities","streaming":true}"#))
+            .await
+            .unwrap();
+        ws_manager.send(chunk(0)).await.unwrap();
+        ws_manager
+            .send(capabilities(
+                r#"{"type":"capabilities","audio_codecs":["wav"]}"#,
+            ))
+            .await
+            .unwrap();
+        ws_manager.send(chunk(1)).await.unwrap();
+        ws_manager.send(chunk(2)).await.unwrap();
+        actix::clock::sleep(Duration::from_millis(20)).await;
+
+        let frames: Vec<String> = sent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|frame| !frame.contains(r#""type":"session""#))
+            .cloned()
+            .collect();
+        let raw = format!("binary:{}", AUDIO_CHUNK_HEADER_BYTES + 64);
+        let with_header = format!("binary:{}", AUDIO_CHUNK_HEADER_BYTES + 44 + 64);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0], raw);
+        assert!(frames[1].contains(r#""type":"capabilities""#));
+        assert_eq!(frames[2], with_header);
+        assert_eq!(frames[3], raw);
     }
 }
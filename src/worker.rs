@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Runs CPU-bound work (sentiment analysis, transcoding, compression) on the
+/// blocking thread pool so actor mailboxes and the async runtime stay responsive.
+/// At most `size` jobs run at once; the rest wait their turn.
+#[derive(Debug)]
+pub struct WorkerPool {
+    permits: Semaphore,
+    size: usize,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> Arc<Self> {
+        let size = size.max(1);
+        Arc::new(Self {
+            permits: Semaphore::new(size),
+            size,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub async fn run<F, T>(self: Arc<Self>, job: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        // The semaphore is never closed, so acquiring cannot fail
+        let _permit = self.permits.acquire().await.expect("worker pool closed");
+        match actix_web::rt::task::spawn_blocking(job).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+/// One worker per available CPU.
+pub fn default_pool_size() -> usize {
+    std::thread::available_parallelism().map_or(4, |n| n.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::prelude::*;
+    use std::time::{Duration, Instant};
+
+    struct Echo;
+
+    impl Actor for Echo {
+        type Context = Context<Self>;
+    }
+
+    #[derive(Message)]
+    #[rtype(result = "u32")]
+    struct Ping(u32);
+
+    impl Handler<Ping> for Echo {
+        type Result = u32;
+
+        fn handle(&mut self, msg: Ping, _ctx: &mut Context<Self>) -> u32 {
+            msg.0
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_heavy_job_does_not_stall_messages() {
+        let pool = WorkerPool::new(1);
+        let echo = Echo.start();
+
+        let started = Instant::now();
+        let heavy = actix_web::rt::spawn(pool.clone().run(|| {
+            // Stand-in for a slow transcode
+            std::thread::sleep(Duration::from_millis(300));
+            "transcoded"
+        }));
+
+        for i in 0..10 {
+            assert_eq!(echo.send(Ping(i)).await.unwrap(), i);
+        }
+        assert!(started.elapsed() < Duration::from_millis(300));
+
+        assert_eq!(heavy.await.unwrap(), "transcoded");
+        assert!(started.elapsed() >= Duration::from_millis(300));
+    }
+}